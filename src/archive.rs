@@ -0,0 +1,257 @@
+//! Deterministic tar archives of directories, see [`Compressor`].
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+/// A compression backend for archives, see [`TestDir::archive_to_with`].
+///
+/// Implementations are provided for gzip, using the `gzip` feature, and zstd, using the
+/// `zstd` feature.  Implement this to use other formats or compression settings, or to
+/// stream the archive somewhere else, e.g. to object storage.
+///
+/// [`TestDir::archive_to_with`]: crate::TestDir::archive_to_with
+pub trait Compressor {
+    /// Compresses the tar `archive` stream, writing the result to `output`.
+    fn compress(&self, archive: &mut dyn Read, output: &mut dyn Write) -> io::Result<()>;
+}
+
+/// The gzip [`Compressor`].
+///
+/// This requires the `gzip` feature.
+#[cfg(feature = "gzip")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Gzip {
+    /// The compression level, from `0` to `9`.
+    pub level: u32,
+}
+
+#[cfg(feature = "gzip")]
+impl Default for Gzip {
+    fn default() -> Self {
+        Self { level: 6 }
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl Compressor for Gzip {
+    fn compress(&self, archive: &mut dyn Read, output: &mut dyn Write) -> io::Result<()> {
+        let level = flate2::Compression::new(self.level);
+        let mut encoder = flate2::write::GzEncoder::new(output, level);
+        io::copy(archive, &mut encoder)?;
+        encoder.finish()?;
+        Ok(())
+    }
+}
+
+/// The zstd [`Compressor`].
+///
+/// This requires the `zstd` feature.
+#[cfg(feature = "zstd")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Zstd {
+    /// The compression level, from `1` to `22`.  `0` uses zstd's default level.
+    pub level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl Default for Zstd {
+    fn default() -> Self {
+        Self {
+            level: zstd::DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Compressor for Zstd {
+    fn compress(&self, archive: &mut dyn Read, output: &mut dyn Write) -> io::Result<()> {
+        zstd::stream::copy_encode(archive, output, self.level)
+    }
+}
+
+/// Writes a deterministic tar archive of `dir` to the file `dest`.
+///
+/// The entries are prefixed with the final component of `dir`, so the archive extracts
+/// into a single directory.
+pub(crate) fn archive_to(dir: &Path, dest: &Path) -> Result<()> {
+    if dest.starts_with(dir) {
+        bail!("Can not archive {} into itself", dir.display());
+    }
+    let file =
+        File::create(dest).with_context(|| format!("Failed to create {}", dest.display()))?;
+    let mut writer = BufWriter::new(file);
+    write_archive(dir, &mut writer)?;
+    writer
+        .flush()
+        .with_context(|| format!("Failed to write {}", dest.display()))
+}
+
+/// Writes a deterministic tar archive of `dir`, compressed using `compressor`, to `dest`.
+///
+/// The uncompressed archive is first written to a temporary file next to `dest`.
+pub(crate) fn archive_to_with(dir: &Path, dest: &Path, compressor: &dyn Compressor) -> Result<()> {
+    let mut tmp_name = dest
+        .file_name()
+        .with_context(|| format!("Not a file: {}", dest.display()))?
+        .to_os_string();
+    tmp_name.push(format!(".tmp-{}", std::process::id()));
+    let tmp = dest.with_file_name(tmp_name);
+    let res = archive_to(dir, &tmp).and_then(|_| {
+        let mut archive = BufReader::new(File::open(&tmp)?);
+        let file =
+            File::create(dest).with_context(|| format!("Failed to create {}", dest.display()))?;
+        let mut output = BufWriter::new(file);
+        compressor
+            .compress(&mut archive, &mut output)
+            .and_then(|_| output.flush())
+            .with_context(|| format!("Failed to compress {}", dest.display()))
+    });
+    fs::remove_file(&tmp).ok();
+    res
+}
+
+/// Writes a deterministic tar archive of `dir` to `writer`.
+///
+/// Entries are sorted by name and their metadata normalised, so archiving the same
+/// contents always results in the same bytes.  Symlinks are archived as symlinks.
+pub(crate) fn write_archive(dir: &Path, writer: impl Write) -> Result<()> {
+    let root = dir
+        .file_name()
+        .with_context(|| format!("Can not archive {}", dir.display()))?;
+    let mut builder = tar::Builder::new(writer);
+    builder.mode(tar::HeaderMode::Deterministic);
+    builder.follow_symlinks(false);
+    append_dir(&mut builder, dir, Path::new(root))?;
+    builder
+        .into_inner()
+        .context("Failed to finish archive")?
+        .flush()
+        .context("Failed to write archive")
+}
+
+/// Appends the directory `path` and its sorted contents as `name` to the archive.
+fn append_dir<W: Write>(builder: &mut tar::Builder<W>, path: &Path, name: &Path) -> Result<()> {
+    builder
+        .append_dir(name, path)
+        .with_context(|| format!("Failed to archive {}", path.display()))?;
+    let mut entries = path
+        .read_dir()
+        .with_context(|| format!("Failed read_dir() on {}", path.display()))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed read_dir() on {}", path.display()))?;
+    entries.sort_by_key(|dirent| dirent.file_name());
+    for dirent in entries {
+        let entry_path = dirent.path();
+        let entry_name = name.join(dirent.file_name());
+        if dirent.file_type()?.is_dir() {
+            append_dir(builder, &entry_path, &entry_name)?;
+        } else {
+            builder
+                .append_path_with_name(&entry_path, &entry_name)
+                .with_context(|| format!("Failed to archive {}", entry_path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_archive_deterministic() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("test_fail");
+        fs::create_dir_all(dir.join("b")).unwrap();
+        fs::write(dir.join("b/out.txt"), "output").unwrap();
+        fs::write(dir.join("a.log"), "log").unwrap();
+
+        let first = temp.path().join("first.tar");
+        archive_to(&dir, &first).unwrap();
+        fs::write(dir.join("a.log"), "log").unwrap();
+        let mut second = Vec::new();
+        write_archive(&dir, &mut second).unwrap();
+        assert_eq!(fs::read(&first).unwrap(), second);
+
+        let mut archive = tar::Archive::new(File::open(&first).unwrap());
+        let names: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "test_fail",
+                "test_fail/a.log",
+                "test_fail/b",
+                "test_fail/b/out.txt"
+            ]
+            .map(std::path::PathBuf::from)
+        );
+        assert!(archive_to(&dir, &dir.join("self.tar")).is_err());
+    }
+
+    struct Identity;
+
+    impl Compressor for Identity {
+        fn compress(&self, archive: &mut dyn Read, output: &mut dyn Write) -> io::Result<()> {
+            io::copy(archive, output).map(|_| ())
+        }
+    }
+
+    #[test]
+    fn test_archive_to_with() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("test_fail");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("out.txt"), "output").unwrap();
+        let mut expected = Vec::new();
+        write_archive(&dir, &mut expected).unwrap();
+
+        let dest = temp.path().join("archive.tar");
+        archive_to_with(&dir, &dest, &Identity).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), expected);
+        assert_eq!(fs::read_dir(temp.path()).unwrap().count(), 2);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("test_fail");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("out.txt"), "output").unwrap();
+        let mut expected = Vec::new();
+        write_archive(&dir, &mut expected).unwrap();
+
+        let dest = temp.path().join("archive.tar.gz");
+        archive_to_with(&dir, &dest, &Gzip::default()).unwrap();
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(File::open(&dest).unwrap())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, expected);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("test_fail");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("out.txt"), "output").unwrap();
+        let mut expected = Vec::new();
+        write_archive(&dir, &mut expected).unwrap();
+
+        let dest = temp.path().join("archive.tar.zst");
+        archive_to_with(&dir, &dest, &Zstd::default()).unwrap();
+        let decoded = zstd::stream::decode_all(File::open(&dest).unwrap()).unwrap();
+        assert_eq!(decoded, expected);
+    }
+}
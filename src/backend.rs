@@ -0,0 +1,272 @@
+//! The filesystem operations used by [`NumberedDir`], see [`DirBackend`].
+//!
+//! [`NumberedDir`]: crate::NumberedDir
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+use crate::lock::FileLock;
+
+#[cfg(unix)]
+pub(crate) use std::os::unix::fs::symlink as symlink_dir;
+#[cfg(windows)]
+pub(crate) use std::os::windows::fs::symlink_dir;
+
+/// How long [`StdBackend`] waits for the lock of a metadata file.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Symlinks are not supported on other platforms, e.g. wasm.
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn symlink_dir(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks are not supported on this platform",
+    ))
+}
+
+/// The filesystem operations used to manage numbered directories.
+///
+/// A [`NumberedDir`] performs all operations on its own layout through its backend:
+/// creating the numbered directory and its subdirectories, listing and removing existing
+/// numbered directories, maintaining the `-current` and `-previous` symlinks and reading and
+/// writing the small metadata files like `format-version`.  By default this is the
+/// [`StdBackend`], a different backend can be used with
+/// [`NumberedDirBuilder::backend`], e.g. the in-memory `MemoryBackend` for unit-testing
+/// code using testdir, or a wrapper counting the operations.
+///
+/// Functionality beyond managing the layout, like age-based [`Retention`], VCS
+/// recording, private parent permissions, canonicalization, strict subdirectory checks and
+/// anything done with the paths by the tests themselves, always uses [`std::fs`].
+///
+/// This requires the `backend` feature.
+///
+/// [`NumberedDir`]: crate::NumberedDir
+/// [`NumberedDirBuilder::backend`]: crate::NumberedDirBuilder::backend
+/// [`Retention`]: crate::Retention
+pub trait DirBackend: fmt::Debug + Send + Sync {
+    /// Creates the directory at `path`, its parent must exist.
+    ///
+    /// Must fail with [`io::ErrorKind::AlreadyExists`] if `path` already exists.
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Creates the directory at `path` and all its missing parents.
+    ///
+    /// Existing directories are not an error.
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Removes the directory at `path` with all its contents.
+    ///
+    /// The `threads` are a hint of how many threads may be used to do so.
+    fn remove_dir_all(&self, path: &Path, threads: usize) -> io::Result<()>;
+
+    /// Returns the paths of all entries of the directory at `path`, in any order.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// Whether `path` is a directory, following symlinks.
+    fn is_dir(&self, path: &Path) -> bool;
+
+    /// Points the symlink at `link` to the directory `target`.
+    ///
+    /// An existing symlink at `link` is replaced.
+    fn symlink_dir(&self, target: &Path, link: &Path) -> io::Result<()>;
+
+    /// Returns the target of the symlink at `link`.
+    fn read_link(&self, link: &Path) -> io::Result<PathBuf>;
+
+    /// Writes `contents` to the file at `path`, replacing it if it exists.
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    /// Returns the contents of the file at `path`.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Renames the file at `from` to `to`, replacing `to` if it exists.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Flushes the file or directory at `path` to durable storage.
+    ///
+    /// This is used by [`NumberedDirBuilder::durable`], by default nothing is done.
+    ///
+    /// [`NumberedDirBuilder::durable`]: crate::NumberedDirBuilder::durable
+    fn sync(&self, path: &Path) -> io::Result<()> {
+        let _ = path;
+        Ok(())
+    }
+
+    /// Runs `update` while holding an exclusive lock for the file at `path`.
+    ///
+    /// This is used to update metadata files which concurrent processes write to, like
+    /// `path-names.json`.  By default `update` is only serialised with other updates in the
+    /// same process, which is enough for backends not shared between processes.
+    fn locked(&self, path: &Path, update: &mut dyn FnMut() -> io::Result<()>) -> io::Result<()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        let _ = path;
+        let _guard = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        update()
+    }
+}
+
+/// The [`DirBackend`] using the real filesystem through [`std::fs`], the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdBackend;
+
+impl DirBackend for StdBackend {
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path, threads: usize) -> io::Result<()> {
+        crate::cleanup::remove_dir_all_parallel(path, threads)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::read_dir(path)?
+            .map(|dirent| dirent.map(|dirent| dirent.path()))
+            .collect()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn symlink_dir(&self, target: &Path, link: &Path) -> io::Result<()> {
+        if link.symlink_metadata().is_ok() {
+            // This can fail on windows, treat it as best-effort.
+            fs::remove_file(link).ok();
+        }
+        symlink_dir(target, link)
+    }
+
+    fn read_link(&self, link: &Path) -> io::Result<PathBuf> {
+        fs::read_link(link)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn locked(&self, path: &Path, update: &mut dyn FnMut() -> io::Result<()>) -> io::Result<()> {
+        let mut lock_path = path.as_os_str().to_os_string();
+        lock_path.push(".lock");
+        let _lock = FileLock::acquire(lock_path, LOCK_TIMEOUT).map_err(io::Error::other)?;
+        update()
+    }
+
+    fn sync(&self, path: &Path) -> io::Result<()> {
+        // Only UNIX supports opening directories to sync them.
+        if cfg!(not(unix)) && path.is_dir() {
+            return Ok(());
+        }
+        fs::File::open(path)?.sync_all()
+    }
+}
+
+/// Returns the shared [`StdBackend`].
+pub(crate) fn std_backend() -> Arc<dyn DirBackend> {
+    static STD_BACKEND: Lazy<Arc<dyn DirBackend>> = Lazy::new(|| Arc::new(StdBackend));
+    STD_BACKEND.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_std_backend() {
+        let temp = tempfile::tempdir().unwrap();
+        let backend = StdBackend;
+        let dir = temp.path().join("a/b");
+        backend.create_dir_all(&dir).unwrap();
+        let err = backend.create_dir(&dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        backend.write(&dir.join("file"), b"data").unwrap();
+        assert_eq!(backend.read(&dir.join("file")).unwrap(), b"data");
+        backend.sync(&dir.join("file")).unwrap();
+        assert_eq!(backend.read_dir(&dir).unwrap(), vec![dir.join("file")]);
+
+        let link = temp.path().join("link");
+        backend.symlink_dir(&dir, &link).unwrap();
+        backend.symlink_dir(&temp.path().join("a"), &link).unwrap();
+        assert_eq!(backend.read_link(&link).unwrap(), temp.path().join("a"));
+        assert!(backend.is_dir(&link));
+
+        backend.remove_dir_all(&temp.path().join("a"), 1).unwrap();
+        assert!(!backend.is_dir(&dir));
+    }
+
+    #[cfg(feature = "backend")]
+    #[test]
+    fn test_counting_backend() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Debug, Default)]
+        struct Counting {
+            create_dir: Arc<AtomicUsize>,
+        }
+
+        impl DirBackend for Counting {
+            fn create_dir(&self, path: &Path) -> io::Result<()> {
+                self.create_dir.fetch_add(1, Ordering::Relaxed);
+                StdBackend.create_dir(path)
+            }
+            fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+                StdBackend.create_dir_all(path)
+            }
+            fn remove_dir_all(&self, path: &Path, threads: usize) -> io::Result<()> {
+                StdBackend.remove_dir_all(path, threads)
+            }
+            fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+                StdBackend.read_dir(path)
+            }
+            fn is_dir(&self, path: &Path) -> bool {
+                StdBackend.is_dir(path)
+            }
+            fn symlink_dir(&self, target: &Path, link: &Path) -> io::Result<()> {
+                StdBackend.symlink_dir(target, link)
+            }
+            fn read_link(&self, link: &Path) -> io::Result<PathBuf> {
+                StdBackend.read_link(link)
+            }
+            fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+                StdBackend.write(path, contents)
+            }
+            fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+                StdBackend.read(path)
+            }
+            fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+                StdBackend.rename(from, to)
+            }
+        }
+
+        let temp = tempfile::tempdir().unwrap();
+        let counter = Arc::new(AtomicUsize::new(0));
+        let dir = crate::NumberedDirBuilder::new(String::from("base"))
+            .set_parent(temp.path().to_path_buf())
+            .backend(Counting {
+                create_dir: counter.clone(),
+            })
+            .create()
+            .unwrap();
+        dir.create_subdir("mod/test").unwrap();
+        assert_eq!(counter.load(Ordering::Relaxed), 2);
+        assert!(dir.path().join("mod/test").is_dir());
+    }
+}
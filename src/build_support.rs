@@ -0,0 +1,77 @@
+//! Support for build scripts.
+//!
+//! By default [`init_testdir!`] runs `cargo metadata` to find the cargo target directory.
+//! This can be avoided by capturing the target directory in a build script, which bakes it
+//! into the test binaries.  Call this from the `main` function of your `build.rs`:
+//!
+//! ```no_run
+//! testdir::build_support::emit_target_dir();
+//! ```
+//!
+//! This requires `testdir` to be listed in the `[build-dependencies]` section of
+//! `Cargo.toml` as well.
+//!
+//! [`init_testdir!`]: crate::init_testdir
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The environment variable used to pass the target directory: `TESTDIR_TARGET_DIR`.
+///
+/// This is set at compile-time by [`emit_target_dir`] and read by
+/// [`init_testdir!`](crate::init_testdir) using [`std::option_env!`].
+pub const TARGET_DIR_ENV: &str = "TESTDIR_TARGET_DIR";
+
+/// Returns the cargo target directory, when called from a build script.
+///
+/// This uses `CARGO_TARGET_DIR` if it is set, or else derives the target directory from
+/// the `OUT_DIR` of the build script.  Returns `None` if not called from a build script.
+pub fn target_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("CARGO_TARGET_DIR").map(PathBuf::from) {
+        if dir.is_absolute() {
+            return Some(dir);
+        }
+    }
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR")?);
+    let cross = env::var_os("TARGET") != env::var_os("HOST");
+    target_dir_from_out_dir(&out_dir, cross)
+}
+
+/// Emits the target directory as compile-time environment variable for the crate.
+///
+/// Call this from a build script to let [`init_testdir!`](crate::init_testdir) use the
+/// target directory without running `cargo metadata`.
+///
+/// # Panics
+///
+/// If not called from a build script.
+pub fn emit_target_dir() {
+    let dir = target_dir().expect("Not run from a build script, OUT_DIR not set");
+    println!("cargo:rerun-if-env-changed=CARGO_TARGET_DIR");
+    println!("cargo:rustc-env={}={}", TARGET_DIR_ENV, dir.display());
+}
+
+/// Derives the target directory from a build script's `OUT_DIR`.
+///
+/// The `OUT_DIR` looks like `target/[triple/]profile/build/pkg-hash/out`, the triple is
+/// present when cross-compiling.
+fn target_dir_from_out_dir(out_dir: &Path, cross: bool) -> Option<PathBuf> {
+    let levels = if cross { 5 } else { 4 };
+    out_dir.ancestors().nth(levels).map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_dir_from_out_dir() {
+        let out_dir = Path::new("/src/target/debug/build/pkg-1234/out");
+        let dir = target_dir_from_out_dir(out_dir, false).unwrap();
+        assert_eq!(dir, Path::new("/src/target"));
+
+        let out_dir = Path::new("/src/target/aarch64-unknown-linux-gnu/debug/build/pkg-1234/out");
+        let dir = target_dir_from_out_dir(out_dir, true).unwrap();
+        assert_eq!(dir, Path::new("/src/target"));
+    }
+}
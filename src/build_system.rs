@@ -0,0 +1,114 @@
+//! Detection of build system test runners, see [`BuildSystemEnv`].
+
+use std::env;
+use std::path::PathBuf;
+use std::sync::Once;
+
+use crate::{ExportLayout, TESTDIR};
+
+/// The environment variables with the private temporary directory of a test and the
+/// directory for undeclared outputs.
+const BUILD_SYSTEM_VARS: [(&str, &str); 1] = [("TEST_TMPDIR", "TEST_UNDECLARED_OUTPUTS_DIR")];
+
+/// The directory name in the outputs directory into which the global testdir is exported:
+/// `testdir`.
+const EXPORT_DIR_NAME: &str = "testdir";
+
+/// The environment provided to tests by a build system like Bazel.
+///
+/// Under Bazel tests are not run by cargo, so there is no cargo parent process and no target
+/// directory.  Instead each test gets a private temporary directory in `TEST_TMPDIR` and
+/// files written to `TEST_UNDECLARED_OUTPUTS_DIR` are kept as test outputs.  Any test
+/// runner providing these variables is detected.
+///
+/// When detected [`testdir!`] uses the temporary directory as the parent of the global
+/// [`NumberedDir`].  When the outputs directory is given the test directories are exported
+/// into its `testdir` directory when the test process exits, using
+/// [`ExportLayout::tree`].
+///
+/// [`testdir!`]: crate::testdir
+/// [`NumberedDir`]: crate::NumberedDir
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildSystemEnv {
+    /// The private temporary directory of the test.
+    pub tmpdir: PathBuf,
+    /// The directory of which the contents are kept as test outputs, if any.
+    pub outputs_dir: Option<PathBuf>,
+}
+
+impl BuildSystemEnv {
+    /// Detects the environment of a build system test runner.
+    ///
+    /// Returns `None` when not running under a build system, which is the case when run by
+    /// cargo.
+    pub fn detect() -> Option<Self> {
+        Self::from_env(|name| env::var_os(name).map(PathBuf::from))
+    }
+
+    /// Detects the environment from the environment variables, looked up using `var`.
+    fn from_env(var: impl Fn(&str) -> Option<PathBuf>) -> Option<Self> {
+        let nonempty = |name| var(name).filter(|path| !path.as_os_str().is_empty());
+        BUILD_SYSTEM_VARS.iter().find_map(|(tmpdir, outputs_dir)| {
+            Some(Self {
+                tmpdir: nonempty(tmpdir)?,
+                outputs_dir: nonempty(outputs_dir),
+            })
+        })
+    }
+}
+
+/// Registers exporting the global testdir when the process exits, if running under a build
+/// system with an outputs directory.
+pub(crate) fn register_from_env() {
+    static REGISTER: Once = Once::new();
+    if BuildSystemEnv::detect().is_some_and(|env| env.outputs_dir.is_some()) {
+        REGISTER.call_once(|| crate::summary::register_exit_handler(export_outputs));
+    }
+}
+
+extern "C" fn export_outputs() {
+    let Some(outputs_dir) = BuildSystemEnv::detect().and_then(|env| env.outputs_dir) else {
+        return;
+    };
+    if let Some(numdir) = TESTDIR.get() {
+        let dest = outputs_dir.join(EXPORT_DIR_NAME);
+        if let Err(err) = numdir.export(&dest, ExportLayout::tree()) {
+            eprintln!("Failed to export testdir to {}: {err:#}", dest.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_system_from_env() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            BuildSystemEnv::from_env(|name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, val)| PathBuf::from(val))
+            })
+        };
+        assert_eq!(env(&[]), None);
+        assert_eq!(env(&[("TEST_TMPDIR", "")]), None);
+        assert_eq!(
+            env(&[("TEST_TMPDIR", "/tmp/test")]),
+            Some(BuildSystemEnv {
+                tmpdir: PathBuf::from("/tmp/test"),
+                outputs_dir: None,
+            })
+        );
+        assert_eq!(
+            env(&[
+                ("TEST_TMPDIR", "/tmp/test"),
+                ("TEST_UNDECLARED_OUTPUTS_DIR", "/out")
+            ]),
+            Some(BuildSystemEnv {
+                tmpdir: PathBuf::from("/tmp/test"),
+                outputs_dir: Some(PathBuf::from("/out")),
+            })
+        );
+    }
+}
@@ -3,14 +3,25 @@
 use std::ffi::OsString;
 use std::fmt;
 use std::fs;
-use std::num::NonZeroU8;
+use std::io;
+use std::num::{NonZeroU8, NonZeroUsize};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::SystemTime;
 
-use anyhow::{Context, Error, Result};
+use anyhow::{anyhow, Context, Result};
 
-use crate::{NumberedDir, KEEP_DEFAULT, ROOT_DEFAULT};
+use crate::backend::{std_backend, DirBackend};
+use crate::numbered_dir::{
+    current_entry_count, run_id_dirs, validate_base, CreateOptions, NumberedDirIter,
+};
+use crate::simplify::simplify_path;
+use crate::{
+    CleanupReport, Collision, Error, NumberedDir, PortableNames, Ramdisk, Retention, RetentionMap,
+    RetryStrategy, RootStrategy, SubdirLimits, VcsInfo, XdgCache, XdgRuntime, KEEP_DEFAULT,
+    ROOT_DEFAULT,
+};
 
 /// Builder to create a [`NumberedDir`].
 ///
@@ -29,10 +40,13 @@ use crate::{NumberedDir, KEEP_DEFAULT, ROOT_DEFAULT};
 /// The basic constructor uses a *root* of `testdir-of-$USER` placed in the system's default
 /// temporary director location as per [`std::env::temp_dir`].  To customise the root you
 /// can use [`NumberedDirBuilder::root`] or [`NumberedDirBuilder::user_root].  The temporary
-/// directory provider can also be changed using [`NumberedDirBuilder::tmpdir_provider`].
+/// directory provider can also be changed using [`NumberedDirBuilder::tmpdir_provider`] or
+/// [`NumberedDirBuilder::root_strategy`].
 ///
 /// If you simply want an absolute path as parent directory for the numbered directory use
-/// the [`NumberedDirBuilder::set_parent`] function.
+/// the [`NumberedDirBuilder::set_parent`] function.  On shared machines
+/// [`NumberedDirBuilder::shared_root`] can be used to create a per-user parent directory
+/// inside a common location.
 ///
 /// Sometimes you may have some external condition which signals that an existing numbered
 /// directory should be re-used.  The [`NumberedDirBuilder::reusefn] can be used for this.
@@ -53,11 +67,57 @@ pub struct NumberedDirBuilder {
     parent: PathBuf,
     /// The base of the numbered dir, its name without the number suffix.
     base: String,
-    /// The number of numbered dirs to keep around **after** the new directory is created.
-    count: NonZeroU8,
+    /// The policy of which numbered dirs to keep around **after** the new directory is
+    /// created, for each base.
+    retention: RetentionMap,
     /// Function to determine whether to re-use a numbered dir.
     #[allow(clippy::type_complexity)]
     reuse_fn: Option<Arc<Box<dyn Fn(&Path) -> bool + Send + Sync>>>,
+    /// The limits for subdirectories of the created numbered dir.
+    limits: SubdirLimits,
+    /// What to do when a subdirectory of the created numbered dir already exists.
+    collision: Collision,
+    /// How names of subdirectories of the created numbered dir which can not be used on
+    /// Windows are handled.
+    portable_names: PortableNames,
+    /// Whether created directories and files are flushed to disk.
+    durable: bool,
+    /// Whether to write the `-current.path` pointer file.
+    pointer_file: bool,
+    /// Whether to create the `-current` symlink.
+    current_symlink: bool,
+    /// Whether to maintain the `-previous` symlink.
+    previous_symlink: bool,
+    /// Whether the `-current` symlink is relative.
+    relative_symlink: bool,
+    /// Whether the parent is a per-user directory which must only be accessible by the
+    /// current user.
+    private_parent: bool,
+    /// Whether to canonicalize the parent before creating the numbered dir.
+    canonicalize: bool,
+    /// Whether to strip the Windows `\\?\` prefix from the parent.
+    strip_unc_prefix: bool,
+    /// Whether to replace a macOS `/private/var` prefix of the parent with `/var`.
+    normalize_private: bool,
+    /// Whether failing to remove obsolete numbered dirs is not an error.
+    continue_on_cleanup_error: bool,
+    /// The number of threads used to remove obsolete numbered dirs.
+    cleanup_threads: NonZeroUsize,
+    /// How to retry creating the numbered dir under contention.
+    retry: RetryStrategy,
+    /// Hooks called with the path of a created, re-used or removed numbered dir.
+    hooks: Hooks,
+    /// Function called with the report of cleaning up obsolete numbered dirs.
+    #[allow(clippy::type_complexity)]
+    cleanup_fn: Option<Arc<dyn Fn(&CleanupReport) + Send + Sync>>,
+    /// The run identifier to name the numbered dir after instead of a number.
+    run_id: Option<String>,
+    /// Whether to write the `vcs.json` file into a new numbered dir.
+    record_vcs: bool,
+    /// Whether to append the short commit hash to the run identifier.
+    vcs_in_name: bool,
+    /// The filesystem operations used to manage the numbered dirs.
+    backend: Arc<dyn DirBackend>,
 }
 
 impl fmt::Debug for NumberedDirBuilder {
@@ -65,8 +125,29 @@ impl fmt::Debug for NumberedDirBuilder {
         f.debug_struct("NumberedDirBuilder")
             .field("parent", &self.parent)
             .field("base", &self.base)
-            .field("count", &self.count)
+            .field("retention", &self.retention)
             .field("reusefn", &"<Fn(&Path) -> bool>")
+            .field("limits", &self.limits)
+            .field("collision", &self.collision)
+            .field("portable_names", &self.portable_names)
+            .field("durable", &self.durable)
+            .field("pointer_file", &self.pointer_file)
+            .field("current_symlink", &self.current_symlink)
+            .field("previous_symlink", &self.previous_symlink)
+            .field("relative_symlink", &self.relative_symlink)
+            .field("private_parent", &self.private_parent)
+            .field("canonicalize", &self.canonicalize)
+            .field("strip_unc_prefix", &self.strip_unc_prefix)
+            .field("normalize_private", &self.normalize_private)
+            .field("continue_on_cleanup_error", &self.continue_on_cleanup_error)
+            .field("cleanup_threads", &self.cleanup_threads)
+            .field("retry", &self.retry)
+            .field("hooks", &self.hooks)
+            .field("cleanup_fn", &"<Fn(&CleanupReport)>")
+            .field("run_id", &self.run_id)
+            .field("record_vcs", &self.record_vcs)
+            .field("vcs_in_name", &self.vcs_in_name)
+            .field("backend", &self.backend)
             .finish()
     }
 }
@@ -76,16 +157,43 @@ impl NumberedDirBuilder {
     ///
     /// By default the *root* will be set to `testdir-of-$USER`. (using [`ROOT_DEFAULT`])
     /// and the count will be set to `8` ([`KEEP_DEFAULT`]).
+    ///
+    /// The `base` must not contain path separators, this is checked by
+    /// [`NumberedDirBuilder::validate`].
     pub fn new(base: String) -> Self {
-        if base.contains('/') || base.contains('\\') {
-            panic!("base must not contain path separators");
-        }
-        let root = format!("{}-of-{}", ROOT_DEFAULT, whoami::username());
+        // Miri can not look up the user.
+        let user = if crate::private::miri_mode() {
+            String::from("miri")
+        } else {
+            whoami::username()
+        };
+        let root = format!("{}-of-{}", ROOT_DEFAULT, user);
         Self {
-            parent: std::env::temp_dir().join(root),
+            parent: crate::root::temp_dir().join(root),
             base,
-            count: KEEP_DEFAULT.unwrap(),
+            retention: RetentionMap::new(Retention::Count(KEEP_DEFAULT.unwrap())),
             reuse_fn: None,
+            limits: SubdirLimits::default(),
+            collision: Collision::default(),
+            portable_names: PortableNames::default(),
+            durable: false,
+            pointer_file: false,
+            current_symlink: true,
+            previous_symlink: false,
+            relative_symlink: false,
+            private_parent: false,
+            canonicalize: false,
+            strip_unc_prefix: false,
+            normalize_private: false,
+            continue_on_cleanup_error: false,
+            cleanup_threads: NonZeroUsize::MIN,
+            retry: RetryStrategy::default(),
+            hooks: Hooks::default(),
+            cleanup_fn: None,
+            run_id: None,
+            record_vcs: false,
+            vcs_in_name: false,
+            backend: std_backend(),
         }
     }
 
@@ -101,6 +209,7 @@ impl NumberedDirBuilder {
     /// default temporary directory location.
     pub fn root(&mut self, root: impl Into<String>) -> &mut Self {
         self.parent.set_file_name(root.into());
+        self.private_parent = false;
         self
     }
 
@@ -112,6 +221,26 @@ impl NumberedDirBuilder {
     pub fn user_root(&mut self, prefix: &str) -> &mut Self {
         let root = format!("{}{}", prefix, whoami::username());
         self.parent.set_file_name(root);
+        self.private_parent = false;
+        self
+    }
+
+    /// Uses a per-user directory inside a shared *root*.
+    ///
+    /// This is intended for shared machines with a common location for test artifacts,
+    /// e.g. `/srv/test-artifacts`.  The [`NumberedDir`] will be created inside a
+    /// subdirectory named after the current user, e.g. `/srv/test-artifacts/$USER`.  On
+    /// UNIX the permissions of this per-user directory are restricted to the current user
+    /// so users can not trample each other's directories.
+    ///
+    /// The shared *root* must already exist as a directory, usually created by an
+    /// administrator with permissions allowing all users to create directories in it, e.g.
+    /// mode `1777` like `/tmp`.  It is not created automatically since it would be owned by
+    /// the first user.  Creating the [`NumberedDir`] fails if the root does not exist or is
+    /// a symlink, or if the per-user directory already exists but is owned by another user.
+    pub fn shared_root(&mut self, root: impl Into<PathBuf>) -> &mut Self {
+        self.parent = root.into().join(whoami::username());
+        self.private_parent = true;
         self
     }
 
@@ -129,6 +258,98 @@ impl NumberedDirBuilder {
         self
     }
 
+    /// Uses a [`RootStrategy`] to select the directory to place the *root* into.
+    ///
+    /// Like [`NumberedDirBuilder::tmpdir_provider`] this changes the location in which the
+    /// *root* will be placed, but uses a reusable strategy such as
+    /// [`ContainerAware`](crate::ContainerAware).  If the strategy does not select a
+    /// directory the current location is kept.
+    pub fn root_strategy(&mut self, strategy: &dyn RootStrategy) -> &mut Self {
+        if let Some(tmpdir) = strategy.tmpdir() {
+            self.tmpdir_provider(|| tmpdir);
+        }
+        self
+    }
+
+    /// Places the *root* in the user's cache directory.
+    ///
+    /// This uses `$XDG_CACHE_HOME` on Linux, see [`XdgCache`] for the details and
+    /// fallbacks on other platforms.
+    pub fn root_xdg_cache(&mut self) -> &mut Self {
+        self.root_strategy(&XdgCache)
+    }
+
+    /// Places the *root* on a RAM-backed filesystem if available.
+    ///
+    /// This prefers `/dev/shm` on Linux and otherwise keeps using the system's temporary
+    /// directory, see [`Ramdisk`].  To use a specific ramdisk pass a configured
+    /// [`Ramdisk`] to [`NumberedDirBuilder::root_strategy`] instead.
+    pub fn root_ramdisk(&mut self) -> &mut Self {
+        self.root_strategy(&Ramdisk::new())
+    }
+
+    /// Places the *root* in the user's runtime directory.
+    ///
+    /// This uses `$XDG_RUNTIME_DIR`, see [`XdgRuntime`] for the details and fallbacks.
+    pub fn root_xdg_runtime(&mut self) -> &mut Self {
+        self.root_strategy(&XdgRuntime)
+    }
+
+    /// Places the [`NumberedDir`] in the `tmp` directory of the cargo target directory.
+    ///
+    /// This sets the parent directory to `target_dir/tmp`, as used by cargo for
+    /// `CARGO_TARGET_TMPDIR`, so the numbered directories and their `-current` symlink do
+    /// not clutter the root of the target directory.  Retention and the symlinks are
+    /// managed inside this directory, as with any other parent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::NumberedDirBuilder;
+    ///
+    /// let target = std::env::temp_dir().join("testdir-target-tmp-example");
+    /// let numdir = NumberedDirBuilder::new(String::from("testdir"))
+    ///     .target_tmp(&target)
+    ///     .create()
+    ///     .unwrap();
+    /// assert!(numdir.path().starts_with(target.join("tmp")));
+    /// ```
+    pub fn target_tmp(&mut self, target_dir: impl AsRef<Path>) -> &mut Self {
+        self.set_parent(target_dir.as_ref().join(TARGET_TMP_DIR_NAME))
+    }
+
+    /// Appends the cargo build profile to the *base*-name, e.g. `testdir-debug`.
+    ///
+    /// This keeps separate numbered directories, with separate retention, for test runs of
+    /// different build profiles like `cargo test` and `cargo test --release`.  The profile
+    /// is detected from the location of the running executable in the target directory, so
+    /// custom profiles are supported.  Doctests fall back to `debug` or `release`.
+    ///
+    /// This changes the current *base*-name, so must be called after
+    /// [`NumberedDirBuilder::base`].
+    pub fn per_profile(&mut self) -> &mut Self {
+        self.base = format!("{}-{}", self.base, crate::target_dir::build_profile());
+        self
+    }
+
+    /// Appends the target triple of cross-compiled tests to the *base*-name, e.g.
+    /// `testdir-aarch64-unknown-linux-gnu`.
+    ///
+    /// This keeps separate numbered directories, with separate retention, for test runs for
+    /// different targets, e.g. using runners or emulators.  The target triple is detected
+    /// from the location of the running executable in the target directory, so is only
+    /// known when cargo was invoked with an explicit `--target`.  Otherwise the *base*-name
+    /// is not changed.
+    ///
+    /// This changes the current *base*-name, so must be called after
+    /// [`NumberedDirBuilder::base`].
+    pub fn per_target(&mut self) -> &mut Self {
+        if let Some(triple) = crate::target_dir::target_triple() {
+            self.base = format!("{}-{}", self.base, triple);
+        }
+        self
+    }
+
     /// Sets the parent directory for the [`NumberedDir`].
     ///
     /// This does not follow the *root* concept anymore, instead it directly sets the full
@@ -136,12 +357,51 @@ impl NumberedDirBuilder {
     /// probably want this to be an absolute path but this is not enforced.
     ///
     /// Be aware that it is a requirement that the last component of the parent directory is
-    /// valid UTF-8.
+    /// valid UTF-8, this is checked by [`NumberedDirBuilder::validate`].
     pub fn set_parent(&mut self, path: PathBuf) -> &mut Self {
-        if path.file_name().and_then(|name| name.to_str()).is_none() {
-            panic!("Last component of parent is not UTF-8");
-        }
         self.parent = path;
+        self.private_parent = false;
+        self
+    }
+
+    /// Resolves the parent directory to an absolute path without symlinks.
+    ///
+    /// When enabled the parent directory is created if needed and canonicalized before the
+    /// [`NumberedDir`] is created in it, so that [`NumberedDir::path`] is an absolute,
+    /// symlink-free path.  Disabled by default.
+    ///
+    /// Problems with the parent directory, like a nonexistent drive or missing
+    /// permissions, are reported by [`NumberedDirBuilder::create`] with the parent
+    /// directory in the error message.  The underlying [`std::io::Error`] is the `source` of
+    /// the returned [`Error::Io`].
+    pub fn canonicalize(&mut self, enable: bool) -> &mut Self {
+        self.canonicalize = enable;
+        self
+    }
+
+    /// Strips the Windows verbatim `\\?\` prefix from the parent directory.
+    ///
+    /// Canonical paths on Windows start with `\\?\`, e.g. `\\?\C:\Users`, which many
+    /// tools do not understand and which makes paths embedded in snapshots differ from the
+    /// ones users type.  When enabled the prefix is removed if the path means the same
+    /// without it, like [dunce] does.  Paths only valid in the verbatim form, e.g. because
+    /// they are too long, are kept.  Disabled by default.
+    ///
+    /// [dunce]: https://docs.rs/dunce
+    pub fn strip_unc_prefix(&mut self, enable: bool) -> &mut Self {
+        self.strip_unc_prefix = enable;
+        self
+    }
+
+    /// Replaces a `/private/var`, `/private/tmp` or `/private/etc` prefix of the parent
+    /// directory with `/var`, `/tmp` or `/etc`.
+    ///
+    /// On macOS these directories are symlinks into `/private`, so a canonicalized
+    /// temporary directory does not match [`std::env::temp_dir`].  The prefix is only
+    /// replaced if the shorter path is such a symlink, on other platforms this does nothing.
+    /// Disabled by default.
+    pub fn normalize_private(&mut self, enable: bool) -> &mut Self {
+        self.normalize_private = enable;
         self
     }
 
@@ -149,8 +409,28 @@ impl NumberedDirBuilder {
     ///
     /// If creating the new [`NumberedDir`] would exceed this number, older directories will
     /// be removed.
+    ///
+    /// This is a shorthand for [`NumberedDirBuilder::retention`] with [`Retention::Count`].
     pub fn count(&mut self, count: NonZeroU8) -> &mut Self {
-        self.count = count;
+        self.retention.default = Retention::Count(count);
+        self
+    }
+
+    /// Sets the [`Retention`] policy deciding which older directories are removed.
+    ///
+    /// This is the policy for all bases without their own policy set using
+    /// [`NumberedDirBuilder::base_retention`].
+    pub fn retention(&mut self, retention: Retention) -> &mut Self {
+        self.retention.default = retention;
+        self
+    }
+
+    /// Sets the [`Retention`] policy for the given `base`.
+    ///
+    /// A single builder can manage several bases in the same parent directory using
+    /// [`NumberedDirBuilder::create_base`], each with their own policy.
+    pub fn base_retention(&mut self, base: impl Into<String>, retention: Retention) -> &mut Self {
+        self.retention.insert(base.into(), retention);
         self
     }
 
@@ -158,6 +438,8 @@ impl NumberedDirBuilder {
     ///
     /// The provided function will be called with each existing numbered directory and if it
     /// returns `true` this directory will be re-used instead of a new one being created.
+    /// Directories created with an incompatible format are never re-used, see
+    /// [`NumberedDir::is_compatible`].
     pub fn reusefn<F>(&mut self, f: F) -> &mut Self
     where
         F: Fn(&Path) -> bool + Send + Sync + 'static,
@@ -172,25 +454,681 @@ impl NumberedDirBuilder {
         self
     }
 
+    /// Creates the new [`NumberedDir`] even if obsolete directories can not be removed.
+    ///
+    /// By default failing to remove an obsolete directory, e.g. because a lingering
+    /// process still has a file open on Windows, fails [`NumberedDirBuilder::create`].
+    /// When enabled the failures are recorded in [`CleanupReport::errors`] instead and the
+    /// directories are retried during the next cleanup.
+    pub fn continue_on_cleanup_error(&mut self, enable: bool) -> &mut Self {
+        self.continue_on_cleanup_error = enable;
+        self
+    }
+
+    /// Sets the number of threads used to remove obsolete directories.
+    ///
+    /// Removing old runs with very many small files can dominate the time to create a new
+    /// directory, especially on network filesystems.  With more than one thread the
+    /// contents of each obsolete directory are removed in parallel.  Defaults to `1`.
+    pub fn cleanup_threads(&mut self, threads: NonZeroUsize) -> &mut Self {
+        self.cleanup_threads = threads;
+        self
+    }
+
+    /// Sets the [`RetryStrategy`] used when the next number is taken by another process.
+    ///
+    /// Defaults to 16 attempts without sleeping in between.
+    pub fn retry_strategy(&mut self, retry: RetryStrategy) -> &mut Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Calls `f` with the path of a newly created [`NumberedDir`].
+    ///
+    /// This can be used for custom setup, e.g. changing ownership or registering the
+    /// directory with an artifact uploader.
+    ///
+    /// When creating the global testdir the hooks run before it is initialised: in `f`
+    /// [`is_initialized`](crate::is_initialized) returns `false` and using the global
+    /// testdir, e.g. with `testdir!()`, panics.  This applies to all hooks.
+    pub fn on_create<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&Path) + Send + Sync + 'static,
+    {
+        self.hooks.on_create = Some(Arc::new(f));
+        self
+    }
+
+    /// Calls `f` with the path of a re-used [`NumberedDir`].
+    ///
+    /// See [`NumberedDirBuilder::reusefn`].
+    pub fn on_reuse<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&Path) + Send + Sync + 'static,
+    {
+        self.hooks.on_reuse = Some(Arc::new(f));
+        self
+    }
+
+    /// Calls `f` with the path of each obsolete directory removed when creating a new
+    /// [`NumberedDir`].
+    ///
+    /// The directory no longer exists when `f` is called.
+    pub fn on_remove<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&Path) + Send + Sync + 'static,
+    {
+        self.hooks.on_remove = Some(Arc::new(f));
+        self
+    }
+
+    /// Calls `f` with the [`CleanupReport`] when a new [`NumberedDir`] is created.
+    ///
+    /// The report describes the obsolete directories removed according to the
+    /// [`Retention`] policy.  It is not called when an existing directory is re-used.
+    pub fn on_cleanup<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&CleanupReport) + Send + Sync + 'static,
+    {
+        self.cleanup_fn = Some(Arc::new(f));
+        self
+    }
+
+    /// Limits the number of subdirectories which can be created.
+    ///
+    /// Once [`NumberedDir::create_subdir`] has created `max` new subdirectories in this
+    /// process further calls will return an error.  Use `None` to remove the limit, which
+    /// is the default.
+    pub fn max_subdirs(&mut self, max: Option<usize>) -> &mut Self {
+        self.limits.max_subdirs = max;
+        self
+    }
+
+    /// Limits the depth of subdirectories which can be created.
+    ///
+    /// [`NumberedDir::create_subdir`] will return an error when given a relative path with
+    /// more than `max` components.  Use `None` to remove the limit, which is the default.
+    pub fn max_depth(&mut self, max: Option<usize>) -> &mut Self {
+        self.limits.max_depth = max;
+        self
+    }
+
+    /// Sets what [`NumberedDir::create_subdir`] does when the subdirectory already exists.
+    ///
+    /// By default the existing subdirectory is re-used, see [`Collision`].  Frameworks
+    /// generating their own unique names can use [`Collision::Error`] to detect clashes.
+    pub fn collision(&mut self, strategy: Collision) -> &mut Self {
+        self.collision = strategy;
+        self
+    }
+
+    /// Sets how subdirectory names which can not be used on Windows are handled.
+    ///
+    /// By default reserved device names like `CON`, names ending in a dot or space and names
+    /// which could differ only in case from a sibling are encoded on all platforms, see
+    /// [`PortableNames`].
+    pub fn portable_names(&mut self, mode: PortableNames) -> &mut Self {
+        self.portable_names = mode;
+        self
+    }
+
+    /// Flushes created directories and files to disk.
+    ///
+    /// When enabled the new [`NumberedDir`], its metadata files like `format-version`, the
+    /// `-current` symlink and `-current.path` pointer file, and any subdirectories created
+    /// by [`NumberedDir::create_subdir`] are fsynced, including the entries in their parent
+    /// directories.  This is useful for test suites exercising crash-consistency, where a
+    /// host crash would otherwise leave half-created run state behind.  This is slower and
+    /// disabled by default.
+    ///
+    /// Directories can only be synced on UNIX, elsewhere only files are flushed.
+    pub fn durable(&mut self, enable: bool) -> &mut Self {
+        self.durable = enable;
+        self
+    }
+
+    /// Uses `backend` for the filesystem operations managing the numbered directories.
+    ///
+    /// By default the real filesystem is used through the [`StdBackend`], see
+    /// [`DirBackend`] for which operations go through the backend.
+    ///
+    /// This requires the `backend` feature.
+    ///
+    /// [`StdBackend`]: crate::StdBackend
+    #[cfg(feature = "backend")]
+    pub fn backend(&mut self, backend: impl DirBackend + 'static) -> &mut Self {
+        self.backend = Arc::new(backend);
+        self
+    }
+
+    /// Names the directory after a run identifier instead of the next sequential number.
+    ///
+    /// With a run identifier, e.g. `ci-1234`, the directory is named `$base-ci-1234` which
+    /// makes its location reproducible, e.g. for CI systems collecting artifacts.  If the
+    /// directory already exists it is re-used, regardless of
+    /// [`NumberedDirBuilder::reusefn`].  Older run-id directories are removed based on
+    /// their age, the most recently modified ones are kept according to the
+    /// [`Retention`] policy.  Run-id directories are recognised by the `run-id` file
+    /// written into them, so numbered directories of the same base and directories of
+    /// other bases sharing the prefix are left alone.
+    ///
+    /// The identifier must not be empty, contain path separators, be a number or start
+    /// with `current`, otherwise [`NumberedDirBuilder::create`] fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::NumberedDirBuilder;
+    ///
+    /// let parent = std::env::temp_dir().join("testdir-run-id-example");
+    /// let mut builder = NumberedDirBuilder::new(String::from("testdir"));
+    /// builder.set_parent(parent.clone()).run_id("ci-1234");
+    /// let numdir = builder.create().unwrap();
+    /// assert_eq!(numdir.path(), parent.join("testdir-ci-1234"));
+    /// assert_eq!(numdir.run_id(), Some("ci-1234"));
+    /// # std::fs::remove_dir_all(&parent).ok();
+    /// ```
+    pub fn run_id(&mut self, run_id: impl Into<String>) -> &mut Self {
+        self.run_id = Some(run_id.into());
+        self
+    }
+
+    /// Uses the identifier of the current CI pipeline as [`NumberedDirBuilder::run_id`].
+    ///
+    /// This detects the run identifier of common CI systems from the environment, so all
+    /// test shards of one CI pipeline share a single directory:
+    ///
+    /// * GitHub Actions: `GITHUB_RUN_ID`, resulting in `gh-$GITHUB_RUN_ID`.
+    /// * GitLab CI: `CI_PIPELINE_ID`, resulting in `gl-$CI_PIPELINE_ID`.
+    /// * Jenkins and others: `BUILD_ID`, resulting in `build-$BUILD_ID`.
+    ///
+    /// If none of these are set, e.g. when not running on CI, the builder is not changed.
+    pub fn ci_run_id(&mut self) -> &mut Self {
+        if let Some(run_id) = detect_ci_run_id(|name| std::env::var(name).ok()) {
+            self.run_id = Some(run_id);
+        }
+        self
+    }
+
+    /// Enables recording the version control revision in new numbered directories.
+    ///
+    /// The revision is detected using [`VcsInfo::detect`] and written to a `vcs.json` file
+    /// in the new [`NumberedDir`], where it is available from [`Run::vcs`] and included
+    /// in the manifest of [`NumberedDir::export`].  If no revision could be detected
+    /// nothing is written.  Disabled by default.
+    ///
+    /// [`Run::vcs`]: crate::Run::vcs
+    pub fn record_vcs(&mut self, enable: bool) -> &mut Self {
+        self.record_vcs = enable;
+        self
+    }
+
+    /// Appends the short commit hash to the [`NumberedDirBuilder::run_id`].
+    ///
+    /// The directory is then named e.g. `$base-ci-1234-g0123abcd`, using the revision
+    /// detected by [`VcsInfo::detect`].  This only applies when a run identifier is set,
+    /// the names of sequentially numbered directories can not be changed.  If no revision
+    /// could be detected the run identifier is used as is.  Disabled by default.
+    pub fn vcs_in_name(&mut self, enable: bool) -> &mut Self {
+        self.vcs_in_name = enable;
+        self
+    }
+
+    /// Returns the run identifier to use, including the commit hash if enabled.
+    fn full_run_id(&self) -> Option<String> {
+        let run_id = self.run_id.as_ref()?;
+        let vcs = self.vcs_in_name.then(VcsInfo::detect).flatten();
+        match vcs {
+            Some(vcs) => Some(format!("{}-g{}", run_id, vcs.short_commit())),
+            None => Some(run_id.clone()),
+        }
+    }
+
+    /// Enables writing a `-current.path` pointer file.
+    ///
+    /// In addition to the `-current` symlink this writes a plain-text file named
+    /// `$base-current.path` containing the absolute path of the newly created
+    /// [`NumberedDir`].  This is useful for tools which can not follow symlinks, or on
+    /// Windows where the symlink is best-effort.  Disabled by default.
+    pub fn pointer_file(&mut self, enable: bool) -> &mut Self {
+        self.pointer_file = enable;
+        self
+    }
+
+    /// Enables or disables creating the `-current` symlink.
+    ///
+    /// Some environments do not cope well with symlinks, e.g. locked-down Windows agents
+    /// or inspection tools.  When disabled the `-current` symlink is neither created nor
+    /// updated, an existing one is left alone.  Enabled by default.
+    ///
+    /// For the global [`NumberedDir`] this can be controlled using
+    /// `init_testdir!(current_symlink = false)` or the [`CURRENT_SYMLINK_ENV`] environment
+    /// variable.
+    ///
+    /// [`CURRENT_SYMLINK_ENV`]: crate::CURRENT_SYMLINK_ENV
+    pub fn current_symlink(&mut self, enable: bool) -> &mut Self {
+        self.current_symlink = enable;
+        self
+    }
+
+    /// Enables maintaining a `-previous` symlink.
+    ///
+    /// When a new [`NumberedDir`] is created the `$base-previous` symlink is pointed to the
+    /// directory which the `-current` symlink pointed to before, making it easy to compare
+    /// the artifacts of the last two runs.  This requires the `-current` symlink, see
+    /// [`NumberedDirBuilder::current_symlink`].  Disabled by default.
+    pub fn previous_symlink(&mut self, enable: bool) -> &mut Self {
+        self.previous_symlink = enable;
+        self
+    }
+
+    /// Makes the `-current` symlink target relative to the parent directory.
+    ///
+    /// By default the `-current` symlink points to the absolute path of the most recent
+    /// [`NumberedDir`].  With a relative target the symlink remains valid when the whole
+    /// parent directory is copied or mounted elsewhere, e.g. into a container or an
+    /// artifact archive.
+    pub fn relative_symlink(&mut self, enable: bool) -> &mut Self {
+        self.relative_symlink = enable;
+        self
+    }
+
     /// Creates a new [`NumberedDir`] as configured.
-    pub fn create(&self) -> Result<NumberedDir> {
-        if !self.parent.exists() {
-            fs::create_dir_all(&self.parent).context("Failed to create root directory")?;
+    pub fn create(&self) -> Result<NumberedDir, Error> {
+        self.validate()?;
+        let mut numdir = self.create_or_reuse()?;
+        numdir.limits = self.limits;
+        numdir.collision = self.collision;
+        numdir.portable_names = self.portable_names;
+        numdir.durable = self.durable;
+        Ok(numdir)
+    }
+
+    /// Creates a [`NumberedDir`] for another `base` using this builder's configuration.
+    ///
+    /// The [`Retention`] policy set for `base` using
+    /// [`NumberedDirBuilder::base_retention`] is used, or the default policy if there is
+    /// none.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::num::NonZeroU8;
+    /// use testdir::{NumberedDirBuilder, Retention};
+    ///
+    /// let parent = std::env::temp_dir().join("testdir-create-base-example");
+    /// let mut builder = NumberedDirBuilder::new(String::from("testdir"));
+    /// builder
+    ///     .set_parent(parent.clone())
+    ///     .base_retention("benchdir", Retention::Count(NonZeroU8::new(2).unwrap()));
+    /// builder.create().unwrap();
+    /// let benches = builder.create_base("benchdir").unwrap();
+    /// assert_eq!(benches.base(), "benchdir");
+    /// # std::fs::remove_dir_all(&parent).ok();
+    /// ```
+    pub fn create_base(&self, base: &str) -> Result<NumberedDir, Error> {
+        let mut builder = self.clone();
+        builder.base = base.to_string();
+        builder.create()
+    }
+
+    /// Checks that the configuration can be used to create a [`NumberedDir`].
+    ///
+    /// Returns [`Error::InvalidBase`] if the *base* contains path separators and
+    /// [`Error::InvalidParent`] if the last component of the parent directory is missing or
+    /// not valid UTF-8.  This is also done by [`NumberedDirBuilder::create`] and
+    /// [`NumberedDirBuilder::plan`], before anything else.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::{Error, NumberedDirBuilder};
+    ///
+    /// let builder = NumberedDirBuilder::new(String::from("a/b"));
+    /// assert!(matches!(builder.validate(), Err(Error::InvalidBase { .. })));
+    /// ```
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_base(&self.base)?;
+        if self
+            .parent
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_none()
+        {
+            return Err(Error::InvalidParent {
+                parent: self.parent.clone(),
+            });
         }
-        if !self.parent.is_dir() {
-            return Err(Error::msg("Path for root is not a directory"));
+        Ok(())
+    }
+
+    /// Computes what [`NumberedDirBuilder::create`] would do, without doing it.
+    ///
+    /// The returned [`CreatePlan`] contains the directory which would be re-used or
+    /// created, and the obsolete directories which would be removed.  Nothing is written to
+    /// the filesystem, not even the parent directory is created.  Concurrent processes
+    /// creating directories can make the actual result differ from the plan.
+    ///
+    /// This is useful for dry-runs of cleanup jobs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::NumberedDirBuilder;
+    ///
+    /// let parent = std::env::temp_dir().join("testdir-plan-example");
+    /// # std::fs::remove_dir_all(&parent).ok();
+    /// let mut builder = NumberedDirBuilder::new(String::from("base"));
+    /// builder.set_parent(parent.clone());
+    /// let plan = builder.plan().unwrap();
+    /// assert_eq!(plan.path, parent.join("base-0"));
+    /// assert!(plan.remove.is_empty());
+    /// assert!(!parent.exists());
+    /// ```
+    pub fn plan(&self) -> Result<CreatePlan, Error> {
+        self.validate()?;
+        let parent = if self.canonicalize && self.parent.exists() {
+            fs::canonicalize(&self.parent).with_context(|| {
+                format!("Failed to canonicalize root: {}", self.parent.display())
+            })?
+        } else {
+            self.parent.clone()
+        };
+        let parent = self.simplify(&parent);
+        let run_id = self.full_run_id();
+        let first = match run_id {
+            Some(ref run_id) => format!("{}-{}", self.base, run_id),
+            None => format!("{}-0", self.base),
+        };
+        if !self.backend.is_dir(&parent) {
+            return Ok(CreatePlan {
+                path: parent.join(first),
+                reuse: false,
+                remove: Vec::new(),
+            });
+        }
+        if let Some(ref run_id) = run_id {
+            let path = parent.join(first);
+            let reuse = self.backend.is_dir(&path);
+            let remove = if reuse {
+                Vec::new()
+            } else {
+                self.retention.get(&self.base).obsolete_by_age(
+                    run_id_dirs(&*self.backend, &parent, &self.base, run_id)?,
+                    true,
+                    SystemTime::now(),
+                )
+            };
+            return Ok(CreatePlan {
+                path,
+                reuse,
+                remove,
+            });
         }
         if let Some(ref reuse_fn) = self.reuse_fn {
-            for numdir in NumberedDir::iterate(&self.parent, &self.base)? {
-                if reuse_fn(numdir.path()) {
+            for numdir in self.iterate(&parent)? {
+                if reuse_fn(numdir.path()) && numdir.is_compatible() {
+                    return Ok(CreatePlan {
+                        path: numdir.path().to_path_buf(),
+                        reuse: true,
+                        remove: Vec::new(),
+                    });
+                }
+            }
+        }
+        let (next, remove) = match current_entry_count(&self.backend, &parent, &self.base) {
+            Some(current) => {
+                let obsolete = self.retention.get(&self.base).obsolete_numdirs(
+                    self.iterate(&parent)?,
+                    current,
+                    true,
+                    SystemTime::now(),
+                );
+                let remove = obsolete.iter().map(|d| d.path().to_path_buf()).collect();
+                (current.wrapping_add(1), remove)
+            }
+            None => (0, Vec::new()),
+        };
+        Ok(CreatePlan {
+            path: parent.join(format!("{}-{}", self.base, next)),
+            reuse: false,
+            remove,
+        })
+    }
+
+    /// Returns the parent directory in which the [`NumberedDir`] is created.
+    pub(crate) fn parent(&self) -> &Path {
+        &self.parent
+    }
+
+    /// Returns the retention policies for all bases.
+    pub(crate) fn retention_map(&self) -> &RetentionMap {
+        &self.retention
+    }
+
+    fn create_or_reuse(&self) -> Result<NumberedDir> {
+        let parent = self.prepare_parent()?;
+        let run_id = self.full_run_id();
+        if run_id.is_none() {
+            if let Some(ref reuse_fn) = self.reuse_fn {
+                for numdir in self.iterate(&parent)? {
+                    if reuse_fn(numdir.path()) && numdir.is_compatible() {
+                        if let Some(ref on_reuse) = self.hooks.on_reuse {
+                            on_reuse(numdir.path());
+                        }
+                        return Ok(numdir);
+                    }
+                }
+            }
+        }
+        let mut options = CreateOptions::new(self.retention.get(&self.base));
+        options.pointer_file = self.pointer_file;
+        options.current_symlink = self.current_symlink;
+        options.previous_symlink = self.previous_symlink;
+        options.relative_symlink = self.relative_symlink;
+        options.continue_on_cleanup_error = self.continue_on_cleanup_error;
+        options.cleanup_threads = self.cleanup_threads.get();
+        options.retry = self.retry;
+        options.durable = self.durable;
+        options.backend = self.backend.clone();
+        let numdir = match run_id {
+            Some(ref run_id) => {
+                let (numdir, reused) =
+                    NumberedDir::create_with_run_id(&parent, &self.base, run_id, &options)?;
+                if reused {
+                    if let Some(ref on_reuse) = self.hooks.on_reuse {
+                        on_reuse(numdir.path());
+                    }
                     return Ok(numdir);
                 }
+                numdir
+            }
+            None => NumberedDir::create_with(&parent, &self.base, &options)?,
+        };
+        if self.record_vcs {
+            if let Some(vcs) = VcsInfo::detect() {
+                vcs.write(numdir.path())?;
+            }
+        }
+        if let Some(ref on_remove) = self.hooks.on_remove {
+            for path in numdir.removed_dirs() {
+                on_remove(path);
+            }
+        }
+        if let Some(ref on_create) = self.hooks.on_create {
+            on_create(numdir.path());
+        }
+        if let Some(ref cleanup_fn) = self.cleanup_fn {
+            cleanup_fn(numdir.cleanup_report());
+        }
+        Ok(numdir)
+    }
+
+    /// Returns the existing numbered dirs of the base in `parent`.
+    fn iterate(&self, parent: &Path) -> Result<NumberedDirIter> {
+        NumberedDirIter::try_new_in(self.backend.clone(), parent, &self.base)
+    }
+
+    /// Creates and validates the parent directory, returning the path to use.
+    fn prepare_parent(&self) -> Result<PathBuf> {
+        if self.private_parent {
+            if let Some(root) = self.parent.parent() {
+                check_shared_root(root)?;
+            }
+        }
+        if !self.backend.is_dir(&self.parent) {
+            match self.backend.create_dir_all(&self.parent) {
+                Ok(()) => (),
+                // Reported as not a directory below.
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => (),
+                Err(err) => {
+                    return Err(err).with_context(|| {
+                        format!("Failed to create root directory: {}", self.parent.display())
+                    })
+                }
             }
         }
-        NumberedDir::create(&self.parent, &self.base, self.count)
+        if !self.backend.is_dir(&self.parent) {
+            return Err(anyhow!(
+                "Path for root is not a directory: {}",
+                self.parent.display()
+            ));
+        }
+        if self.private_parent {
+            restrict_permissions(&self.parent)?;
+        }
+        let parent = if self.canonicalize {
+            fs::canonicalize(&self.parent).with_context(|| {
+                format!("Failed to canonicalize root: {}", self.parent.display())
+            })?
+        } else {
+            self.parent.clone()
+        };
+        Ok(self.simplify(&parent))
+    }
+
+    /// Applies [`NumberedDirBuilder::strip_unc_prefix`] and
+    /// [`NumberedDirBuilder::normalize_private`] to the parent directory `parent`.
+    fn simplify(&self, parent: &Path) -> PathBuf {
+        simplify_path(parent, self.strip_unc_prefix, self.normalize_private)
     }
 }
 
+/// The directory in the cargo target directory used by [`NumberedDirBuilder::target_tmp`]:
+/// `tmp`.
+const TARGET_TMP_DIR_NAME: &str = "tmp";
+
+/// A hook called with the path of a numbered dir.
+type PathHook = Arc<dyn Fn(&Path) + Send + Sync>;
+
+/// The lifecycle hooks of a [`NumberedDirBuilder`].
+#[derive(Clone, Default)]
+struct Hooks {
+    on_create: Option<PathHook>,
+    on_reuse: Option<PathHook>,
+    on_remove: Option<PathHook>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hook = |hook: &Option<PathHook>| hook.as_ref().map(|_| "<Fn(&Path)>");
+        f.debug_struct("Hooks")
+            .field("on_create", &hook(&self.on_create))
+            .field("on_reuse", &hook(&self.on_reuse))
+            .field("on_remove", &hook(&self.on_remove))
+            .finish()
+    }
+}
+
+/// What [`NumberedDirBuilder::create`] would do, returned by [`NumberedDirBuilder::plan`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CreatePlan {
+    /// The path of the [`NumberedDir`] which would be created or re-used.
+    pub path: PathBuf,
+    /// Whether an existing directory would be re-used.
+    pub reuse: bool,
+    /// The obsolete directories which would be removed.
+    pub remove: Vec<PathBuf>,
+}
+
+/// The environment variables identifying a CI run, with the prefix used for the run id.
+const CI_RUN_ID_VARS: [(&str, &str); 3] = [
+    ("GITHUB_RUN_ID", "gh"),
+    ("CI_PIPELINE_ID", "gl"),
+    ("BUILD_ID", "build"),
+];
+
+/// Returns the run id of the current CI run, looking up variables using `var`.
+fn detect_ci_run_id(var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    CI_RUN_ID_VARS.iter().find_map(|(name, prefix)| {
+        var(name)
+            .filter(|id| !id.is_empty() && !id.contains(['/', '\\']))
+            .map(|id| format!("{prefix}-{id}"))
+    })
+}
+
+/// Checks that the shared root of [`NumberedDirBuilder::shared_root`] can be used.
+///
+/// The root must already exist as a real directory.  Creating it would give it the
+/// ownership and permissions of the first user, so other users could not create their own
+/// directories in it.  A symlink is refused as it could have been planted by another user.
+fn check_shared_root(root: &Path) -> Result<()> {
+    let metadata = match fs::symlink_metadata(root) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(anyhow!("Shared root does not exist: {}", root.display()))
+        }
+        Err(err) => {
+            return Err(err).with_context(|| {
+                format!("Failed to read shared root metadata: {}", root.display())
+            })
+        }
+    };
+    if metadata.file_type().is_symlink() {
+        return Err(anyhow!("Shared root is a symlink: {}", root.display()));
+    }
+    if !metadata.is_dir() {
+        return Err(anyhow!(
+            "Shared root is not a directory: {}",
+            root.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Restricts the permissions of a directory to the current user.
+///
+/// The directory must be owned by the current user, otherwise another user could have
+/// created it in a shared location beforehand.
+#[cfg(unix)]
+pub(crate) fn restrict_permissions(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let metadata = fs::metadata(dir).context("Failed to read root directory metadata")?;
+    // SAFETY: geteuid() has no preconditions and can not fail.
+    let euid = unsafe { libc::geteuid() };
+    if metadata.uid() != euid {
+        return Err(anyhow!(
+            "Directory is owned by another user (uid {}): {}",
+            metadata.uid(),
+            dir.display()
+        ));
+    }
+    if metadata.permissions().mode() & 0o077 != 0 {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to restrict permissions of {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Restricts the permissions of a directory to the current user.
+///
+/// On non-UNIX platforms the default permissions are relied upon.
+#[cfg(not(unix))]
+pub(crate) fn restrict_permissions(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -213,6 +1151,51 @@ mod tests {
         assert!(root.starts_with("testdir-of-"));
     }
 
+    #[test]
+    fn test_builder_validate() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut builder = NumberedDirBuilder::new(String::from("a\\b"));
+        builder.set_parent(parent.path().join("root"));
+        assert!(matches!(builder.create(), Err(Error::InvalidBase { .. })));
+        assert!(!parent.path().join("root").exists());
+
+        builder
+            .base(String::from("base"))
+            .set_parent(PathBuf::from("/"));
+        assert!(matches!(builder.plan(), Err(Error::InvalidParent { .. })));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let name = std::ffi::OsStr::from_bytes(b"root-\xff");
+            builder.set_parent(parent.path().join(name));
+            assert!(matches!(
+                builder.validate(),
+                Err(Error::InvalidParent { .. })
+            ));
+        }
+        builder.set_parent(parent.path().join("root"));
+        assert!(builder.validate().is_ok());
+    }
+
+    #[test]
+    fn test_builder_durable() {
+        let parent = tempfile::tempdir().unwrap();
+        let dir = NumberedDirBuilder::new(String::from("base"))
+            .set_parent(parent.path().to_path_buf())
+            .pointer_file(true)
+            .durable(true)
+            .create()
+            .unwrap();
+        assert!(dir.durable());
+        assert!(dir
+            .path()
+            .join(crate::numbered_dir::FORMAT_VERSION_FILE_NAME)
+            .is_file());
+        assert!(parent.path().join("base-current.path").is_file());
+        assert!(dir.create_subdir("mod/test").unwrap().is_dir());
+    }
+
     #[test]
     fn test_builder_root() {
         let parent = tempfile::tempdir().unwrap();
@@ -245,6 +1228,26 @@ mod tests {
         assert!(root.starts_with("myroot-"));
     }
 
+    #[test]
+    fn test_builder_root_strategy() {
+        #[derive(Debug)]
+        struct Fixed(PathBuf);
+
+        impl RootStrategy for Fixed {
+            fn tmpdir(&self) -> Option<PathBuf> {
+                Some(self.0.clone())
+            }
+        }
+
+        let parent = tempfile::tempdir().unwrap();
+        let dir = NumberedDirBuilder::new(String::from("base"))
+            .root("myroot")
+            .root_strategy(&Fixed(parent.path().to_path_buf()))
+            .create()
+            .unwrap();
+        assert_eq!(dir.path(), parent.path().join("myroot").join("base-0"));
+    }
+
     #[test]
     fn test_builder_set_parent() {
         let temp = tempfile::tempdir().unwrap();
@@ -257,6 +1260,176 @@ mod tests {
         assert_eq!(dir.path(), parent.join("base-0"));
     }
 
+    #[test]
+    fn test_builder_shared_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = NumberedDirBuilder::new(String::from("base"))
+            .shared_root(temp.path())
+            .create()
+            .unwrap();
+        let user_dir = temp.path().join(whoami::username());
+        assert_eq!(dir.path(), user_dir.join("base-0"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&user_dir).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+    }
+
+    #[test]
+    fn test_builder_shared_root_missing() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().join("missing");
+        let res = NumberedDirBuilder::new(String::from("base"))
+            .shared_root(&root)
+            .create();
+        assert!(res.is_err());
+        assert!(!root.exists());
+
+        fs::write(&root, "not a directory").unwrap();
+        let res = NumberedDirBuilder::new(String::from("base"))
+            .shared_root(&root)
+            .create();
+        assert!(res.is_err());
+
+        #[cfg(unix)]
+        {
+            let link = temp.path().join("link");
+            std::os::unix::fs::symlink(temp.path(), &link).unwrap();
+            let res = NumberedDirBuilder::new(String::from("base"))
+                .shared_root(&link)
+                .create();
+            assert!(res.is_err());
+            assert!(!temp.path().join(whoami::username()).exists());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_builder_shared_root_other_owner() {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        if unsafe { libc::geteuid() } != 0 {
+            // Creating a directory owned by another user requires root.
+            return;
+        }
+        let temp = tempfile::tempdir().unwrap();
+        let user_dir = temp.path().join(whoami::username());
+        fs::create_dir(&user_dir).unwrap();
+        let path = CString::new(user_dir.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::chown(path.as_ptr(), 65534, 65534) }, 0);
+
+        let res = NumberedDirBuilder::new(String::from("base"))
+            .shared_root(temp.path())
+            .create();
+        assert!(res.is_err());
+        assert!(!user_dir.join("base-0").exists());
+    }
+
+    #[test]
+    fn test_builder_canonicalize() {
+        let temp = tempfile::tempdir().unwrap();
+        let parent = temp.path().join("one").join("..").join("two");
+        let dir = NumberedDirBuilder::new(String::from("base"))
+            .set_parent(parent)
+            .canonicalize(true)
+            .create()
+            .unwrap();
+        let expected = temp.path().canonicalize().unwrap().join("two/base-0");
+        assert_eq!(dir.path(), expected);
+    }
+
+    #[test]
+    fn test_builder_per_profile() {
+        let temp = tempfile::tempdir().unwrap();
+        let numdir = NumberedDirBuilder::new(String::from("base"))
+            .set_parent(temp.path().to_path_buf())
+            .per_profile()
+            .create()
+            .unwrap();
+        let base = format!("base-{}", crate::target_dir::build_profile());
+        assert_eq!(numdir.base(), base);
+        assert_eq!(numdir.path(), temp.path().join(format!("{base}-0")));
+    }
+
+    #[test]
+    fn test_builder_per_target() {
+        let mut builder = NumberedDirBuilder::new(String::from("base"));
+        builder.per_target().per_profile();
+        let base = match crate::target_dir::target_triple() {
+            Some(triple) => format!("base-{triple}-{}", crate::target_dir::build_profile()),
+            None => format!("base-{}", crate::target_dir::build_profile()),
+        };
+        assert_eq!(builder.base, base);
+    }
+
+    #[test]
+    fn test_builder_target_tmp() {
+        let target = tempfile::tempdir().unwrap();
+        let mut builder = NumberedDirBuilder::new(String::from("base"));
+        builder
+            .target_tmp(target.path())
+            .count(NonZeroU8::new(1).unwrap());
+        let first = builder.create().unwrap();
+        let second = builder.create().unwrap();
+        let tmp = target.path().join("tmp");
+        assert_eq!(second.path(), tmp.join("base-1"));
+        assert!(!first.path().exists());
+        assert_eq!(
+            fs::read_link(tmp.join("base-current")).unwrap(),
+            second.path()
+        );
+        assert_eq!(fs::read_dir(target.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_builder_simplify_parent() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = NumberedDirBuilder::new(String::from("base"))
+            .set_parent(temp.path().to_path_buf())
+            .canonicalize(true)
+            .strip_unc_prefix(true)
+            .normalize_private(true)
+            .create()
+            .unwrap();
+        let canonical = temp.path().canonicalize().unwrap();
+        let expected = simplify_path(&canonical, true, true).join("base-0");
+        assert_eq!(dir.path(), expected);
+        assert!(!dir.path().to_string_lossy().starts_with(r"\\?\"));
+    }
+
+    #[test]
+    fn test_builder_parent_not_dir() {
+        let temp = tempfile::tempdir().unwrap();
+        let parent = temp.path().join("file");
+        fs::write(&parent, "not a dir").unwrap();
+        let err = NumberedDirBuilder::new(String::from("base"))
+            .set_parent(parent)
+            .canonicalize(true)
+            .create()
+            .unwrap_err();
+        assert!(err.to_string().contains("not a directory"));
+    }
+
+    #[test]
+    fn test_builder_reuse_incompatible() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut builder = NumberedDirBuilder::new(String::from("base"));
+        builder.set_parent(temp.path().to_path_buf());
+        builder.reusefn(|_| true);
+
+        let dir0 = builder.create().unwrap();
+        let dir1 = builder.create().unwrap();
+        assert_eq!(dir0, dir1);
+
+        fs::remove_file(dir0.path().join("format-version")).unwrap();
+        let dir2 = builder.create().unwrap();
+        assert_ne!(dir0, dir2);
+    }
+
     #[test]
     fn test_builder_count() {
         let temp = tempfile::tempdir().unwrap();
@@ -272,4 +1445,193 @@ mod tests {
         assert!(!dir0.path().is_dir());
         assert!(dir1.path().is_dir());
     }
+
+    #[test]
+    fn test_builder_pointer_file() {
+        let parent = tempfile::tempdir().unwrap();
+        let dir = NumberedDirBuilder::new(String::from("base"))
+            .set_parent(parent.path().to_path_buf())
+            .pointer_file(true)
+            .create()
+            .unwrap();
+        let pointer = fs::read_to_string(parent.path().join("base-current.path")).unwrap();
+        assert!(Path::new(&pointer).ends_with(dir.path().file_name().unwrap()));
+    }
+
+    #[test]
+    fn test_builder_limits() {
+        let parent = tempfile::tempdir().unwrap();
+        let dir = NumberedDirBuilder::new(String::from("base"))
+            .tmpdir_provider(|| parent.path().to_path_buf())
+            .max_subdirs(Some(1))
+            .max_depth(Some(1))
+            .create()
+            .unwrap();
+        assert_eq!(dir.limits().max_subdirs, Some(1));
+        assert!(dir.create_subdir("one/two").is_err());
+        assert!(dir.create_subdir("one").is_ok());
+        assert!(dir.create_subdir("two").is_err());
+    }
+
+    #[test]
+    fn test_builder_on_cleanup() {
+        use std::sync::Mutex;
+
+        let parent = tempfile::tempdir().unwrap();
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let mut builder = NumberedDirBuilder::new(String::from("base"));
+        builder
+            .set_parent(parent.path().to_path_buf())
+            .count(NonZeroU8::new(1).unwrap());
+        let sink = reports.clone();
+        builder.on_cleanup(move |report| sink.lock().unwrap().push(report.clone()));
+        let dir0 = builder.create().unwrap();
+        fs::write(dir0.path().join("data"), "1234").unwrap();
+        let size = crate::dir_size(dir0.path()).unwrap();
+        builder.create().unwrap();
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert!(reports[0].is_empty());
+        assert_eq!(reports[1].removed, vec![dir0.path().to_path_buf()]);
+        assert_eq!(reports[1].bytes_freed, size);
+    }
+
+    #[test]
+    fn test_builder_create_base() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut builder = NumberedDirBuilder::new(String::from("testdir"));
+        builder
+            .set_parent(parent.path().to_path_buf())
+            .base_retention("benchdir", Retention::Count(NonZeroU8::new(1).unwrap()));
+        for _ in 0..2 {
+            builder.create().unwrap();
+            builder.create_base("benchdir").unwrap();
+        }
+        assert!(parent.path().join("testdir-0").is_dir());
+        assert!(parent.path().join("testdir-1").is_dir());
+        assert!(!parent.path().join("benchdir-0").exists());
+        assert!(parent.path().join("benchdir-1").is_dir());
+    }
+
+    #[test]
+    fn test_builder_plan() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut builder = NumberedDirBuilder::new(String::from("base"));
+        builder
+            .set_parent(parent.path().to_path_buf())
+            .count(NonZeroU8::new(2).unwrap());
+        let dir0 = builder.create().unwrap();
+        builder.create().unwrap();
+
+        let plan = builder.plan().unwrap();
+        assert_eq!(plan.path, parent.path().join("base-2"));
+        assert!(!plan.reuse);
+        assert_eq!(plan.remove, vec![dir0.path().to_path_buf()]);
+        assert!(dir0.path().exists());
+        assert_eq!(builder.create().unwrap().path(), plan.path);
+
+        builder.reusefn(|_| true);
+        let plan = builder.plan().unwrap();
+        assert!(plan.reuse);
+        assert!(plan.remove.is_empty());
+    }
+
+    #[test]
+    fn test_builder_run_id() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut builder = NumberedDirBuilder::new(String::from("base"));
+        builder
+            .set_parent(parent.path().to_path_buf())
+            .run_id("ci-1");
+
+        let plan = builder.plan().unwrap();
+        assert_eq!(plan.path, parent.path().join("base-ci-1"));
+        assert!(!plan.reuse);
+
+        let dir = builder.create().unwrap();
+        assert_eq!(dir.path(), plan.path);
+        assert_eq!(dir.number(), 0);
+        assert!(builder.plan().unwrap().reuse);
+        assert_eq!(builder.create().unwrap(), dir);
+
+        builder.run_id("1");
+        assert!(builder.create().is_err());
+    }
+
+    #[test]
+    fn test_builder_record_vcs() {
+        let Some(vcs) = VcsInfo::detect() else {
+            return;
+        };
+        let parent = tempfile::tempdir().unwrap();
+        let mut builder = NumberedDirBuilder::new(String::from("base"));
+        builder
+            .set_parent(parent.path().to_path_buf())
+            .record_vcs(true)
+            .vcs_in_name(true)
+            .run_id("ci-1");
+        let dir = builder.create().unwrap();
+        let expected = format!("base-ci-1-g{}", vcs.short_commit());
+        assert_eq!(dir.path(), parent.path().join(expected));
+        assert_eq!(crate::Run::load(dir.path()).unwrap().vcs(), Some(&vcs));
+    }
+
+    #[test]
+    fn test_detect_ci_run_id() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(detect_ci_run_id(env(&[])), None);
+        assert_eq!(
+            detect_ci_run_id(env(&[("BUILD_ID", "7"), ("GITHUB_RUN_ID", "42")])),
+            Some(String::from("gh-42"))
+        );
+        assert_eq!(
+            detect_ci_run_id(env(&[("CI_PIPELINE_ID", "9")])),
+            Some(String::from("gl-9"))
+        );
+        assert_eq!(
+            detect_ci_run_id(env(&[("GITHUB_RUN_ID", ""), ("BUILD_ID", "7")])),
+            Some(String::from("build-7"))
+        );
+    }
+
+    #[test]
+    fn test_builder_hooks() {
+        use std::sync::Mutex;
+
+        let parent = tempfile::tempdir().unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut builder = NumberedDirBuilder::new(String::from("base"));
+        builder
+            .set_parent(parent.path().to_path_buf())
+            .count(NonZeroU8::new(1).unwrap());
+        let sink = events.clone();
+        builder.on_create(move |p| sink.lock().unwrap().push(("create", p.to_path_buf())));
+        let sink = events.clone();
+        builder.on_reuse(move |p| sink.lock().unwrap().push(("reuse", p.to_path_buf())));
+        let sink = events.clone();
+        builder.on_remove(move |p| sink.lock().unwrap().push(("remove", p.to_path_buf())));
+
+        builder.create().unwrap();
+        builder.create().unwrap();
+        builder.reusefn(|_| true);
+        builder.create().unwrap();
+
+        let base = |n| parent.path().join(format!("base-{n}"));
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ("create", base(0)),
+                ("remove", base(0)),
+                ("create", base(1)),
+                ("reuse", base(1)),
+            ]
+        );
+    }
 }
@@ -0,0 +1,36 @@
+//! Capability-based handles of test directories, see [`open_cap_dir`].
+
+use std::path::Path;
+
+use anyhow::Context;
+use cap_std::ambient_authority;
+use cap_std::fs::Dir;
+
+use crate::Error;
+
+/// Opens the directory at `path` as a capability-based [`Dir`] handle.
+///
+/// All operations using the returned [`Dir`] are sandboxed to the directory: paths
+/// escaping it, like `../file`, absolute paths or symlinks pointing outside, are rejected.
+/// This gives test code a guarantee that it can not accidentally write outside of its test
+/// directory.  Use [`testdir_cap!`] to create and open the test directory at once.
+///
+/// This requires the `cap-std` feature.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{open_cap_dir, testdir};
+///
+/// let dir = open_cap_dir(testdir!()).unwrap();
+/// dir.write("file", "content").unwrap();
+/// assert!(dir.write("../escape", "content").is_err());
+/// ```
+///
+/// [`testdir_cap!`]: crate::testdir_cap
+pub fn open_cap_dir(path: impl AsRef<Path>) -> Result<Dir, Error> {
+    let path = path.as_ref();
+    let dir = Dir::open_ambient_dir(path, ambient_authority())
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok(dir)
+}
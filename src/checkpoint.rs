@@ -0,0 +1,104 @@
+//! Checkpoints of test directories, see [`TestDir::checkpoint`].
+//!
+//! [`TestDir::checkpoint`]: crate::TestDir::checkpoint
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::cleanup::remove_entry;
+use crate::export::copy_entry;
+
+/// The directory inside a test directory in which checkpoints are stored.
+pub(crate) const CHECKPOINT_DIR_NAME: &str = ".testdir-checkpoints";
+
+/// Returns the path of the checkpoint `name` of the directory `dir`.
+fn checkpoint_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        bail!("Invalid checkpoint name: {name:?}");
+    }
+    Ok(dir.join(CHECKPOINT_DIR_NAME).join(name))
+}
+
+/// Returns the entries of `dir`, except for the checkpoints.
+fn entries(dir: &Path) -> Result<Vec<PathBuf>> {
+    let readdir = dir
+        .read_dir()
+        .with_context(|| format!("Failed read_dir() on {}", dir.display()))?;
+    let mut entries = Vec::new();
+    for dirent in readdir {
+        let dirent = dirent.with_context(|| format!("Failed read_dir() on {}", dir.display()))?;
+        if dirent.file_name() != CHECKPOINT_DIR_NAME {
+            entries.push(dirent.path());
+        }
+    }
+    Ok(entries)
+}
+
+/// Copies the contents of `dir` into the checkpoint `name`, replacing an existing one.
+pub(crate) fn checkpoint(dir: &Path, name: &str) -> Result<()> {
+    let snapshot = checkpoint_path(dir, name)?;
+    remove_entry(&snapshot).with_context(|| format!("Failed to remove {}", snapshot.display()))?;
+    fs::create_dir_all(&snapshot)
+        .with_context(|| format!("Failed to create {}", snapshot.display()))?;
+    for entry in entries(dir)? {
+        if let Some(file_name) = entry.file_name() {
+            copy_entry(&entry, &snapshot.join(file_name), false)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the contents of `dir` with the checkpoint `name`.
+pub(crate) fn restore(dir: &Path, name: &str) -> Result<()> {
+    let snapshot = checkpoint_path(dir, name)?;
+    if !snapshot.is_dir() {
+        bail!("No checkpoint named {name:?} in {}", dir.display());
+    }
+    for entry in entries(dir)? {
+        remove_entry(&entry).with_context(|| format!("Failed to remove {}", entry.display()))?;
+    }
+    for entry in entries(&snapshot)? {
+        if let Some(file_name) = entry.file_name() {
+            copy_entry(&entry, &dir.join(file_name), false)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_restore() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/state.txt"), "pristine").unwrap();
+
+        checkpoint(dir, "setup").unwrap();
+        fs::write(dir.join("sub/state.txt"), "modified").unwrap();
+        fs::write(dir.join("new.txt"), "new").unwrap();
+        checkpoint(dir, "later").unwrap();
+
+        restore(dir, "setup").unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("sub/state.txt")).unwrap(),
+            "pristine"
+        );
+        assert!(!dir.join("new.txt").exists());
+
+        restore(dir, "later").unwrap();
+        assert!(dir.join("new.txt").is_file());
+        assert!(!dir
+            .join(CHECKPOINT_DIR_NAME)
+            .join("later")
+            .join(CHECKPOINT_DIR_NAME)
+            .exists());
+
+        assert!(restore(dir, "missing").is_err());
+        assert!(checkpoint(dir, "../escape").is_err());
+    }
+}
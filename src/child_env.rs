@@ -0,0 +1,114 @@
+//! Propagating the managed directories to child processes, see [`from_env`].
+
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// The environment variable with the path of the global [`NumberedDir`]: `TESTDIR_RUN_DIR`.
+///
+/// Like [`TEST_DIR_ENV`] this is set on the commands spawned using
+/// [`TestDir::export_env`], [`TestDir::capture_output`] or the `cmd_ext` traits, once the
+/// global [`NumberedDir`] is created.  The environment of the test process itself is not
+/// modified, since that is not safe while other test threads run.
+///
+/// [`NumberedDir`]: crate::NumberedDir
+/// [`TestDir::export_env`]: crate::TestDir::export_env
+/// [`TestDir::capture_output`]: crate::TestDir::capture_output
+pub const RUN_DIR_ENV: &str = "TESTDIR_RUN_DIR";
+
+/// The environment variable with the path of the test directory: `TESTDIR_TEST_DIR`.
+///
+/// Tests run in parallel threads of the same process, so this can not be set for the whole
+/// process.  Instead it is set on the commands spawned using [`TestDir::export_env`],
+/// [`TestDir::capture_output`] or the `cmd_ext` traits.
+///
+/// [`TestDir::export_env`]: crate::TestDir::export_env
+/// [`TestDir::capture_output`]: crate::TestDir::capture_output
+pub const TEST_DIR_ENV: &str = "TESTDIR_TEST_DIR";
+
+/// The directories exported to a child process, see [`from_env`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvDirs {
+    /// The global [`NumberedDir`](crate::NumberedDir) of the test run, from [`RUN_DIR_ENV`].
+    pub run_dir: PathBuf,
+    /// The directory of the test which spawned the process, from [`TEST_DIR_ENV`].
+    pub test_dir: Option<PathBuf>,
+}
+
+/// Returns the directories exported to this process by the test which spawned it.
+///
+/// Helper binaries spawned by tests, or the code under test itself, can use this to write
+/// into the directories managed by testdir.  Returns `None` if this process was not spawned
+/// by a test using testdir, i.e. [`RUN_DIR_ENV`] is not set.
+///
+/// # Examples
+///
+/// A test exports the directories to the command it spawns:
+///
+/// ```
+/// use std::process::Command;
+/// use testdir::{testdir, TestDir, RUN_DIR_ENV, TEST_DIR_ENV};
+///
+/// let dir = TestDir::new(testdir!());
+/// let mut cmd = Command::new("cargo");
+/// dir.export_env(&mut cmd);
+/// assert!(cmd.get_envs().any(|(key, _)| key == RUN_DIR_ENV));
+/// assert!(cmd.get_envs().any(|(key, _)| key == TEST_DIR_ENV));
+/// ```
+///
+/// Inside the spawned process:
+///
+/// ```no_run
+/// let dirs = testdir::from_env().expect("not spawned by a test");
+/// let test_dir = dirs.test_dir.unwrap_or(dirs.run_dir);
+/// std::fs::write(test_dir.join("helper.log"), "started").unwrap();
+/// ```
+pub fn from_env() -> Option<EnvDirs> {
+    from_vars(|name| env::var_os(name).map(PathBuf::from))
+}
+
+/// Reads the directories from the environment variables, looked up using `var`.
+fn from_vars(var: impl Fn(&str) -> Option<PathBuf>) -> Option<EnvDirs> {
+    let nonempty = |name| var(name).filter(|path| !path.as_os_str().is_empty());
+    Some(EnvDirs {
+        run_dir: nonempty(RUN_DIR_ENV)?,
+        test_dir: nonempty(TEST_DIR_ENV),
+    })
+}
+
+/// Returns the environment variables to set on a child process spawned for `test_dir`.
+pub(crate) fn child_vars(test_dir: &Path) -> Vec<(&'static str, OsString)> {
+    let mut vars = Vec::with_capacity(3);
+    if let Some(run_dir) = crate::current_path() {
+        vars.push((RUN_DIR_ENV, run_dir.into_os_string()));
+    }
+    vars.push((TEST_DIR_ENV, test_dir.as_os_str().to_os_string()));
+    vars.extend(crate::private::cargo_pid_env());
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_vars() {
+        let dirs = |vars: &'static [(&'static str, &'static str)]| {
+            from_vars(|name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, val)| PathBuf::from(val))
+            })
+        };
+        assert_eq!(dirs(&[]), None);
+        assert_eq!(dirs(&[(RUN_DIR_ENV, "")]), None);
+        assert_eq!(dirs(&[(TEST_DIR_ENV, "/run/test")]), None);
+        assert_eq!(
+            dirs(&[(RUN_DIR_ENV, "/run"), (TEST_DIR_ENV, "/run/test")]),
+            Some(EnvDirs {
+                run_dir: PathBuf::from("/run"),
+                test_dir: Some(PathBuf::from("/run/test")),
+            })
+        );
+    }
+}
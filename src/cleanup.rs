@@ -0,0 +1,460 @@
+//! The [`CleanupReport`] and supporting code.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use anyhow::Result;
+
+use crate::numbered_dir::remove_numbered_dir;
+use crate::{dir_size, NumberedDir};
+
+/// What was removed when cleaning up obsolete numbered directories.
+///
+/// This is returned by [`gc_root`](crate::gc_root) and available from
+/// [`NumberedDir::cleanup_report`] for the cleanup done while creating a directory.
+/// [`NumberedDirBuilder::on_cleanup`](crate::NumberedDirBuilder::on_cleanup) can be used
+/// to be notified of it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CleanupReport {
+    /// The removed directories.
+    pub removed: Vec<PathBuf>,
+    /// The total size of the files in the removed directories, in bytes.
+    pub bytes_freed: u64,
+    /// The directories which could not be removed.
+    ///
+    /// This is only populated when cleanup errors do not abort creating a new directory,
+    /// see [`NumberedDirBuilder::continue_on_cleanup_error`].
+    ///
+    /// [`NumberedDirBuilder::continue_on_cleanup_error`]:
+    ///     crate::NumberedDirBuilder::continue_on_cleanup_error
+    pub errors: Vec<CleanupError>,
+}
+
+/// A directory which could not be removed, part of a [`CleanupReport`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CleanupError {
+    /// The directory which could not be removed.
+    pub path: PathBuf,
+    /// A description of the error.
+    pub message: String,
+}
+
+impl CleanupReport {
+    /// Returns `true` if nothing was removed and no errors occurred.
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.errors.is_empty()
+    }
+
+    /// Removes a numbered directory using `threads` threads, recording it in the report.
+    pub(crate) fn remove(&mut self, numdir: &NumberedDir, threads: usize) -> Result<()> {
+        // Only the symlink of an adopted directory is removed, not the files of its target.
+        let bytes = if is_symlink(numdir.path()) {
+            0
+        } else {
+            // Best effort, the directory could be concurrently removed by another process.
+            dir_size(numdir.path()).unwrap_or(0)
+        };
+        remove_numbered_dir(numdir, threads)?;
+        self.removed.push(numdir.path().to_path_buf());
+        self.bytes_freed += bytes;
+        Ok(())
+    }
+
+    /// Removes a numbered directory, recording any error in the report instead of failing.
+    pub(crate) fn try_remove(&mut self, numdir: &NumberedDir, threads: usize) {
+        if let Err(err) = self.remove(numdir, threads) {
+            self.errors.push(CleanupError {
+                path: numdir.path().to_path_buf(),
+                message: format!("{err:#}"),
+            });
+        }
+    }
+}
+
+impl fmt::Display for CleanupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "removed {} directories, freed {} bytes",
+            self.removed.len(),
+            self.bytes_freed
+        )?;
+        if !self.errors.is_empty() {
+            write!(f, ", failed to remove {} directories", self.errors.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Recursively removes a directory, removing its entries using `threads` threads.
+///
+/// Directories with many small files, especially on network filesystems, are removed much
+/// faster in parallel.  The threads share a queue of pending entries: files are removed
+/// directly while the entries of directories are pushed onto the queue, so the files at any
+/// depth are spread over all threads.  No more threads are started than there are pending
+/// entries.  The emptied directories are removed once all files are gone.  If `path` is a
+/// symlink only the symlink is removed, its target is left alone.
+pub(crate) fn remove_dir_all_parallel(path: &Path, threads: usize) -> io::Result<()> {
+    // Removing a symlink with remove_dir_all() only unlinks it, like for a single thread.
+    if threads <= 1 || is_symlink(path) {
+        return remove_dir_all(path);
+    }
+    let pending: Vec<PathBuf> = std::fs::read_dir(path)?
+        .filter_map(|dirent| dirent.ok())
+        .map(|dirent| dirent.path())
+        .collect();
+    let removal = ParallelRemoval {
+        threads,
+        state: Mutex::new(RemovalState {
+            pending,
+            active: 0,
+            spawned: 0,
+            first_err: None,
+        }),
+        wake: Condvar::new(),
+    };
+    thread::scope(|scope| {
+        let mut state = removal.lock();
+        removal.spawn_workers(scope, &mut state);
+    });
+    if let Some(err) = removal.state.into_inner().ok().and_then(|s| s.first_err) {
+        return Err(err);
+    }
+    // Only the emptied directories are left.
+    remove_dir_all(path)
+}
+
+/// The work queue shared by the threads of [`remove_dir_all_parallel`].
+struct ParallelRemoval {
+    /// The maximum number of threads to use.
+    threads: usize,
+    state: Mutex<RemovalState>,
+    /// Notified when entries are added to the queue or a thread finishes an entry.
+    wake: Condvar,
+}
+
+/// The mutable state of [`ParallelRemoval`].
+struct RemovalState {
+    /// The entries not yet picked up by any thread.
+    pending: Vec<PathBuf>,
+    /// The number of threads currently working on an entry.
+    active: usize,
+    /// The number of threads started so far.
+    spawned: usize,
+    first_err: Option<io::Error>,
+}
+
+impl ParallelRemoval {
+    fn lock(&self) -> MutexGuard<'_, RemovalState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Starts more threads while there are more pending entries than threads.
+    fn spawn_workers<'scope, 'env>(
+        &'env self,
+        scope: &'scope thread::Scope<'scope, 'env>,
+        state: &mut RemovalState,
+    ) {
+        while state.spawned < self.threads && state.spawned < state.pending.len() {
+            state.spawned += 1;
+            scope.spawn(|| self.work(scope));
+        }
+    }
+
+    /// Processes entries from the queue until it is empty and no thread can add more.
+    fn work<'scope, 'env>(&'env self, scope: &'scope thread::Scope<'scope, 'env>) {
+        loop {
+            let entry = {
+                let mut state = self.lock();
+                loop {
+                    if let Some(entry) = state.pending.pop() {
+                        state.active += 1;
+                        break entry;
+                    }
+                    if state.active == 0 {
+                        return;
+                    }
+                    state = self
+                        .wake
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+            };
+            let res = expand_or_remove(&entry);
+            let mut state = self.lock();
+            state.active -= 1;
+            match res {
+                Ok(children) => state.pending.extend(children),
+                Err(err) => {
+                    state.first_err.get_or_insert(err);
+                }
+            }
+            self.spawn_workers(scope, &mut state);
+            self.wake.notify_all();
+        }
+    }
+}
+
+/// Removes a file or symlink, returning the entries of a directory instead.
+///
+/// The directory itself is left in place, it is removed once it is empty.
+fn expand_or_remove(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let res = match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => std::fs::read_dir(path).and_then(|entries| {
+            entries
+                .map(|dirent| dirent.map(|dirent| dirent.path()))
+                .collect()
+        }),
+        Ok(_) => std::fs::remove_file(path).map(|()| Vec::new()),
+        Err(err) => Err(err),
+    };
+    match res {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        res => res,
+    }
+}
+
+/// Whether `path` itself is a symlink.
+fn is_symlink(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+/// Removes a file, symlink or directory, it not existing is not an error.
+pub(crate) fn remove_entry(path: &Path) -> io::Result<()> {
+    let res = match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(err) => Err(err),
+    };
+    match res {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+/// Recursively removes a directory, robust against the quirks of Windows.
+///
+/// On Windows this clears read-only attributes and retries on sharing violations, see the
+/// Windows implementation.  On other platforms this is [`std::fs::remove_dir_all`].
+#[cfg(not(windows))]
+pub(crate) fn remove_dir_all(path: &Path) -> io::Result<()> {
+    std::fs::remove_dir_all(path)
+}
+
+/// Recursively removes a directory, robust against the quirks of Windows.
+///
+/// On Windows removing a directory frequently fails right after a child process exited,
+/// because virus scanners or the indexer still have files open, or because files are
+/// read-only.  This clears read-only attributes, retries with backoff on sharing
+/// violations and uses extended-length paths to support long paths.  On other platforms
+/// this is [`std::fs::remove_dir_all`].
+#[cfg(windows)]
+pub(crate) fn remove_dir_all(path: &Path) -> io::Result<()> {
+    use std::time::Duration;
+
+    const RETRIES: u32 = 8;
+    let path = extended_length_path(path);
+    let mut delay = Duration::from_millis(10);
+    let mut attempt = 0;
+    loop {
+        clear_readonly(&path);
+        match std::fs::remove_dir_all(&path) {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt < RETRIES && is_transient(&err) => {
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Whether the error is likely caused by another process briefly holding a file open.
+#[cfg(windows)]
+fn is_transient(err: &io::Error) -> bool {
+    const ERROR_ACCESS_DENIED: i32 = 5;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_DIR_NOT_EMPTY: i32 = 145;
+    matches!(
+        err.raw_os_error(),
+        Some(ERROR_ACCESS_DENIED | ERROR_SHARING_VIOLATION | ERROR_DIR_NOT_EMPTY)
+    )
+}
+
+/// Clears the read-only attribute of everything inside `path`, best effort.
+#[cfg(windows)]
+fn clear_readonly(path: &Path) {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return;
+    };
+    let mut permissions = metadata.permissions();
+    if permissions.readonly() {
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        std::fs::set_permissions(path, permissions).ok();
+    }
+    if metadata.is_dir() {
+        if let Ok(readdir) = std::fs::read_dir(path) {
+            for dirent in readdir.filter_map(|dirent| dirent.ok()) {
+                clear_readonly(&dirent.path());
+            }
+        }
+    }
+}
+
+/// Converts an absolute path to an extended-length path, lifting the `MAX_PATH` limit.
+#[cfg(windows)]
+fn extended_length_path(path: &Path) -> PathBuf {
+    let Some(s) = path.to_str() else {
+        return path.to_path_buf();
+    };
+    if s.starts_with(r"\\?\") || !path.is_absolute() {
+        return path.to_path_buf();
+    }
+    let s = s.replace('/', r"\");
+    if let Some(unc) = s.strip_prefix(r"\\") {
+        PathBuf::from(format!(r"\\?\UNC\{unc}"))
+    } else {
+        PathBuf::from(format!(r"\\?\{s}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::num::NonZeroU8;
+
+    use super::*;
+
+    #[test]
+    fn test_remove() {
+        let parent = tempfile::tempdir().unwrap();
+        let numdir =
+            NumberedDir::create(parent.path(), "base", NonZeroU8::new(2).unwrap()).unwrap();
+        fs::write(numdir.path().join("data"), "0123456789").unwrap();
+        let size = dir_size(numdir.path()).unwrap();
+        assert!(size > 10);
+
+        let mut report = CleanupReport::default();
+        report.remove(&numdir, 1).unwrap();
+        assert!(!numdir.path().exists());
+        assert_eq!(report.removed, vec![numdir.path().to_path_buf()]);
+        assert_eq!(report.bytes_freed, size);
+        assert_eq!(
+            report.to_string(),
+            format!("removed 1 directories, freed {size} bytes")
+        );
+    }
+
+    #[test]
+    fn test_remove_dir_all() {
+        let parent = tempfile::tempdir().unwrap();
+        let dir = parent.path().join("dir");
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("a/b/file"), "data").unwrap();
+        let mut permissions = fs::metadata(dir.join("a/b/file")).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(dir.join("a/b/file"), permissions).unwrap();
+        remove_dir_all(&dir).unwrap();
+        assert!(!dir.exists());
+        assert_eq!(
+            remove_dir_all(&dir).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_remove_dir_all_parallel() {
+        let parent = tempfile::tempdir().unwrap();
+        let dir = parent.path().join("dir");
+        for i in 0..20 {
+            fs::create_dir_all(dir.join(format!("sub{i}/nested"))).unwrap();
+            fs::write(dir.join(format!("sub{i}/nested/file")), "data").unwrap();
+            fs::write(dir.join(format!("file{i}")), "data").unwrap();
+        }
+        remove_dir_all_parallel(&dir, 4).unwrap();
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_remove_dir_all_parallel_deep() {
+        let parent = tempfile::tempdir().unwrap();
+        let dir = parent.path().join("dir");
+        let mut deep = dir.clone();
+        for depth in 0..16 {
+            deep = deep.join(format!("level{depth}"));
+            fs::create_dir_all(deep.join("empty")).unwrap();
+            for i in 0..8 {
+                fs::write(deep.join(format!("file{i}")), "data").unwrap();
+            }
+        }
+        remove_dir_all_parallel(&dir, 4).unwrap();
+        assert!(!dir.exists());
+        assert_eq!(fs::read_dir(parent.path()).unwrap().count(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_symlink() {
+        let parent = tempfile::tempdir().unwrap();
+        let external = parent.path().join("external");
+        fs::create_dir_all(external.join("sub")).unwrap();
+        fs::write(external.join("sub/file"), "keep").unwrap();
+        let link = parent.path().join("base-0");
+        std::os::unix::fs::symlink(&external, &link).unwrap();
+        let numdir = NumberedDir::new(link.clone(), "base", 0);
+
+        let mut report = CleanupReport::default();
+        report.remove(&numdir, 4).unwrap();
+        assert!(link.symlink_metadata().is_err());
+        assert!(external.join("sub/file").is_file());
+        assert_eq!(report.removed, vec![link]);
+        assert_eq!(report.bytes_freed, 0);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_extended_length_path() {
+        assert_eq!(
+            extended_length_path(Path::new(r"C:\dir/sub")),
+            PathBuf::from(r"\\?\C:\dir\sub")
+        );
+        assert_eq!(
+            extended_length_path(Path::new(r"\\server\share\dir")),
+            PathBuf::from(r"\\?\UNC\server\share\dir")
+        );
+        assert_eq!(extended_length_path(Path::new("rel")), PathBuf::from("rel"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_try_remove() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let parent = tempfile::tempdir().unwrap();
+        let numdir =
+            NumberedDir::create(parent.path(), "base", NonZeroU8::new(2).unwrap()).unwrap();
+        fs::create_dir(numdir.path().join("sub")).unwrap();
+        fs::write(numdir.path().join("sub/file"), "").unwrap();
+        let sub = numdir.path().join("sub");
+        fs::set_permissions(&sub, fs::Permissions::from_mode(0o500)).unwrap();
+        if fs::write(sub.join("probe"), "").is_ok() {
+            // Running with privileges which ignore permissions.
+            return;
+        }
+
+        let mut report = CleanupReport::default();
+        report.try_remove(&numdir, 1);
+        fs::set_permissions(&sub, fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(report.removed.is_empty());
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].path, numdir.path());
+        assert!(report
+            .to_string()
+            .ends_with("failed to remove 1 directories"));
+    }
+}
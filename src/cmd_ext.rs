@@ -0,0 +1,139 @@
+//! Extension traits to run commands from other crates inside a [`TestDir`].
+//!
+//! These are available with the `assert_cmd` and `duct` features and let the command run
+//! with the test directory as working directory and have its output captured into
+//! `stdout.log` and `stderr.log` in the test directory, like
+//! [`TestDir::capture_output`] does.  Running the command also exports the test directory
+//! to it like [`TestDir::export_env`].
+
+use anyhow::Result;
+
+use crate::{CapturedRun, Error, TestDir};
+
+/// Extensions for [`assert_cmd::Command`].
+///
+/// # Examples
+///
+/// ```
+/// use assert_cmd::Command;
+/// use testdir::cmd_ext::AssertCmdExt;
+/// use testdir::{testdir, TestDir};
+///
+/// let dir = TestDir::new(testdir!());
+/// Command::new("cargo")
+///     .arg("--version")
+///     .testdir_cwd(&dir)
+///     .testdir_assert(&dir)
+///     .unwrap()
+///     .success();
+/// assert!(dir.join("stdout.log").is_file());
+/// ```
+#[cfg(feature = "assert_cmd")]
+pub trait AssertCmdExt {
+    /// Runs the command with the test directory as working directory.
+    fn testdir_cwd(&mut self, dir: &TestDir) -> &mut Self;
+
+    /// Runs the command, capturing its output into the test directory.
+    fn testdir_output(&mut self, dir: &TestDir) -> Result<CapturedRun, Error>;
+
+    /// Runs the command, capturing its output, and returns an [`assert_cmd::assert::Assert`].
+    fn testdir_assert(&mut self, dir: &TestDir) -> Result<assert_cmd::assert::Assert, Error>;
+}
+
+#[cfg(feature = "assert_cmd")]
+impl AssertCmdExt for assert_cmd::Command {
+    fn testdir_cwd(&mut self, dir: &TestDir) -> &mut Self {
+        self.current_dir(dir.path())
+    }
+
+    fn testdir_output(&mut self, dir: &TestDir) -> Result<CapturedRun, Error> {
+        let output = self.envs(dir.env_vars()).output()?;
+        dir.write_output_logs(&output).map_err(Error::from)
+    }
+
+    fn testdir_assert(&mut self, dir: &TestDir) -> Result<assert_cmd::assert::Assert, Error> {
+        let output = self.envs(dir.env_vars()).output()?;
+        dir.write_output_logs(&output)?;
+        Ok(assert_cmd::assert::Assert::new(output))
+    }
+}
+
+/// Extensions for [`duct::Expression`].
+///
+/// The exit status is not checked when capturing output, inspect
+/// [`CapturedRun::status`] instead.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::cmd_ext::DuctExt;
+/// use testdir::{testdir, TestDir};
+///
+/// let dir = TestDir::new(testdir!());
+/// let run = duct::cmd!("cargo", "--version")
+///     .testdir_cwd(&dir)
+///     .testdir_output(&dir)
+///     .unwrap();
+/// assert!(run.status.success());
+/// ```
+#[cfg(feature = "duct")]
+pub trait DuctExt {
+    /// Runs the expression with the test directory as working directory.
+    fn testdir_cwd(&self, dir: &TestDir) -> Self;
+
+    /// Runs the expression, capturing its output into the test directory.
+    fn testdir_output(&self, dir: &TestDir) -> Result<CapturedRun, Error>;
+}
+
+#[cfg(feature = "duct")]
+impl DuctExt for duct::Expression {
+    fn testdir_cwd(&self, dir: &TestDir) -> Self {
+        self.dir(dir.path())
+    }
+
+    fn testdir_output(&self, dir: &TestDir) -> Result<CapturedRun, Error> {
+        let expr = dir
+            .env_vars()
+            .into_iter()
+            .fold(self.clone(), |expr, (name, value)| expr.env(name, value));
+        let output = expr.stdout_capture().stderr_capture().unchecked().run()?;
+        dir.write_output_logs(&output).map_err(Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(unix, feature = "assert_cmd"))]
+    #[test]
+    fn test_assert_cmd() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        let run = assert_cmd::Command::new("sh")
+            .args(["-c", "pwd; echo err >&2"])
+            .testdir_cwd(&dir)
+            .testdir_output(&dir)
+            .unwrap();
+        assert!(run.status.success());
+        assert_eq!(std::fs::read_to_string(run.stderr_path).unwrap(), "err\n");
+        let pwd = String::from_utf8(run.stdout).unwrap();
+        assert_eq!(
+            std::fs::canonicalize(pwd.trim()).unwrap(),
+            std::fs::canonicalize(temp.path()).unwrap()
+        );
+    }
+
+    #[cfg(all(unix, feature = "duct"))]
+    #[test]
+    fn test_duct() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        let run = duct::cmd!("sh", "-c", "echo out; exit 2")
+            .testdir_cwd(&dir)
+            .testdir_output(&dir)
+            .unwrap();
+        assert_eq!(run.status.code(), Some(2));
+        assert_eq!(std::fs::read_to_string(run.stdout_path).unwrap(), "out\n");
+    }
+}
@@ -0,0 +1,60 @@
+//! Build directories for compile tests, see [`CompileTestGuard`].
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// The environment variable redirected by [`CompileTestGuard`]: `CARGO_TARGET_DIR`.
+const TARGET_DIR_ENV: &str = "CARGO_TARGET_DIR";
+
+/// Redirects the cargo target directory of compile tests into a test directory.
+///
+/// Compile test frameworks like [trybuild] build their test cases using `cargo` in the
+/// target directory reported by `cargo metadata`.  While this guard is alive the
+/// `CARGO_TARGET_DIR` environment variable of the process points to the `target`
+/// directory inside the test directory, so that the compiler artifacts of these tests end
+/// up in the numbered directory and are cleaned up with it.  The previous value is
+/// restored when dropped.
+///
+/// As the environment is shared by the whole process this guard should only be used by
+/// one test at a time.  The guard must outlive the compile tests, e.g. trybuild runs its
+/// tests when its `TestCases` are dropped so the guard must be created first.
+///
+/// [trybuild]: https://docs.rs/trybuild
+#[derive(Debug)]
+#[must_use = "the target directory is restored when the guard is dropped"]
+pub struct CompileTestGuard {
+    target_dir: PathBuf,
+    previous: Option<OsString>,
+}
+
+impl CompileTestGuard {
+    /// Creates the `target` directory in `dir` and points `CARGO_TARGET_DIR` to it.
+    pub(crate) fn new(dir: &Path) -> Result<Self> {
+        let target_dir = dir.join("target");
+        fs::create_dir_all(&target_dir)
+            .with_context(|| format!("Failed to create {}", target_dir.display()))?;
+        let previous = std::env::var_os(TARGET_DIR_ENV);
+        std::env::set_var(TARGET_DIR_ENV, &target_dir);
+        Ok(Self {
+            target_dir,
+            previous,
+        })
+    }
+
+    /// Returns the target directory used by the compile tests.
+    pub fn target_dir(&self) -> &Path {
+        &self.target_dir
+    }
+}
+
+impl Drop for CompileTestGuard {
+    fn drop(&mut self) {
+        match self.previous.take() {
+            Some(previous) => std::env::set_var(TARGET_DIR_ENV, previous),
+            None => std::env::remove_var(TARGET_DIR_ENV),
+        }
+    }
+}
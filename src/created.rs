@@ -0,0 +1,120 @@
+//! Tracking of the directories created by the [`testdir!`](crate::testdir) macro.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::NumberedDir;
+
+/// All the directories returned by the macros in this process.
+static CREATED: Mutex<Vec<CreatedDir>> = Mutex::new(Vec::new());
+
+/// The [`NumberedDir`] in which each directory returned by the macros was created.
+static OWNERS: Mutex<Vec<(PathBuf, NumberedDir)>> = Mutex::new(Vec::new());
+
+/// The scope with which a directory was created by [`testdir!`](crate::testdir).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// Created for a test function, using `testdir!()` or `testdir!(TestScope)`.
+    Test,
+    /// Created for a module, using `testdir!(ModuleScope)`.
+    Module,
+    /// Created using an explicit path, e.g. `testdir!("sub/dir")`.
+    Custom,
+}
+
+/// A directory created by [`testdir!`](crate::testdir), as returned by [`created_dirs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CreatedDir {
+    /// The path of the directory.
+    pub path: PathBuf,
+    /// The scope used to create the directory.
+    pub scope: Scope,
+    /// The name of the thread which created the directory.
+    ///
+    /// When running tests using the standard test harness this is the name of the test.
+    pub thread: Option<String>,
+}
+
+/// Returns the directories created by [`testdir!`](crate::testdir) in this process.
+///
+/// The directories are returned in the order in which they were first returned by the
+/// macro, each directory is only included once.  This allows harness-level teardown code
+/// to iterate over what the tests produced, e.g. to upload them selectively.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{created_dirs, testdir, Scope};
+///
+/// let dir = testdir!("some/dir");
+/// let created = created_dirs();
+/// assert!(created.iter().any(|c| c.path == dir && c.scope == Scope::Custom));
+/// ```
+pub fn created_dirs() -> Vec<CreatedDir> {
+    CREATED
+        .lock()
+        .map(|created| created.clone())
+        .unwrap_or_default()
+}
+
+/// Returns the test-scoped directory created by the thread with this name.
+pub(crate) fn test_dir_for_thread(name: &str) -> Option<PathBuf> {
+    let created = CREATED.lock().ok()?;
+    created
+        .iter()
+        .find(|c| c.scope == Scope::Test && c.thread.as_deref() == Some(name))
+        .map(|c| c.path.clone())
+}
+
+/// Returns the [`NumberedDir`] in which the macros created the directory at `path`.
+pub(crate) fn owner_of(path: &Path) -> Option<NumberedDir> {
+    let owners = OWNERS.lock().ok()?;
+    owners
+        .iter()
+        .rev()
+        .find(|(owned, _)| owned == path)
+        .map(|(_, numdir)| numdir.clone())
+}
+
+/// Records that the directory at `path` was created in `numdir`.
+pub(crate) fn record_owner(path: &Path, numdir: &NumberedDir) {
+    if let Ok(mut owners) = OWNERS.lock() {
+        owners.retain(|(owned, _)| owned != path);
+        owners.push((path.to_path_buf(), numdir.clone()));
+    }
+}
+
+/// Records a directory returned by the macros.
+pub(crate) fn record(path: &Path, scope: Scope) {
+    if let Ok(mut created) = CREATED.lock() {
+        if !created.iter().any(|c| c.path == path) {
+            created.push(CreatedDir {
+                path: path.to_path_buf(),
+                scope,
+                thread: std::thread::current().name().map(String::from),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let path = Path::new("/some/unique/test_record");
+        record(path, Scope::Test);
+        record(path, Scope::Test);
+        let created: Vec<_> = created_dirs()
+            .into_iter()
+            .filter(|c| c.path == path)
+            .collect();
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0].scope, Scope::Test);
+
+        let thread = std::thread::current().name().unwrap().to_string();
+        assert_eq!(created[0].thread.as_ref(), Some(&thread));
+        assert_eq!(test_dir_for_thread(&thread), Some(path.to_path_buf()));
+    }
+}
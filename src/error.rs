@@ -0,0 +1,220 @@
+//! The [`Error`] type returned by the public API.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// The error returned by testdir's fallible functions.
+///
+/// The variants allow matching on the most common causes of failure, anything else is
+/// reported as [`Error::Other`].  The [`Display`](fmt::Display) implementation only
+/// describes the failure itself, the underlying cause is available from
+/// [`Error::source`](StdError::source) so that error reporters, like `anyhow`'s `{:#}`
+/// formatting, show the whole chain.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Creating a directory failed.
+    Create {
+        /// The directory which could not be created.
+        path: PathBuf,
+        /// The underlying I/O error.
+        source: io::Error,
+    },
+    /// Removing an obsolete numbered directory failed.
+    Cleanup {
+        /// The directory which could not be removed.
+        path: PathBuf,
+        /// The underlying I/O error.
+        source: io::Error,
+    },
+    /// The **base** of a numbered directory is invalid.
+    InvalidBase {
+        /// The rejected base.
+        base: String,
+    },
+    /// The parent directory of a numbered directory is invalid.
+    ///
+    /// Its last component must be valid UTF-8, see
+    /// [`NumberedDirBuilder::set_parent`](crate::NumberedDirBuilder::set_parent).
+    InvalidParent {
+        /// The rejected parent directory.
+        parent: PathBuf,
+    },
+    /// The run identifier of a numbered directory is invalid.
+    InvalidRunId {
+        /// The rejected run identifier.
+        run_id: String,
+    },
+    /// The subdirectory already exists.
+    ///
+    /// This is returned by [`NumberedDir::create_subdir`] with [`Collision::Error`].
+    ///
+    /// [`NumberedDir::create_subdir`]: crate::NumberedDir::create_subdir
+    /// [`Collision::Error`]: crate::Collision::Error
+    SubdirExists {
+        /// The existing subdirectory.
+        path: PathBuf,
+    },
+    /// The relative path of a subdirectory would escape its numbered directory.
+    ///
+    /// See [`NumberedDir::create_subdir_strict`](crate::NumberedDir::create_subdir_strict).
+    PathTraversal {
+        /// The rejected relative path.
+        path: PathBuf,
+    },
+    /// A subdirectory name can not be used on Windows.
+    ///
+    /// This is returned with [`PortableNames::Reject`](crate::PortableNames::Reject).
+    NonPortableName {
+        /// The rejected relative path.
+        path: PathBuf,
+    },
+    /// An existing numbered directory should be re-used but is not compatible.
+    ///
+    /// This happens when the directory of a run identifier was created by an incompatible
+    /// version of testdir, see [`NumberedDir::is_compatible`](crate::NumberedDir::is_compatible).
+    ReuseConflict {
+        /// The directory which can not be re-used.
+        path: PathBuf,
+    },
+    /// The global [`NumberedDir`](crate::NumberedDir) was not initialised.
+    ///
+    /// The `name` is set for named global directories, see [`init_named`](crate::init_named).
+    NotInitialized {
+        /// The name of the named global directory, `None` for the default one.
+        name: Option<String>,
+    },
+    /// Any other I/O error.
+    Io {
+        /// What was being done when the error occurred.
+        context: String,
+        /// The underlying I/O error.
+        source: io::Error,
+    },
+    /// Any other error.
+    Other(Box<dyn StdError + Send + Sync + 'static>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Create { path, .. } => write!(f, "Failed to create {}", path.display()),
+            Error::Cleanup { path, .. } => write!(f, "Failed to remove {}", path.display()),
+            Error::InvalidBase { base } => {
+                write!(f, "base must not contain path separators: {base:?}")
+            }
+            Error::InvalidParent { parent } => write!(
+                f,
+                "last component of parent must be valid UTF-8: {}",
+                parent.display()
+            ),
+            Error::InvalidRunId { run_id } => write!(
+                f,
+                "run id must not be empty, contain path separators, be a number or start \
+                 with \"current\": {run_id:?}"
+            ),
+            Error::SubdirExists { path } => write!(f, "Subdir already exists: {}", path.display()),
+            Error::PathTraversal { path } => {
+                write!(
+                    f,
+                    "Subdir escapes the numbered directory: {}",
+                    path.display()
+                )
+            }
+            Error::NonPortableName { path } => {
+                write!(f, "Subdir name not portable to Windows: {}", path.display())
+            }
+            Error::ReuseConflict { path } => {
+                write!(f, "Can not re-use incompatible {}", path.display())
+            }
+            Error::NotInitialized { name: None } => {
+                f.write_str("testdir not initialised, use init_testdir!() first")
+            }
+            Error::NotInitialized { name: Some(name) } => {
+                write!(f, "named testdir {name:?} not initialised")
+            }
+            Error::Io { context, .. } => f.write_str(context),
+            Error::Other(err) => fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Create { source, .. }
+            | Error::Cleanup { source, .. }
+            | Error::Io { source, .. } => Some(source),
+            Error::InvalidBase { .. }
+            | Error::InvalidParent { .. }
+            | Error::InvalidRunId { .. }
+            | Error::SubdirExists { .. }
+            | Error::PathTraversal { .. }
+            | Error::NonPortableName { .. }
+            | Error::ReuseConflict { .. }
+            | Error::NotInitialized { .. } => None,
+            Error::Other(err) => err.source(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io {
+            context: String::from("I/O error"),
+            source,
+        }
+    }
+}
+
+/// Converts the errors used internally.
+///
+/// An [`Error`] wrapped by the internal error is recovered, so that matching on it works.
+/// An [`io::Error`] cause becomes [`Error::Io`], keeping the context messages.
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<Error>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        let messages: Vec<_> = err.chain().map(ToString::to_string).collect();
+        let context = match messages.split_last() {
+            Some((_, context)) if !context.is_empty() => context.join(": "),
+            _ => String::from("I/O error"),
+        };
+        match err.downcast::<io::Error>() {
+            Ok(source) => Error::Io { context, source },
+            Err(err) => Error::Other(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn test_from_anyhow() {
+        let err = Error::from(anyhow::Error::new(Error::InvalidBase {
+            base: String::from("a/b"),
+        }));
+        assert!(matches!(err, Error::InvalidBase { ref base } if base == "a/b"));
+
+        let res: anyhow::Result<()> = Err(io::Error::from(io::ErrorKind::NotFound))
+            .context("inner")
+            .context("outer");
+        let err = Error::from(res.unwrap_err());
+        assert_eq!(err.to_string(), "outer: inner");
+        assert!(
+            matches!(err, Error::Io { ref source, .. } if source.kind() == io::ErrorKind::NotFound)
+        );
+
+        let err = Error::from(anyhow::anyhow!("boom"));
+        assert!(matches!(err, Error::Other(_)));
+        assert_eq!(err.to_string(), "boom");
+    }
+}
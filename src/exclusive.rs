@@ -0,0 +1,150 @@
+//! Serialising access to shared on-disk resources, see [`exclusive`].
+
+use std::ops::Deref;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+
+use crate::lock::FileLock;
+use crate::Error;
+
+/// The directory in the numbered directory holding the exclusive directories: `exclusive`.
+const EXCLUSIVE_DIR_NAME: &str = "exclusive";
+
+/// How long [`exclusive`] waits for the lease by default: 10 minutes.
+pub const EXCLUSIVE_TIMEOUT_DEFAULT: Duration = Duration::from_secs(600);
+
+/// Leases the shared directory of the resource `name`, waiting until no one else holds it.
+///
+/// Some tests need to use a single on-disk resource, e.g. one database file or a fixed
+/// socket path, which can not be used by several tests at the same time.  All tests of the
+/// run leasing the same `name` get the same directory `exclusive/<name>` inside the global
+/// [`NumberedDir`], but only one of them at a time holds the [`ExclusiveGuard`].  This
+/// works across the threads of one test binary as well as across the processes of a single
+/// `cargo test` run, which share the global [`NumberedDir`].
+///
+/// The lease is released when the guard is dropped.  If the process holding it crashes the
+/// lease is released as well.  This waits at most [`EXCLUSIVE_TIMEOUT_DEFAULT`], use
+/// [`exclusive_timeout`] to wait a different time.
+///
+/// Be aware that you should have called [`init_testdir!`] before calling this, like for
+/// [`with_testdir`](crate::with_testdir).
+///
+/// # Examples
+///
+/// ```
+/// use testdir::init_testdir;
+///
+/// init_testdir!();
+/// let db = testdir::exclusive("database").unwrap();
+/// std::fs::write(db.join("db.sqlite"), "").unwrap();
+/// ```
+///
+/// [`NumberedDir`]: crate::NumberedDir
+/// [`init_testdir!`]: crate::init_testdir
+pub fn exclusive(name: &str) -> Result<ExclusiveGuard, Error> {
+    exclusive_timeout(name, EXCLUSIVE_TIMEOUT_DEFAULT)
+}
+
+/// Leases the shared directory of the resource `name` like [`exclusive`], waiting at most
+/// `timeout`.
+pub fn exclusive_timeout(name: &str, timeout: Duration) -> Result<ExclusiveGuard, Error> {
+    let parent = crate::with_testdir(|numdir| numdir.path().join(EXCLUSIVE_DIR_NAME));
+    acquire(&parent, name, timeout)
+}
+
+/// Acquires the lease of `name` in the directory `parent`.
+fn acquire(parent: &Path, name: &str, timeout: Duration) -> Result<ExclusiveGuard, Error> {
+    let mut components = Path::new(name).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        return Err(anyhow!("Invalid exclusive resource name: {name:?}").into());
+    }
+    let path = parent.join(name);
+    std::fs::create_dir_all(&path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let lock = FileLock::acquire_long(parent.join(format!("{name}.lock")), timeout)?;
+    Ok(ExclusiveGuard { path, _lock: lock })
+}
+
+/// The lease of a shared directory, returned by [`exclusive`].
+///
+/// This dereferences to the path of the directory, which is kept between leases so the
+/// next holder finds whatever the previous one left behind.
+#[derive(Debug)]
+#[must_use = "the lease is released when the guard is dropped"]
+pub struct ExclusiveGuard {
+    path: PathBuf,
+    _lock: FileLock,
+}
+
+impl ExclusiveGuard {
+    /// Returns the path of the shared directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Deref for ExclusiveGuard {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_exclusive_serialises() {
+        let temp = Arc::new(tempfile::tempdir().unwrap());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let temp = temp.clone();
+                thread::spawn(move || {
+                    let guard = acquire(temp.path(), "counter", Duration::from_secs(10)).unwrap();
+                    let file = guard.join("count");
+                    let count: u32 = fs::read_to_string(&file)
+                        .map(|s| s.parse().unwrap())
+                        .unwrap_or(0);
+                    thread::sleep(Duration::from_millis(5));
+                    fs::write(&file, (count + 1).to_string()).unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let count = fs::read_to_string(temp.path().join("counter/count")).unwrap();
+        assert_eq!(count, "4");
+    }
+
+    #[test]
+    fn test_exclusive_timeout() {
+        let temp = tempfile::tempdir().unwrap();
+        let guard = acquire(temp.path(), "db", Duration::from_secs(1)).unwrap();
+        assert_eq!(guard.path(), temp.path().join("db"));
+        assert!(guard.is_dir());
+        assert!(acquire(temp.path(), "db", Duration::from_millis(10)).is_err());
+        assert!(acquire(temp.path(), "other", Duration::from_millis(10)).is_ok());
+        drop(guard);
+        assert!(acquire(temp.path(), "db", Duration::from_millis(10)).is_ok());
+    }
+
+    #[test]
+    fn test_exclusive_name() {
+        let temp = tempfile::tempdir().unwrap();
+        for name in ["", "a/b", "..", "/abs"] {
+            assert!(acquire(temp.path(), name, Duration::from_millis(10)).is_err());
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! Exporting run directories for artifact upload, see [`ExportLayout`].
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::{Outcome, Run, VcsInfo};
+
+/// The file name of the manifest written by [`NumberedDir::export`]: `manifest.json`.
+///
+/// [`NumberedDir::export`]: crate::NumberedDir::export
+pub const EXPORT_MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// How [`NumberedDir::export`] lays out the exported test directories.
+///
+/// By default the test directories are copied into a flattened layout.  Each test
+/// directory is placed directly in the destination, named after its path inside the run
+/// directory with the components joined by dots, e.g. `mycrate.tests.test_write`.  This is
+/// easier to browse in the artifact viewers of CI systems than the deeply nested module
+/// hierarchy.
+///
+/// [`NumberedDir::export`]: crate::NumberedDir::export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExportLayout {
+    flatten: bool,
+    hardlink: bool,
+    failed_only: bool,
+}
+
+impl Default for ExportLayout {
+    fn default() -> Self {
+        Self::flat()
+    }
+}
+
+impl ExportLayout {
+    /// The flattened layout, with one directory per test directly in the destination.
+    pub fn flat() -> Self {
+        Self {
+            flatten: true,
+            hardlink: false,
+            failed_only: false,
+        }
+    }
+
+    /// Keeps the layout of the run directory, with test directories nested in modules.
+    pub fn tree() -> Self {
+        Self {
+            flatten: false,
+            hardlink: false,
+            failed_only: false,
+        }
+    }
+
+    /// Hardlinks files instead of copying them.
+    ///
+    /// This is much faster for large artifacts, but only works when the destination is on
+    /// the same filesystem.  Files which can not be hardlinked are copied instead.
+    pub fn hardlink(mut self, enable: bool) -> Self {
+        self.hardlink = enable;
+        self
+    }
+
+    /// Only exports the test directories of failed tests.
+    ///
+    /// These are the test directories for which [`Outcome::Failed`] was recorded, see
+    /// [`record_outcome`](crate::record_outcome).
+    ///
+    /// [`Outcome::Failed`]: crate::Outcome::Failed
+    pub fn failed_only(mut self, enable: bool) -> Self {
+        self.failed_only = enable;
+        self
+    }
+
+    /// Returns the name inside the destination for the test directory at `rel`.
+    fn dest_name(&self, rel: &Path) -> PathBuf {
+        if self.flatten {
+            let components: Vec<_> = rel
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            PathBuf::from(components.join("."))
+        } else {
+            rel.to_path_buf()
+        }
+    }
+}
+
+/// Exports the test directories of the run directory `src` into `dest`.
+///
+/// Returns the path of the written manifest.
+pub(crate) fn export(src: &Path, dest: &Path, layout: ExportLayout) -> Result<PathBuf> {
+    if dest.starts_with(src) {
+        bail!("Can not export {} into itself", src.display());
+    }
+    let run = Run::load(src)?;
+    fs::create_dir_all(dest).with_context(|| format!("Failed to create {}", dest.display()))?;
+    let mut tests = Vec::new();
+    for subdir in run.subdirs() {
+        if layout.failed_only && subdir.outcome != Some(Outcome::Failed) {
+            continue;
+        }
+        let name = layout.dest_name(&subdir.path);
+        copy_tree(&src.join(&subdir.path), &dest.join(&name), layout.hardlink)?;
+        tests.push(json!({
+            "name": name,
+            "source": subdir.path,
+            "bytes": subdir.bytes,
+            "outcome": subdir.outcome.map(|outcome| outcome.as_str()),
+        }));
+    }
+    let manifest = json!({
+        "run": src,
+        "number": run.number(),
+        "vcs": run.vcs().map(VcsInfo::to_json),
+        "tests": Value::Array(tests),
+    });
+    let path = dest.join(EXPORT_MANIFEST_FILE_NAME);
+    let data = serde_json::to_vec_pretty(&manifest)?;
+    fs::write(&path, data).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Recursively copies, or hardlinks, the directory `src` to `dest`.
+///
+/// Symlinks are recreated on UNIX and followed elsewhere.
+pub(crate) fn copy_tree(src: &Path, dest: &Path, hardlink: bool) -> Result<()> {
+    fs::create_dir_all(dest).with_context(|| format!("Failed to create {}", dest.display()))?;
+    let readdir = src
+        .read_dir()
+        .with_context(|| format!("Failed read_dir() on {}", src.display()))?;
+    for dirent in readdir {
+        let dirent = dirent.with_context(|| format!("Failed read_dir() on {}", src.display()))?;
+        copy_entry(&dirent.path(), &dest.join(dirent.file_name()), hardlink)?;
+    }
+    Ok(())
+}
+
+/// Copies, or hardlinks, the file, symlink or directory tree `from` to `to`.
+pub(crate) fn copy_entry(from: &Path, to: &Path, hardlink: bool) -> Result<()> {
+    #[cfg(unix)]
+    if from.symlink_metadata()?.file_type().is_symlink() {
+        let target = fs::read_link(from)?;
+        std::os::unix::fs::symlink(target, to)
+            .with_context(|| format!("Failed to create symlink {}", to.display()))?;
+        return Ok(());
+    }
+    if from.is_dir() {
+        copy_tree(from, to, hardlink)?;
+    } else if !hardlink || fs::hard_link(from, to).is_err() {
+        fs::copy(from, to).with_context(|| format!("Failed to copy {}", from.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_dir(parent: &Path) -> PathBuf {
+        let run_dir = parent.join("testdir-3");
+        fs::create_dir_all(run_dir.join("krate/mod/test_a/nested")).unwrap();
+        fs::create_dir_all(run_dir.join("krate/mod/test_b")).unwrap();
+        fs::write(run_dir.join("krate/mod/test_a/out.txt"), "12345").unwrap();
+        fs::write(run_dir.join("krate/mod/test_a/nested/more.txt"), "6").unwrap();
+        fs::write(run_dir.join("format-version"), "1 0.9\n").unwrap();
+        run_dir
+    }
+
+    #[test]
+    fn test_export_flat() {
+        let parent = tempfile::tempdir().unwrap();
+        let src = run_dir(parent.path());
+        let dest = parent.path().join("artifacts");
+
+        let manifest = export(&src, &dest, ExportLayout::flat()).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("krate.mod.test_a/out.txt")).unwrap(),
+            "12345"
+        );
+        assert!(dest.join("krate.mod.test_a/nested/more.txt").is_file());
+        assert!(dest.join("krate.mod.test_b").is_dir());
+        assert!(!dest.join("format-version").exists());
+
+        let manifest: Value = serde_json::from_slice(&fs::read(manifest).unwrap()).unwrap();
+        assert_eq!(manifest["number"], 3);
+        assert_eq!(manifest["tests"][0]["name"], "krate.mod.test_a");
+        assert_eq!(manifest["tests"][0]["bytes"], 6);
+        assert_eq!(manifest["tests"][1]["source"], "krate/mod/test_b");
+    }
+
+    #[test]
+    fn test_export_tree_hardlink() {
+        let parent = tempfile::tempdir().unwrap();
+        let src = run_dir(parent.path());
+        let dest = parent.path().join("artifacts");
+
+        export(&src, &dest, ExportLayout::tree().hardlink(true)).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("krate/mod/test_a/out.txt")).unwrap(),
+            "12345"
+        );
+        assert!(dest.join(EXPORT_MANIFEST_FILE_NAME).is_file());
+        assert!(export(&src, &src.join("export"), ExportLayout::tree()).is_err());
+    }
+
+    #[test]
+    fn test_export_failed_only() {
+        let parent = tempfile::tempdir().unwrap();
+        let src = run_dir(parent.path());
+        crate::record_outcome_at(src.join("krate/mod/test_b"), Outcome::Failed).unwrap();
+        let dest = parent.path().join("artifacts");
+
+        let manifest = export(&src, &dest, ExportLayout::flat().failed_only(true)).unwrap();
+        assert!(!dest.join("krate.mod.test_a").exists());
+        assert!(dest.join("krate.mod.test_b").is_dir());
+        let manifest: Value = serde_json::from_slice(&fs::read(manifest).unwrap()).unwrap();
+        assert_eq!(manifest["tests"][0]["outcome"], "failed");
+    }
+}
@@ -0,0 +1,244 @@
+//! Generators for fixture files, used by [`TestDir`](crate::TestDir).
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use anyhow::{Context, Result};
+
+use crate::ChildPath;
+
+/// A small, fast and deterministic pseudo-random number generator.
+///
+/// This is SplitMix64, which is more than good enough for fixture data.  The output for a
+/// given seed must never change, tests rely on the content being reproducible.
+#[derive(Debug, Clone)]
+pub(crate) struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Writes `size` pseudo-random bytes generated from `seed` to `child`.
+pub(crate) fn write_random_file(child: &ChildPath, size: u64, seed: u64) -> Result<()> {
+    child.create_parent()?;
+    let file =
+        File::create(child).with_context(|| format!("Failed to create {}", child.display()))?;
+    let mut writer = BufWriter::new(file);
+    let mut rng = SplitMix64::new(seed);
+    let mut remaining = size;
+    while remaining > 0 {
+        let bytes = rng.next_u64().to_le_bytes();
+        let n = remaining.min(bytes.len() as u64) as usize;
+        writer
+            .write_all(&bytes[..n])
+            .with_context(|| format!("Failed to write {}", child.display()))?;
+        remaining -= n as u64;
+    }
+    writer
+        .flush()
+        .with_context(|| format!("Failed to write {}", child.display()))
+}
+
+/// Writes a tree of `count` random files of `size` bytes each below `root`.
+///
+/// Files are spread over subdirectories holding at most 16 files each.
+pub(crate) fn write_random_tree(
+    root: &ChildPath,
+    count: usize,
+    size: u64,
+    seed: u64,
+) -> Result<Vec<ChildPath>> {
+    let mut seeds = SplitMix64::new(seed);
+    let mut files = Vec::with_capacity(count);
+    for i in 0..count {
+        let child = root.child(format!("d{:03}/f{:05}.bin", i / 16, i));
+        write_random_file(&child, size, seeds.next_u64())?;
+        files.push(child);
+    }
+    Ok(files)
+}
+
+/// Creates a sparse file of `logical_size` bytes at `child`.
+///
+/// The file is marked as sparse where the platform requires this and then extended to the
+/// full size, so no data blocks are allocated on filesystems supporting sparse files.
+pub(crate) fn create_sparse_file(child: &ChildPath, logical_size: u64) -> Result<()> {
+    child.create_parent()?;
+    let file =
+        File::create(child).with_context(|| format!("Failed to create {}", child.display()))?;
+    #[cfg(windows)]
+    set_sparse(&file).with_context(|| format!("Failed to mark {} sparse", child.display()))?;
+    file.set_len(logical_size)
+        .with_context(|| format!("Failed to set length of {}", child.display()))
+}
+
+/// Marks a file as sparse using `FSCTL_SET_SPARSE`.
+///
+/// On unix this is not needed, extending a file using `ftruncate` leaves a hole.
+#[cfg(windows)]
+fn set_sparse(file: &File) -> std::io::Result<()> {
+    use std::os::raw::{c_int, c_void};
+    use std::os::windows::io::AsRawHandle;
+
+    const FSCTL_SET_SPARSE: u32 = 0x0009_00c4;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn DeviceIoControl(
+            device: *mut c_void,
+            control_code: u32,
+            in_buffer: *mut c_void,
+            in_buffer_size: u32,
+            out_buffer: *mut c_void,
+            out_buffer_size: u32,
+            bytes_returned: *mut u32,
+            overlapped: *mut c_void,
+        ) -> c_int;
+    }
+
+    let mut returned = 0u32;
+    // SAFETY: The handle is valid for the lifetime of `file` and no buffers are passed.
+    let ok = unsafe {
+        DeviceIoControl(
+            file.as_raw_handle() as *mut c_void,
+            FSCTL_SET_SPARSE,
+            std::ptr::null_mut(),
+            0,
+            std::ptr::null_mut(),
+            0,
+            &mut returned,
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Creates a named pipe at `child`, readable and writable by the owner.
+#[cfg(unix)]
+pub(crate) fn create_fifo(child: &ChildPath) -> Result<()> {
+    child.create_parent()?;
+    let path = c_path(child)?;
+    // SAFETY: The path is a valid null-terminated string.
+    if unsafe { libc::mkfifo(path.as_ptr(), 0o600) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to create fifo {}", child.display()));
+    }
+    Ok(())
+}
+
+/// Creates a character device node at `child`.
+///
+/// This usually requires elevated privileges.
+#[cfg(unix)]
+pub(crate) fn create_char_device(child: &ChildPath, major: u32, minor: u32) -> Result<()> {
+    child.create_parent()?;
+    let path = c_path(child)?;
+    let dev = libc::makedev(major as _, minor as _);
+    // SAFETY: The path is a valid null-terminated string.
+    if unsafe { libc::mknod(path.as_ptr(), libc::S_IFCHR | 0o600, dev) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to create device node {}", child.display()));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn c_path(path: &std::path::Path) -> Result<std::ffi::CString> {
+    use std::os::unix::ffi::OsStrExt;
+
+    std::ffi::CString::new(path.as_os_str().as_bytes())
+        .with_context(|| format!("Path contains a null byte: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::TestDir;
+
+    #[test]
+    fn test_splitmix_is_stable() {
+        let mut rng = SplitMix64::new(0);
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+    }
+
+    #[test]
+    fn test_random_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        let a = dir.create_random_file("a/one.bin", 1001, 7).unwrap();
+        let b = dir.create_random_file("b/two.bin", 1001, 7).unwrap();
+        let c = dir.create_random_file("c.bin", 1001, 8).unwrap();
+        assert_eq!(fs::metadata(&a).unwrap().len(), 1001);
+        assert_eq!(a.read().unwrap(), b.read().unwrap());
+        assert_ne!(a.read().unwrap(), c.read().unwrap());
+    }
+
+    #[test]
+    fn test_sparse_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        let size = 1 << 40;
+        let file = dir.create_sparse_file("sparse/huge.img", size).unwrap();
+        let meta = fs::metadata(&file).unwrap();
+        assert_eq!(meta.len(), size);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert!(meta.blocks() * 512 < size);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fifo() {
+        use std::os::unix::fs::FileTypeExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        let fifo = dir.create_fifo("ipc/pipe").unwrap();
+        assert!(fs::metadata(&fifo).unwrap().file_type().is_fifo());
+        assert!(dir.create_fifo("ipc/pipe").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_char_device() {
+        use std::os::unix::fs::FileTypeExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        // Only privileged users may create device nodes.
+        if let Ok(dev) = dir.create_char_device("dev/null", 1, 3) {
+            assert!(fs::metadata(&dev).unwrap().file_type().is_char_device());
+        }
+    }
+
+    #[test]
+    fn test_random_tree() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        let files = dir.create_random_tree("tree", 20, 64, 1).unwrap();
+        assert_eq!(files.len(), 20);
+        assert!(temp.path().join("tree/d000/f00015.bin").is_file());
+        assert!(temp.path().join("tree/d001/f00019.bin").is_file());
+        assert_ne!(files[0].read().unwrap(), files[1].read().unwrap());
+    }
+}
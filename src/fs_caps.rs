@@ -0,0 +1,120 @@
+//! Probing of filesystem capabilities, see [`fs_caps`].
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::{ChildPath, Error};
+
+/// The capabilities of a filesystem, as found by [`fs_caps`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FsCapabilities {
+    /// Whether symlinks can be created.
+    pub symlinks: bool,
+    /// Whether hardlinks can be created.
+    pub hardlinks: bool,
+    /// Whether file names which only differ in case refer to different files.
+    pub case_sensitive: bool,
+    /// Whether files can be sparse, that is not allocate disk space for holes.
+    pub sparse_files: bool,
+    /// Whether paths longer than 260 bytes, the traditional Windows limit, can be used.
+    pub long_paths: bool,
+}
+
+/// Probes the filesystem of `dir` for its capabilities.
+///
+/// Tests exercising symlinks or other features not supported by all filesystems can use
+/// this to skip themselves instead of failing on e.g. network mounts or FAT filesystems.
+/// The probing is done using files in a temporary directory inside `dir`, which is
+/// removed again afterwards.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{fs_caps, testdir};
+///
+/// let dir = testdir!();
+/// let caps = fs_caps(&dir).unwrap();
+/// if !caps.symlinks {
+///     return;
+/// }
+/// ```
+pub fn fs_caps(dir: impl AsRef<Path>) -> Result<FsCapabilities, Error> {
+    let probe = dir
+        .as_ref()
+        .join(format!(".testdir-fs-caps-{}", std::process::id()));
+    fs::create_dir_all(&probe)
+        .with_context(|| format!("Failed to create probe dir {}", probe.display()))?;
+    let caps = probe_caps(&probe);
+    fs::remove_dir_all(&probe)
+        .with_context(|| format!("Failed to remove probe dir {}", probe.display()))?;
+    caps.map_err(Error::from)
+}
+
+fn probe_caps(probe: &Path) -> Result<FsCapabilities> {
+    let file = probe.join("file");
+    fs::write(&file, "probe").with_context(|| format!("Failed to write {}", file.display()))?;
+
+    #[cfg(unix)]
+    let symlinks = std::os::unix::fs::symlink(&file, probe.join("symlink")).is_ok();
+    #[cfg(windows)]
+    let symlinks = std::os::windows::fs::symlink_file(&file, probe.join("symlink")).is_ok();
+    #[cfg(not(any(unix, windows)))]
+    let symlinks = false;
+
+    let hardlinks = fs::hard_link(&file, probe.join("hardlink")).is_ok();
+    let case_sensitive = !probe.join("FILE").exists();
+
+    Ok(FsCapabilities {
+        symlinks,
+        hardlinks,
+        case_sensitive,
+        sparse_files: probe_sparse(probe),
+        long_paths: probe_long_paths(probe),
+    })
+}
+
+fn probe_sparse(probe: &Path) -> bool {
+    const SIZE: u64 = 64 * 1024 * 1024;
+    let child = ChildPath::from(probe.join("sparse"));
+    if crate::fixtures::create_sparse_file(&child, SIZE).is_err() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fs::metadata(&child).is_ok_and(|meta| meta.blocks() * 512 < SIZE)
+    }
+    #[cfg(not(unix))]
+    {
+        cfg!(windows)
+    }
+}
+
+fn probe_long_paths(probe: &Path) -> bool {
+    let mut path = probe.to_path_buf();
+    while path.as_os_str().len() <= 300 {
+        path.push("d".repeat(50));
+    }
+    fs::create_dir_all(&path).is_ok() && fs::write(path.join("file"), "probe").is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fs_caps() {
+        let temp = tempfile::tempdir().unwrap();
+        let caps = fs_caps(temp.path()).unwrap();
+        if cfg!(target_os = "linux") {
+            assert!(caps.symlinks);
+            assert!(caps.hardlinks);
+            assert!(caps.case_sensitive);
+            assert!(caps.long_paths);
+        }
+        assert_eq!(temp.path().read_dir().unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,348 @@
+//! Garbage collection of numbered directories across all bases in a parent directory.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+
+use crate::backend::{std_backend, DirBackend, StdBackend};
+use crate::numbered_dir::{current_entry_count, update_current_symlink, write_pointer_file};
+use crate::{CleanupReport, Error, NumberedDir, RetentionMap};
+
+/// Applies a [`Retention`] policy to all bases found in a parent directory.
+///
+/// Normally each [`NumberedDir`] only cleans up older directories of its own **base** when
+/// a new directory is created.  When several bases share a parent directory, e.g. several
+/// crates using `/tmp/testdir-of-$USER`, the bases which are no longer used are never
+/// cleaned up.  This finds all bases in `parent` and removes all obsolete directories for
+/// each of them.
+///
+/// Unlike creating a new [`NumberedDir`] no room is reserved for a new directory, so
+/// [`Retention::Count`] keeps exactly that many directories for each base.  If all
+/// directories of a base are removed its `-current`, `-previous` and `-last-failed`
+/// symlinks and `-current.path` pointer file are removed as well.  A [`RetentionMap`] can
+/// be passed to apply a different policy to each base.
+///
+/// Returns a [`CleanupReport`] of the removed directories.
+///
+/// # Examples
+///
+/// ```
+/// use std::num::NonZeroU8;
+/// use testdir::{gc_root, NumberedDir, Retention};
+///
+/// let parent = std::env::temp_dir().join("testdir-gc-example");
+/// # std::fs::remove_dir_all(&parent).ok();
+/// let count = NonZeroU8::new(8).unwrap();
+/// NumberedDir::create(&parent, "one", count).unwrap();
+/// NumberedDir::create(&parent, "one", count).unwrap();
+/// NumberedDir::create(&parent, "two", count).unwrap();
+///
+/// let report = gc_root(&parent, Retention::Count(NonZeroU8::new(1).unwrap())).unwrap();
+/// assert_eq!(report.removed, vec![parent.join("one-0")]);
+/// # std::fs::remove_dir_all(&parent).ok();
+/// ```
+///
+/// [`Retention`]: crate::Retention
+/// [`Retention::Count`]: crate::Retention::Count
+pub fn gc_root(
+    parent: impl AsRef<Path>,
+    retention: impl Into<RetentionMap>,
+) -> Result<CleanupReport, Error> {
+    let parent = parent.as_ref();
+    let retention = retention.into();
+    let mut report = CleanupReport::default();
+    for base in find_bases(parent)? {
+        let Some(current) = current_entry_count(&std_backend(), parent, &base) else {
+            continue;
+        };
+        let obsolete =
+            retention
+                .get(&base)
+                .obsolete_dirs(parent, &base, current, false, SystemTime::now())?;
+        for numdir in obsolete {
+            report.remove(&numdir, 1)?;
+        }
+        if NumberedDir::iterate(parent, &base)?.next().is_none() {
+            fs::remove_file(parent.join(format!("{}-current", base))).ok();
+            fs::remove_file(parent.join(format!("{}-current.path", base))).ok();
+            fs::remove_file(parent.join(format!("{}-previous", base))).ok();
+            fs::remove_file(parent.join(format!("{}-last-failed", base))).ok();
+        }
+    }
+    Ok(report)
+}
+
+/// Renumbers the numbered directories of a base to a contiguous range starting at 0.
+///
+/// After manually deleting numbered directories, or after the numbers wrapped around, the
+/// numbering can become sparse and confusing.  This renames the remaining directories so
+/// that the oldest one becomes `base-0`, keeping their order.  The `-current` symlink, and
+/// the `-current.path` pointer file if it exists, are updated to point to the most recent
+/// directory.  The `-previous` and `-last-failed` symlinks are updated to the new names of
+/// the directories they pointed to, or removed if those directories no longer exist.
+/// Relative symlinks stay relative.
+///
+/// This must not be run concurrently with other processes using the numbered directories.
+///
+/// Returns the renumbered directories, oldest first.
+pub fn compact(parent: impl AsRef<Path>, base: &str) -> Result<Vec<NumberedDir>, Error> {
+    let parent = parent.as_ref();
+    let numbers = chronological_numbers(NumberedDir::iterate(parent, base)?.map(|d| d.number()));
+    let renumbered: BTreeMap<u16, u16> = numbers.iter().copied().zip(0..).collect();
+
+    // Move everything out of the way first so renames can not collide.
+    let mut moved = Vec::with_capacity(numbers.len());
+    for number in numbers {
+        let from = parent.join(format!("{}-{}", base, number));
+        let tmp = parent.join(format!(".{}-compact-{}", base, number));
+        fs::rename(&from, &tmp).with_context(|| format!("Failed to rename {}", from.display()))?;
+        moved.push(tmp);
+    }
+    let mut compacted = Vec::with_capacity(moved.len());
+    for (number, tmp) in moved.iter().enumerate() {
+        let to = parent.join(format!("{}-{}", base, number));
+        fs::rename(tmp, &to).with_context(|| format!("Failed to rename {}", tmp.display()))?;
+        compacted.push(NumberedDir::new(to, base, number as u16));
+    }
+
+    if let Some(latest) = compacted.last() {
+        let current = parent.join(format!("{}-current", base));
+        let relative = fs::read_link(current).is_ok_and(|target| target.is_relative());
+        update_current_symlink(&StdBackend, parent, base, latest.path(), relative);
+        if parent.join(format!("{}-current.path", base)).exists() {
+            write_pointer_file(&StdBackend, parent, base, latest.path())?;
+        }
+    }
+    renumber_symlink(parent, base, "previous", &renumbered);
+    renumber_symlink(parent, base, "last-failed", &renumbered);
+    Ok(compacted)
+}
+
+/// Points the `base-suffix` symlink to the new name of its renumbered target directory.
+///
+/// `renumbered` maps the old numbers to the new numbers.  If the symlink pointed to a
+/// directory which does not exist anymore it is removed.  This is best-effort, failures are
+/// ignored.
+fn renumber_symlink(parent: &Path, base: &str, suffix: &str, renumbered: &BTreeMap<u16, u16>) {
+    let link = parent.join(format!("{}-{}", base, suffix));
+    let Ok(target) = fs::read_link(&link) else {
+        return;
+    };
+    let prefix = format!("{}-", base);
+    let new_number = target
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix(&prefix))
+        .and_then(|number| number.parse::<u16>().ok())
+        .and_then(|number| renumbered.get(&number));
+    match new_number {
+        Some(number) => {
+            let name = format!("{}-{}", base, number);
+            let new_target = if target.is_relative() {
+                PathBuf::from(name)
+            } else {
+                parent.join(name)
+            };
+            StdBackend.symlink_dir(&new_target, &link).ok();
+        }
+        None => {
+            fs::remove_file(&link).ok();
+        }
+    }
+}
+
+/// Sorts directory numbers from oldest to most recent, taking wraparound into account.
+///
+/// The sequence is assumed to start after the largest gap between numbers.
+fn chronological_numbers(numbers: impl Iterator<Item = u16>) -> Vec<u16> {
+    let mut numbers: Vec<u16> = numbers.collect();
+    numbers.sort_unstable();
+    if numbers.len() < 2 {
+        return numbers;
+    }
+    let mut start = 0;
+    let mut largest_gap = 0u32;
+    for i in 0..numbers.len() {
+        let prev = if i == 0 {
+            u32::from(numbers[numbers.len() - 1])
+        } else {
+            u32::from(numbers[i - 1])
+        };
+        let cur = u32::from(numbers[i]);
+        let gap = if i == 0 {
+            cur + 0x10000 - prev
+        } else {
+            cur - prev
+        };
+        if gap > largest_gap {
+            largest_gap = gap;
+            start = i;
+        }
+    }
+    numbers.rotate_left(start);
+    numbers
+}
+
+/// Returns all the bases of numbered directories in `parent`.
+pub(crate) fn find_bases(parent: &Path) -> Result<BTreeSet<String>> {
+    let mut bases = BTreeSet::new();
+    let readdir = parent
+        .read_dir()
+        .with_context(|| format!("Failed read_dir() on {}", parent.display()))?;
+    for dirent in readdir.filter_map(|dirent| dirent.ok()) {
+        if !dirent.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+            continue;
+        }
+        let os_name = dirent.file_name();
+        let base = os_name
+            .to_str()
+            .and_then(|name| name.rsplit_once('-'))
+            .filter(|(_, suffix)| suffix.parse::<u16>().is_ok())
+            .map(|(base, _)| base.to_string());
+        if let Some(base) = base {
+            bases.insert(base);
+        }
+    }
+    Ok(bases)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU8;
+
+    use super::*;
+    use crate::Retention;
+
+    #[test]
+    fn test_find_bases() {
+        let parent = tempfile::tempdir().unwrap();
+        let count = NonZeroU8::new(3).unwrap();
+        NumberedDir::create(parent.path(), "one", count).unwrap();
+        NumberedDir::create(parent.path(), "two-parts", count).unwrap();
+        fs::create_dir(parent.path().join("not-numbered")).unwrap();
+        fs::write(parent.path().join("file-0"), "not a dir").unwrap();
+
+        let bases = find_bases(parent.path()).unwrap();
+        assert_eq!(
+            bases.into_iter().collect::<Vec<_>>(),
+            vec!["one".to_string(), "two-parts".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_chronological_numbers() {
+        let numbers = chronological_numbers([3, 1, 7].into_iter());
+        assert_eq!(numbers, vec![1, 3, 7]);
+
+        let numbers = chronological_numbers([1, 65534, 0, 65535].into_iter());
+        assert_eq!(numbers, vec![65534, 65535, 0, 1]);
+    }
+
+    #[test]
+    fn test_compact() {
+        let parent = tempfile::tempdir().unwrap();
+        let count = NonZeroU8::new(8).unwrap();
+        for _ in 0..4 {
+            NumberedDir::create(parent.path(), "base", count).unwrap();
+        }
+        fs::write(parent.path().join("base-3/marker"), "latest").unwrap();
+        NumberedDir::new(parent.path().join("base-1"), "base", 1)
+            .mark_failed()
+            .unwrap();
+        fs::remove_dir_all(parent.path().join("base-0")).unwrap();
+        fs::remove_dir_all(parent.path().join("base-2")).unwrap();
+
+        let dirs = compact(parent.path(), "base").unwrap();
+        let numbers: Vec<u16> = dirs.iter().map(|d| d.number()).collect();
+        assert_eq!(numbers, vec![0, 1]);
+        assert!(parent.path().join("base-1/marker").is_file());
+        assert!(!parent.path().join("base-3").exists());
+
+        #[cfg(unix)]
+        {
+            let current = fs::read_link(parent.path().join("base-current")).unwrap();
+            assert_eq!(current, parent.path().join("base-1"));
+        }
+        let last_failed = parent.path().join("base-last-failed");
+        assert_eq!(
+            fs::read_link(&last_failed).unwrap(),
+            parent.path().join("base-0")
+        );
+
+        fs::remove_dir_all(parent.path().join("base-0")).unwrap();
+        compact(parent.path(), "base").unwrap();
+        assert!(last_failed.symlink_metadata().is_err());
+        assert!(parent.path().join("base-0/marker").is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_compact_previous() {
+        use std::os::unix::fs::symlink;
+
+        let parent = tempfile::tempdir().unwrap();
+        let count = NonZeroU8::new(8).unwrap();
+        for _ in 0..4 {
+            NumberedDir::create(parent.path(), "base", count).unwrap();
+        }
+        fs::remove_dir_all(parent.path().join("base-0")).unwrap();
+        let previous = parent.path().join("base-previous");
+        symlink(parent.path().join("base-2"), &previous).unwrap();
+
+        compact(parent.path(), "base").unwrap();
+        assert_eq!(
+            fs::read_link(&previous).unwrap(),
+            parent.path().join("base-1")
+        );
+
+        fs::remove_file(&previous).unwrap();
+        symlink("base-1", &previous).unwrap();
+        fs::remove_dir_all(parent.path().join("base-0")).unwrap();
+        compact(parent.path(), "base").unwrap();
+        assert_eq!(fs::read_link(&previous).unwrap(), Path::new("base-0"));
+
+        fs::remove_file(&previous).unwrap();
+        symlink(parent.path().join("base-7"), &previous).unwrap();
+        compact(parent.path(), "base").unwrap();
+        assert!(previous.symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn test_gc_root_map() {
+        let parent = tempfile::tempdir().unwrap();
+        let count = NonZeroU8::new(8).unwrap();
+        for _ in 0..3 {
+            NumberedDir::create(parent.path(), "one", count).unwrap();
+            NumberedDir::create(parent.path(), "two", count).unwrap();
+        }
+
+        let map = RetentionMap::new(Retention::Count(NonZeroU8::new(1).unwrap()))
+            .base("two", Retention::Count(NonZeroU8::new(3).unwrap()));
+        let report = gc_root(parent.path(), map).unwrap();
+        assert_eq!(report.removed.len(), 2);
+        assert!(!parent.path().join("one-1").exists());
+        assert!(parent.path().join("one-2").is_dir());
+        assert!(parent.path().join("two-0").is_dir());
+    }
+
+    #[test]
+    fn test_gc_root() {
+        let parent = tempfile::tempdir().unwrap();
+        let count = NonZeroU8::new(8).unwrap();
+        for _ in 0..3 {
+            NumberedDir::create(parent.path(), "one", count).unwrap();
+            NumberedDir::create(parent.path(), "two", count).unwrap();
+        }
+
+        let report = gc_root(parent.path(), Retention::Count(NonZeroU8::new(2).unwrap())).unwrap();
+        assert_eq!(report.removed.len(), 2);
+        assert!(!parent.path().join("one-0").exists());
+        assert!(parent.path().join("one-1").is_dir());
+        assert!(parent.path().join("one-2").is_dir());
+        assert!(!parent.path().join("two-0").exists());
+        assert!(parent.path().join("two-2").is_dir());
+    }
+}
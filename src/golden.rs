@@ -0,0 +1,281 @@
+//! Golden directory testing, see [`assert_matches`].
+//!
+//! A golden directory is a committed directory tree with the expected output of a test.
+//! The test writes its output into its test directory, which is then compared against the
+//! golden directory.  When the output changes intentionally the golden directory is
+//! updated by running the tests with the [`UPDATE_ENV`] environment variable set, after
+//! which the changes can be reviewed and committed like any other change.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::Error;
+
+/// The environment variable enabling updating golden directories: `UPDATE_GOLDEN`.
+///
+/// Setting this to a non-empty value other than `0` makes [`assert_matches`] replace the
+/// contents of the golden directory with those of the test directory instead of comparing
+/// them.
+pub const UPDATE_ENV: &str = "UPDATE_GOLDEN";
+
+/// A difference between a test directory and its golden directory.
+///
+/// The paths are relative to both directories.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Difference {
+    /// The path exists in the golden directory but not in the test directory.
+    Missing(PathBuf),
+    /// The path exists in the test directory but not in the golden directory.
+    Unexpected(PathBuf),
+    /// The path exists in both, but the contents or the file type differ.
+    Changed(PathBuf),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Missing(path) => write!(f, "missing: {}", path.display()),
+            Difference::Unexpected(path) => write!(f, "unexpected: {}", path.display()),
+            Difference::Changed(path) => write!(f, "changed: {}", path.display()),
+        }
+    }
+}
+
+/// Asserts the test directory matches the golden directory.
+///
+/// A relative `golden_dir` is relative to the package root, from `CARGO_MANIFEST_DIR`, so
+/// usually this is something like `tests/golden/name`.  A golden directory which does not
+/// exist is treated as empty.
+///
+/// When [`UPDATE_ENV`] is set the golden directory is instead replaced by a copy of the
+/// test directory and this always succeeds.
+///
+/// # Panics
+///
+/// If the directories differ, listing all differences, or if they could not be read.
+///
+/// # Examples
+///
+/// ```no_run
+/// use testdir::{golden, testdir};
+///
+/// let dir = testdir!();
+/// std::fs::write(dir.join("output.txt"), "hello").unwrap();
+/// golden::assert_matches(&dir, "tests/golden/hello");
+/// ```
+#[track_caller]
+pub fn assert_matches(test_dir: impl AsRef<Path>, golden_dir: impl AsRef<Path>) {
+    let test_dir = test_dir.as_ref();
+    let golden_dir = golden_path(golden_dir.as_ref());
+    if update_enabled() {
+        if let Err(err) = update(test_dir, &golden_dir) {
+            panic!("Failed to update {}: {err:#}", golden_dir.display());
+        }
+        return;
+    }
+    let differences = match compare(test_dir, &golden_dir) {
+        Ok(differences) => differences,
+        Err(err) => panic!("Failed to compare {}: {err:#}", golden_dir.display()),
+    };
+    if !differences.is_empty() {
+        let list: Vec<String> = differences.iter().map(|diff| format!("  {diff}")).collect();
+        panic!(
+            "{} does not match golden directory {}:\n{}\nRun with {UPDATE_ENV}=1 to update it",
+            test_dir.display(),
+            golden_dir.display(),
+            list.join("\n"),
+        );
+    }
+}
+
+/// Compares the test directory with the golden directory, returning all differences.
+///
+/// Files are compared by their contents, symlinks are followed.  Directories only match
+/// directories, but empty directories are compared as well.  Unlike [`assert_matches`]
+/// relative paths are not resolved against the package root.
+pub fn compare(
+    test_dir: impl AsRef<Path>,
+    golden_dir: impl AsRef<Path>,
+) -> Result<Vec<Difference>, Error> {
+    let actual = collect(test_dir.as_ref())?;
+    let expected = match collect(golden_dir.as_ref()) {
+        Ok(entries) => entries,
+        Err(_) if !golden_dir.as_ref().exists() => BTreeMap::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let mut differences = Vec::new();
+    for (path, entry) in &expected {
+        match actual.get(path) {
+            None => differences.push(Difference::Missing(path.clone())),
+            Some(actual) if actual != entry => differences.push(Difference::Changed(path.clone())),
+            Some(_) => (),
+        }
+    }
+    for path in actual.keys() {
+        if !expected.contains_key(path) {
+            differences.push(Difference::Unexpected(path.clone()));
+        }
+    }
+    differences.sort_by(|a, b| difference_path(a).cmp(difference_path(b)));
+    Ok(differences)
+}
+
+/// Replaces the contents of the golden directory with a copy of the test directory.
+///
+/// Unlike [`assert_matches`] relative paths are not resolved against the package root.
+pub fn update(test_dir: impl AsRef<Path>, golden_dir: impl AsRef<Path>) -> Result<(), Error> {
+    let test_dir = test_dir.as_ref();
+    let golden_dir = golden_dir.as_ref();
+    if !test_dir.is_dir() {
+        return Err(anyhow!("Not a directory: {}", test_dir.display()).into());
+    }
+    match fs::remove_dir_all(golden_dir) {
+        Ok(()) => (),
+        Err(err) if err.kind() == ErrorKind::NotFound => (),
+        Err(err) => {
+            return Err(anyhow::Error::new(err)
+                .context(format!("Failed to remove {}", golden_dir.display()))
+                .into())
+        }
+    }
+    crate::export::copy_tree(test_dir, golden_dir, false)?;
+    Ok(())
+}
+
+/// Whether [`UPDATE_ENV`] enables updating golden directories.
+fn update_enabled() -> bool {
+    matches!(env::var(UPDATE_ENV), Ok(val) if !val.is_empty() && val != "0")
+}
+
+/// Resolves a relative golden directory against the package root.
+fn golden_path(path: &Path) -> PathBuf {
+    match env::var_os("CARGO_MANIFEST_DIR") {
+        Some(root) if path.is_relative() => Path::new(&root).join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn difference_path(diff: &Difference) -> &Path {
+    match diff {
+        Difference::Missing(path) | Difference::Unexpected(path) | Difference::Changed(path) => {
+            path
+        }
+    }
+}
+
+/// An entry of a directory tree, `None` for a directory or the contents of a file.
+type Entry = Option<Vec<u8>>;
+
+/// Reads all entries in the tree at `root`, keyed by their path relative to `root`.
+fn collect(root: &Path) -> Result<BTreeMap<PathBuf, Entry>> {
+    let mut entries = BTreeMap::new();
+    collect_into(root, Path::new(""), &mut entries)?;
+    Ok(entries)
+}
+
+fn collect_into(root: &Path, rel: &Path, entries: &mut BTreeMap<PathBuf, Entry>) -> Result<()> {
+    let dir = root.join(rel);
+    let readdir = dir
+        .read_dir()
+        .with_context(|| format!("Failed read_dir() on {}", dir.display()))?;
+    for dirent in readdir {
+        let dirent = dirent.with_context(|| format!("Failed read_dir() on {}", dir.display()))?;
+        let rel_path = rel.join(dirent.file_name());
+        let path = dirent.path();
+        if path.is_dir() {
+            entries.insert(rel_path.clone(), None);
+            collect_into(root, &rel_path, entries)?;
+        } else {
+            let content =
+                fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            entries.insert(rel_path, Some(content));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_tree(root: &Path, files: &[(&str, &str)]) {
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let temp = tempfile::tempdir().unwrap();
+        let actual = temp.path().join("actual");
+        let golden = temp.path().join("golden");
+        write_tree(
+            &actual,
+            &[("same.txt", "a"), ("sub/changed.txt", "new"), ("extra", "")],
+        );
+        write_tree(
+            &golden,
+            &[("same.txt", "a"), ("sub/changed.txt", "old"), ("gone", "")],
+        );
+        assert_eq!(
+            compare(&actual, &golden).unwrap(),
+            vec![
+                Difference::Unexpected(PathBuf::from("extra")),
+                Difference::Missing(PathBuf::from("gone")),
+                Difference::Changed(PathBuf::from("sub/changed.txt")),
+            ]
+        );
+
+        let diffs = compare(&actual, temp.path().join("nonexistent")).unwrap();
+        assert_eq!(diffs.len(), 4);
+        assert!(compare(temp.path().join("nonexistent"), &golden).is_err());
+    }
+
+    #[test]
+    fn test_update() {
+        let temp = tempfile::tempdir().unwrap();
+        let actual = temp.path().join("actual");
+        let golden = temp.path().join("golden");
+        write_tree(&actual, &[("out.txt", "new"), ("sub/more.txt", "more")]);
+        write_tree(&golden, &[("out.txt", "old"), ("stale.txt", "")]);
+        assert!(!compare(&actual, &golden).unwrap().is_empty());
+
+        update(&actual, &golden).unwrap();
+        assert_eq!(compare(&actual, &golden).unwrap(), vec![]);
+        assert!(!golden.join("stale.txt").exists());
+    }
+
+    #[test]
+    fn test_assert_matches() {
+        let temp = tempfile::tempdir().unwrap();
+        let actual = temp.path().join("actual");
+        let golden = temp.path().join("golden");
+        write_tree(&actual, &[("out.txt", "same")]);
+        write_tree(&golden, &[("out.txt", "same")]);
+        assert_matches(&actual, &golden);
+
+        fs::write(actual.join("out.txt"), "different").unwrap();
+        let result = std::panic::catch_unwind(|| assert_matches(&actual, &golden));
+        let err = result.unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.contains("changed: out.txt"), "{msg}");
+    }
+
+    #[test]
+    fn test_golden_path() {
+        let path = golden_path(Path::new("tests/golden/foo"));
+        assert_eq!(
+            path,
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/foo")
+        );
+        assert_eq!(golden_path(&path), path);
+    }
+}
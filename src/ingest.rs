@@ -0,0 +1,179 @@
+//! Ingesting test harness output into run directories, see [`ingest_libtest_json`].
+
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+
+use crate::outcome::record_outcome_at;
+use crate::{Error, Outcome, Run};
+
+/// The filename of the test results in the numbered directory: `test-results.json`.
+pub const TEST_RESULTS_FILE_NAME: &str = "test-results.json";
+
+/// The result of [`ingest_libtest_json`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IngestReport {
+    /// The tests which were matched to a test directory, with the relative path of it.
+    pub matched: Vec<(String, PathBuf)>,
+    /// The tests for which no single matching test directory was found.
+    pub unmatched: Vec<String>,
+}
+
+/// Merges the outcomes and durations from libtest JSON output into a run directory.
+///
+/// The `json` is the output of `cargo test -- -Z unstable-options --format json
+/// --report-time`, one JSON object per line.  Lines which are not JSON test events are
+/// ignored, so the output can be passed unfiltered.
+///
+/// Each finished test is matched to the test directory created by `testdir!()` for it,
+/// using the test name: the test directory's path must end in the components of the test
+/// name.  For matched tests the outcome is recorded in the test directory, like
+/// [`record_outcome_at`].  All tests, including unmatched ones, are merged into the
+/// [`TEST_RESULTS_FILE_NAME`] file of the run directory, which maps test names to their
+/// outcome, execution time in seconds and test directory.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::testdir;
+///
+/// let dir = testdir!("ingest_example/test_it");
+/// let run_dir = dir.parent().unwrap().parent().unwrap();
+/// let output = r#"{ "type": "test", "event": "ok", "name": "ingest_example::test_it" }"#;
+/// let report = testdir::ingest_libtest_json(run_dir, output.as_bytes()).unwrap();
+/// assert_eq!(report.matched.len(), 1);
+/// ```
+pub fn ingest_libtest_json(
+    run_dir: impl AsRef<Path>,
+    json: impl BufRead,
+) -> Result<IngestReport, Error> {
+    let run_dir = run_dir.as_ref();
+    let run = Run::load(run_dir)?;
+    let results_path = run_dir.join(TEST_RESULTS_FILE_NAME);
+    let mut results: Map<String, Value> = match fs::read_to_string(&results_path) {
+        Ok(data) => serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse {}", results_path.display()))?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Map::new(),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("Failed to read {}", results_path.display()))
+                .map_err(Error::from)
+        }
+    };
+    let mut report = IngestReport::default();
+    for line in json.lines() {
+        let line = line.context("Failed to read libtest output")?;
+        let Ok(event) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        if event["type"] != "test" {
+            continue;
+        }
+        let (Some(name), Some(outcome)) = (event["name"].as_str(), parse_event(&event)) else {
+            continue;
+        };
+        let dir = find_test_dir(&run, name);
+        match dir {
+            Some(ref dir) => {
+                record_outcome_at(run_dir.join(dir), outcome)?;
+                report.matched.push((name.to_string(), dir.clone()));
+            }
+            None => report.unmatched.push(name.to_string()),
+        }
+        let entry = json!({
+            "outcome": outcome.as_str(),
+            "exec_time": event.get("exec_time"),
+            "dir": dir,
+        });
+        results.insert(name.to_string(), entry);
+    }
+    let data = serde_json::to_vec_pretty(&results).context("Failed to serialize test results")?;
+    fs::write(&results_path, data)
+        .with_context(|| format!("Failed to write {}", results_path.display()))?;
+    Ok(report)
+}
+
+/// Returns the outcome of a finished test event, `None` for other events.
+fn parse_event(event: &Value) -> Option<Outcome> {
+    match event["event"].as_str()? {
+        "ok" => Some(Outcome::Passed),
+        "failed" | "timeout" => Some(Outcome::Failed),
+        "ignored" => Some(Outcome::Skipped),
+        _ => None,
+    }
+}
+
+/// Returns the only test directory of the run matching the test `name`.
+fn find_test_dir(run: &Run, name: &str) -> Option<PathBuf> {
+    let components: Vec<&str> = name.split("::").collect();
+    let mut matches = run.subdirs().iter().filter(|subdir| {
+        let path: Vec<_> = subdir.path.components().collect();
+        path.len() >= components.len()
+            && path[path.len() - components.len()..]
+                .iter()
+                .zip(&components)
+                .all(|(component, name)| {
+                    let component = component.as_os_str().to_string_lossy();
+                    component == *name || component.strip_prefix("r#") == Some(*name)
+                })
+    });
+    let found = matches.next()?;
+    match matches.next() {
+        Some(_) => None,
+        None => Some(found.path.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ingest() {
+        let parent = tempfile::tempdir().unwrap();
+        let run_dir = parent.path().join("testdir-0");
+        fs::create_dir_all(run_dir.join("krate/tests/test_ok")).unwrap();
+        fs::create_dir_all(run_dir.join("krate/tests/test_fail")).unwrap();
+        fs::create_dir_all(run_dir.join("krate/other/r#test_fail")).unwrap();
+        let output = r#"
+running 3 tests
+{ "type": "suite", "event": "started", "test_count": 3 }
+{ "type": "test", "event": "started", "name": "tests::test_ok" }
+{ "type": "test", "name": "tests::test_ok", "event": "ok", "exec_time": 0.5 }
+{ "type": "test", "name": "tests::test_fail", "event": "failed", "stdout": "boom" }
+{ "type": "test", "name": "test_fail", "event": "failed" }
+{ "type": "test", "name": "tests::test_none", "event": "ignored" }
+"#;
+
+        let report = ingest_libtest_json(&run_dir, output.as_bytes()).unwrap();
+        assert_eq!(
+            report.matched,
+            vec![
+                (
+                    String::from("tests::test_ok"),
+                    PathBuf::from("krate/tests/test_ok")
+                ),
+                (
+                    String::from("tests::test_fail"),
+                    PathBuf::from("krate/tests/test_fail")
+                ),
+            ]
+        );
+        assert_eq!(report.unmatched, vec!["test_fail", "tests::test_none"]);
+
+        let run = Run::load(&run_dir).unwrap();
+        let outcomes: Vec<_> = run.subdirs().iter().map(|sub| sub.outcome).collect();
+        assert_eq!(
+            outcomes,
+            [None, Some(Outcome::Failed), Some(Outcome::Passed)]
+        );
+        let results: Value =
+            serde_json::from_slice(&fs::read(run_dir.join(TEST_RESULTS_FILE_NAME)).unwrap())
+                .unwrap();
+        assert_eq!(results["tests::test_ok"]["exec_time"], 0.5);
+        assert_eq!(results["tests::test_none"]["outcome"], "skipped");
+    }
+}
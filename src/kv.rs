@@ -0,0 +1,188 @@
+//! The per-run key-value store.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+
+use crate::lock::FileLock;
+use crate::Error;
+
+/// The filename of the key-value store in the numbered directory: `run-kv.json`.
+pub(crate) const RUN_KV_FILE_NAME: &str = "run-kv.json";
+
+/// How long to wait for the lock of the store.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns the [`RunKv`] store of the global [`NumberedDir`].
+///
+/// All test processes of a single `cargo test` run share the same global [`NumberedDir`]
+/// and thus the same store.  This can be used to share dynamically allocated resources
+/// between test binaries, e.g. ports or container IDs.
+///
+/// Be aware that you should have called [`init_testdir!`] before calling this, like for
+/// [`with_testdir`].
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{init_testdir, run_kv};
+///
+/// init_testdir!();
+/// let port = run_kv().get_or_set_with("db_port", || String::from("5432")).unwrap();
+/// assert_eq!(run_kv().get("db_port").unwrap(), Some(port));
+/// ```
+///
+/// [`NumberedDir`]: crate::NumberedDir
+/// [`init_testdir!`]: crate::init_testdir
+/// [`with_testdir`]: crate::with_testdir
+pub fn run_kv() -> RunKv {
+    crate::with_testdir(|dir| RunKv::open(dir.path().join(RUN_KV_FILE_NAME)))
+}
+
+/// A small persistent key-value store shared between processes.
+///
+/// The store is a JSON file holding an object of string values.  Each operation locks the
+/// file, so concurrent access from several threads or processes is safe.  Use [`run_kv`]
+/// to get the store of the current run.
+#[derive(Debug, Clone)]
+pub struct RunKv {
+    path: PathBuf,
+}
+
+impl RunKv {
+    /// Opens the store at `path`, it is created on the first write.
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Returns the path of the JSON file backing this store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the value of `key`.
+    pub fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        let _lock = self.lock()?;
+        Ok(self.read()?.remove(key).and_then(into_string))
+    }
+
+    /// Sets `key` to `value`, returning the previous value.
+    pub fn set(&self, key: &str, value: impl Into<String>) -> Result<Option<String>, Error> {
+        let _lock = self.lock()?;
+        let mut map = self.read()?;
+        let prev = map.insert(key.to_string(), Value::String(value.into()));
+        self.write(&map)?;
+        Ok(prev.and_then(into_string))
+    }
+
+    /// Returns all keys with their values.
+    pub fn entries(&self) -> Result<BTreeMap<String, String>, Error> {
+        let _lock = self.lock()?;
+        Ok(self
+            .read()?
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, into_string(value)?)))
+            .collect())
+    }
+
+    /// Removes `key`, returning its value.
+    pub fn remove(&self, key: &str) -> Result<Option<String>, Error> {
+        let _lock = self.lock()?;
+        let mut map = self.read()?;
+        let prev = map.remove(key);
+        if prev.is_some() {
+            self.write(&map)?;
+        }
+        Ok(prev.and_then(into_string))
+    }
+
+    /// Returns the value of `key`, setting it using `func` if it is not yet set.
+    ///
+    /// This is atomic, across processes only one caller will set the value.
+    pub fn get_or_set_with(
+        &self,
+        key: &str,
+        func: impl FnOnce() -> String,
+    ) -> Result<String, Error> {
+        let _lock = self.lock()?;
+        let mut map = self.read()?;
+        if let Some(value) = map.get(key).and_then(Value::as_str) {
+            return Ok(value.to_string());
+        }
+        let value = func();
+        map.insert(key.to_string(), Value::String(value.clone()));
+        self.write(&map)?;
+        Ok(value)
+    }
+
+    fn lock(&self) -> Result<FileLock> {
+        let mut lock_path = self.path.clone().into_os_string();
+        lock_path.push(".lock");
+        FileLock::acquire(lock_path, LOCK_TIMEOUT)
+    }
+
+    fn read(&self) -> Result<Map<String, Value>> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Invalid key-value store: {}", self.path.display())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Map::new()),
+            Err(err) => Err(err).with_context(|| format!("Failed to read {}", self.path.display())),
+        }
+    }
+
+    fn write(&self, map: &Map<String, Value>) -> Result<()> {
+        let content = serde_json::to_string_pretty(map)?;
+        fs::write(&self.path, content)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+fn into_string(value: Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_run_kv() {
+        let dir = tempfile::tempdir().unwrap();
+        let kv = RunKv::open(dir.path().join("kv.json"));
+        assert_eq!(kv.get("key").unwrap(), None);
+        assert_eq!(kv.set("key", "value").unwrap(), None);
+        assert_eq!(kv.get("key").unwrap(), Some(String::from("value")));
+        assert_eq!(kv.set("key", "other").unwrap(), Some(String::from("value")));
+        assert_eq!(
+            kv.entries().unwrap(),
+            BTreeMap::from([(String::from("key"), String::from("other"))])
+        );
+        assert_eq!(kv.remove("key").unwrap(), Some(String::from("other")));
+        assert_eq!(kv.get("key").unwrap(), None);
+    }
+
+    #[test]
+    fn test_run_kv_get_or_set_with() {
+        let dir = tempfile::tempdir().unwrap();
+        let kv = Arc::new(RunKv::open(dir.path().join("kv.json")));
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let kv = kv.clone();
+                thread::spawn(move || kv.get_or_set_with("port", || i.to_string()).unwrap())
+            })
+            .collect();
+        let values: Vec<String> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert!(values.iter().all(|v| *v == values[0]));
+    }
+}
@@ -53,18 +53,129 @@
 #![warn(missing_docs, missing_debug_implementations, clippy::all)]
 
 use std::num::NonZeroU8;
+use std::path::PathBuf;
 
+use anyhow::Result;
 use once_cell::sync::OnceCell;
 
+#[cfg(feature = "tar")]
+mod archive;
+mod backend;
+pub mod build_support;
+mod build_system;
 mod builder;
+#[cfg(feature = "cap-std")]
+mod cap;
+mod checkpoint;
+mod child_env;
+mod cleanup;
+#[cfg(any(feature = "assert_cmd", feature = "duct"))]
+pub mod cmd_ext;
+mod compile_test;
+mod created;
+mod error;
+mod exclusive;
+mod export;
+mod fixtures;
+mod fs_caps;
+mod gc;
+pub mod golden;
+mod ingest;
+mod kv;
+mod lock;
+#[cfg(feature = "log")]
+pub mod logger;
 mod macros;
+#[cfg(feature = "backend")]
+mod memory;
+#[cfg(feature = "mimic")]
+pub mod mimic;
+mod named;
 mod numbered_dir;
+mod outcome;
+#[cfg(feature = "panic-hook")]
+mod panic_hook;
+mod path_mapper;
+#[cfg(unix)]
+mod permissions;
+mod portable;
+mod process;
+mod protect;
+mod quota;
+mod retention;
+mod retry;
+mod root;
+mod run;
+mod scoped;
+mod session;
+mod simplify;
+#[cfg(unix)]
+mod socket;
+mod summary;
+mod target_dir;
+mod test_dir;
+mod vcs;
+mod wait;
+mod workspace;
 
 #[doc(hidden)]
 pub mod private;
 
-pub use builder::NumberedDirBuilder;
-pub use numbered_dir::{NumberedDir, NumberedDirIter};
+#[cfg(feature = "tar")]
+pub use archive::Compressor;
+#[cfg(feature = "gzip")]
+pub use archive::Gzip;
+#[cfg(feature = "zstd")]
+pub use archive::Zstd;
+#[cfg(feature = "backend")]
+pub use backend::{DirBackend, StdBackend};
+pub use build_system::BuildSystemEnv;
+pub use builder::{CreatePlan, NumberedDirBuilder};
+#[cfg(feature = "cap-std")]
+pub use cap::open_cap_dir;
+pub use cargo_metadata::MetadataCommand;
+pub use child_env::{from_env, EnvDirs, RUN_DIR_ENV, TEST_DIR_ENV};
+pub use cleanup::{CleanupError, CleanupReport};
+pub use compile_test::CompileTestGuard;
+pub use created::{created_dirs, CreatedDir, Scope};
+pub use error::Error;
+pub use exclusive::{exclusive, exclusive_timeout, ExclusiveGuard, EXCLUSIVE_TIMEOUT_DEFAULT};
+pub use export::{ExportLayout, EXPORT_MANIFEST_FILE_NAME};
+pub use fs_caps::{fs_caps, FsCapabilities};
+pub use gc::{compact, gc_root};
+pub use ingest::{ingest_libtest_json, IngestReport, TEST_RESULTS_FILE_NAME};
+pub use kv::{run_kv, RunKv};
+#[cfg(feature = "backend")]
+pub use memory::MemoryBackend;
+pub use named::{init_named, named_testdir, with_named_testdir};
+pub use numbered_dir::{Collision, NumberedDir, NumberedDirIter, SubdirLimits};
+pub use outcome::{record_outcome, record_outcome_at, Outcome, OutcomeGuard};
+#[cfg(feature = "panic-hook")]
+pub use panic_hook::{install_panic_hook, PANIC_FILE_NAME};
+pub use path_mapper::{map_module_path, map_module_path_unraw, PathMapper};
+#[cfg(unix)]
+pub use permissions::{PermissionGuard, PermissionMatrix};
+pub use portable::{PortableNames, PATH_NAMES_FILE_NAME};
+pub use protect::ProtectGuard;
+pub use quota::{dir_size, QuotaAction, QuotaGuard, SizeQuota};
+pub use retention::{Retention, RetentionMap};
+pub use retry::RetryStrategy;
+pub use root::{
+    in_container, is_tmpfs, ramdisk_dir, ContainerAware, Ramdisk, RootStrategy, SystemTemp,
+    XdgCache, XdgRuntime,
+};
+pub use run::{Run, RunSubdir};
+pub use scoped::scoped_override;
+pub use session::{TestSession, SESSION_MANIFEST_FILE_NAME};
+pub use summary::{print_summary_at_exit, run_summary, RunSummary, SUMMARY_ENV};
+pub use target_dir::{
+    target_dir_source, MetadataHook, TargetDirSource, METADATA_TIMEOUT_DEFAULT,
+    METADATA_TIMEOUT_ENV,
+};
+pub use test_dir::{CapturedRun, ChildPath, TestDir};
+pub use vcs::VcsInfo;
+pub use wait::{wait_for_file_containing, wait_for_path};
+pub use workspace::workspace_crates;
 
 /// Default to build the `root` for [`NumberedDirBuilder`] and [`testdir!`] from: `testdir`.
 pub const ROOT_DEFAULT: &str = "testdir";
@@ -73,11 +184,102 @@ pub const ROOT_DEFAULT: &str = "testdir";
 /// [`testdir!`]: `8`.
 pub const KEEP_DEFAULT: Option<NonZeroU8> = NonZeroU8::new(8);
 
+/// The environment variable controlling the `-current` symlink of the global
+/// [`NumberedDir`]: `TESTDIR_CURRENT_SYMLINK`.
+///
+/// Setting this to `0` disables the symlink, setting it to any other non-empty value
+/// enables it.  This takes precedence over `init_testdir!(current_symlink = ...)`, see
+/// [`NumberedDirBuilder::current_symlink`].
+pub const CURRENT_SYMLINK_ENV: &str = "TESTDIR_CURRENT_SYMLINK";
+
+/// The environment variable enabling Miri mode: `TESTDIR_MIRI`.
+///
+/// Miri does not support inspecting processes and capturing backtraces is very slow, so
+/// in Miri mode [`testdir!`] does not look for the parent Cargo process, test names are only
+/// derived from thread names and the global [`NumberedDir`] is created in a fixed
+/// `testdir-miri` directory in [`std::env::temp_dir`] unless the target directory was
+/// captured using [`build_support`].  Without a Cargo process each test binary uses a new
+/// [`NumberedDir`].  Miri needs `-Zmiri-disable-isolation` to access the filesystem.
+///
+/// Miri mode is always enabled when compiled under `cfg(miri)`.  Setting this to a
+/// non-empty value other than `0` enables it as well.
+pub const MIRI_ENV: &str = "TESTDIR_MIRI";
+
 /// **Private** The global [`NumberedDir`] instance used by [`with_testdir`].
 ///
 /// Do not use this directly, use [`init_testdir!`] to initialise this.
 #[doc(hidden)]
-pub static TESTDIR: OnceCell<NumberedDir> = OnceCell::new();
+pub static TESTDIR: private::GlobalTestdir = private::GlobalTestdir::new();
+
+/// **Private** The global [`PathMapper`] used by [`testdir!`].
+///
+/// Do not use this directly, use `init_testdir!(path_mapper = ...)` to initialise this.
+#[doc(hidden)]
+pub static PATH_MAPPER: OnceCell<PathMapper> = OnceCell::new();
+
+/// **Private** The hook customising `cargo metadata` used by [`init_testdir!`].
+///
+/// Do not use this directly, use `init_testdir!(metadata_command = ...)` to initialise this.
+#[doc(hidden)]
+pub static METADATA_HOOK: OnceCell<MetadataHook> = OnceCell::new();
+
+/// **Private** The builder set by [`override_builder_for_tests`].
+static BUILDER_OVERRIDE: OnceCell<NumberedDirBuilder> = OnceCell::new();
+
+/// Redirects the global [`NumberedDir`] to be created using `builder`.
+///
+/// Crates embedding testdir in their own test support code can use this in their unit
+/// tests to create the global [`NumberedDir`] in a temporary location instead of the real
+/// target directory.  Once set, [`init_testdir!`], [`testdir!`] and [`with_testdir`] use
+/// this builder instead of their normal configuration.
+///
+/// This is only effective before the global [`NumberedDir`] is first used, and only the
+/// first override is used.  Returns `false` if the override is not effective.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{testdir, NumberedDirBuilder};
+///
+/// let parent = std::env::temp_dir().join("testdir-override-example");
+/// let mut builder = NumberedDirBuilder::new(String::from("support"));
+/// builder.set_parent(parent.clone());
+/// assert!(testdir::override_builder_for_tests(builder));
+/// assert!(testdir!().starts_with(&parent));
+/// ```
+pub fn override_builder_for_tests(builder: NumberedDirBuilder) -> bool {
+    TESTDIR.get().is_none() && BUILDER_OVERRIDE.set(builder).is_ok()
+}
+
+/// Initialises the global [`NumberedDir`] instance using a [`NumberedDirBuilder`].
+///
+/// This is an alternative to [`init_testdir!`] for binaries with a custom `main()`, e.g.
+/// custom test harnesses, fuzzers or benchmarks, which want full control over how the
+/// global [`NumberedDir`] used by [`testdir!`] and [`with_testdir`] is created.  It must be
+/// called before any other use of the global [`NumberedDir`].
+///
+/// If the global [`NumberedDir`] is already initialised the builder is not used and the
+/// existing instance is returned.
+///
+/// Unlike [`init_testdir!`] this does not write a Cargo PID file into the directory, so
+/// [`NumberedDirBuilder::reusefn`] is not configured automatically.
+///
+/// # Examples
+///
+/// ```no_run
+/// use testdir::{testdir, NumberedDirBuilder};
+///
+/// fn main() {
+///     let mut builder = NumberedDirBuilder::new(String::from("bench"));
+///     builder.root("my-bench-root");
+///     testdir::init_with(builder).expect("failed to init testdir");
+///
+///     let dir = testdir!("some/bench");
+/// }
+/// ```
+pub fn init_with(builder: NumberedDirBuilder) -> Result<&'static NumberedDir, Error> {
+    TESTDIR.get_or_try_init(|| builder.create())
+}
 
 /// Executes a function passing the global [`NumberedDir`] instance.
 ///
@@ -88,6 +290,8 @@ pub static TESTDIR: OnceCell<NumberedDir> = OnceCell::new();
 /// Be aware that you should have called [`init_testdir!`] before calling this so that the
 /// global testdir was initialised correctly.  Otherwise you will get a dummy testdir name.
 ///
+/// If you only need the path of the global [`NumberedDir`] use [`testdir_run_root!`].
+///
 /// # Examples
 ///
 /// ```
@@ -102,12 +306,144 @@ pub fn with_testdir<F, R>(func: F) -> R
 where
     F: FnOnce(&NumberedDir) -> R,
 {
+    if let Some(test_dir) = scoped::current() {
+        return func(&test_dir);
+    }
     let test_dir = TESTDIR.get_or_init(|| {
+        if let Some(testdir) = private::create_overridden() {
+            return testdir.expect("Failed to create testdir");
+        }
         let mut builder = NumberedDirBuilder::new(String::from("init_testdir-not-called"));
         builder.reusefn(private::reuse_cargo);
+        builder.current_symlink(private::current_symlink());
         let testdir = builder.create().expect("Failed to create testdir");
-        private::create_cargo_pid_file(testdir.path());
+        private::create_cargo_pid_file(&testdir);
         testdir
     });
     func(test_dir)
 }
+
+/// Executes a function passing the global [`NumberedDir`] instance, if initialised.
+///
+/// Unlike [`with_testdir`] this does not fall back to a dummy `init_testdir-not-called`
+/// directory when [`init_testdir!`] was not yet called, instead
+/// [`Error::NotInitialized`] is returned.  A builder set using
+/// [`override_builder_for_tests`] is still used to create the global [`NumberedDir`].
+///
+/// # Examples
+///
+/// ```
+/// use testdir::init_testdir;
+///
+/// assert!(testdir::try_with_testdir(|dir| dir.path().to_path_buf()).is_err());
+/// init_testdir!();
+/// let path = testdir::try_with_testdir(|dir| dir.path().to_path_buf()).unwrap();
+/// assert!(path.is_dir());
+/// ```
+pub fn try_with_testdir<F, R>(func: F) -> Result<R, Error>
+where
+    F: FnOnce(&NumberedDir) -> R,
+{
+    if let Some(test_dir) = scoped::current() {
+        return Ok(func(&test_dir));
+    }
+    let test_dir = TESTDIR.get_or_try_init(|| match private::create_overridden() {
+        Some(testdir) => testdir,
+        None => Err(Error::NotInitialized { name: None }),
+    })?;
+    Ok(func(test_dir))
+}
+
+/// Returns the path of the global [`NumberedDir`], if initialised.
+///
+/// This is a shorthand for getting the path using [`with_testdir`], e.g. for debugging
+/// prints and helper crates, but never creates anything.  `None` is returned if the global
+/// [`NumberedDir`] was not yet initialised, e.g. by [`init_testdir!`] or [`testdir!`].  A
+/// [`scoped_override`] active on this thread is returned instead.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::testdir;
+///
+/// let dir = testdir!();
+/// let run_dir = testdir::current_path().unwrap();
+/// assert!(dir.starts_with(run_dir));
+/// ```
+pub fn current_path() -> Option<PathBuf> {
+    if let Some(test_dir) = scoped::current() {
+        return Some(test_dir.path().to_path_buf());
+    }
+    TESTDIR.get().map(|numdir| numdir.path().to_path_buf())
+}
+
+/// Returns the path of the test directory of the currently running test, if created.
+///
+/// This is the directory created by `testdir!()` or `testdir!(TestScope)` on the current
+/// thread, which is the test's own thread when using the standard test harness.  `None` is
+/// returned if the current test did not create one.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::testdir;
+///
+/// let dir = testdir!();
+/// assert_eq!(testdir::current_test_path(), Some(dir));
+/// ```
+pub fn current_test_path() -> Option<PathBuf> {
+    std::thread::current()
+        .name()
+        .and_then(created::test_dir_for_thread)
+}
+
+/// Resets the global [`NumberedDir`] instance, so that it is initialised again.
+///
+/// Frameworks running several logical test sessions in one long-lived process can use
+/// this to start a new session, the next use of [`testdir!`] or [`with_testdir`] creates
+/// the global [`NumberedDir`] again, just like on the first use.  Alternatively
+/// [`init_with`] can be called to initialise it using a different builder.  Note that
+/// [`init_testdir!`] re-uses the numbered directory of the same Cargo invocation, so
+/// a different builder is needed to get a new one.
+///
+/// The previous [`NumberedDir`] is returned, if it was initialised.  It is not removed
+/// and remains valid.  Calling this while the global [`NumberedDir`] is being initialised,
+/// e.g. from a builder hook, returns `None` and does not affect the initialisation.
+///
+/// This requires the `testing-internals` feature.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{init_testdir, NumberedDirBuilder};
+///
+/// let first = init_testdir!();
+/// assert_eq!(testdir::reset_testdir(), Some(first));
+/// assert!(!testdir::is_initialized());
+///
+/// let mut builder = NumberedDirBuilder::new(String::from("session"));
+/// builder.set_parent(first.path().parent().unwrap().to_path_buf());
+/// let second = testdir::init_with(builder).unwrap();
+/// assert_ne!(first, second);
+/// ```
+#[cfg(feature = "testing-internals")]
+pub fn reset_testdir() -> Option<&'static NumberedDir> {
+    TESTDIR.reset()
+}
+
+/// Returns whether the global [`NumberedDir`] instance is initialised.
+///
+/// This is the case once [`init_testdir!`], [`init_with`] or any of the functions and
+/// macros using the global [`NumberedDir`] were called.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::init_testdir;
+///
+/// init_testdir!();
+/// assert!(testdir::is_initialized());
+/// ```
+pub fn is_initialized() -> bool {
+    TESTDIR.get().is_some()
+}
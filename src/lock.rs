@@ -0,0 +1,141 @@
+//! A simple cross-process file lock.
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Error, Result};
+
+/// The delay between attempts to acquire the lock.
+const RETRY_DELAY: Duration = Duration::from_millis(5);
+
+/// Locks older than this are assumed to be left behind by a crashed process.
+const STALE_AFTER: Duration = Duration::from_secs(30);
+
+/// A lock held by exclusively creating a lock file.
+///
+/// The lock is released by removing the lock file when this is dropped.  This is portable
+/// across platforms and works between threads as well as processes.  Lock files which are
+/// older than a while are assumed to be left behind by crashed processes and are broken.
+#[derive(Debug)]
+pub(crate) struct FileLock {
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Acquires the lock at `path`, blocking until it is available or `timeout` expires.
+    pub(crate) fn acquire(path: impl Into<PathBuf>, timeout: Duration) -> Result<Self> {
+        Self::acquire_with(path.into(), timeout, is_stale)
+    }
+
+    /// Acquires a lock which may be held for a long time, like [`FileLock::acquire`].
+    ///
+    /// Instead of assuming old lock files are left behind, the lock is only broken once the
+    /// process which created it no longer exists.
+    pub(crate) fn acquire_long(path: impl Into<PathBuf>, timeout: Duration) -> Result<Self> {
+        Self::acquire_with(path.into(), timeout, is_orphaned)
+    }
+
+    fn acquire_with(path: PathBuf, timeout: Duration, is_stale: fn(&Path) -> bool) -> Result<Self> {
+        let start = SystemTime::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    write!(file, "{}", std::process::id()).ok();
+                    return Ok(Self { path });
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    if is_stale(&path) {
+                        fs::remove_file(&path).ok();
+                        continue;
+                    }
+                }
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("Failed to create lock {}", path.display()))
+                }
+            }
+            if start.elapsed().unwrap_or_default() > timeout {
+                return Err(Error::msg(format!(
+                    "Timeout acquiring lock {}",
+                    path.display()
+                )));
+            }
+            thread::sleep(RETRY_DELAY);
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Returns whether the process which created the lock file no longer exists.
+///
+/// Where processes can not be inspected, or the lock file does not contain a process ID,
+/// this falls back to [`is_stale`].
+fn is_orphaned(path: &Path) -> bool {
+    if crate::private::is_restricted() {
+        return is_stale(path);
+    }
+    match fs::read_to_string(path)
+        .ok()
+        .and_then(|pid| pid.parse().ok())
+    {
+        Some(pid) => crate::process::exe_path(pid).is_none(),
+        None => is_stale(path),
+    }
+}
+
+/// Returns whether the lock file was left behind a long time ago.
+fn is_stale(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age > STALE_AFTER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lock");
+        let lock = FileLock::acquire(&path, Duration::from_secs(1)).unwrap();
+        assert!(path.is_file());
+        assert!(FileLock::acquire(&path, Duration::from_millis(10)).is_err());
+        drop(lock);
+        assert!(!path.exists());
+        assert!(FileLock::acquire(&path, Duration::from_millis(10)).is_ok());
+    }
+
+    #[test]
+    fn test_lock_long() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lock");
+        let lock = FileLock::acquire_long(&path, Duration::from_secs(1)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+        assert!(FileLock::acquire_long(&path, Duration::from_millis(10)).is_err());
+        drop(lock);
+
+        let mut child = std::process::Command::new("cargo")
+            .arg("--version")
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        fs::write(&path, pid.to_string()).unwrap();
+        assert!(FileLock::acquire_long(&path, Duration::from_millis(10)).is_ok());
+    }
+}
@@ -0,0 +1,142 @@
+//! A [`log`] logger writing into the test directories.
+//!
+//! This requires the `log` feature.  Once installed using [`init`] each log record is
+//! appended to a `test.log` file in the test directory of the test which emitted it, as
+//! created by `testdir!()`.  The test is identified by the name of the thread emitting the
+//! record, which is how the standard test harness runs each test.  Records emitted before
+//! the test created its directory, or from outside tests, are written to stderr.
+//!
+//! ```
+//! use testdir::testdir;
+//!
+//! testdir::logger::init(log::LevelFilter::Info).ok();
+//! let dir = testdir!();
+//! log::info!("hello");
+//! assert!(dir.join("test.log").is_file());
+//! ```
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::created::test_dir_for_thread;
+
+/// The name of the log file in the test directory: `test.log`.
+pub const LOG_FILE_NAME: &str = "test.log";
+
+/// Installs the [`TestDirLogger`] as global logger.
+///
+/// Fails if another logger was already installed.
+pub fn init(level: LevelFilter) -> Result<(), SetLoggerError> {
+    log::set_boxed_logger(Box::new(TestDirLogger::new(level)))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// A logger appending the records of each test to a file in its test directory.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug)]
+pub struct TestDirLogger {
+    level: LevelFilter,
+    files: Mutex<HashMap<PathBuf, File>>,
+}
+
+impl TestDirLogger {
+    /// Creates a new logger, logging records up to `level`.
+    ///
+    /// Usually [`init`] is used instead, but this can be used to wrap the logger.
+    pub fn new(level: LevelFilter) -> Self {
+        Self {
+            level,
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Writes to the log file of the test directory, returns `false` if not possible.
+    fn write_test_log(&self, line: &str) -> bool {
+        let Some(dir) = std::thread::current().name().and_then(test_dir_for_thread) else {
+            return false;
+        };
+        let Ok(mut files) = self.files.lock() else {
+            return false;
+        };
+        let path = dir.join(LOG_FILE_NAME);
+        if !files.contains_key(&path) {
+            match OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(file) => files.insert(path.clone(), file),
+                Err(_) => return false,
+            };
+        }
+        files
+            .get_mut(&path)
+            .is_some_and(|file| file.write_all(line.as_bytes()).is_ok())
+    }
+}
+
+impl Log for TestDirLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "[{} {}] {}\n",
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if !self.write_test_log(&line) {
+            eprint!("{line}");
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut files) = self.files.lock() {
+            for file in files.values_mut() {
+                file.flush().ok();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::created;
+    use crate::Scope;
+
+    #[test]
+    fn test_logger() {
+        let dir = tempfile::tempdir().unwrap();
+        created::record(dir.path(), Scope::Test);
+
+        let logger = TestDirLogger::new(LevelFilter::Info);
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Info)
+                .target("mytarget")
+                .args(format_args!("hello"))
+                .build(),
+        );
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Debug)
+                .args(format_args!("ignored"))
+                .build(),
+        );
+        logger.flush();
+
+        let content = fs::read_to_string(dir.path().join(LOG_FILE_NAME)).unwrap();
+        assert_eq!(content, "[INFO mytarget] hello\n");
+    }
+}
@@ -35,10 +35,50 @@
 ///   something which implements ``AsRef<Path>``, e.g. a simple `"sub/dir"` can be used or
 ///   something more advanced evaluating to a path, usually [`Path`] or [`PathBuf`].
 ///
+/// All of these can be prefixed by `named = "name",` to create the directory in a named
+/// global [`NumberedDir`] initialised using [`init_named`] instead of the default one, e.g.
+/// `testdir!(named = "scratch")` or `testdir!(named = "scratch", "sub/dir")`.
+///
+/// Further options can be given before all of these:
+///
+/// * `clean = true`: If the directory already exists, e.g. because it is used by several
+///   invocations in the same test, its contents are removed.  The directory is always
+///   empty when returned, e.g. `testdir!(clean = true, ModuleScope)`.
+///
+/// * `create = false`: The path is returned without creating the directory, only the
+///   numbered directory itself is created.  This is useful for testing code which should
+///   create the directory itself, e.g. `testdir!(create = false, "new/dir")`.  An already
+///   existing directory is not removed, combine this with `clean = true` to empty it.
+///
+/// * `strict = false`: By default the path may not contain `..` components or symlinks
+///   leading outside of the numbered directory, see [`NumberedDir::create_subdir_strict`].
+///   This disables these checks, e.g. `testdir!(strict = false, "../shared")`.
+///
+/// * `canonicalize = true`: The returned path is canonicalized, so it is absolute and
+///   contains no symlinks, e.g. `testdir!(canonicalize = true)`.  This also works with
+///   `create = false`.
+///
+/// * `strip_unc_prefix = true`: The Windows verbatim `\\?\` prefix, as returned by
+///   canonicalizing, is removed from the returned path if it means the same without it.
+///   See [`NumberedDirBuilder::strip_unc_prefix`].
+///
+/// * `normalize_private = true`: A `/private/var`, `/private/tmp` or `/private/etc`
+///   prefix, as returned by canonicalizing on macOS, is replaced by `/var`, `/tmp` or
+///   `/etc`.  See [`NumberedDirBuilder::normalize_private`].
+///
+/// * `attempt = n`: The test-scoped directory gets an `attempt-n` subdirectory, so all
+///   attempts of a retried test are kept, e.g. `testdir!(attempt = 2)`.  Test runners
+///   retrying flaky tests can be detected instead using `init_testdir!(per_attempt = true)`.
+///   Other scopes are not affected.
+///
+/// Snapshot tests embedding paths usually want all three, e.g.
+/// `testdir!(canonicalize = true, strip_unc_prefix = true, normalize_private = true)`.
+///
 /// # Panics
 ///
 /// If there is any problem with creating the directories or cleaning up old ones this will
-/// panic.
+/// panic.  Using a named [`NumberedDir`] which was not initialised also panics.  Use
+/// [`try_testdir`] to handle these errors instead.
 ///
 /// # Examples
 ///
@@ -75,36 +115,356 @@
 /// [lazy_static]: https://docs.rs/lazy_static
 /// [`NumberedDir`]: crate::NumberedDir
 /// [`PathBuf`]: std::path::PathBuf
+/// [`init_named`]: crate::init_named
+/// [`try_testdir`]: crate::try_testdir
+/// [`NumberedDir::create_subdir_strict`]: crate::NumberedDir::create_subdir_strict
+/// [`NumberedDirBuilder::strip_unc_prefix`]: crate::NumberedDirBuilder::strip_unc_prefix
+/// [`NumberedDirBuilder::normalize_private`]: crate::NumberedDirBuilder::normalize_private
 #[macro_export]
 macro_rules! testdir {
-    () => {
-        $crate::testdir!(TestScope)
+    // Internal rules, receiving the `SubdirOptions` to use.
+    ( @opts $opts:expr; ) => {
+        $crate::testdir!(@opts $opts; TestScope)
     };
-    ( TestScope ) => {{
-        $crate::init_testdir!();
+    ( @opts $opts:expr; clean = $clean:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $opts.clean($clean); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; create = $create:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $opts.create($create); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; strict = $strict:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $opts.strict($strict); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; canonicalize = $canonicalize:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $opts.canonicalize($canonicalize); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; strip_unc_prefix = $strip_unc_prefix:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $opts.strip_unc_prefix($strip_unc_prefix); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; normalize_private = $normalize_private:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $opts.normalize_private($normalize_private); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; attempt = $attempt:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $opts.attempt($attempt); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; TestScope ) => {{
+        if !$crate::private::is_overridden() {
+            $crate::init_testdir!();
+        }
         let module_path = ::std::module_path!();
         let test_name = $crate::private::extract_test_name(&module_path);
-        let subdir_path = ::std::path::Path::new(&module_path.replace("::", "/")).join(&test_name);
-        $crate::with_testdir(move |tdir| {
-            tdir.create_subdir(subdir_path)
+        let opts = $opts;
+        let subdir_path = opts.attempt_dir($crate::private::module_dir(&module_path).join(&test_name));
+        let path = $crate::with_testdir(move |tdir| {
+            opts.subdir(tdir, subdir_path)
                 .expect("Failed to create test-scoped sub-directory")
-        })
+        });
+        $crate::private::record_created(path, $crate::Scope::Test)
     }};
-    ( ModuleScope ) => {{
-        $crate::init_testdir!();
+    ( @opts $opts:expr; ModuleScope ) => {{
+        if !$crate::private::is_overridden() {
+            $crate::init_testdir!();
+        }
         let module_path = ::std::module_path!();
-        let subdir_path = ::std::path::Path::new(&module_path.replace("::", "/")).join("mod");
-        $crate::with_testdir(move |tdir| {
-            tdir.create_subdir(subdir_path)
+        let subdir_path = $crate::private::module_dir(&module_path).join("mod");
+        let opts = $opts;
+        let path = $crate::with_testdir(move |tdir| {
+            opts.subdir(tdir, subdir_path)
                 .expect("Failed to create module-scoped sub-directory")
-        })
+        });
+        $crate::private::record_created(path, $crate::Scope::Module)
     }};
-    ( $e:expr ) => {{
-        $crate::init_testdir!();
-        $crate::with_testdir(move |tdir| {
-            tdir.create_subdir($e)
+    ( @opts $opts:expr; named = $name:expr ) => {
+        $crate::testdir!(@opts $opts; named = $name, TestScope)
+    };
+    ( @opts $opts:expr; named = $name:expr, TestScope ) => {{
+        let module_path = ::std::module_path!();
+        let test_name = $crate::private::extract_test_name(&module_path);
+        let opts = $opts;
+        let subdir_path = opts.attempt_dir($crate::private::module_dir(&module_path).join(&test_name));
+        let path = $crate::with_named_testdir($name, move |tdir| {
+            opts.subdir(tdir, subdir_path)
+                .expect("Failed to create test-scoped sub-directory")
+        });
+        $crate::private::record_created(path, $crate::Scope::Test)
+    }};
+    ( @opts $opts:expr; named = $name:expr, ModuleScope ) => {{
+        let module_path = ::std::module_path!();
+        let subdir_path = $crate::private::module_dir(&module_path).join("mod");
+        let opts = $opts;
+        let path = $crate::with_named_testdir($name, move |tdir| {
+            opts.subdir(tdir, subdir_path)
+                .expect("Failed to create module-scoped sub-directory")
+        });
+        $crate::private::record_created(path, $crate::Scope::Module)
+    }};
+    ( @opts $opts:expr; named = $name:expr, $e:expr ) => {{
+        let opts = $opts;
+        let path = $crate::with_named_testdir($name, move |tdir| {
+            opts.subdir(tdir, $e)
                 .expect("Failed to create sub-directory")
-        })
+        });
+        $crate::private::record_created(path, $crate::Scope::Custom)
+    }};
+    ( @opts $opts:expr; $e:expr ) => {{
+        if !$crate::private::is_overridden() {
+            $crate::init_testdir!();
+        }
+        let opts = $opts;
+        let path = $crate::with_testdir(move |tdir| {
+            opts.subdir(tdir, $e)
+                .expect("Failed to create sub-directory")
+        });
+        $crate::private::record_created(path, $crate::Scope::Custom)
+    }};
+
+    () => {
+        $crate::testdir!(TestScope)
+    };
+    ( TestScope ) => {
+        $crate::testdir!(@opts $crate::private::SubdirOptions::new(); TestScope)
+    };
+    ( ModuleScope ) => {
+        $crate::testdir!(@opts $crate::private::SubdirOptions::new(); ModuleScope)
+    };
+    ( clean = $clean:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $crate::private::SubdirOptions::new(); clean = $clean $(, $($rest)* )?)
+    };
+    ( create = $create:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $crate::private::SubdirOptions::new(); create = $create $(, $($rest)* )?)
+    };
+    ( strict = $strict:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $crate::private::SubdirOptions::new(); strict = $strict $(, $($rest)* )?)
+    };
+    ( canonicalize = $canonicalize:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $crate::private::SubdirOptions::new(); canonicalize = $canonicalize $(, $($rest)* )?)
+    };
+    ( strip_unc_prefix = $strip_unc_prefix:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $crate::private::SubdirOptions::new(); strip_unc_prefix = $strip_unc_prefix $(, $($rest)* )?)
+    };
+    ( normalize_private = $normalize_private:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $crate::private::SubdirOptions::new(); normalize_private = $normalize_private $(, $($rest)* )?)
+    };
+    ( attempt = $attempt:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $crate::private::SubdirOptions::new(); attempt = $attempt $(, $($rest)* )?)
+    };
+    ( named = $name:expr $(, $($rest:tt)* )? ) => {
+        $crate::testdir!(@opts $crate::private::SubdirOptions::new(); named = $name $(, $($rest)* )?)
+    };
+    ( $e:expr ) => {
+        $crate::testdir!(@opts $crate::private::SubdirOptions::new(); $e)
+    };
+}
+
+/// Creates a test directory like [`testdir`], returning an error instead of panicking.
+///
+/// This accepts the same arguments as [`testdir`] but evaluates to a
+/// `Result<PathBuf, testdir::Error>`.  An error is returned if creating the global
+/// [`NumberedDir`] or the test directory fails, or if the named [`NumberedDir`] was not
+/// initialised.  This is useful for test helpers, examples or binaries which want to handle
+/// these failures themselves.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::try_testdir;
+///
+/// fn main() -> Result<(), testdir::Error> {
+///     let path = try_testdir!("example/output")?;
+///     assert!(path.is_dir());
+///     assert!(try_testdir!(named = "not-initialised", "dir").is_err());
+///     Ok(())
+/// }
+/// ```
+///
+/// [`NumberedDir`]: crate::NumberedDir
+#[macro_export]
+macro_rules! try_testdir {
+    // Internal rules, receiving the `SubdirOptions` to use.
+    ( @opts $opts:expr; ) => {
+        $crate::try_testdir!(@opts $opts; TestScope)
+    };
+    ( @opts $opts:expr; clean = $clean:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $opts.clean($clean); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; create = $create:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $opts.create($create); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; strict = $strict:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $opts.strict($strict); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; canonicalize = $canonicalize:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $opts.canonicalize($canonicalize); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; strip_unc_prefix = $strip_unc_prefix:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $opts.strip_unc_prefix($strip_unc_prefix); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; normalize_private = $normalize_private:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $opts.normalize_private($normalize_private); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; attempt = $attempt:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $opts.attempt($attempt); $( $($rest)* )?)
+    };
+    ( @opts $opts:expr; TestScope ) => {{
+        let module_path = ::std::module_path!();
+        let test_name = $crate::private::extract_test_name(&module_path);
+        let opts = $opts;
+        let subdir_path = opts.attempt_dir($crate::private::module_dir(&module_path).join(&test_name));
+        $crate::private::with_override_or(
+            || $crate::init_testdir!(@try),
+            |tdir| opts.subdir(tdir, subdir_path),
+        )
+            .map(|path| $crate::private::record_created(path, $crate::Scope::Test))
+    }};
+    ( @opts $opts:expr; ModuleScope ) => {{
+        let module_path = ::std::module_path!();
+        let subdir_path = $crate::private::module_dir(&module_path).join("mod");
+        $crate::private::with_override_or(
+            || $crate::init_testdir!(@try),
+            |tdir| $opts.subdir(tdir, subdir_path),
+        )
+            .map(|path| $crate::private::record_created(path, $crate::Scope::Module))
+    }};
+    ( @opts $opts:expr; named = $name:expr ) => {
+        $crate::try_testdir!(@opts $opts; named = $name, TestScope)
+    };
+    ( @opts $opts:expr; named = $name:expr, TestScope ) => {{
+        let module_path = ::std::module_path!();
+        let test_name = $crate::private::extract_test_name(&module_path);
+        let opts = $opts;
+        let subdir_path = opts.attempt_dir($crate::private::module_dir(&module_path).join(&test_name));
+        $crate::private::named_testdir($name)
+            .and_then(|tdir| opts.subdir(tdir, subdir_path))
+            .map(|path| $crate::private::record_created(path, $crate::Scope::Test))
+    }};
+    ( @opts $opts:expr; named = $name:expr, ModuleScope ) => {{
+        let module_path = ::std::module_path!();
+        let subdir_path = $crate::private::module_dir(&module_path).join("mod");
+        $crate::private::named_testdir($name)
+            .and_then(|tdir| $opts.subdir(tdir, subdir_path))
+            .map(|path| $crate::private::record_created(path, $crate::Scope::Module))
+    }};
+    ( @opts $opts:expr; named = $name:expr, $e:expr ) => {{
+        $crate::private::named_testdir($name)
+            .and_then(|tdir| $opts.subdir(tdir, $e))
+            .map(|path| $crate::private::record_created(path, $crate::Scope::Custom))
+    }};
+    ( @opts $opts:expr; $e:expr ) => {{
+        $crate::private::with_override_or(
+            || $crate::init_testdir!(@try),
+            |tdir| $opts.subdir(tdir, $e),
+        )
+            .map(|path| $crate::private::record_created(path, $crate::Scope::Custom))
+    }};
+
+    () => {
+        $crate::try_testdir!(TestScope)
+    };
+    ( TestScope ) => {
+        $crate::try_testdir!(@opts $crate::private::SubdirOptions::new(); TestScope)
+    };
+    ( ModuleScope ) => {
+        $crate::try_testdir!(@opts $crate::private::SubdirOptions::new(); ModuleScope)
+    };
+    ( clean = $clean:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $crate::private::SubdirOptions::new(); clean = $clean $(, $($rest)* )?)
+    };
+    ( create = $create:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $crate::private::SubdirOptions::new(); create = $create $(, $($rest)* )?)
+    };
+    ( strict = $strict:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $crate::private::SubdirOptions::new(); strict = $strict $(, $($rest)* )?)
+    };
+    ( canonicalize = $canonicalize:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $crate::private::SubdirOptions::new(); canonicalize = $canonicalize $(, $($rest)* )?)
+    };
+    ( strip_unc_prefix = $strip_unc_prefix:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $crate::private::SubdirOptions::new(); strip_unc_prefix = $strip_unc_prefix $(, $($rest)* )?)
+    };
+    ( normalize_private = $normalize_private:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $crate::private::SubdirOptions::new(); normalize_private = $normalize_private $(, $($rest)* )?)
+    };
+    ( attempt = $attempt:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $crate::private::SubdirOptions::new(); attempt = $attempt $(, $($rest)* )?)
+    };
+    ( named = $name:expr $(, $($rest:tt)* )? ) => {
+        $crate::try_testdir!(@opts $crate::private::SubdirOptions::new(); named = $name $(, $($rest)* )?)
+    };
+    ( $e:expr ) => {
+        $crate::try_testdir!(@opts $crate::private::SubdirOptions::new(); $e)
+    };
+}
+
+/// Returns the path of the global [`NumberedDir`] used by the [`testdir`] macro.
+///
+/// This initialises the global [`NumberedDir`] if needed, like [`testdir`], but does not
+/// create any sub-directory.  Instead the path of the numbered run directory itself is
+/// returned as a [`PathBuf`].  This is useful for harness-level setup code which wants to
+/// share things between all tests of a run, e.g. caches or global servers.
+///
+/// # Panics
+///
+/// If there is any problem with creating the directories or cleaning up old ones this will
+/// panic.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{testdir, testdir_run_root};
+///
+/// let root = testdir_run_root!();
+/// let dir = testdir!("some/subdir");
+/// assert!(dir.starts_with(&root));
+/// ```
+///
+/// [`NumberedDir`]: crate::NumberedDir
+/// [`PathBuf`]: std::path::PathBuf
+#[macro_export]
+macro_rules! testdir_run_root {
+    () => {{
+        if !$crate::private::is_overridden() {
+            $crate::init_testdir!();
+        }
+        $crate::with_testdir(|tdir| tdir.path().to_path_buf())
+    }};
+}
+
+/// Creates a test directory and opens it as a capability-based `cap_std::fs::Dir`.
+///
+/// This takes the same arguments as [`testdir`] and opens the resulting directory using
+/// [`open_cap_dir`](crate::open_cap_dir).  All filesystem operations done through the
+/// returned `Dir` are confined to the test directory, writing to `../file` or an absolute
+/// path fails instead of touching files outside of it.
+///
+/// This requires the `cap-std` feature.
+///
+/// # Panics
+///
+/// Like [`testdir`] this panics if the directory can not be created, or if it can not be
+/// opened.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::testdir_cap;
+///
+/// let dir = testdir_cap!();
+/// dir.create_dir("sub").unwrap();
+/// dir.write("sub/file", "content").unwrap();
+/// assert_eq!(dir.read_to_string("sub/file").unwrap(), "content");
+/// assert!(dir.write("../escape", "content").is_err());
+/// ```
+#[cfg(feature = "cap-std")]
+#[macro_export]
+macro_rules! testdir_cap {
+    ( $( $args:tt )* ) => {{
+        let path = $crate::testdir!($( $args )*);
+        match $crate::open_cap_dir(&path) {
+            ::std::result::Result::Ok(dir) => dir,
+            ::std::result::Result::Err(err) => {
+                ::std::panic!("Failed to open test directory {}: {:#}", path.display(), err)
+            }
+        }
     }};
 }
 
@@ -114,6 +474,65 @@ macro_rules! testdir {
 /// [`NumberedDir`] instance inside the cargo target directory.  It must be called before
 /// any call to [`with_testdir`](crate::with_testdir) to ensure this is initialised.
 ///
+/// The global [`NumberedDir`] is created in the cargo target directory used to build the
+/// test, found from the location of the test executable.  Where this is not possible, e.g.
+/// for doctests, `cargo metadata` is run unless the target directory was captured at build
+/// time using [`build_support`](crate::build_support).  In Miri mode a fixed directory is
+/// used instead, see [`MIRI_ENV`](crate::MIRI_ENV).  When run by a build system like Bazel
+/// its temporary directory is used, see [`BuildSystemEnv`](crate::BuildSystemEnv).  The
+/// full order and which source was used is described by
+/// [`TargetDirSource`](crate::TargetDirSource).
+///
+/// On wasm targets, where neither process inspection nor symlinks are available, the
+/// `target` directory relative to the current directory is used, which with WASI is usually
+/// the preopened directory of the crate.  On `wasm32-unknown-unknown` there is no filesystem
+/// at all, use [`try_testdir`](crate::try_testdir) to get an error instead of a panic there.
+///
+/// The mapping of module paths to directories used by [`testdir`] can be customised by
+/// passing a [`PathMapper`]: `init_testdir!(path_mapper = my_fn)`.  This must be called
+/// before any other invocation of [`testdir`] or `init_testdir!` since the mapper can only
+/// be set once, later attempts to set it are ignored.
+///
+/// The `cargo metadata` invocation can be customised using a [`MetadataHook`], e.g. to
+/// pass `--offline`: `init_testdir!(metadata_command = my_fn)`.  If `cargo metadata` takes
+/// longer than [`METADATA_TIMEOUT_DEFAULT`] it is killed and the directory of the test
+/// executable is used instead.  This timeout can be changed using
+/// `init_testdir!(metadata_timeout = Duration::from_secs(5))` or the
+/// [`METADATA_TIMEOUT_ENV`] environment variable.  Like the mapper these must be set before
+/// the global [`NumberedDir`] is created.
+///
+/// To keep the root of the target directory tidy the numbered directories can be placed in
+/// `target/tmp` instead using `init_testdir!(target_tmp = true)`, see
+/// [`NumberedDirBuilder::target_tmp`].
+///
+/// Test runs of different build profiles, e.g. `cargo test` and `cargo test --release`,
+/// can use separate numbered directories using `init_testdir!(per_profile = true)`, see
+/// [`NumberedDirBuilder::per_profile`].  Likewise cross-compiled test runs for different
+/// targets can be kept separate using `init_testdir!(per_target = true)`, see
+/// [`NumberedDirBuilder::per_target`].
+///
+/// The `-current` symlink pointing to the latest numbered directory can be disabled using
+/// `init_testdir!(current_symlink = false)`, see
+/// [`NumberedDirBuilder::current_symlink`].
+///
+/// Flaky tests retried by the test runner reuse the same test directory for each attempt.
+/// With `init_testdir!(per_attempt = true)` the retry attempt is detected, currently from
+/// the `NEXTEST_ATTEMPT` environment variable set by cargo-nextest, and each test-scoped
+/// directory gets an `attempt-N` subdirectory.  This keeps all attempts, to compare a
+/// failing attempt with a passing one.  Without a detected retry `attempt-1` is used.
+///
+/// The numbered directories are named `testdir-N`, another base name can be used with
+/// `init_testdir!(base = "name")`.  All crates of a workspace share the same target
+/// directory, so when they use the same base name the test binaries of all crates run by a
+/// single `cargo test --workspace` share the same numbered directory.  Using
+/// `init_testdir!(workspace = true)` places the test directories of each crate in a
+/// subdirectory named after its package and registers it, so tests can find the
+/// directories of the other crates using [`workspace_crates`](crate::workspace_crates).
+///
+/// Child processes spawned using [`TestDir::export_env`](crate::TestDir::export_env) get
+/// the path of the global [`NumberedDir`] in the [`RUN_DIR_ENV`](crate::RUN_DIR_ENV)
+/// environment variable, they can find it using [`from_env`](crate::from_env).
+///
 /// # Examples
 ///
 /// ```
@@ -125,30 +544,191 @@ macro_rules! testdir {
 /// assert!(path.ends_with("some/subdir"));
 /// ```
 ///
+/// Using a custom [`PathMapper`]:
+///
+/// ```
+/// use testdir::{init_testdir, testdir};
+///
+/// init_testdir!(path_mapper = testdir::map_module_path_unraw);
+/// let path = testdir!(ModuleScope);
+/// assert!(path.ends_with("mod"));
+/// ```
+///
+/// Running `cargo metadata` offline:
+///
+/// ```
+/// use testdir::{init_testdir, testdir, MetadataCommand};
+///
+/// fn offline(cmd: &mut MetadataCommand) {
+///     cmd.no_deps().other_options(vec![String::from("--offline")]);
+/// }
+///
+/// init_testdir!(metadata_command = offline);
+/// let path = testdir!();
+/// assert!(path.is_dir());
+/// ```
+///
+/// Several options can be combined:
+///
+/// ```
+/// use testdir::{init_testdir, testdir};
+///
+/// init_testdir!(target_tmp = true, per_profile = true);
+/// let path = testdir!();
+/// assert!(path.is_dir());
+/// ```
+///
+/// Sharing the numbered directory with the other crates of a workspace:
+///
+/// ```
+/// use testdir::{init_testdir, testdir};
+///
+/// init_testdir!(workspace = true);
+/// let path = testdir!();
+/// assert!(path.to_string_lossy().contains("testdir"));
+/// ```
+///
+/// Without the `-current` symlink:
+///
+/// ```
+/// use testdir::{init_testdir, testdir};
+///
+/// init_testdir!(current_symlink = false);
+/// let path = testdir!();
+/// assert!(path.is_dir());
+/// ```
+///
 /// [`NumberedDir`]: crate::NumberedDir
+/// [`NumberedDirBuilder::current_symlink`]: crate::NumberedDirBuilder::current_symlink
+/// [`NumberedDirBuilder::target_tmp`]: crate::NumberedDirBuilder::target_tmp
+/// [`NumberedDirBuilder::per_profile`]: crate::NumberedDirBuilder::per_profile
+/// [`NumberedDirBuilder::per_target`]: crate::NumberedDirBuilder::per_target
+/// [`PathMapper`]: crate::PathMapper
+/// [`MetadataHook`]: crate::MetadataHook
+/// [`METADATA_TIMEOUT_DEFAULT`]: crate::METADATA_TIMEOUT_DEFAULT
+/// [`METADATA_TIMEOUT_ENV`]: crate::METADATA_TIMEOUT_ENV
 #[macro_export]
 macro_rules! init_testdir {
+    ( path_mapper = $mapper:expr $(, $($rest:tt)* )? ) => {{
+        $crate::PATH_MAPPER.get_or_init(|| $mapper);
+        $crate::init_testdir!($( $($rest)* )?)
+    }};
+    ( metadata_command = $hook:expr $(, $($rest:tt)* )? ) => {{
+        $crate::METADATA_HOOK.get_or_init(|| $hook);
+        $crate::init_testdir!($( $($rest)* )?)
+    }};
+    ( metadata_timeout = $timeout:expr $(, $($rest:tt)* )? ) => {{
+        $crate::private::set_metadata_timeout($timeout);
+        $crate::init_testdir!($( $($rest)* )?)
+    }};
+    ( target_tmp = $enable:expr $(, $($rest:tt)* )? ) => {{
+        if $crate::TESTDIR.get().is_none() {
+            $crate::private::set_target_tmp($enable);
+        }
+        $crate::init_testdir!($( $($rest)* )?)
+    }};
+    ( per_target = $enable:expr $(, $($rest:tt)* )? ) => {{
+        if $crate::TESTDIR.get().is_none() {
+            $crate::private::set_per_target($enable);
+        }
+        $crate::init_testdir!($( $($rest)* )?)
+    }};
+    ( per_attempt = $enable:expr $(, $($rest:tt)* )? ) => {{
+        if $crate::TESTDIR.get().is_none() {
+            $crate::private::set_per_attempt($enable);
+        }
+        $crate::init_testdir!($( $($rest)* )?)
+    }};
+    ( per_profile = $enable:expr $(, $($rest:tt)* )? ) => {{
+        if $crate::TESTDIR.get().is_none() {
+            $crate::private::set_per_profile($enable);
+        }
+        $crate::init_testdir!($( $($rest)* )?)
+    }};
+    ( base = $base:expr $(, $($rest:tt)* )? ) => {{
+        if $crate::TESTDIR.get().is_none() {
+            $crate::private::set_base($base);
+        }
+        $crate::init_testdir!($( $($rest)* )?)
+    }};
+    ( workspace = $enable:expr $(, $($rest:tt)* )? ) => {{
+        if $crate::TESTDIR.get().is_none() && $enable {
+            $crate::private::set_workspace_crate(::std::env!("CARGO_PKG_NAME"));
+        }
+        $crate::init_testdir!($( $($rest)* )?)
+    }};
+    ( current_symlink = $enable:expr $(, $($rest:tt)* )? ) => {{
+        if $crate::TESTDIR.get().is_none() {
+            $crate::private::set_current_symlink($enable);
+        }
+        $crate::init_testdir!($( $($rest)* )?)
+    }};
     () => {{
-        $crate::TESTDIR.get_or_init(move || {
-            let parent = match $crate::private::cargo_metadata::MetadataCommand::new().exec() {
-                Ok(metadata) => metadata.target_directory.into(),
-                Err(_) => {
-                    // In some environments cargo-metadata is not available,
-                    // e.g. cargo-dinghy.  Use the directory of test executable.
-                    let current_exe = ::std::env::current_exe().expect("no current exe");
-                    current_exe
-                        .parent()
-                        .expect("no parent dir for current exe")
-                        .into()
-                }
-            };
-            let pkg_name = "testdir";
-            let mut builder = $crate::NumberedDirBuilder::new(pkg_name.to_string());
-            builder.set_parent(parent);
+        $crate::init_testdir!(@try).expect("Failed to create testdir")
+    }};
+    // Internal rule, returning the `Result` of initialising.
+    ( @try ) => {{
+        $crate::TESTDIR.get_or_try_init(move || {
+            if let Some(testdir) = $crate::private::create_overridden() {
+                return testdir;
+            }
+            let parent =
+                $crate::private::resolve_target_dir(::std::option_env!("TESTDIR_TARGET_DIR"));
+            let mut builder =
+                $crate::NumberedDirBuilder::new($crate::private::base().to_string());
+            if $crate::private::target_tmp() {
+                builder.target_tmp(parent);
+            } else {
+                builder.set_parent(parent);
+            }
+            if $crate::private::per_target() {
+                builder.per_target();
+            }
+            if $crate::private::per_profile() {
+                builder.per_profile();
+            }
             builder.reusefn($crate::private::reuse_cargo);
-            let testdir = builder.create().expect("Failed to create testdir");
-            $crate::private::create_cargo_pid_file(testdir.path());
-            testdir
+            builder.current_symlink($crate::private::current_symlink());
+            let testdir = builder.create()?;
+            $crate::private::create_cargo_pid_file(&testdir);
+            $crate::private::record_target_dir_source(&testdir)?;
+            $crate::private::register_workspace_crate(&testdir)?;
+            $crate::private::init_from_env();
+            ::std::result::Result::Ok::<_, $crate::Error>(testdir)
         })
     }};
 }
+
+/// Initialises the global [`NumberedDir`] when the binary starts.
+///
+/// Module-level statics, e.g. a `Lazy<PathBuf>` using [`with_testdir`], can be evaluated
+/// before any test called [`init_testdir`], in which case they would get a dummy testdir.
+/// When invoked once at the crate level this macro registers a constructor function which
+/// calls [`init_testdir`] before `main` runs, so the global [`NumberedDir`] is always
+/// initialised correctly.  Any arguments are passed on to [`init_testdir`].
+///
+/// This requires the `ctor` feature.
+///
+/// # Examples
+///
+/// ```
+/// testdir::auto_init_testdir!();
+///
+/// # fn main() {
+/// let path = testdir::with_testdir(|dir| dir.path().to_path_buf());
+/// assert!(!path.ends_with("init_testdir-not-called"));
+/// # }
+/// ```
+///
+/// [`NumberedDir`]: crate::NumberedDir
+/// [`with_testdir`]: crate::with_testdir
+#[cfg(feature = "ctor")]
+#[macro_export]
+macro_rules! auto_init_testdir {
+    ( $( $args:tt )* ) => {
+        #[$crate::private::ctor]
+        fn __testdir_auto_init() {
+            $crate::init_testdir!( $( $args )* );
+        }
+    };
+}
@@ -0,0 +1,366 @@
+//! The in-memory [`MemoryBackend`].
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::DirBackend;
+
+/// The maximum number of symlinks followed when resolving a path.
+const MAX_SYMLINKS: usize = 40;
+
+/// A [`DirBackend`] keeping all directories and files in memory.
+///
+/// Nothing is written to disk, which makes this usable under Miri and for pure-logic unit
+/// tests of code using [`NumberedDir`].  Clones share the same contents, so a clone can be
+/// given to [`NumberedDirBuilder::backend`] while the original is used to inspect the
+/// result.
+///
+/// Paths not created in the backend do not exist, except for those without a parent like
+/// `/`.  Only the operations going through the [`DirBackend`] see these contents, paths
+/// returned by [`NumberedDir::create_subdir`] can not be used with [`std::fs`].
+///
+/// This requires the `backend` feature.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use testdir::{DirBackend, MemoryBackend, NumberedDirBuilder};
+///
+/// let memory = MemoryBackend::new();
+/// let numdir = NumberedDirBuilder::new(String::from("base"))
+///     .set_parent("/virtual/root".into())
+///     .backend(memory.clone())
+///     .create()
+///     .unwrap();
+/// let subdir = numdir.create_subdir("mod/test").unwrap();
+/// assert!(memory.is_dir(&subdir));
+/// assert!(!Path::new("/virtual/root").exists());
+/// ```
+///
+/// [`NumberedDir`]: crate::NumberedDir
+/// [`NumberedDir::create_subdir`]: crate::NumberedDir::create_subdir
+/// [`NumberedDirBuilder::backend`]: crate::NumberedDirBuilder::backend
+#[derive(Clone, Debug, Default)]
+pub struct MemoryBackend {
+    nodes: Arc<Mutex<BTreeMap<PathBuf, Node>>>,
+}
+
+/// An entry of the [`MemoryBackend`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node {
+    Dir,
+    File(Vec<u8>),
+    Symlink(PathBuf),
+}
+
+impl MemoryBackend {
+    /// Creates an empty backend.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the paths of all entries, sorted.
+    ///
+    /// Symlinks are not followed, their own paths are returned.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.lock().keys().cloned().collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<PathBuf, Node>> {
+        self.nodes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Returns the path to `path` with all symlinks resolved.
+fn resolve(nodes: &BTreeMap<PathBuf, Node>, path: &Path) -> io::Result<PathBuf> {
+    let mut hops = 0;
+    let mut resolved = PathBuf::new();
+    let mut pending: Vec<PathBuf> = vec![path.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        let mut components = path.components();
+        while let Some(component) = components.next() {
+            match component {
+                Component::CurDir => continue,
+                Component::ParentDir => {
+                    resolved.pop();
+                    continue;
+                }
+                other => resolved.push(other),
+            }
+            if let Some(Node::Symlink(target)) = nodes.get(&resolved) {
+                hops += 1;
+                if hops > MAX_SYMLINKS {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "too many levels of symbolic links",
+                    ));
+                }
+                resolved.pop();
+                pending.push(components.as_path().to_path_buf());
+                pending.push(target.clone());
+                break;
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+/// Returns the path to `path` with all symlinks resolved except the final component.
+fn resolve_parent(nodes: &BTreeMap<PathBuf, Node>, path: &Path) -> io::Result<PathBuf> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => Ok(resolve(nodes, parent)?.join(name)),
+        _ => resolve(nodes, path),
+    }
+}
+
+/// Checks that the parent of the resolved `path` is a directory.
+fn check_parent(nodes: &BTreeMap<PathBuf, Node>, path: &Path) -> io::Result<()> {
+    match path.parent() {
+        None => Ok(()),
+        Some(parent) if parent.parent().is_none() => Ok(()),
+        Some(parent) => match nodes.get(parent) {
+            Some(Node::Dir) => Ok(()),
+            Some(_) => Err(not_a_directory(parent)),
+            None => Err(not_found(parent)),
+        },
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} not found", path.display()),
+    )
+}
+
+fn already_exists(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists", path.display()),
+    )
+}
+
+fn not_a_directory(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} is not a directory", path.display()),
+    )
+}
+
+impl DirBackend for MemoryBackend {
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.lock();
+        let path = resolve_parent(&nodes, path)?;
+        if nodes.contains_key(&path) {
+            return Err(already_exists(&path));
+        }
+        check_parent(&nodes, &path)?;
+        nodes.insert(path, Node::Dir);
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.lock();
+        let path = resolve(&nodes, path)?;
+        let mut ancestors: Vec<_> = path.ancestors().filter(|a| a.parent().is_some()).collect();
+        ancestors.reverse();
+        for ancestor in ancestors {
+            match nodes.get(ancestor) {
+                Some(Node::Dir) => (),
+                Some(_) => return Err(already_exists(ancestor)),
+                None => {
+                    nodes.insert(ancestor.to_path_buf(), Node::Dir);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &Path, _threads: usize) -> io::Result<()> {
+        let mut nodes = self.lock();
+        let path = resolve_parent(&nodes, path)?;
+        if nodes.remove(&path).is_none() {
+            return Err(not_found(&path));
+        }
+        nodes.retain(|entry, _| !entry.starts_with(&path));
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let nodes = self.lock();
+        let resolved = resolve(&nodes, path)?;
+        if resolved.parent().is_some() {
+            match nodes.get(&resolved) {
+                Some(Node::Dir) => (),
+                Some(_) => return Err(not_a_directory(path)),
+                None => return Err(not_found(path)),
+            }
+        }
+        Ok(nodes
+            .keys()
+            .filter(|entry| entry.parent() == Some(&resolved))
+            .filter_map(|entry| entry.file_name())
+            .map(|name| path.join(name))
+            .collect())
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        let nodes = self.lock();
+        match resolve(&nodes, path) {
+            Ok(path) if path.parent().is_none() => true,
+            Ok(path) => nodes.get(&path) == Some(&Node::Dir),
+            Err(_) => false,
+        }
+    }
+
+    fn symlink_dir(&self, target: &Path, link: &Path) -> io::Result<()> {
+        let mut nodes = self.lock();
+        let link = resolve_parent(&nodes, link)?;
+        match nodes.get(&link) {
+            None | Some(Node::Symlink(_)) => (),
+            Some(_) => return Err(already_exists(&link)),
+        }
+        check_parent(&nodes, &link)?;
+        nodes.insert(link, Node::Symlink(target.to_path_buf()));
+        Ok(())
+    }
+
+    fn read_link(&self, link: &Path) -> io::Result<PathBuf> {
+        let nodes = self.lock();
+        let resolved = resolve_parent(&nodes, link)?;
+        match nodes.get(&resolved) {
+            Some(Node::Symlink(target)) => Ok(target.clone()),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a symlink", link.display()),
+            )),
+            None => Err(not_found(link)),
+        }
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let mut nodes = self.lock();
+        let path = resolve(&nodes, path)?;
+        if nodes.get(&path) == Some(&Node::Dir) {
+            return Err(already_exists(&path));
+        }
+        check_parent(&nodes, &path)?;
+        nodes.insert(path, Node::File(contents.to_vec()));
+        Ok(())
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let nodes = self.lock();
+        let resolved = resolve(&nodes, path)?;
+        match nodes.get(&resolved) {
+            Some(Node::File(contents)) => Ok(contents.clone()),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a file", path.display()),
+            )),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut nodes = self.lock();
+        let from = resolve_parent(&nodes, from)?;
+        let to = resolve_parent(&nodes, to)?;
+        if !nodes.contains_key(&from) {
+            return Err(not_found(&from));
+        }
+        check_parent(&nodes, &to)?;
+        let moved: Vec<_> = nodes
+            .keys()
+            .filter(|entry| entry.starts_with(&from))
+            .cloned()
+            .collect();
+        nodes.retain(|entry, _| !entry.starts_with(&to));
+        for entry in moved {
+            if let Some(node) = nodes.remove(&entry) {
+                let rel = entry.strip_prefix(&from).unwrap_or(Path::new(""));
+                nodes.insert(to.join(rel), node);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU8;
+
+    use super::*;
+    use crate::{NumberedDirBuilder, Retention};
+
+    #[test]
+    fn test_memory_backend() {
+        let memory = MemoryBackend::new();
+        let root = Path::new("/mem");
+        memory.create_dir_all(&root.join("a/b")).unwrap();
+        assert!(memory.is_dir(&root.join("a/b")));
+        let err = memory.create_dir(&root.join("a")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        let err = memory.create_dir(&root.join("x/y")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        memory.write(&root.join("a/file"), b"data").unwrap();
+        assert_eq!(memory.read(&root.join("a/file")).unwrap(), b"data");
+        let mut entries = memory.read_dir(&root.join("a")).unwrap();
+        entries.sort();
+        assert_eq!(entries, vec![root.join("a/b"), root.join("a/file")]);
+
+        memory
+            .symlink_dir(Path::new("a"), &root.join("link"))
+            .unwrap();
+        assert!(memory.is_dir(&root.join("link/b")));
+        assert_eq!(memory.read(&root.join("link/file")).unwrap(), b"data");
+        memory
+            .symlink_dir(&root.join("a/b"), &root.join("link"))
+            .unwrap();
+        assert_eq!(
+            memory.read_link(&root.join("link")).unwrap(),
+            root.join("a/b")
+        );
+
+        memory.rename(&root.join("a"), &root.join("c")).unwrap();
+        assert!(memory.is_dir(&root.join("c/b")));
+        assert!(!memory.is_dir(&root.join("a")));
+        memory.remove_dir_all(&root.join("c"), 1).unwrap();
+        assert_eq!(memory.paths(), vec![root.to_path_buf(), root.join("link")]);
+    }
+
+    #[test]
+    fn test_memory_numbered_dirs() {
+        let memory = MemoryBackend::new();
+        let parent = PathBuf::from("/testdir-memory-backend");
+        let mut builder = NumberedDirBuilder::new(String::from("base"));
+        builder
+            .set_parent(parent.clone())
+            .retention(Retention::Count(NonZeroU8::new(2).unwrap()))
+            .backend(memory.clone());
+        for _ in 0..3 {
+            builder.create().unwrap();
+        }
+        let numdir = builder.create().unwrap();
+        assert_eq!(numdir.path(), parent.join("base-3"));
+        assert_eq!(numdir.removed_dirs(), [parent.join("base-1")]);
+        assert!(numdir.is_compatible());
+        assert!(memory.is_dir(&parent.join("base-current")));
+        assert!(!memory.is_dir(&parent.join("base-1")));
+
+        let subdir = numdir.create_subdir("mod/test").unwrap();
+        assert!(memory.is_dir(&subdir));
+        let encoded = numdir.create_subdir("mod/con").unwrap();
+        assert_eq!(encoded, numdir.path().join("mod/_con"));
+        let names = memory.read(&numdir.path().join("path-names.json")).unwrap();
+        let names: serde_json::Value = serde_json::from_slice(&names).unwrap();
+        assert_eq!(names["mod/_con"], "mod/con");
+        assert!(!parent.exists());
+    }
+}
@@ -0,0 +1,73 @@
+//! Test directories for custom test harnesses using [`libtest_mimic`].
+//!
+//! Custom harnesses do not run their tests in threads named after the test, so
+//! [`testdir!`](crate::testdir) can not detect the test name.  Instead [`trial`] creates the
+//! directory for each [`Trial`] up front and passes it to the trial's closure.
+
+use std::path::PathBuf;
+
+use libtest_mimic::{Failed, Trial};
+
+use crate::{with_testdir, Scope};
+
+/// Creates a [`Trial`] with its own test directory.
+///
+/// The directory is created inside the global [`NumberedDir`](crate::NumberedDir) before
+/// the trial runs, at the path of the trial `name` with any `::` separators turned into
+/// directories.  It is passed to `runner` when the trial runs.
+///
+/// # Examples
+///
+/// ```no_run
+/// use libtest_mimic::{Arguments, Trial};
+///
+/// fn main() {
+///     testdir::init_testdir!();
+///     let args = Arguments::from_args();
+///     let trials = vec![testdir::mimic::trial("parser::test_parse", |dir| {
+///         std::fs::write(dir.join("input.txt"), "1 + 2")?;
+///         Ok(())
+///     })];
+///     libtest_mimic::run(&args, trials).exit();
+/// }
+/// ```
+///
+/// # Panics
+///
+/// If the directory could not be created.
+pub fn trial<F>(name: impl Into<String>, runner: F) -> Trial
+where
+    F: FnOnce(PathBuf) -> Result<(), Failed> + Send + 'static,
+{
+    let name = name.into();
+    let rel_path = name.split("::").collect::<PathBuf>();
+    let path = with_testdir(|dir| dir.create_subdir(rel_path))
+        .expect("Failed to create trial test directory");
+    Trial::test(name, move || {
+        crate::created::record(&path, Scope::Test);
+        runner(path)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use libtest_mimic::Arguments;
+
+    use super::*;
+
+    #[test]
+    fn test_trial() {
+        let trials = vec![trial("mimic::test_pass", |dir| {
+            assert!(dir.ends_with("mimic/test_pass"));
+            assert!(dir.is_dir());
+            Ok(())
+        })];
+        let args = Arguments {
+            test_threads: Some(1),
+            quiet: true,
+            ..Default::default()
+        };
+        let conclusion = libtest_mimic::run(&args, trials);
+        assert_eq!(conclusion.num_passed, 1);
+    }
+}
@@ -0,0 +1,99 @@
+//! Named global [`NumberedDir`]s, in addition to the default global one.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+
+use crate::{Error, NumberedDir, NumberedDirBuilder};
+
+/// The registry of named global [`NumberedDir`]s.
+///
+/// The directories are leaked so they can be handed out with a `'static` lifetime, just
+/// like the default global [`NumberedDir`].
+static NAMED: Lazy<Mutex<HashMap<String, &'static NumberedDir>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Initialises a named global [`NumberedDir`] using a [`NumberedDirBuilder`].
+///
+/// Besides the default global [`NumberedDir`] used by [`testdir!`](crate::testdir) a
+/// process can maintain any number of named ones, e.g. one in the cargo target directory
+/// and one on a large scratch disk.  Use [`with_named_testdir`] or
+/// `testdir!(named = "name")` to use them.
+///
+/// If the named [`NumberedDir`] is already initialised the builder is not used and the
+/// existing instance is returned.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{testdir, NumberedDirBuilder};
+///
+/// let mut builder = NumberedDirBuilder::new(String::from("scratch"));
+/// builder.root("testdir-scratch-example");
+/// testdir::init_named("scratch", builder).unwrap();
+///
+/// let path = testdir!(named = "scratch", "big/files");
+/// assert!(path.ends_with("big/files"));
+/// ```
+pub fn init_named(name: &str, builder: NumberedDirBuilder) -> Result<&'static NumberedDir, Error> {
+    let mut named = NAMED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(numdir) = named.get(name) {
+        return Ok(numdir);
+    }
+    let numdir: &'static NumberedDir = Box::leak(Box::new(builder.create()?));
+    named.insert(name.to_string(), numdir);
+    Ok(numdir)
+}
+
+/// Returns the named global [`NumberedDir`], if it was initialised.
+pub fn named_testdir(name: &str) -> Option<&'static NumberedDir> {
+    NAMED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(name)
+        .copied()
+}
+
+/// Executes a function passing the named global [`NumberedDir`].
+///
+/// This is the equivalent of [`with_testdir`](crate::with_testdir) for the directories
+/// initialised using [`init_named`].
+///
+/// # Panics
+///
+/// If no [`NumberedDir`] with this name was initialised.
+pub fn with_named_testdir<F, R>(name: &str, func: F) -> R
+where
+    F: FnOnce(&NumberedDir) -> R,
+{
+    let numdir = named_testdir(name)
+        .unwrap_or_else(|| panic!("testdir: named testdir {name:?} not initialised"));
+    func(numdir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut builder = NumberedDirBuilder::new(String::from("named"));
+        builder.set_parent(parent.path().to_path_buf());
+        assert!(named_testdir("test_named").is_none());
+        let numdir = init_named("test_named", builder.clone()).unwrap();
+        assert_eq!(init_named("test_named", builder).unwrap(), numdir);
+        let path = with_named_testdir("test_named", |dir| dir.path().to_path_buf());
+        assert_eq!(path, parent.path().join("named-0"));
+    }
+
+    #[test]
+    #[should_panic(expected = "not initialised")]
+    fn test_named_missing() {
+        with_named_testdir("test_named_missing", |_| ());
+    }
+}
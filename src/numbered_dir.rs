@@ -2,15 +2,33 @@
 
 use std::io::ErrorKind;
 use std::num::NonZeroU8;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 use std::{fs, io};
 
-#[cfg(unix)]
-use std::os::unix::fs::symlink as symlink_dir;
-#[cfg(windows)]
-use std::os::windows::fs::symlink_dir;
+use anyhow::{anyhow, Context, Result};
 
-use anyhow::{Context, Error, Result};
+use crate::backend::{std_backend, symlink_dir, DirBackend};
+use crate::portable::{self, PortableNames, PATH_NAMES_FILE_NAME};
+use crate::{CleanupReport, Error, Retention, RetryStrategy};
+
+/// The filename in which the format version of a numbered dir is stored: `format-version`.
+pub(crate) const FORMAT_VERSION_FILE_NAME: &str = "format-version";
+
+/// The filename marking a directory created for a run identifier: `run-id`.
+///
+/// It contains the run identifier, only directories with this marker are removed as
+/// obsolete run-id directories.
+const RUN_ID_FILE_NAME: &str = "run-id";
+
+/// The highest suffix tried by [`Collision::Suffix`].
+const MAX_SUBDIR_SUFFIX: u16 = 1000;
+
+/// The version of the on-disk layout of numbered directories.
+///
+/// Bump this when changing the contents of the numbered directories in an incompatible way.
+const LAYOUT_VERSION: u32 = 1;
 
 /// A sequentially numbered directory.
 ///
@@ -20,16 +38,147 @@ use anyhow::{Context, Error, Result};
 ///
 /// The directory has a **parent** directory in which the numbered directory is created, as
 /// well as a **base** which is used as the directory name to which to affix the number.
-#[derive(Clone, Debug, PartialEq, Eq)]
+///
+/// Two instances compare equal if they refer to the same numbered directory, regardless of
+/// any configured [`SubdirLimits`].
+#[derive(Clone, Debug)]
 pub struct NumberedDir {
     path: PathBuf,
     /// The **base**, could also be extracted from `path`, needs to remain consistent.
     base: String,
     /// The number, could also be extracted from `path`, needs to remain consistent.
     number: u16,
+    /// The limits enforced by [`NumberedDir::create_subdir`].
+    pub(crate) limits: SubdirLimits,
+    /// What [`NumberedDir::create_subdir`] does when the subdirectory exists.
+    pub(crate) collision: Collision,
+    /// How [`NumberedDir::subdir_path`] handles names which can not be used on Windows.
+    pub(crate) portable_names: PortableNames,
+    /// Whether [`NumberedDir::create_subdir`] flushes created directories to disk.
+    pub(crate) durable: bool,
+    /// The subdirectories created by [`NumberedDir::create_subdir`], shared between clones.
+    created: Arc<Mutex<Vec<PathBuf>>>,
+    /// The cleanup of obsolete directories done when this directory was created.
+    cleanup: CleanupReport,
+    /// The run identifier used instead of the number, if any.
+    run_id: Option<String>,
+    /// The filesystem operations used on this directory.
+    backend: Arc<dyn DirBackend>,
+}
+
+impl PartialEq for NumberedDir {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.base == other.base && self.number == other.number
+    }
+}
+
+impl Eq for NumberedDir {}
+
+/// Limits on the subdirectories created by [`NumberedDir::create_subdir`].
+///
+/// These guard against buggy tests creating huge numbers of directories, e.g. by calling
+/// [`testdir!`](crate::testdir) in a loop.  By default there are no limits.  The limits can
+/// be configured using [`NumberedDirBuilder::max_subdirs`] and
+/// [`NumberedDirBuilder::max_depth`].
+///
+/// [`NumberedDirBuilder::max_subdirs`]: crate::NumberedDirBuilder::max_subdirs
+/// [`NumberedDirBuilder::max_depth`]: crate::NumberedDirBuilder::max_depth
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubdirLimits {
+    /// The maximum number of subdirectories created by this process.
+    pub max_subdirs: Option<usize>,
+    /// The maximum number of components of the relative path of a subdirectory.
+    pub max_depth: Option<usize>,
+}
+
+/// What [`NumberedDir::create_subdir`] does when the subdirectory already exists.
+///
+/// This is configured using [`NumberedDirBuilder::collision`].
+///
+/// [`NumberedDirBuilder::collision`]: crate::NumberedDirBuilder::collision
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Collision {
+    /// Returns the existing subdirectory, the default.
+    #[default]
+    Reuse,
+    /// Creates a sibling with a numbered suffix, e.g. `test_name-1`, instead.
+    Suffix,
+    /// Returns [`Error::SubdirExists`].
+    Error,
+}
+
+/// Options controlling the creation of a new [`NumberedDir`].
+///
+/// These are configured by the [`NumberedDirBuilder`](crate::NumberedDirBuilder).
+#[derive(Clone, Debug)]
+pub(crate) struct CreateOptions {
+    /// The policy deciding which older directories to keep.
+    pub(crate) retention: Retention,
+    /// Whether to write the `base-current.path` pointer file.
+    pub(crate) pointer_file: bool,
+    /// Whether to create the `base-current` symlink.
+    pub(crate) current_symlink: bool,
+    /// Whether to point the `base-previous` symlink to the directory `base-current`
+    /// pointed to before.
+    pub(crate) previous_symlink: bool,
+    /// Whether the `base-current` symlink target is relative to the parent directory.
+    pub(crate) relative_symlink: bool,
+    /// Whether failing to remove an obsolete directory is recorded instead of an error.
+    pub(crate) continue_on_cleanup_error: bool,
+    /// The number of threads used to remove each obsolete directory.
+    pub(crate) cleanup_threads: usize,
+    /// How to retry when the next number is already taken.
+    pub(crate) retry: RetryStrategy,
+    /// Whether created directories and files are flushed to disk.
+    pub(crate) durable: bool,
+    /// The filesystem operations used to create the directory.
+    pub(crate) backend: Arc<dyn DirBackend>,
+}
+
+impl CreateOptions {
+    pub(crate) fn new(retention: Retention) -> Self {
+        Self {
+            retention,
+            pointer_file: false,
+            current_symlink: true,
+            previous_symlink: false,
+            relative_symlink: false,
+            continue_on_cleanup_error: false,
+            cleanup_threads: 1,
+            retry: RetryStrategy::default(),
+            durable: false,
+            backend: std_backend(),
+        }
+    }
 }
 
 impl NumberedDir {
+    pub(crate) fn new(path: PathBuf, base: &str, number: u16) -> Self {
+        Self::new_in(std_backend(), path, base, number)
+    }
+
+    /// Creates the instance for a directory managed by `backend`.
+    pub(crate) fn new_in(
+        backend: Arc<dyn DirBackend>,
+        path: PathBuf,
+        base: &str,
+        number: u16,
+    ) -> Self {
+        Self {
+            path,
+            base: base.to_string(),
+            number,
+            limits: SubdirLimits::default(),
+            collision: Collision::default(),
+            portable_names: PortableNames::default(),
+            durable: false,
+            created: Arc::new(Mutex::new(Vec::new())),
+            cleanup: CleanupReport::default(),
+            run_id: None,
+            backend,
+        }
+    }
+
     /// Creates the next sequential numbered directory.
     ///
     /// The directory will be created inside `parent` and will start with the name given in
@@ -43,27 +192,204 @@ impl NumberedDir {
     /// subdirectories are recursively removed.  Care is taken to avoid removing new
     /// directories concurrently created by parallel invocations in other threads or
     /// processes..
-    pub fn create(parent: impl AsRef<Path>, base: &str, count: NonZeroU8) -> Result<Self> {
-        if base.contains('/') || base.contains('\\') {
-            return Err(Error::msg("base must not contain path separators"));
-        }
-        fs::create_dir_all(&parent).context("Could not create parent")?;
-        let next_count = match current_entry_count(&parent, base) {
+    pub fn create(parent: impl AsRef<Path>, base: &str, count: NonZeroU8) -> Result<Self, Error> {
+        Self::create_with(parent, base, &CreateOptions::new(Retention::Count(count)))
+            .map_err(Error::from)
+    }
+
+    /// Creates the next sequential numbered directory using the given [`CreateOptions`].
+    pub(crate) fn create_with(
+        parent: impl AsRef<Path>,
+        base: &str,
+        options: &CreateOptions,
+    ) -> Result<Self> {
+        validate_base(base)?;
+        let parent = parent.as_ref();
+        options
+            .backend
+            .create_dir_all(parent)
+            .context("Could not create parent")?;
+        let (next_count, cleanup) = match current_entry_count(&options.backend, parent, base) {
             Some(current_count) => {
-                remove_obsolete_dirs(&parent, base, current_count, u8::from(count) - 1)?;
-                current_count.wrapping_add(1)
+                let cleanup = remove_obsolete_dirs(parent, base, current_count, options)?;
+                (current_count.wrapping_add(1), cleanup)
             }
-            None => 0,
+            None => (0, CleanupReport::default()),
         };
-        create_next_dir(&parent, base, next_count)
+        let mut numdir = create_next_dir(parent, base, next_count, options)?;
+        numdir.cleanup = cleanup;
+        Ok(numdir)
+    }
+
+    /// Creates or re-uses the directory named after the `run_id` using the given options.
+    ///
+    /// Instead of the next sequential number the directory is named `base-run_id`.  If it
+    /// already exists it is re-used, which is returned as the boolean.  Otherwise obsolete
+    /// run-id directories are removed based on their age, see [`run_id_dirs`].
+    pub(crate) fn create_with_run_id(
+        parent: impl AsRef<Path>,
+        base: &str,
+        run_id: &str,
+        options: &CreateOptions,
+    ) -> Result<(Self, bool)> {
+        let parent = parent.as_ref();
+        validate_base(base)?;
+        validate_run_id(run_id)?;
+        let backend = &options.backend;
+        backend
+            .create_dir_all(parent)
+            .context("Could not create parent")?;
+        let path = parent.join(format!("{}-{}", base, run_id));
+        let mut numdir = NumberedDir::new_in(backend.clone(), path.clone(), base, 0);
+        numdir.run_id = Some(run_id.to_string());
+        if backend.is_dir(&path) {
+            if !numdir.is_compatible() {
+                return Err(Error::ReuseConflict { path }.into());
+            }
+            return Ok((numdir, true));
+        }
+        let mut report = CleanupReport::default();
+        let obsolete = options.retention.obsolete_by_age(
+            run_id_dirs(&**backend, parent, base, run_id)?,
+            true,
+            SystemTime::now(),
+        );
+        for old in obsolete {
+            let old = NumberedDir::new_in(backend.clone(), old, base, 0);
+            if options.continue_on_cleanup_error {
+                report.try_remove(&old, options.cleanup_threads);
+            } else {
+                report.remove(&old, options.cleanup_threads)?;
+            }
+        }
+        match backend.create_dir(&path) {
+            Ok(_) => (),
+            // Created concurrently by another process of the same run.
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => return Ok((numdir, true)),
+            Err(source) => return Err(Error::Create { path, source }.into()),
+        }
+        let marker = path.join(RUN_ID_FILE_NAME);
+        backend
+            .write(&marker, run_id.as_bytes())
+            .with_context(|| format!("Failed to write {}", marker.display()))?;
+        finish_new_dir(parent, base, &path, options)?;
+        numdir.cleanup = report;
+        Ok((numdir, false))
+    }
+
+    /// Adopts an existing directory as the next sequential numbered directory.
+    ///
+    /// The `existing` directory is moved into `parent` using the next available number for
+    /// `base`, keeping all its contents.  This allows migrating an ad-hoc artifacts
+    /// directory to be managed as a [`NumberedDir`] without losing its history.  If the
+    /// directory can not be moved, e.g. because it is on a different filesystem, a symlink
+    /// to it is created instead.
+    ///
+    /// The adopted directory becomes the most recent numbered directory and the `-current`
+    /// symlink is updated.  No older directories are removed.
+    ///
+    /// Taken numbers are skipped like for [`NumberedDir::create`], using the attempts of the
+    /// default [`RetryStrategy`].  [`Error::Create`] is returned if they are all taken.
+    pub fn adopt(
+        parent: impl AsRef<Path>,
+        base: &str,
+        existing: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        let parent = parent.as_ref();
+        let existing = existing.as_ref();
+        validate_base(base)?;
+        if !existing.is_dir() {
+            return Err(anyhow!("Not a directory: {}", existing.display()).into());
+        }
+        fs::create_dir_all(parent).context("Could not create parent")?;
+        let mut next_count = current_entry_count(&std_backend(), parent, base)
+            .map(|count| count.wrapping_add(1))
+            .unwrap_or(0);
+        let retry = RetryStrategy::default();
+        let mut attempt = 1;
+        loop {
+            let path = parent.join(format!("{}-{}", base, next_count));
+            if path.symlink_metadata().is_err() {
+                if fs::rename(existing, &path).is_err() {
+                    let target = existing.canonicalize()?;
+                    symlink_dir(target, &path).with_context(|| {
+                        format!(
+                            "Failed to adopt {} as {}",
+                            existing.display(),
+                            path.display()
+                        )
+                    })?;
+                }
+                let options = CreateOptions::new(Retention::default());
+                finish_new_dir(parent, base, &path, &options)?;
+                return Ok(Self::new(path, base, next_count));
+            }
+            if attempt >= retry.max_attempts() {
+                let source = io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("no free number found in {attempt} attempts"),
+                );
+                return Err(Error::Create { path, source });
+            }
+            next_count = next_count.wrapping_add(1);
+            attempt += 1;
+            retry.pause();
+        }
+    }
+
+    /// Returns the most recent numbered directory of `base` in `parent`, creating nothing.
+    ///
+    /// The directory is found using the `base-current` symlink, or if there is none the
+    /// `base-current.path` pointer file written when
+    /// [`NumberedDirBuilder::pointer_file`](crate::NumberedDirBuilder::pointer_file) is
+    /// enabled.  This is useful for tooling and teardown scripts working on the latest run.
+    /// No directories are removed and the `-current` symlink is not changed.
+    ///
+    /// An error is returned if neither exists or they do not point to an existing
+    /// directory of `base`.
+    pub fn adopt_current(parent: impl AsRef<Path>, base: &str) -> Result<Self, Error> {
+        let parent = parent.as_ref();
+        validate_base(base)?;
+        let symlink = parent.join(format!("{}-current", base));
+        let pointer = parent.join(format!("{}-current.path", base));
+        let path = match fs::read_link(&symlink) {
+            Ok(target) => parent.join(target),
+            Err(_) => match fs::read_to_string(&pointer) {
+                Ok(content) => PathBuf::from(content.trim_end_matches(['\r', '\n'])),
+                Err(_) => {
+                    return Err(
+                        anyhow!("No current {} directory in {}", base, parent.display()).into(),
+                    )
+                }
+            },
+        };
+        if !path.is_dir() {
+            return Err(anyhow!("Current directory does not exist: {}", path.display()).into());
+        }
+        let suffix = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(base))
+            .and_then(|rest| rest.strip_prefix('-'))
+            .ok_or_else(|| anyhow!("Not a {} directory: {}", base, path.display()))?;
+        match suffix.parse::<u16>() {
+            Ok(number) => Ok(Self::new(path, base, number)),
+            Err(_) => {
+                validate_run_id(suffix)?;
+                let run_id = suffix.to_string();
+                let mut numdir = Self::new(path, base, 0);
+                numdir.run_id = Some(run_id);
+                Ok(numdir)
+            }
+        }
     }
 
     /// Returns an iterator over all [`NumberedDir`] entries in a parent directory.
     ///
     /// This iterator can be used to get access to existing [`NumberedDir`] directories
     /// without creating a new one.
-    pub fn iterate(parent: impl AsRef<Path>, base: &str) -> Result<NumberedDirIter> {
-        NumberedDirIter::try_new(parent, base)
+    pub fn iterate(parent: impl AsRef<Path>, base: &str) -> Result<NumberedDirIter, Error> {
+        NumberedDirIter::try_new_in(std_backend(), parent.as_ref(), base).map_err(Error::from)
     }
 
     /// Returns the path of this numbered directory instance.
@@ -81,40 +407,367 @@ impl NumberedDir {
 
     /// Returns the number suffix of this [`NumberedDir`] instance.
     ///
-    /// The number is the suffix of the final component of [`NumberedDir::path`].
+    /// The number is the suffix of the final component of [`NumberedDir::path`].  For
+    /// directories named after a run identifier this is always `0`, see
+    /// [`NumberedDir::run_id`].
     pub fn number(&self) -> u16 {
         self.number
     }
 
+    /// Returns the run identifier this directory is named after, if any.
+    ///
+    /// This is set when the directory was created using
+    /// [`NumberedDirBuilder::run_id`](crate::NumberedDirBuilder::run_id), in which case the
+    /// final component of [`NumberedDir::path`] is the **base** suffixed with the run
+    /// identifier instead of a number.
+    pub fn run_id(&self) -> Option<&str> {
+        self.run_id.as_deref()
+    }
+
+    /// Returns whether this directory was created with a compatible format.
+    ///
+    /// Each numbered directory contains a `format-version` file recording the on-disk
+    /// layout version and the semver-compatible version of this crate which created it.
+    /// This returns `false` if these differ from the current ones, or if the file is
+    /// missing.  The [`NumberedDirBuilder`] will not re-use incompatible directories.
+    ///
+    /// [`NumberedDirBuilder`]: crate::NumberedDirBuilder
+    pub fn is_compatible(&self) -> bool {
+        self.backend
+            .read(&self.path.join(FORMAT_VERSION_FILE_NAME))
+            .map(|content| String::from_utf8_lossy(&content).trim() == format_version())
+            .unwrap_or(false)
+    }
+
+    /// Returns the subdirectories created using [`NumberedDir::create_subdir`].
+    ///
+    /// Only the subdirectories created by this process, and which did not yet exist, are
+    /// returned.  They are returned in the order they were created.
+    pub fn created_subdirs(&self) -> Vec<PathBuf> {
+        self.created
+            .lock()
+            .map(|created| created.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the obsolete directories removed when this directory was created.
+    ///
+    /// This is empty if the directory was re-used instead of created.
+    pub fn removed_dirs(&self) -> &[PathBuf] {
+        &self.cleanup.removed
+    }
+
+    /// Returns the [`CleanupReport`] of the cleanup done when this directory was created.
+    ///
+    /// This is empty if the directory was re-used instead of created.
+    pub fn cleanup_report(&self) -> &CleanupReport {
+        &self.cleanup
+    }
+
+    /// Exports the test directories of this run into `dest` for uploading as artifacts.
+    ///
+    /// The test directories, as listed by [`Run::subdirs`], are copied or hardlinked into
+    /// `dest` as described by the [`ExportLayout`].  The files testdir itself keeps in the
+    /// numbered directory are not exported.  A manifest describing the exported test
+    /// directories is written to [`EXPORT_MANIFEST_FILE_NAME`] in `dest` and its path is
+    /// returned.  `dest` must not be inside this numbered directory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::{testdir, ExportLayout};
+    ///
+    /// let dir = testdir!();
+    /// std::fs::write(dir.join("output.txt"), "hello").unwrap();
+    /// let dest = tempfile::tempdir().unwrap();
+    /// testdir::with_testdir(|numdir| numdir.export(dest.path(), ExportLayout::flat()))
+    ///     .unwrap();
+    /// assert!(dest.path().join("manifest.json").is_file());
+    /// ```
+    ///
+    /// [`Run::subdirs`]: crate::Run::subdirs
+    /// [`ExportLayout`]: crate::ExportLayout
+    /// [`EXPORT_MANIFEST_FILE_NAME`]: crate::EXPORT_MANIFEST_FILE_NAME
+    pub fn export(
+        &self,
+        dest: impl AsRef<Path>,
+        layout: crate::ExportLayout,
+    ) -> Result<PathBuf, Error> {
+        crate::export::export(&self.path, dest.as_ref(), layout).map_err(Error::from)
+    }
+
+    /// Marks this directory as belonging to a run with failing tests.
+    ///
+    /// This points the `$base-last-failed` symlink in the parent directory to this
+    /// directory, so the artifacts of the most recent failing run can be found quickly,
+    /// even after several successful runs.  Calling this several times, even from
+    /// concurrent processes sharing this directory, is fine.
+    ///
+    /// Note that the directory the symlink points to is still removed by the normal
+    /// [`Retention`] policy.
+    pub fn mark_failed(&self) -> Result<(), Error> {
+        let parent = self
+            .path
+            .parent()
+            .ok_or_else(|| anyhow!("numbered dir has no parent"))?;
+        let link = parent.join(format!("{}-last-failed", self.base));
+        if self
+            .backend
+            .read_link(&link)
+            .is_ok_and(|target| target == self.path)
+        {
+            return Ok(());
+        }
+        match self.backend.symlink_dir(&self.path, &link) {
+            Ok(()) => Ok(()),
+            // Another process sharing this directory marked it concurrently.
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => Ok(()),
+            Err(err) => Err(err)
+                .with_context(|| format!("Failed to create {}", link.display()))
+                .map_err(Error::from),
+        }
+    }
+
+    /// Writes a deterministic tar archive of this numbered directory to `dest`.
+    ///
+    /// See [`TestDir::archive_to`](crate::TestDir::archive_to) for details.  `dest` must
+    /// not be inside this numbered directory.
+    ///
+    /// This requires the `tar` feature.
+    #[cfg(feature = "tar")]
+    pub fn archive_to(&self, dest: impl AsRef<Path>) -> Result<(), Error> {
+        crate::archive::archive_to(&self.path, dest.as_ref()).map_err(Error::from)
+    }
+
+    /// Writes a compressed deterministic tar archive of this numbered directory to `dest`.
+    ///
+    /// See [`TestDir::archive_to_with`](crate::TestDir::archive_to_with) for details.
+    ///
+    /// This requires the `tar` feature.
+    #[cfg(feature = "tar")]
+    pub fn archive_to_with(
+        &self,
+        dest: impl AsRef<Path>,
+        compressor: &dyn crate::Compressor,
+    ) -> Result<(), Error> {
+        crate::archive::archive_to_with(&self.path, dest.as_ref(), compressor).map_err(Error::from)
+    }
+
+    /// Returns the [`SubdirLimits`] enforced by [`NumberedDir::create_subdir`].
+    pub fn limits(&self) -> SubdirLimits {
+        self.limits
+    }
+
+    /// Returns what [`NumberedDir::create_subdir`] does when the subdirectory exists.
+    pub fn collision(&self) -> Collision {
+        self.collision
+    }
+
+    /// Returns whether created directories are flushed to disk, see
+    /// [`NumberedDirBuilder::durable`](crate::NumberedDirBuilder::durable).
+    pub fn durable(&self) -> bool {
+        self.durable
+    }
+
+    /// Returns how names which can not be used on Windows are handled.
+    pub fn portable_names(&self) -> PortableNames {
+        self.portable_names
+    }
+
+    /// Returns the [`DirBackend`] used for this directory.
+    ///
+    /// This requires the `backend` feature.
+    #[cfg(feature = "backend")]
+    pub fn backend(&self) -> &dyn DirBackend {
+        &*self.backend
+    }
+
+    /// Returns the path of a subdirectory within this numbered directory, without creating it.
+    ///
+    /// This performs the same checks as [`NumberedDir::create_subdir`], except for the
+    /// maximum number of subdirectories since nothing is created.  Names which can not be
+    /// used on Windows are handled as configured by [`PortableNames`].
+    pub fn subdir_path(&self, rel_path: impl AsRef<Path>) -> Result<PathBuf, Error> {
+        let rel_path = rel_path.as_ref();
+        if !rel_path.is_relative() {
+            return Err(anyhow!("Not a relative path: {}", rel_path.display()).into());
+        }
+        let rel_path =
+            &portable::portable_path(&*self.backend, &self.path, rel_path, self.portable_names)?;
+        if let Some(max_depth) = self.limits.max_depth {
+            let depth = rel_path
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .count();
+            if depth > max_depth {
+                return Err(anyhow!(
+                    "Subdir exceeds maximum depth of {}: {}",
+                    max_depth,
+                    rel_path.display()
+                )
+                .into());
+            }
+        }
+        Ok(self.path.join(rel_path))
+    }
+
     /// Creates a subdirecotry within this numbered directory.
     ///
-    /// If the subdirectory already exists nothing is done.
+    /// If the subdirectory already exists what happens depends on the [`Collision`]
+    /// strategy.  By default nothing is done and the existing subdirectory is returned.
+    /// With [`Collision::Suffix`] the first free sibling named `$name-1`, `$name-2`, etc.
+    /// is created instead and returned.
+    ///
+    /// An error is returned if creating the subdirectory would exceed the configured
+    /// [`SubdirLimits`].
     ///
     /// There is no particular safety from malicious input, the numbered directory can be
-    /// trivially escaped using the parent directory location: `../somewhere/else`.
-    pub fn create_subdir(&self, rel_path: impl AsRef<Path>) -> Result<PathBuf> {
+    /// trivially escaped using the parent directory location: `../somewhere/else`.  Use
+    /// [`NumberedDir::create_subdir_strict`] when the path is derived from untrusted input,
+    /// e.g. test parameters.
+    pub fn create_subdir(&self, rel_path: impl AsRef<Path>) -> Result<PathBuf, Error> {
         let rel_path = rel_path.as_ref();
-        if !rel_path.is_relative() {
-            return Err(Error::msg(format!(
-                "Not a relative path: {}",
-                rel_path.display()
-            )));
+        let full_path = self.subdir_path(rel_path)?;
+        if self.collision == Collision::Reuse && self.backend.is_dir(&full_path) {
+            return Ok(full_path);
+        }
+        if let Some(max_subdirs) = self.limits.max_subdirs {
+            if self.created.lock().map_or(0, |created| created.len()) >= max_subdirs {
+                return Err(anyhow!(
+                    "Maximum number of {} subdirs exceeded: {}",
+                    max_subdirs,
+                    rel_path.display()
+                )
+                .into());
+            }
         }
 
-        if let Some(parent) = rel_path.parent() {
-            let parent_path = self.path.join(parent);
-            fs::create_dir_all(&parent_path).with_context(|| {
+        if let Some(parent_path) = full_path.parent() {
+            self.backend.create_dir_all(parent_path).with_context(|| {
                 format!("Failed to create subdir parent: {}", parent_path.display())
             })?;
         }
 
-        let full_path = self.path.join(rel_path);
-        match fs::create_dir(&full_path) {
-            Ok(_) => Ok(full_path),
-            Err(err) if matches!(err.kind(), io::ErrorKind::AlreadyExists) => Ok(full_path),
-            Err(_) => Err(Error::msg(
-                "subdir conflict: all filename alternatives exhausted",
-            )),
+        for suffix in 0..=MAX_SUBDIR_SUFFIX {
+            let mut path = full_path.clone();
+            if suffix > 0 {
+                let mut name = full_path.file_name().unwrap_or_default().to_os_string();
+                name.push(format!("-{suffix}"));
+                path.set_file_name(name);
+            }
+            match self.backend.create_dir(&path) {
+                Ok(_) => {
+                    if let Ok(mut created) = self.created.lock() {
+                        created.push(path.clone());
+                    }
+                    if full_path != self.path.join(rel_path) {
+                        self.record_path_name(&path, rel_path)?;
+                    }
+                    if self.durable {
+                        let parent = path.parent().unwrap_or(&self.path);
+                        sync(&*self.backend, &path)?;
+                        sync(&*self.backend, parent)?;
+                    }
+                    return Ok(path);
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => match self.collision {
+                    Collision::Reuse => return Ok(path),
+                    Collision::Error => return Err(Error::SubdirExists { path }),
+                    Collision::Suffix => continue,
+                },
+                Err(source) => return Err(Error::Create { path, source }),
+            }
+        }
+        Err(anyhow!("All suffixes for subdir taken: {}", rel_path.display()).into())
+    }
+
+    /// Records the requested relative path of the encoded subdirectory `path`.
+    ///
+    /// The [`PATH_NAMES_FILE_NAME`] file is updated using the backend, it has the same
+    /// format and lock file as a [`RunKv`] store.
+    ///
+    /// [`RunKv`]: crate::RunKv
+    fn record_path_name(&self, path: &Path, rel_path: &Path) -> Result<()> {
+        let encoded = path.strip_prefix(&self.path).unwrap_or(path);
+        let names_file = self.path.join(PATH_NAMES_FILE_NAME);
+        self.backend
+            .locked(&names_file, &mut || {
+                let mut names: serde_json::Map<String, serde_json::Value> =
+                    match self.backend.read(&names_file) {
+                        Ok(data) => serde_json::from_slice(&data).map_err(io::Error::other)?,
+                        Err(err) if err.kind() == ErrorKind::NotFound => Default::default(),
+                        Err(err) => return Err(err),
+                    };
+                names.insert(
+                    encoded.to_string_lossy().into_owned(),
+                    rel_path.to_string_lossy().into_owned().into(),
+                );
+                let data = serde_json::to_vec_pretty(&names).map_err(io::Error::other)?;
+                self.backend.write(&names_file, &data)
+            })
+            .context("Failed to record encoded subdir name")?;
+        Ok(())
+    }
+
+    /// Creates a subdirectory like [`NumberedDir::create_subdir`], ensuring it stays inside.
+    ///
+    /// [`Error::PathTraversal`] is returned if `rel_path` contains `..` components or if
+    /// any existing part of it is a symlink pointing outside of this numbered directory.
+    /// After creating the subdirectory its canonical path is verified to be inside the
+    /// canonical path of this numbered directory.  This is used by [`testdir!`] unless
+    /// `strict = false` is given.
+    ///
+    /// [`testdir!`]: crate::testdir
+    pub fn create_subdir_strict(&self, rel_path: impl AsRef<Path>) -> Result<PathBuf, Error> {
+        let rel_path = rel_path.as_ref();
+        self.check_contained(rel_path)?;
+        let path = self.create_subdir(rel_path)?;
+        self.check_canonical(rel_path, &path)?;
+        Ok(path)
+    }
+
+    /// Checks that `rel_path` can not escape this numbered directory, without creating it.
+    ///
+    /// This is the check done by [`NumberedDir::create_subdir_strict`] before creating the
+    /// subdirectory.  The components are checked after encoding them as by
+    /// [`PortableNames`], since those are the directories which will be created.
+    pub(crate) fn check_contained(&self, rel_path: &Path) -> Result<(), Error> {
+        let escape = || Error::PathTraversal {
+            path: rel_path.to_path_buf(),
+        };
+        let encoded =
+            portable::portable_path(&*self.backend, &self.path, rel_path, self.portable_names)?;
+        let mut path = self.path.clone();
+        for component in encoded.components() {
+            match component {
+                Component::Normal(name) => path.push(name),
+                Component::CurDir => continue,
+                Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                    return Err(escape())
+                }
+            }
+            match path.symlink_metadata() {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    self.check_canonical(rel_path, &path)?
+                }
+                Ok(_) => (),
+                // Nothing below a missing component can be a symlink.
+                Err(_) => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the canonical form of `path`, created for `rel_path`, is inside.
+    fn check_canonical(&self, rel_path: &Path, path: &Path) -> Result<(), Error> {
+        let root = fs::canonicalize(&self.path)
+            .with_context(|| format!("Failed to canonicalize {}", self.path.display()))?;
+        match fs::canonicalize(path) {
+            Ok(canonical) if canonical.starts_with(&root) => Ok(()),
+            // A dangling symlink, which would be followed when creating the directory.
+            _ => Err(Error::PathTraversal {
+                path: rel_path.to_path_buf(),
+            }),
         }
     }
 }
@@ -122,75 +775,279 @@ impl NumberedDir {
 /// Remove obsolete numbered directories.
 ///
 /// The [`NumberedDir`] is identified by the parent directory `dir` and its base name
-/// `base`.  It will retain a maximum number of `keep` directories starting from `current`.
-/// By setting `keep` to `0` it will remove all directories.
+/// `base`.  The directories to remove are determined by the [`Retention`] policy, starting
+/// from `current`, leaving room for one more directory to be created.  Returns a
+/// [`CleanupReport`] of the removed directories.  Failing to remove a directory is an error
+/// unless [`CreateOptions::continue_on_cleanup_error`] is set.
 ///
 /// Any directories with higher numbers than `current` will be left alone as they are
 /// assumed to be created by concurrent processes creating the same numbered directories.
-fn remove_obsolete_dirs(dir: impl AsRef<Path>, base: &str, current: u16, keep: u8) -> Result<()> {
-    let oldest_to_keep = current.wrapping_sub(keep as u16).wrapping_add(1);
-    let oldest_to_delete = current.wrapping_add(u16::MAX / 2);
-    assert!(oldest_to_keep != oldest_to_delete);
-
-    for numdir in NumberedDir::iterate(&dir, base)? {
-        if (oldest_to_keep > oldest_to_delete
-            && (numdir.number < oldest_to_keep && numdir.number >= oldest_to_delete))
-            || (oldest_to_keep < oldest_to_delete
-                && (numdir.number < oldest_to_keep || numdir.number >= oldest_to_delete))
-        {
-            match fs::remove_dir_all(numdir.path()) {
-                Ok(_) => (),
-                Err(err) if err.kind() == ErrorKind::NotFound => (),
-                Err(err) => {
-                    return Err(err)
-                        .with_context(|| format!("Failed to remove {}", numdir.path().display()))
-                }
-            }
+fn remove_obsolete_dirs(
+    dir: impl AsRef<Path>,
+    base: &str,
+    current: u16,
+    options: &CreateOptions,
+) -> Result<CleanupReport> {
+    let mut report = CleanupReport::default();
+    let numdirs = NumberedDirIter::try_new_in(options.backend.clone(), dir.as_ref(), base)?;
+    let obsolete = options
+        .retention
+        .obsolete_numdirs(numdirs, current, true, SystemTime::now());
+    for numdir in obsolete {
+        if options.continue_on_cleanup_error {
+            report.try_remove(&numdir, options.cleanup_threads);
+        } else {
+            report.remove(&numdir, options.cleanup_threads)?;
         }
     }
+    Ok(report)
+}
+
+/// Checks whether `run_id` can be used as suffix of a directory name.
+///
+/// It must not be empty, contain path separators or be confused with a number or the
+/// `-current` symlink.
+fn validate_run_id(run_id: &str) -> Result<(), Error> {
+    if run_id.is_empty()
+        || run_id.contains('/')
+        || run_id.contains('\\')
+        || run_id == "."
+        || run_id == ".."
+        || run_id.parse::<u16>().is_ok()
+        || run_id.starts_with("current")
+    {
+        return Err(Error::InvalidRunId {
+            run_id: run_id.to_string(),
+        });
+    }
+    Ok(())
+}
 
+/// Checks whether `base` can be used as the prefix of a directory name.
+pub(crate) fn validate_base(base: &str) -> Result<(), Error> {
+    if base.contains('/') || base.contains('\\') {
+        return Err(Error::InvalidBase {
+            base: base.to_string(),
+        });
+    }
     Ok(())
 }
 
+/// Returns the existing run-id directories other than the one for `run_id`.
+///
+/// These are the directories in `dir` named `base-*` which are not numbered directories
+/// and contain a [`RUN_ID_FILE_NAME`] marker matching their name, sorted by modification
+/// time, most recent first.  Symlinks, files and directories of other bases which happen
+/// to share the prefix are skipped.
+pub(crate) fn run_id_dirs(
+    backend: &dyn DirBackend,
+    dir: &Path,
+    base: &str,
+    run_id: &str,
+) -> Result<Vec<PathBuf>> {
+    let prefix = format!("{}-", base);
+    let entries = backend
+        .read_dir(dir)
+        .with_context(|| format!("Failed read_dir() on {}", dir.display()))?;
+    let mut candidates: Vec<(SystemTime, PathBuf)> = entries
+        .into_iter()
+        .filter(|path| {
+            let Some(suffix) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(&prefix))
+            else {
+                return false;
+            };
+            suffix != run_id
+                && suffix.parse::<u16>().is_err()
+                && !suffix.starts_with("current")
+                && backend.read_link(path).is_err()
+                && backend
+                    .read(&path.join(RUN_ID_FILE_NAME))
+                    .is_ok_and(|marker| marker == suffix.as_bytes())
+        })
+        .map(|path| {
+            let mtime = path
+                .symlink_metadata()
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (mtime, path)
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.cmp(a));
+    Ok(candidates.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Removes a numbered directory with all its contents.
+///
+/// The contents are removed using `threads` threads.  If the directory no longer exists
+/// this is not an error, it is possible that multiple processes are racing to clean up
+/// the same directory.
+pub(crate) fn remove_numbered_dir(numdir: &NumberedDir, threads: usize) -> Result<()> {
+    match numdir.backend.remove_dir_all(numdir.path(), threads) {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(source) => Err(Error::Cleanup {
+            path: numdir.path().to_path_buf(),
+            source,
+        }
+        .into()),
+    }
+}
+
 /// Attempt to create the next numbered directory.
 ///
 /// The directory will be placed in `dir` and its name composed of the `base` and
-/// `next_count`.  If this directory can not be created it is assumed another process
-/// created it already and the count is increased and tried again.  This is repeated maximum
-/// 16 times after which this gives up.
+/// `next_count`.  If this directory already exists it is assumed another process created
+/// it and the count is increased and tried again, as configured by the [`RetryStrategy`] in
+/// the `options`.  Other errors are not retried.
 ///
-/// Once the directory is created the `-current` symlink is also created.
-fn create_next_dir(dir: impl AsRef<Path>, base: &str, mut next_count: u16) -> Result<NumberedDir> {
-    let mut last_err = None;
-    for _i in 0..16 {
+/// Once the directory is created the `-current` symlink is also created, as well as the
+/// `-current.path` pointer file if enabled in the `options`.
+fn create_next_dir(
+    dir: impl AsRef<Path>,
+    base: &str,
+    mut next_count: u16,
+    options: &CreateOptions,
+) -> Result<NumberedDir> {
+    let retry = &options.retry;
+    let mut attempt = 1;
+    loop {
         let name = format!("{}-{}", base, next_count);
         let path = dir.as_ref().join(name);
-        match fs::create_dir(&path) {
+        match options.backend.create_dir(&path) {
             Ok(_) => {
-                let current = dir.as_ref().join(format!("{}-current", base));
-                if current.exists() {
-                    // This can fail on windows, treat it as best-effort.
-                    fs::remove_file(&current).ok();
-                }
-                // Could be racing other processes, should not fail
-                symlink_dir(&path, &current).ok();
-                return Ok(NumberedDir {
-                    path,
-                    base: base.to_string(),
-                    number: next_count,
-                });
+                finish_new_dir(dir.as_ref(), base, &path, options)?;
+                let backend = options.backend.clone();
+                return Ok(NumberedDir::new_in(backend, path, base, next_count));
             }
-            Err(err) => {
+            Err(err) if attempt < retry.max_attempts() && retry.is_retryable(&err) => {
                 next_count = next_count.wrapping_add(1);
-                last_err = Some(err);
+                attempt += 1;
+                retry.pause();
             }
+            Err(source) => return Err(Error::Create { path, source }.into()),
         }
     }
-    Err(Error::new(last_err.expect("no last error")).context("Failed to create numbered dir"))
 }
 
-fn current_entry_count(dir: impl AsRef<Path>, base: &str) -> Option<u16> {
-    NumberedDirIter::try_new(dir, base)
+/// Finishes setting up a newly created numbered directory at `path`.
+///
+/// This writes the `format-version` file, updates the `-previous` and `-current` symlinks
+/// and writes the `-current.path` pointer file, if enabled.  When the options are
+/// durable all of these, as well as the new directory itself, are flushed to disk.
+fn finish_new_dir(dir: &Path, base: &str, path: &Path, options: &CreateOptions) -> Result<()> {
+    let backend = &*options.backend;
+    let version_file = path.join(FORMAT_VERSION_FILE_NAME);
+    backend
+        .write(&version_file, format_version().as_bytes())
+        .with_context(|| format!("Failed to write {}", version_file.display()))?;
+    if options.durable {
+        sync(backend, &version_file)?;
+        sync(backend, path)?;
+    }
+    if options.current_symlink {
+        if options.previous_symlink {
+            update_previous_symlink(backend, dir, base);
+        }
+        update_current_symlink(backend, dir, base, path, options.relative_symlink);
+    }
+    if options.pointer_file {
+        write_pointer_file(backend, dir, base, path)?;
+        if options.durable {
+            sync(backend, &dir.join(format!("{}-current.path", base)))?;
+        }
+    }
+    if options.durable {
+        sync(backend, dir)?;
+    }
+    Ok(())
+}
+
+/// Flushes the file or directory at `path` to disk using the `backend`.
+pub(crate) fn sync(backend: &dyn DirBackend, path: &Path) -> Result<()> {
+    backend
+        .sync(path)
+        .with_context(|| format!("Failed to sync {}", path.display()))
+}
+
+/// Points the `base-current` symlink in `dir` to `path`.
+///
+/// If `relative` is set the symlink target is only the final component of `path`, which
+/// must be located in `dir`.  This is best-effort, failures are ignored.
+pub(crate) fn update_current_symlink(
+    backend: &dyn DirBackend,
+    dir: &Path,
+    base: &str,
+    path: &Path,
+    relative: bool,
+) {
+    let current = dir.join(format!("{}-current", base));
+    let path = match path.file_name() {
+        Some(name) if relative => Path::new(name),
+        _ => path,
+    };
+    // Could be racing other processes, should not fail
+    backend.symlink_dir(path, &current).ok();
+}
+
+/// Points the `base-previous` symlink in `dir` to the target of `base-current`.
+///
+/// This must be called before the `base-current` symlink is updated.  If there is no
+/// `base-current` symlink nothing is done.  This is best-effort, failures are ignored.
+fn update_previous_symlink(backend: &dyn DirBackend, dir: &Path, base: &str) {
+    let Ok(target) = backend.read_link(&dir.join(format!("{}-current", base))) else {
+        return;
+    };
+    let previous = dir.join(format!("{}-previous", base));
+    backend.symlink_dir(&target, &previous).ok();
+}
+
+/// Returns the contents of the `format-version` file.
+///
+/// This is the [`LAYOUT_VERSION`] followed by the semver-compatible part of the crate
+/// version, e.g. `1 0.9`.
+fn format_version() -> String {
+    let mut parts = env!("CARGO_PKG_VERSION").split('.');
+    let major = parts.next().unwrap_or("0");
+    let compat = if major == "0" {
+        format!("0.{}", parts.next().unwrap_or("0"))
+    } else {
+        major.to_string()
+    };
+    format!("{} {}", LAYOUT_VERSION, compat)
+}
+
+/// Writes the `base-current.path` pointer file.
+///
+/// This contains the absolute path of the numbered directory, for tools which can not
+/// follow the `-current` symlink.  The file is written to a temporary file first and
+/// renamed so readers never observe a partially written file.
+pub(crate) fn write_pointer_file(
+    backend: &dyn DirBackend,
+    dir: &Path,
+    base: &str,
+    path: &Path,
+) -> Result<()> {
+    let abs_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let pointer = dir.join(format!("{}-current.path", base));
+    let tmp = dir.join(format!(".{}-current.path.{}", base, std::process::id()));
+    backend
+        .write(&tmp, abs_path.to_string_lossy().as_bytes())
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    backend
+        .rename(&tmp, &pointer)
+        .with_context(|| format!("Failed to write {}", pointer.display()))?;
+    Ok(())
+}
+
+pub(crate) fn current_entry_count(
+    backend: &Arc<dyn DirBackend>,
+    dir: impl AsRef<Path>,
+    base: &str,
+) -> Option<u16> {
+    NumberedDirIter::try_new_in(backend.clone(), dir.as_ref(), base)
         .ok()?
         .map(|entry| entry.number)
         .max()
@@ -204,18 +1061,22 @@ fn current_entry_count(dir: impl AsRef<Path>, base: &str) -> Option<u16> {
 pub struct NumberedDirIter {
     /// The **base** plus the hyphen of the [`NumberedDir`] we are iterating over.
     prefix: String,
-    /// Iterator of directory entries in which to look for our [`NumberedDir`] instances.
-    readdir: fs::ReadDir,
+    /// The directory entries in which to look for our [`NumberedDir`] instances.
+    entries: std::vec::IntoIter<PathBuf>,
+    /// The filesystem operations used on the found directories.
+    backend: Arc<dyn DirBackend>,
 }
 
 impl NumberedDirIter {
-    fn try_new(dir: impl AsRef<Path>, base: &str) -> Result<Self> {
+    /// Lists the entries of `dir` using `backend`.
+    pub(crate) fn try_new_in(backend: Arc<dyn DirBackend>, dir: &Path, base: &str) -> Result<Self> {
+        let entries = backend
+            .read_dir(dir)
+            .with_context(|| format!("Failed read_dir() on {}", dir.display()))?;
         Ok(Self {
             prefix: format!("{}-", base),
-            readdir: dir
-                .as_ref()
-                .read_dir()
-                .with_context(|| format!("Failed read_dir() on {}", dir.as_ref().display()))?,
+            entries: entries.into_iter(),
+            backend,
         })
     }
 }
@@ -225,28 +1086,17 @@ impl Iterator for NumberedDirIter {
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let mut dirent = self.readdir.next()?;
-            while dirent.is_err() {
-                dirent = self.readdir.next()?;
-            }
-            let dirent = dirent.ok()?;
-            let os_name = dirent.file_name();
+            let path = self.entries.next()?;
 
             // We only work with valid UTF-8 entry names, so skip any names which are not.
-            let count = os_name
-                .to_str()
+            let count = path
+                .file_name()
+                .and_then(|name| name.to_str())
                 .and_then(|name| name.strip_prefix(&self.prefix))
                 .and_then(|suffix| suffix.parse::<u16>().ok());
             if let Some(count) = count {
-                return Some(NumberedDir {
-                    path: dirent.path(),
-                    base: self
-                        .prefix
-                        .strip_suffix('-')
-                        .unwrap_or(&self.prefix)
-                        .to_string(),
-                    number: count,
-                });
+                let base = self.prefix.strip_suffix('-').unwrap_or(&self.prefix);
+                return Some(NumberedDir::new_in(self.backend.clone(), path, base, count));
             }
         }
     }
@@ -255,6 +1105,8 @@ impl Iterator for NumberedDirIter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backend::StdBackend;
+    use crate::RunKv;
 
     #[test]
     fn test_numbered_creation() {
@@ -285,6 +1137,7 @@ mod tests {
 
         let dir_3 = NumberedDir::create(parent.path(), "base", NonZeroU8::new(3).unwrap()).unwrap();
         assert_eq!(dir_3.path(), parent.path().join("base-3"));
+        assert_eq!(dir_3.removed_dirs(), [dir_0.path()]);
         assert!(!dir_0.path().exists());
         assert!(dir_1.path().is_dir());
         assert!(dir_2.path().is_dir());
@@ -323,6 +1176,310 @@ mod tests {
         }
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_numbered_creation_no_current_symlink() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut options = CreateOptions::new(Retention::Count(NonZeroU8::new(3).unwrap()));
+        options.current_symlink = false;
+        let dir = NumberedDir::create_with(parent.path(), "base", &options).unwrap();
+        assert!(dir.path().is_dir());
+        assert!(parent
+            .path()
+            .join("base-current")
+            .symlink_metadata()
+            .is_err());
+    }
+
+    #[test]
+    fn test_numbered_creation_previous_symlink() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut options = CreateOptions::new(Retention::Count(NonZeroU8::new(3).unwrap()));
+        options.previous_symlink = true;
+        let previous = parent.path().join("base-previous");
+        let dir_0 = NumberedDir::create_with(parent.path(), "base", &options).unwrap();
+        assert!(previous.symlink_metadata().is_err());
+
+        NumberedDir::create_with(parent.path(), "base", &options).unwrap();
+        assert_eq!(fs::read_link(&previous).unwrap(), dir_0.path());
+        let dir_2 = NumberedDir::create_with(parent.path(), "base", &options).unwrap();
+        assert_eq!(
+            fs::read_link(&previous).unwrap(),
+            parent.path().join("base-1")
+        );
+        assert_eq!(
+            fs::read_link(parent.path().join("base-current")).unwrap(),
+            dir_2.path()
+        );
+    }
+
+    #[test]
+    fn test_numbered_mark_failed() {
+        let parent = tempfile::tempdir().unwrap();
+        let count = NonZeroU8::new(3).unwrap();
+        let last_failed = parent.path().join("base-last-failed");
+        let dir_0 = NumberedDir::create(parent.path(), "base", count).unwrap();
+        dir_0.mark_failed().unwrap();
+        dir_0.mark_failed().unwrap();
+        assert_eq!(fs::read_link(&last_failed).unwrap(), dir_0.path());
+
+        let dir_1 = NumberedDir::create(parent.path(), "base", count).unwrap();
+        assert_eq!(fs::read_link(&last_failed).unwrap(), dir_0.path());
+        dir_1.mark_failed().unwrap();
+        assert_eq!(fs::read_link(&last_failed).unwrap(), dir_1.path());
+    }
+
+    #[test]
+    fn test_numbered_creation_relative_symlink() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut options = CreateOptions::new(Retention::Count(NonZeroU8::new(3).unwrap()));
+        options.relative_symlink = true;
+
+        let dir_0 = NumberedDir::create_with(parent.path(), "base", &options).unwrap();
+        let current = parent.path().join("base-current");
+        assert_eq!(fs::read_link(&current).unwrap(), Path::new("base-0"));
+        assert_eq!(
+            current.canonicalize().unwrap(),
+            dir_0.path().canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_numbered_creation_pointer_file() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut options = CreateOptions::new(Retention::Count(NonZeroU8::new(3).unwrap()));
+
+        let dir_0 = NumberedDir::create_with(parent.path(), "base", &options).unwrap();
+        assert!(!parent.path().join("base-current.path").exists());
+
+        options.pointer_file = true;
+        let dir_1 = NumberedDir::create_with(parent.path(), "base", &options).unwrap();
+        let pointer = fs::read_to_string(parent.path().join("base-current.path")).unwrap();
+        assert_eq!(
+            Path::new(&pointer),
+            dir_1.path().canonicalize().unwrap().as_path()
+        );
+        assert_ne!(dir_0, dir_1);
+    }
+
+    #[test]
+    fn test_numbered_creation_retry() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut options = CreateOptions::new(Retention::Count(NonZeroU8::new(8).unwrap()));
+        // The number wraps around to the taken 0.
+        fs::create_dir(parent.path().join("base-65535")).unwrap();
+        fs::write(parent.path().join("base-0"), "taken").unwrap();
+
+        options.retry = RetryStrategy::new(1);
+        assert!(NumberedDir::create_with(parent.path(), "base", &options).is_err());
+
+        options.retry = RetryStrategy::default();
+        let dir = NumberedDir::create_with(parent.path(), "base", &options).unwrap();
+        assert_eq!(dir.number(), 1);
+    }
+
+    #[test]
+    fn test_numbered_run_id() {
+        let parent = tempfile::tempdir().unwrap();
+        let options = CreateOptions::new(Retention::Count(NonZeroU8::new(2).unwrap()));
+        let numbered = NumberedDir::create_with(parent.path(), "base", &options).unwrap();
+
+        let (dir, reused) =
+            NumberedDir::create_with_run_id(parent.path(), "base", "ci-1", &options).unwrap();
+        assert!(!reused);
+        assert_eq!(dir.path(), parent.path().join("base-ci-1"));
+        assert_eq!(dir.run_id(), Some("ci-1"));
+        assert!(dir.is_compatible());
+        let current = parent.path().join("base-current");
+        assert_eq!(fs::read_link(current).unwrap(), dir.path());
+
+        let (again, reused) =
+            NumberedDir::create_with_run_id(parent.path(), "base", "ci-1", &options).unwrap();
+        assert!(reused);
+        assert_eq!(again, dir);
+
+        for run_id in ["", "a/b", "..", "42", "current"] {
+            assert!(
+                NumberedDir::create_with_run_id(parent.path(), "base", run_id, &options).is_err()
+            );
+        }
+        assert!(numbered.path().is_dir());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_numbered_run_id_retention() {
+        use std::time::Duration;
+
+        let parent = tempfile::tempdir().unwrap();
+        let options = CreateOptions::new(Retention::Count(NonZeroU8::new(2).unwrap()));
+        let epoch = SystemTime::UNIX_EPOCH;
+        for (i, run_id) in ["ci-b", "ci-a"].into_iter().enumerate() {
+            let (dir, _) =
+                NumberedDir::create_with_run_id(parent.path(), "base", run_id, &options).unwrap();
+            let mtime = epoch + Duration::from_secs(i as u64 * 60);
+            fs::File::open(dir.path())
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+        }
+        assert_eq!(
+            run_id_dirs(&StdBackend, parent.path(), "base", "ci-c").unwrap(),
+            vec![
+                parent.path().join("base-ci-a"),
+                parent.path().join("base-ci-b")
+            ]
+        );
+
+        let (_, reused) =
+            NumberedDir::create_with_run_id(parent.path(), "base", "ci-c", &options).unwrap();
+        assert!(!reused);
+        assert!(parent.path().join("base-ci-a").is_dir());
+        assert!(!parent.path().join("base-ci-b").exists());
+        assert!(parent.path().join("base-ci-c").is_dir());
+    }
+
+    #[test]
+    fn test_numbered_run_id_sibling_base() {
+        let parent = tempfile::tempdir().unwrap();
+        let options = CreateOptions::new(Retention::Count(NonZeroU8::new(1).unwrap()));
+        let sibling = NumberedDir::create_with(parent.path(), "foo-bar", &options).unwrap();
+        fs::write(sibling.path().join("file"), "keep").unwrap();
+        let (other, _) =
+            NumberedDir::create_with_run_id(parent.path(), "foo-bar", "ci-1", &options).unwrap();
+        fs::create_dir(parent.path().join("foo-manual")).unwrap();
+
+        assert!(run_id_dirs(&StdBackend, parent.path(), "foo", "ci-1")
+            .unwrap()
+            .is_empty());
+        let (dir, _) =
+            NumberedDir::create_with_run_id(parent.path(), "foo", "ci-1", &options).unwrap();
+        assert_eq!(dir.path(), parent.path().join("foo-ci-1"));
+        assert!(sibling.path().join("file").is_file());
+        assert!(other.path().is_dir());
+        assert!(parent.path().join("foo-manual").is_dir());
+
+        NumberedDir::create_with_run_id(parent.path(), "foo", "ci-2", &options).unwrap();
+        assert!(!dir.path().exists());
+        assert!(sibling.path().join("file").is_file());
+    }
+
+    #[test]
+    fn test_numbered_format_version() {
+        let parent = tempfile::tempdir().unwrap();
+        let dir = NumberedDir::create(parent.path(), "base", NonZeroU8::new(3).unwrap()).unwrap();
+        assert!(dir.is_compatible());
+
+        fs::write(dir.path().join(FORMAT_VERSION_FILE_NAME), "0 0.1").unwrap();
+        assert!(!dir.is_compatible());
+
+        fs::remove_file(dir.path().join(FORMAT_VERSION_FILE_NAME)).unwrap();
+        assert!(!dir.is_compatible());
+    }
+
+    #[test]
+    fn test_numbered_errors() {
+        let parent = tempfile::tempdir().unwrap();
+        let count = NonZeroU8::new(3).unwrap();
+        let options = CreateOptions::new(Retention::default());
+
+        let err = NumberedDir::create(parent.path(), "a/b", count).unwrap_err();
+        assert!(matches!(err, Error::InvalidBase { base } if base == "a/b"));
+
+        let err = Error::from(
+            NumberedDir::create_with_run_id(parent.path(), "base", "42", &options).unwrap_err(),
+        );
+        assert!(matches!(err, Error::InvalidRunId { run_id } if run_id == "42"));
+
+        fs::create_dir(parent.path().join("base-old")).unwrap();
+        let err = Error::from(
+            NumberedDir::create_with_run_id(parent.path(), "base", "old", &options).unwrap_err(),
+        );
+        assert!(matches!(err, Error::ReuseConflict { .. }));
+
+        fs::write(parent.path().join("file"), "").unwrap();
+        let err = NumberedDir::create(parent.path().join("file"), "base", count).unwrap_err();
+        assert!(matches!(err, Error::Io { .. }));
+    }
+
+    #[test]
+    fn test_numbered_adopt() {
+        let parent = tempfile::tempdir().unwrap();
+        let dir_0 = NumberedDir::create(parent.path(), "base", NonZeroU8::new(3).unwrap()).unwrap();
+
+        let existing = parent.path().join("artifacts");
+        fs::create_dir(&existing).unwrap();
+        fs::write(existing.join("old.txt"), "history").unwrap();
+
+        let dir_1 = NumberedDir::adopt(parent.path(), "base", &existing).unwrap();
+        assert_eq!(dir_1.path(), parent.path().join("base-1"));
+        assert_eq!(dir_1.number(), 1);
+        assert!(!existing.exists());
+        assert!(dir_1.is_compatible());
+        assert_eq!(
+            fs::read_to_string(dir_1.path().join("old.txt")).unwrap(),
+            "history"
+        );
+        assert!(dir_0.path().is_dir());
+
+        let dir_2 = NumberedDir::create(parent.path(), "base", NonZeroU8::new(3).unwrap()).unwrap();
+        assert_eq!(dir_2.number(), 2);
+    }
+
+    #[test]
+    fn test_numbered_adopt_exhausted() {
+        let parent = tempfile::tempdir().unwrap();
+        fs::create_dir(parent.path().join("base-65535")).unwrap();
+        let attempts = RetryStrategy::default().max_attempts();
+        for number in 0..attempts {
+            fs::create_dir(parent.path().join(format!("base-{number}"))).unwrap();
+        }
+        let existing = parent.path().join("artifacts");
+        fs::create_dir(&existing).unwrap();
+
+        let res = NumberedDir::adopt(parent.path(), "base", &existing);
+        let last = parent.path().join(format!("base-{}", attempts - 1));
+        assert!(matches!(
+            res,
+            Err(Error::Create { path, source })
+                if path == last && source.kind() == ErrorKind::AlreadyExists
+        ));
+        assert!(existing.is_dir());
+
+        fs::remove_dir(&last).unwrap();
+        let adopted = NumberedDir::adopt(parent.path(), "base", &existing).unwrap();
+        assert_eq!(adopted.path(), last);
+    }
+
+    #[test]
+    fn test_numbered_adopt_current() {
+        let parent = tempfile::tempdir().unwrap();
+        assert!(NumberedDir::adopt_current(parent.path(), "base").is_err());
+
+        NumberedDir::create(parent.path(), "base", NonZeroU8::new(3).unwrap()).unwrap();
+        let dir_1 = NumberedDir::create(parent.path(), "base", NonZeroU8::new(3).unwrap()).unwrap();
+        let current = NumberedDir::adopt_current(parent.path(), "base").unwrap();
+        assert_eq!(current.path(), dir_1.path());
+        assert_eq!(current.number(), 1);
+        assert_eq!(current.run_id(), None);
+        assert!(parent.path().join("base-1").is_dir());
+        assert!(!parent.path().join("base-2").exists());
+
+        let mut options = CreateOptions::new(Retention::Count(NonZeroU8::new(3).unwrap()));
+        options.current_symlink = false;
+        options.pointer_file = true;
+        let dir = NumberedDir::create_with(parent.path(), "ptr", &options).unwrap();
+        let current = NumberedDir::adopt_current(parent.path(), "ptr").unwrap();
+        assert_eq!(
+            current.path().canonicalize().unwrap(),
+            dir.path().canonicalize().unwrap()
+        );
+        assert_eq!(current.number(), 0);
+
+        fs::remove_dir_all(dir_1.path()).unwrap();
+        assert!(NumberedDir::adopt_current(parent.path(), "base").is_err());
+    }
+
     #[test]
     fn test_numbered_subdir() {
         let parent = tempfile::tempdir().unwrap();
@@ -347,6 +1504,153 @@ mod tests {
         assert!(dir.path().join("one").join("two").is_dir());
     }
 
+    #[test]
+    fn test_numbered_subdir_max_depth() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut dir =
+            NumberedDir::create(parent.path(), "base", NonZeroU8::new(3).unwrap()).unwrap();
+        dir.limits.max_depth = Some(2);
+
+        assert!(dir.create_subdir("one/two").is_ok());
+        assert!(dir.create_subdir("one/two/three").is_err());
+    }
+
+    #[test]
+    fn test_numbered_subdir_max_subdirs() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut dir =
+            NumberedDir::create(parent.path(), "base", NonZeroU8::new(3).unwrap()).unwrap();
+        dir.limits.max_subdirs = Some(2);
+
+        assert!(dir.create_subdir("one").is_ok());
+        assert!(dir.create_subdir("two").is_ok());
+        assert!(dir.create_subdir("three").is_err());
+
+        // Re-using an existing subdir is always allowed.
+        assert!(dir.create_subdir("one").is_ok());
+        assert_eq!(
+            dir.created_subdirs(),
+            vec![dir.path().join("one"), dir.path().join("two")]
+        );
+    }
+
+    #[test]
+    fn test_numbered_subdir_collision() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut dir =
+            NumberedDir::create(parent.path(), "base", NonZeroU8::new(3).unwrap()).unwrap();
+        let one = dir.create_subdir("one").unwrap();
+        assert_eq!(dir.create_subdir("one").unwrap(), one);
+
+        dir.collision = Collision::Suffix;
+        assert_eq!(dir.create_subdir("one").unwrap(), dir.path().join("one-1"));
+        assert_eq!(dir.create_subdir("one").unwrap(), dir.path().join("one-2"));
+        assert_eq!(dir.create_subdir("two").unwrap(), dir.path().join("two"));
+
+        dir.collision = Collision::Error;
+        assert!(matches!(
+            dir.create_subdir("one"),
+            Err(Error::SubdirExists { path }) if path == one
+        ));
+        assert!(dir.create_subdir("three").is_ok());
+    }
+
+    #[test]
+    fn test_numbered_subdir_collision_max_suffix() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut dir =
+            NumberedDir::create(parent.path(), "base", NonZeroU8::new(3).unwrap()).unwrap();
+        dir.collision = Collision::Suffix;
+        fs::create_dir(dir.path().join("full")).unwrap();
+        for suffix in 1..MAX_SUBDIR_SUFFIX {
+            fs::create_dir(dir.path().join(format!("full-{suffix}"))).unwrap();
+        }
+        assert_eq!(
+            dir.create_subdir("full").unwrap(),
+            dir.path().join(format!("full-{MAX_SUBDIR_SUFFIX}"))
+        );
+        assert!(dir.create_subdir("full").is_err());
+    }
+
+    #[test]
+    fn test_numbered_subdir_portable() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut dir =
+            NumberedDir::create(parent.path(), "base", NonZeroU8::new(3).unwrap()).unwrap();
+        let nul = dir.create_subdir("mod/nul").unwrap();
+        assert_eq!(nul, dir.path().join("mod/_nul"));
+        let names = RunKv::open(dir.path().join(PATH_NAMES_FILE_NAME));
+        assert_eq!(names.get("mod/_nul").unwrap().as_deref(), Some("mod/nul"));
+        assert_eq!(dir.subdir_path("mod/nul").unwrap(), nul);
+
+        let nested = dir.create_subdir("con/test").unwrap();
+        assert_eq!(nested, dir.path().join("_con/test"));
+        assert_eq!(
+            dir.create_subdir("a./b").unwrap(),
+            dir.path().join("a%2E/b")
+        );
+        assert!(!dir.path().join("con").exists());
+        let names = RunKv::open(dir.path().join(PATH_NAMES_FILE_NAME));
+        assert_eq!(names.get("_con/test").unwrap().as_deref(), Some("con/test"));
+        assert_eq!(
+            dir.create_subdir_strict("aux/test").unwrap(),
+            dir.path().join("_aux/test")
+        );
+
+        let upper = dir.create_subdir("Mod/test").unwrap();
+        let lower = dir.create_subdir("mod/test").unwrap();
+        assert_eq!(lower, dir.path().join("mod/test"));
+        assert_ne!(upper, lower);
+        assert!(upper.is_dir());
+
+        dir.portable_names = PortableNames::Reject;
+        assert!(matches!(
+            dir.create_subdir("test."),
+            Err(Error::NonPortableName { .. })
+        ));
+        dir.portable_names = PortableNames::Allow;
+        if cfg!(unix) {
+            assert_eq!(
+                dir.create_subdir("test.").unwrap(),
+                dir.path().join("test.")
+            );
+        }
+    }
+
+    #[test]
+    fn test_numbered_subdir_strict() {
+        let parent = tempfile::tempdir().unwrap();
+        let dir = NumberedDir::create(parent.path(), "base", NonZeroU8::new(3).unwrap()).unwrap();
+
+        let sub = dir.create_subdir_strict("./one/two").unwrap();
+        assert_eq!(sub, dir.path().join("one/two"));
+        assert!(matches!(
+            dir.create_subdir_strict("one/../../escape"),
+            Err(Error::PathTraversal { .. })
+        ));
+        assert!(!parent.path().join("escape").exists());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(parent.path(), dir.path().join("link")).unwrap();
+            assert!(matches!(
+                dir.create_subdir_strict("link/escape"),
+                Err(Error::PathTraversal { .. })
+            ));
+            assert!(!parent.path().join("escape").exists());
+
+            std::os::unix::fs::symlink(dir.path().join("one"), dir.path().join("inside")).unwrap();
+            assert!(dir.create_subdir_strict("inside/three").is_ok());
+
+            std::os::unix::fs::symlink(parent.path(), dir.path().join("_nul")).unwrap();
+            assert!(matches!(
+                dir.create_subdir_strict("nul/escape"),
+                Err(Error::PathTraversal { .. })
+            ));
+            assert!(!parent.path().join("escape").exists());
+        }
+    }
+
     #[test]
     fn test_iter() {
         let parent = tempfile::tempdir().unwrap();
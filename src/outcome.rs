@@ -0,0 +1,164 @@
+//! Recording of test outcomes, see [`record_outcome`].
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::created::{owner_of, test_dir_for_thread};
+use crate::Error;
+
+/// The filename in which the outcome of a test is stored: `.testdir-outcome`.
+pub(crate) const OUTCOME_FILE_NAME: &str = ".testdir-outcome";
+
+/// The outcome of a test, see [`record_outcome`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// The test passed.
+    Passed,
+    /// The test failed.
+    Failed,
+    /// The test was skipped, e.g. because a required resource was not available.
+    Skipped,
+}
+
+impl Outcome {
+    /// Returns the lowercase name of the outcome, as stored in the test directory.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Passed => "passed",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+
+    /// Parses the name returned by [`Outcome::as_str`].
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "passed" => Some(Self::Passed),
+            "failed" => Some(Self::Failed),
+            "skipped" => Some(Self::Skipped),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Records the outcome of the current test in its test directory.
+///
+/// The test directory is the one created by `testdir!()` or `testdir!(TestScope)` in the
+/// current thread, which is the test's own thread when using the standard test harness.
+/// The outcome is stored in a `.testdir-outcome` file and available from
+/// [`RunSubdir::outcome`].  Recording [`Outcome::Failed`] also marks the numbered
+/// directory containing the test directory as failed, see [`NumberedDir::mark_failed`].
+/// This is the numbered directory the macro used, e.g. a named one for
+/// `testdir!(named = "scratch")` or the one installed by [`scoped_override`].
+///
+/// Use an [`OutcomeGuard`] to record the outcome automatically.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{testdir, Outcome};
+///
+/// let dir = testdir!();
+/// testdir::record_outcome(Outcome::Skipped).unwrap();
+/// ```
+///
+/// [`RunSubdir::outcome`]: crate::RunSubdir::outcome
+/// [`NumberedDir::mark_failed`]: crate::NumberedDir::mark_failed
+/// [`scoped_override`]: crate::scoped_override
+pub fn record_outcome(outcome: Outcome) -> Result<(), Error> {
+    let dir = thread::current()
+        .name()
+        .and_then(test_dir_for_thread)
+        .ok_or_else(|| anyhow!("No test directory created by this thread"))?;
+    record_outcome_at(&dir, outcome)?;
+    if outcome == Outcome::Failed {
+        let numdir = owner_of(&dir)
+            .ok_or_else(|| anyhow!("No numbered directory found for {}", dir.display()))?;
+        numdir.mark_failed()?;
+    }
+    Ok(())
+}
+
+/// Records the outcome of a test in the test directory `dir`.
+///
+/// This is like [`record_outcome`] for custom harnesses which know the test directory,
+/// but does not mark the numbered directory as failed.
+pub fn record_outcome_at(dir: impl AsRef<Path>, outcome: Outcome) -> Result<(), Error> {
+    let path = dir.as_ref().join(OUTCOME_FILE_NAME);
+    fs::write(&path, outcome.as_str())
+        .with_context(|| format!("Failed to write {}", path.display()))
+        .map_err(Error::from)
+}
+
+/// Reads the outcome recorded in the test directory `dir`, if any.
+pub(crate) fn read_outcome(dir: &Path) -> Option<Outcome> {
+    let content = fs::read_to_string(dir.join(OUTCOME_FILE_NAME)).ok()?;
+    Outcome::parse(content.trim())
+}
+
+/// Records the outcome of the current test when dropped.
+///
+/// If the thread is panicking when the guard is dropped [`Outcome::Failed`] is recorded,
+/// otherwise [`Outcome::Passed`].  Create it at the start of a test, after the test
+/// directory was created, and keep it alive for the whole test.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{testdir, OutcomeGuard};
+///
+/// let dir = testdir!();
+/// let _guard = OutcomeGuard::new();
+/// assert!(dir.is_dir());
+/// ```
+#[derive(Debug, Default)]
+#[must_use = "the outcome is recorded when the guard is dropped"]
+pub struct OutcomeGuard {
+    _private: (),
+}
+
+impl OutcomeGuard {
+    /// Creates a guard for the test running on the current thread.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Drop for OutcomeGuard {
+    fn drop(&mut self) {
+        let outcome = if thread::panicking() {
+            Outcome::Failed
+        } else {
+            Outcome::Passed
+        };
+        if let Err(err) = record_outcome(outcome) {
+            eprintln!("testdir: failed to record outcome: {err:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_outcome_at() {
+        let temp = tempfile::tempdir().unwrap();
+        assert_eq!(read_outcome(temp.path()), None);
+        record_outcome_at(temp.path(), Outcome::Failed).unwrap();
+        assert_eq!(read_outcome(temp.path()), Some(Outcome::Failed));
+        record_outcome_at(temp.path(), Outcome::Passed).unwrap();
+        assert_eq!(read_outcome(temp.path()), Some(Outcome::Passed));
+        assert_eq!(Outcome::Skipped.to_string(), "skipped");
+    }
+}
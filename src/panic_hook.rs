@@ -0,0 +1,97 @@
+//! Writing panic reports into test directories, see [`install_panic_hook`].
+
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic;
+use std::sync::Once;
+use std::thread;
+
+use crate::created::test_dir_for_thread;
+
+/// The filename of the panic report in the test directory: `panic.txt`.
+pub const PANIC_FILE_NAME: &str = "panic.txt";
+
+/// Installs a panic hook writing a report into the directory of the panicking test.
+///
+/// The report is written to `panic.txt` in the test directory created by `testdir!()` or
+/// `testdir!(TestScope)` on the panicking thread, and contains the panic message, its
+/// location and a backtrace.  This makes the retained directories of failed tests
+/// self-describing.  Panics on threads without a test directory are not reported.  The
+/// previously installed hook still runs, so the panic is printed as usual.
+///
+/// This is called by [`init_testdir!`](crate::init_testdir) when the `panic-hook` feature
+/// is enabled, calling it again has no effect.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{testdir, PANIC_FILE_NAME};
+///
+/// let dir = testdir!();
+/// let result = std::panic::catch_unwind(|| panic!("oops"));
+/// assert!(result.is_err());
+/// let report = std::fs::read_to_string(dir.join(PANIC_FILE_NAME)).unwrap();
+/// assert!(report.contains("oops"));
+/// ```
+pub fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let prev = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let payload = info.payload();
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("Box<dyn Any>");
+            let location = info.location().map(|loc| loc.to_string());
+            write_report(message, location.as_deref());
+            prev(info);
+        }));
+    });
+}
+
+/// Writes the panic report for the current thread, if it has a test directory.
+fn write_report(message: &str, location: Option<&str>) {
+    let Some(dir) = thread::current().name().and_then(test_dir_for_thread) else {
+        return;
+    };
+    let backtrace = if crate::private::is_restricted() {
+        Backtrace::disabled()
+    } else {
+        Backtrace::force_capture()
+    };
+    let report = format_report(
+        thread::current().name().unwrap_or("<unnamed>"),
+        message,
+        location,
+        &backtrace.to_string(),
+    );
+    let path = dir.join(PANIC_FILE_NAME);
+    if let Err(err) = fs::write(&path, report) {
+        eprintln!("testdir: failed to write {}: {err}", path.display());
+    }
+}
+
+fn format_report(thread: &str, message: &str, location: Option<&str>, backtrace: &str) -> String {
+    format!(
+        "thread: {thread}\nmessage: {message}\nlocation: {}\n\nbacktrace:\n{backtrace}\n",
+        location.unwrap_or("<unknown>"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_report() {
+        let report = format_report("tests::it", "oops", Some("src/lib.rs:1:2"), "0: main");
+        assert_eq!(
+            report,
+            "thread: tests::it\nmessage: oops\nlocation: src/lib.rs:1:2\n\nbacktrace:\n0: main\n"
+        );
+        let report = format_report("tests::it", "oops", None, "");
+        assert!(report.contains("location: <unknown>"));
+    }
+}
@@ -0,0 +1,65 @@
+//! Mapping module paths to directory paths.
+//!
+//! The [`testdir!`] macro uses the module path of the calling code to build the directory
+//! structure inside the [`NumberedDir`].  How this module path is turned into a relative
+//! directory path can be customised using a [`PathMapper`].
+//!
+//! [`NumberedDir`]: crate::NumberedDir
+//! [`testdir!`]: crate::testdir
+
+use std::path::PathBuf;
+
+/// Function which transforms a module path into a relative directory path.
+///
+/// The function receives the module path as returned by [`std::module_path!`], e.g.
+/// `mycrate::tests::r#macro` and must return a relative path.  It can be installed using
+/// `init_testdir!(path_mapper = my_fn)`.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::PathBuf;
+///
+/// fn strip_tests(module_path: &str) -> PathBuf {
+///     let path = module_path.replace("::tests", "");
+///     testdir::map_module_path(&path)
+/// }
+///
+/// let mapper: testdir::PathMapper = strip_tests;
+/// assert_eq!(mapper("mycrate::tests::sub"), PathBuf::from("mycrate/sub"));
+/// ```
+pub type PathMapper = fn(&str) -> PathBuf;
+
+/// The default [`PathMapper`], uses each module path component as a directory.
+///
+/// E.g. `mycrate::tests::r#macro` becomes `mycrate/tests/r#macro`.
+pub fn map_module_path(module_path: &str) -> PathBuf {
+    module_path.split("::").collect()
+}
+
+/// A [`PathMapper`] which strips the `r#` prefix of raw identifiers.
+///
+/// E.g. `mycrate::tests::r#macro` becomes `mycrate/tests/macro`.
+pub fn map_module_path_unraw(module_path: &str) -> PathBuf {
+    module_path
+        .split("::")
+        .map(|component| component.strip_prefix("r#").unwrap_or(component))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_module_path() {
+        let path = map_module_path("mycrate::tests::r#macro");
+        assert_eq!(path, PathBuf::from("mycrate/tests/r#macro"));
+    }
+
+    #[test]
+    fn test_map_module_path_unraw() {
+        let path = map_module_path_unraw("mycrate::tests::r#macro");
+        assert_eq!(path, PathBuf::from("mycrate/tests/macro"));
+    }
+}
@@ -0,0 +1,194 @@
+//! The [`PermissionMatrix`] and supporting code.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::Error;
+
+/// A declared set of files and directories with specific permissions.
+///
+/// Code handling permission errors is awkward to test: the fixtures need to be created
+/// with the right modes, and afterwards the modes need to be restored or the directory
+/// can no longer be removed.  A [`PermissionMatrix`] creates all the declared entries
+/// inside a directory and returns a [`PermissionGuard`] which restores permissive modes
+/// when dropped.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{testdir, PermissionMatrix};
+///
+/// let dir = testdir!();
+/// let guard = PermissionMatrix::new()
+///     .file("unreadable.txt", 0o000)
+///     .dir("no-exec", 0o600)
+///     .file("no-exec/hidden.txt", 0o644)
+///     .create(&dir)
+///     .unwrap();
+/// assert!(dir.join("unreadable.txt").exists());
+/// drop(guard);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PermissionMatrix {
+    entries: Vec<Entry>,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    path: PathBuf,
+    kind: Kind,
+    mode: u32,
+    owner: Option<(u32, u32)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    File,
+    Dir,
+}
+
+impl PermissionMatrix {
+    /// Creates an empty permission matrix.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares an empty file at `rel` with the unix permission bits `mode`.
+    pub fn file(mut self, rel: impl AsRef<Path>, mode: u32) -> Self {
+        self.entries.push(Entry {
+            path: rel.as_ref().to_path_buf(),
+            kind: Kind::File,
+            mode,
+            owner: None,
+        });
+        self
+    }
+
+    /// Declares a directory at `rel` with the unix permission bits `mode`.
+    pub fn dir(mut self, rel: impl AsRef<Path>, mode: u32) -> Self {
+        self.entries.push(Entry {
+            path: rel.as_ref().to_path_buf(),
+            kind: Kind::Dir,
+            mode,
+            owner: None,
+        });
+        self
+    }
+
+    /// Sets the owner of the entry declared last.
+    ///
+    /// Changing ownership usually requires elevated privileges, in which case
+    /// [`PermissionMatrix::create`] fails.
+    ///
+    /// # Panics
+    ///
+    /// If no entry was declared yet.
+    pub fn owner(mut self, uid: u32, gid: u32) -> Self {
+        let entry = self
+            .entries
+            .last_mut()
+            .expect("PermissionMatrix::owner() called before declaring an entry");
+        entry.owner = Some((uid, gid));
+        self
+    }
+
+    /// Creates all declared entries inside `dir`.
+    ///
+    /// All entries are created first, and only then their modes are applied, deepest
+    /// entries first, so that an inaccessible directory can still have its contents set up.
+    pub fn create(&self, dir: impl AsRef<Path>) -> Result<PermissionGuard, Error> {
+        let dir = dir.as_ref();
+        let mut guard = PermissionGuard {
+            entries: Vec::with_capacity(self.entries.len()),
+        };
+        for entry in &self.entries {
+            let path = dir.join(&entry.path);
+            match entry.kind {
+                Kind::Dir => fs::create_dir_all(&path),
+                Kind::File => path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(&path, b"")),
+            }
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+            guard.entries.push((path, entry.kind));
+        }
+
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.path.components().count()));
+        for entry in entries {
+            let path = dir.join(&entry.path);
+            if let Some((uid, gid)) = entry.owner {
+                std::os::unix::fs::chown(&path, Some(uid), Some(gid))
+                    .with_context(|| format!("Failed to change owner of {}", path.display()))?;
+            }
+            fs::set_permissions(&path, fs::Permissions::from_mode(entry.mode))
+                .with_context(|| format!("Failed to set permissions of {}", path.display()))?;
+        }
+        Ok(guard)
+    }
+}
+
+/// Restores permissive modes on the entries created by a [`PermissionMatrix`].
+///
+/// When dropped directories are made `0o700` and files `0o600`, so that the directory can
+/// be removed again.  Ownership is not restored.
+#[derive(Debug)]
+#[must_use = "permissions are restored when the guard is dropped"]
+pub struct PermissionGuard {
+    entries: Vec<(PathBuf, Kind)>,
+}
+
+impl PermissionGuard {
+    /// Restores permissive modes now, rather than when dropped.
+    pub fn restore(&mut self) -> Result<(), Error> {
+        // Parents first, so their contents become reachable.
+        self.entries
+            .sort_by_key(|(path, _)| path.components().count());
+        for (path, kind) in self.entries.drain(..) {
+            let mode = match kind {
+                Kind::Dir => 0o700,
+                Kind::File => 0o600,
+            };
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode))
+                .with_context(|| format!("Failed to restore permissions of {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PermissionGuard {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore() {
+            eprintln!("testdir: {err:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permission_matrix() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().join("root");
+        let guard = PermissionMatrix::new()
+            .dir("locked", 0o000)
+            .file("locked/inner.txt", 0o400)
+            .file("ro.txt", 0o444)
+            .create(&root)
+            .unwrap();
+        let mode = |p: &str| fs::metadata(root.join(p)).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode("locked"), 0o000);
+        assert_eq!(mode("ro.txt"), 0o444);
+
+        drop(guard);
+        assert_eq!(mode("locked"), 0o700);
+        assert_eq!(mode("locked/inner.txt"), 0o600);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
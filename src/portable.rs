@@ -0,0 +1,238 @@
+//! Portable names for subdirectories, see [`PortableNames`].
+
+use std::ffi::{OsStr, OsString};
+use std::path::{Component, Path, PathBuf};
+
+use crate::backend::DirBackend;
+use crate::Error;
+
+/// The file name in the numbered directory recording encoded subdirectory names:
+/// `path-names.json`.
+///
+/// This is a JSON object mapping each encoded relative path, as created by
+/// [`NumberedDir::create_subdir`], to the relative path which was requested.
+///
+/// [`NumberedDir::create_subdir`]: crate::NumberedDir::create_subdir
+pub const PATH_NAMES_FILE_NAME: &str = "path-names.json";
+
+/// The device names reserved on Windows, regardless of case or extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// How subdirectory names which can not be used on Windows are handled.
+///
+/// Windows does not allow file names which are reserved device names, like `CON`, `NUL`
+/// or `COM1`, also not with an extension like `nul.txt`.  Neither does it allow names
+/// ending in a dot or space.  To make test suites behave the same on all platforms such
+/// names are handled on all platforms, by default they are encoded:
+///
+/// - Reserved device names get a `_` prefix, e.g. `con.txt` becomes `_con.txt`.
+/// - Trailing dots and spaces are percent-encoded as `%2E` and `%20`, e.g. `name.`
+///   becomes `name%2E`.
+///
+/// Likewise names which only differ in case, e.g. `Foo` and `foo`, would refer to the same
+/// directory on case-insensitive filesystems.  So names containing uppercase characters get
+/// a `~` and the 8 hex digits of the 32-bit FNV-1a hash of their UTF-8 bytes appended,
+/// e.g. `Foo` becomes `Foo~<hash>`, while all-lowercase names are used as given.  This
+/// only depends on the name itself, so a name always maps to the same directory no matter
+/// which clashing names exist or in which order they are created.  With
+/// [`PortableNames::Reject`] only names differing in case from an existing sibling are
+/// rejected.
+///
+/// Encoded names are recorded in the [`PATH_NAMES_FILE_NAME`] file of the numbered
+/// directory.  This is configured using [`NumberedDirBuilder::portable_names`].
+///
+/// [`NumberedDirBuilder::portable_names`]: crate::NumberedDirBuilder::portable_names
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PortableNames {
+    /// Encodes non-portable names, the default.
+    #[default]
+    Encode,
+    /// Returns [`Error::NonPortableName`] for non-portable names.
+    Reject,
+    /// Uses names as given.
+    Allow,
+}
+
+/// Applies `mode` to all components of the relative path `rel_path` inside `root`.
+pub(crate) fn portable_path(
+    backend: &dyn DirBackend,
+    root: &Path,
+    rel_path: &Path,
+    mode: PortableNames,
+) -> Result<PathBuf, Error> {
+    if mode == PortableNames::Allow {
+        return Ok(rel_path.to_path_buf());
+    }
+    let reject = || Error::NonPortableName {
+        path: rel_path.to_path_buf(),
+    };
+    let mut path = PathBuf::new();
+    for component in rel_path.components() {
+        let Component::Normal(name) = component else {
+            path.push(component);
+            continue;
+        };
+        let uppercase = has_uppercase(name);
+        let mut name = match encode_name(name) {
+            Some(_) if mode == PortableNames::Reject => return Err(reject()),
+            Some(encoded) => encoded,
+            None => name.to_os_string(),
+        };
+        match mode {
+            PortableNames::Reject if has_case_clash(backend, &root.join(&path), &name) => {
+                return Err(reject())
+            }
+            PortableNames::Encode if uppercase => {
+                let hash = fnv1a(name.as_encoded_bytes());
+                name.push(format!("~{hash:08x}"));
+            }
+            _ => (),
+        }
+        path.push(name);
+    }
+    Ok(path)
+}
+
+/// Whether `name` is not all-lowercase, so it could clash with a name differing in case.
+///
+/// This is checked before encoding, the percent-encoding itself uses uppercase digits.
+fn has_uppercase(name: &OsStr) -> bool {
+    name.to_str()
+        .is_some_and(|name| name.to_lowercase() != name)
+}
+
+/// Whether `dir` has an entry only differing in case from `name`, but none named `name`.
+fn has_case_clash(backend: &dyn DirBackend, dir: &Path, name: &OsStr) -> bool {
+    let Some(lower) = name.to_str().map(str::to_lowercase) else {
+        return false;
+    };
+    let Ok(entries) = backend.read_dir(dir) else {
+        return false;
+    };
+    let mut clash = false;
+    for entry in entries {
+        let Some(entry_name) = entry.file_name() else {
+            continue;
+        };
+        if entry_name == name {
+            return false;
+        }
+        clash |= entry_name.to_str().map(str::to_lowercase).as_ref() == Some(&lower);
+    }
+    clash
+}
+
+/// The 32-bit FNV-1a hash.
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Returns the encoded name if `name` is not portable.
+pub(crate) fn encode_name(name: &OsStr) -> Option<OsString> {
+    let name = name.to_str()?;
+    let trimmed = name.trim_end_matches(['.', ' ']);
+    let stem = trimmed.split('.').next().unwrap_or_default().trim_end();
+    let reserved = RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved));
+    if !reserved && trimmed.len() == name.len() {
+        return None;
+    }
+    let mut encoded = String::with_capacity(name.len() + 4);
+    if reserved {
+        encoded.push('_');
+    }
+    encoded.push_str(trimmed);
+    for c in name[trimmed.len()..].chars() {
+        encoded.push_str(if c == '.' { "%2E" } else { "%20" });
+    }
+    Some(encoded.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::StdBackend;
+
+    #[test]
+    fn test_encode_name() {
+        let encode = |name: &str| encode_name(OsStr::new(name)).map(|s| s.into_string().unwrap());
+        assert_eq!(encode("test_con"), None);
+        assert_eq!(encode("console"), None);
+        assert_eq!(encode(".hidden"), None);
+        assert_eq!(encode("CON").as_deref(), Some("_CON"));
+        assert_eq!(encode("nul.txt").as_deref(), Some("_nul.txt"));
+        assert_eq!(encode("Com1 .tar.gz").as_deref(), Some("_Com1 .tar.gz"));
+        assert_eq!(encode("name.").as_deref(), Some("name%2E"));
+        assert_eq!(encode("name. ").as_deref(), Some("name%2E%20"));
+        assert_eq!(encode("aux.").as_deref(), Some("_aux%2E"));
+    }
+
+    #[test]
+    fn test_portable_path() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let path = Path::new("mod/con/test.");
+        assert_eq!(
+            portable_path(&StdBackend, root, path, PortableNames::Encode).unwrap(),
+            Path::new("mod/_con/test%2E")
+        );
+        assert_eq!(
+            portable_path(&StdBackend, root, path, PortableNames::Allow).unwrap(),
+            path
+        );
+        assert!(matches!(
+            portable_path(&StdBackend, root, path, PortableNames::Reject),
+            Err(Error::NonPortableName { .. })
+        ));
+        assert_eq!(
+            portable_path(
+                &StdBackend,
+                root,
+                Path::new("mod/test"),
+                PortableNames::Reject
+            )
+            .unwrap(),
+            Path::new("mod/test")
+        );
+    }
+
+    #[test]
+    fn test_portable_path_case_clash() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let encode =
+            |path: &str| portable_path(&StdBackend, root, Path::new(path), PortableNames::Encode);
+        let module = format!("Mod~{:08x}", fnv1a(b"Mod"));
+        let foo = format!("{module}/Foo~{:08x}", fnv1a(b"Foo"));
+        assert_eq!(encode("Mod/Foo").unwrap(), Path::new(&foo));
+        assert_eq!(encode("mod/foo").unwrap(), Path::new("mod/foo"));
+
+        // The mapping does not depend on which names already exist.
+        std::fs::create_dir_all(root.join("mod/foo")).unwrap();
+        std::fs::create_dir_all(root.join(&foo)).unwrap();
+        assert_eq!(encode("Mod/Foo").unwrap(), Path::new(&foo));
+        assert_eq!(encode("mod/foo").unwrap(), Path::new("mod/foo"));
+        let upper = format!("{module}/FOO~{:08x}", fnv1a(b"FOO"));
+        assert_eq!(encode("Mod/FOO").unwrap(), Path::new(&upper));
+
+        let reject =
+            |path: &str| portable_path(&StdBackend, root, Path::new(path), PortableNames::Reject);
+        assert_eq!(reject("mod/foo").unwrap(), Path::new("mod/foo"));
+        assert!(matches!(
+            reject("mod/FOO"),
+            Err(Error::NonPortableName { .. })
+        ));
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0x811c_9dc5);
+        assert_eq!(fnv1a(b"a"), 0xe40c_292c);
+    }
+}
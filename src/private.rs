@@ -5,25 +5,35 @@
 //! you do discover this module please do refrain from using it directly, there is no API
 //! stability and this will violate semvers.
 
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 
-use once_cell::sync::Lazy;
-use sysinfo::{Pid, ProcessExt, SystemExt};
+use once_cell::sync::{Lazy, OnceCell};
+use sysinfo::{Pid, PidExt};
 
 pub use cargo_metadata;
+#[cfg(feature = "ctor")]
+pub use ctor::ctor;
 
 /// The filename in which we store the Cargo PID: `cargo-pid`.
-const CARGO_PID_FILE_NAME: &str = "cargo-pid";
+pub(crate) const CARGO_PID_FILE_NAME: &str = "cargo-pid";
 
 /// Whether we are a cargo sub-process.
-static CARGO_PID: Lazy<Option<Pid>> = Lazy::new(cargo_pid);
+static CARGO_PID: Lazy<Option<Pid>> = Lazy::new(cached_cargo_pid);
 
-#[cfg(target_family = "unix")]
+/// The environment variable caching the Cargo PID for child processes: `TESTDIR_CARGO_PID`.
+///
+/// The value is `<cargo-pid>:<pid>`, with an empty Cargo PID if there is no Cargo parent
+/// process, where `<pid>` is the process which set it.
+const CARGO_PID_ENV: &str = "TESTDIR_CARGO_PID";
+
+#[cfg(not(target_family = "windows"))]
 const CARGO_NAME: &str = "cargo";
 
-#[cfg(target_family = "unix")]
+#[cfg(not(target_family = "windows"))]
 const NEXTEST_NAME: &str = "cargo-nextest";
 
 #[cfg(target_family = "windows")]
@@ -32,6 +42,57 @@ const CARGO_NAME: &str = "cargo.exe";
 #[cfg(target_family = "windows")]
 const NEXTEST_NAME: &str = "cargo-nextest.exe";
 
+/// Returns the Cargo PID, using the value cached by our parent process if available.
+///
+/// Looking up processes is slow with many processes, so the Cargo PID is exported in
+/// [`CARGO_PID_ENV`] to the commands spawned using
+/// [`TestDir::export_env`](crate::TestDir::export_env), see [`cargo_pid_env`].  The cached
+/// value is only used by direct children of the process which set it, so e.g. a nested
+/// `cargo test` run by a test still finds its own Cargo process.
+fn cached_cargo_pid() -> Option<Pid> {
+    let cached = std::env::var(CARGO_PID_ENV)
+        .ok()
+        .zip(parent_pid())
+        .and_then(|(value, ppid)| parse_cached_cargo_pid(&value, ppid));
+    match cached {
+        Some(cargo_pid) => cargo_pid,
+        None => cargo_pid(),
+    }
+}
+
+/// Returns the [`CARGO_PID_ENV`] variable to set on a child process.
+///
+/// The environment of the test process itself is not modified, since that is not safe while
+/// other test threads run.
+pub(crate) fn cargo_pid_env() -> Option<(&'static str, OsString)> {
+    if is_restricted() {
+        return None;
+    }
+    let value = CARGO_PID.map(|pid| pid.to_string()).unwrap_or_default();
+    Some((
+        CARGO_PID_ENV,
+        format!("{value}:{}", std::process::id()).into(),
+    ))
+}
+
+/// Parses the value of [`CARGO_PID_ENV`], if it was set by the process with PID `ppid`.
+fn parse_cached_cargo_pid(value: &str, ppid: u32) -> Option<Option<Pid>> {
+    let (cargo_pid, setter) = value.rsplit_once(':')?;
+    if setter.parse::<u32>().ok()? != ppid {
+        return None;
+    }
+    if cargo_pid.is_empty() {
+        Some(None)
+    } else {
+        cargo_pid.parse().ok().map(Some)
+    }
+}
+
+/// Returns the process ID of our parent process.
+fn parent_pid() -> Option<u32> {
+    crate::process::parent_pid(std::process::id())
+}
+
 /// Returns the process ID of our parent Cargo process.
 ///
 /// If our parent process is not Cargo, `None` is returned.
@@ -45,26 +106,20 @@ const NEXTEST_NAME: &str = "cargo-nextest.exe";
 // assert!(pidfile.is_file());
 // ```
 fn cargo_pid() -> Option<Pid> {
-    let mut sys = sysinfo::System::new();
-    let pid = sysinfo::get_current_pid().ok()?;
-    let what = sysinfo::ProcessRefreshKind::new();
-    sys.refresh_process_specifics(pid, what);
-    let current = sys.process(pid)?;
-    let ppid = current.parent()?;
-    sys.refresh_process_specifics(ppid, what);
-    let parent = sys.process(ppid)?;
-    let parent_exe = parent.exe();
+    if is_restricted() {
+        return None;
+    }
+    let ppid = crate::process::parent_pid(std::process::id())?;
+    let parent_exe = crate::process::exe_path(ppid)?;
     let parent_file_name = parent_exe.file_name()?;
     if parent_file_name == OsStr::new(CARGO_NAME) || parent_file_name == OsStr::new(NEXTEST_NAME) {
-        Some(parent.pid())
+        Some(Pid::from_u32(ppid))
     } else if parent_file_name == OsStr::new("rustdoc") {
-        let ppid = parent.parent()?;
-        sys.refresh_process_specifics(ppid, what);
-        let parent = sys.process(ppid)?;
-        let parent_exe = parent.exe();
+        let ppid = crate::process::parent_pid(ppid)?;
+        let parent_exe = crate::process::exe_path(ppid)?;
         let parent_file_name = parent_exe.file_name()?;
         if parent_file_name == OsStr::new("cargo") {
-            Some(parent.pid())
+            Some(Pid::from_u32(ppid))
         } else {
             None
         }
@@ -94,15 +149,501 @@ pub fn reuse_cargo(dir: &Path) -> bool {
 
 /// Creates a file storing the Cargo PID if not yet present.
 ///
+/// The file is flushed to disk if the directory was created by a
+/// [`durable`](crate::NumberedDirBuilder::durable) builder.
+///
 /// # Panics
 ///
 /// If the PID file could not be created or written.
-pub fn create_cargo_pid_file(dir: &Path) {
+pub fn create_cargo_pid_file(numdir: &crate::NumberedDir) {
     if let Some(cargo_pid) = *CARGO_PID {
-        let file_name = dir.join(CARGO_PID_FILE_NAME);
+        let file_name = numdir.path().join(CARGO_PID_FILE_NAME);
         if !file_name.exists() {
             fs::write(&file_name, cargo_pid.to_string()).expect("Failed to write Cargo PID");
+            if numdir.durable {
+                use crate::backend::{DirBackend, StdBackend};
+                StdBackend
+                    .sync(&file_name)
+                    .expect("Failed to sync Cargo PID");
+                StdBackend
+                    .sync(numdir.path())
+                    .expect("Failed to sync Cargo PID");
+            }
+        }
+    }
+}
+
+/// The storage of the global [`NumberedDir`](crate::NumberedDir).
+///
+/// This behaves like a `OnceCell`, except that it can be reset when the
+/// `testing-internals` feature is enabled.  The directories are leaked so they can be handed
+/// out with a `'static` lifetime which remains valid after a reset.
+///
+/// The directory is only locked to read or publish it, not while it is being initialised.
+/// This allows the builder hooks run by the initialisation to call e.g.
+/// [`is_initialized`](crate::is_initialized), which returns `false` until initialisation
+/// finishes.
+#[derive(Debug)]
+pub struct GlobalTestdir {
+    dir: Mutex<Option<&'static crate::NumberedDir>>,
+    /// Serialises initialisation so only a single directory is created.
+    init: Mutex<()>,
+}
+
+thread_local! {
+    /// Whether this thread is running the `init` function of a [`GlobalTestdir`].
+    static INITIALISING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Marks the current thread as initialising a [`GlobalTestdir`] while alive.
+struct InitialisingGuard;
+
+impl InitialisingGuard {
+    fn new() -> Self {
+        if INITIALISING.with(|initialising| initialising.replace(true)) {
+            panic!("The global testdir was used while it is being initialised, e.g. from a builder hook");
+        }
+        Self
+    }
+}
+
+impl Drop for InitialisingGuard {
+    fn drop(&mut self) {
+        INITIALISING.with(|initialising| initialising.set(false));
+    }
+}
+
+impl GlobalTestdir {
+    /// Creates the uninitialised storage.
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self {
+            dir: Mutex::new(None),
+            init: Mutex::new(()),
+        }
+    }
+
+    /// Returns the global directory, if initialised.
+    pub fn get(&self) -> Option<&'static crate::NumberedDir> {
+        *self.lock()
+    }
+
+    /// Returns the global directory, initialising it using `init` if needed.
+    pub fn get_or_init<F>(&self, init: F) -> &'static crate::NumberedDir
+    where
+        F: FnOnce() -> crate::NumberedDir,
+    {
+        match self.get_or_try_init(|| Ok::<_, std::convert::Infallible>(init())) {
+            Ok(dir) => dir,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Returns the global directory, initialising it using `init` if needed.
+    ///
+    /// If `init` fails the directory remains uninitialised.  Other threads calling this
+    /// wait for `init` to finish, while using the global directory from `init` itself
+    /// panics instead of deadlocking.
+    pub fn get_or_try_init<F, E>(&self, init: F) -> Result<&'static crate::NumberedDir, E>
+    where
+        F: FnOnce() -> Result<crate::NumberedDir, E>,
+    {
+        if let Some(dir) = self.get() {
+            return Ok(dir);
+        }
+        let _initialising = InitialisingGuard::new();
+        let _init = self
+            .init
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(dir) = self.get() {
+            return Ok(dir);
+        }
+        let new: &'static crate::NumberedDir = Box::leak(Box::new(init()?));
+        let mut dir = self.lock();
+        Ok(*dir.get_or_insert(new))
+    }
+
+    /// Makes the global directory uninitialised again, returning the previous one.
+    #[cfg(feature = "testing-internals")]
+    pub(crate) fn reset(&self) -> Option<&'static crate::NumberedDir> {
+        self.lock().take()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<&'static crate::NumberedDir>> {
+        self.dir
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Whether `init_testdir!(current_symlink = ...)` disabled the `-current` symlink.
+static NO_CURRENT_SYMLINK: AtomicBool = AtomicBool::new(false);
+
+static TARGET_TMP: AtomicBool = AtomicBool::new(false);
+
+/// Records the `target_tmp` argument of `init_testdir!`.
+pub fn set_target_tmp(enable: bool) {
+    TARGET_TMP.store(enable, Ordering::Relaxed);
+}
+
+/// Whether the global testdir is placed in `target/tmp`.
+pub fn target_tmp() -> bool {
+    TARGET_TMP.load(Ordering::Relaxed)
+}
+
+static PER_TARGET: AtomicBool = AtomicBool::new(false);
+
+/// Records the `per_target` argument of `init_testdir!`.
+pub fn set_per_target(enable: bool) {
+    PER_TARGET.store(enable, Ordering::Relaxed);
+}
+
+/// Whether the global testdir has the target triple in its base name.
+pub fn per_target() -> bool {
+    PER_TARGET.load(Ordering::Relaxed)
+}
+
+static PER_PROFILE: AtomicBool = AtomicBool::new(false);
+
+/// Records the `per_profile` argument of `init_testdir!`.
+pub fn set_per_profile(enable: bool) {
+    PER_PROFILE.store(enable, Ordering::Relaxed);
+}
+
+/// Whether the global testdir has the build profile in its base name.
+pub fn per_profile() -> bool {
+    PER_PROFILE.load(Ordering::Relaxed)
+}
+
+/// The default base name of the global testdir: `testdir`.
+const DEFAULT_BASE: &str = "testdir";
+
+static BASE: OnceCell<String> = OnceCell::new();
+
+/// Records the `base` argument of `init_testdir!`, only the first call has an effect.
+pub fn set_base(base: &str) {
+    BASE.get_or_init(|| base.to_string());
+}
+
+/// The base name of the global testdir.
+pub fn base() -> &'static str {
+    BASE.get().map(String::as_str).unwrap_or(DEFAULT_BASE)
+}
+
+static WORKSPACE_CRATE: OnceCell<String> = OnceCell::new();
+
+/// Records the package name for the `workspace` argument of `init_testdir!`.
+pub fn set_workspace_crate(name: &str) {
+    WORKSPACE_CRATE.get_or_init(|| name.to_string());
+}
+
+/// The package name of the crate if the global testdir is shared by the workspace.
+pub fn workspace_crate() -> Option<&'static str> {
+    WORKSPACE_CRATE.get().map(String::as_str)
+}
+
+/// Registers the crate in the global testdir if it is shared by the workspace.
+pub fn register_workspace_crate(numdir: &crate::NumberedDir) -> Result<(), crate::Error> {
+    match workspace_crate() {
+        Some(name) => crate::workspace::register(numdir, name),
+        None => Ok(()),
+    }
+}
+
+static PER_ATTEMPT: AtomicBool = AtomicBool::new(false);
+
+/// Records the `per_attempt` argument of `init_testdir!`.
+pub fn set_per_attempt(enable: bool) {
+    PER_ATTEMPT.store(enable, Ordering::Relaxed);
+}
+
+/// Whether test-scoped directories have a subdirectory for each retry attempt.
+pub fn per_attempt() -> bool {
+    PER_ATTEMPT.load(Ordering::Relaxed)
+}
+
+/// The prefix of the per-attempt directories: `attempt-`.
+const ATTEMPT_DIR_PREFIX: &str = "attempt-";
+
+/// The environment variables of test runners with the 1-based retry attempt of the test.
+const ATTEMPT_VARS: &[&str] = &["NEXTEST_ATTEMPT"];
+
+/// Returns the retry attempt of the current test as set by the test runner, `1` if unknown.
+fn current_attempt() -> u32 {
+    attempt_from_env(|name| std::env::var(name).ok())
+}
+
+/// Finds the retry attempt in the environment variables, looked up using `var`.
+fn attempt_from_env(var: impl Fn(&str) -> Option<String>) -> u32 {
+    ATTEMPT_VARS
+        .iter()
+        .find_map(|name| var(name)?.trim().parse().ok())
+        .filter(|attempt| *attempt > 0)
+        .unwrap_or(1)
+}
+
+/// Records the `current_symlink` argument of `init_testdir!`.
+pub fn set_current_symlink(enable: bool) {
+    NO_CURRENT_SYMLINK.store(!enable, Ordering::Relaxed);
+}
+
+/// Whether the global testdir should have a `-current` symlink.
+///
+/// The [`CURRENT_SYMLINK_ENV`](crate::CURRENT_SYMLINK_ENV) environment variable takes
+/// precedence over the `init_testdir!` argument.
+pub fn current_symlink() -> bool {
+    match std::env::var(crate::CURRENT_SYMLINK_ENV) {
+        Ok(val) if !val.is_empty() => val != "0",
+        _ => !NO_CURRENT_SYMLINK.load(Ordering::Relaxed),
+    }
+}
+
+/// Whether testdir runs in Miri mode, see [`MIRI_ENV`](crate::MIRI_ENV).
+pub fn miri_mode() -> bool {
+    cfg!(miri) || is_enabled(std::env::var(crate::MIRI_ENV).ok().as_deref())
+}
+
+/// Whether processes can not be inspected and backtraces not be captured.
+///
+/// This is the case in Miri mode and on wasm targets.
+pub(crate) fn is_restricted() -> bool {
+    miri_mode() || cfg!(target_family = "wasm")
+}
+
+/// Whether an environment variable with value `val` enables an option.
+fn is_enabled(val: Option<&str>) -> bool {
+    val.is_some_and(|val| !val.is_empty() && val != "0")
+}
+
+/// Records the `metadata_timeout` argument of `init_testdir!`.
+pub fn set_metadata_timeout(timeout: std::time::Duration) {
+    crate::target_dir::set_metadata_timeout(timeout);
+}
+
+/// Returns the parent directory for the global testdir, see [`TargetDirSource`].
+///
+/// The `build_script_dir` is `TESTDIR_TARGET_DIR` at compile time of the calling crate.
+///
+/// [`TargetDirSource`]: crate::TargetDirSource
+pub fn resolve_target_dir(build_script_dir: Option<&str>) -> PathBuf {
+    crate::target_dir::resolve(build_script_dir)
+}
+
+/// The parent directory of the global testdir when run by a build system like Bazel.
+///
+/// See [`BuildSystemEnv`](crate::BuildSystemEnv).
+pub(crate) fn build_system_root() -> Option<PathBuf> {
+    crate::BuildSystemEnv::detect().map(|env| env.tmpdir)
+}
+
+/// The parent directory of the global testdir in Miri mode: `testdir-miri`.
+pub(crate) fn miri_root() -> PathBuf {
+    crate::root::temp_dir().join("testdir-miri")
+}
+
+/// The parent directory of the global testdir on wasm targets: `target`.
+///
+/// This is relative to the current directory, which on WASI runtimes is usually the
+/// preopened directory of the crate, e.g. when using `wasmtime run --dir .` as cargo runner.
+pub(crate) fn wasm_root() -> PathBuf {
+    PathBuf::from("target")
+}
+
+/// Performs the setup requested by the environment when initialising the global testdir.
+pub fn init_from_env() {
+    crate::summary::register_from_env();
+    crate::build_system::register_from_env();
+    #[cfg(feature = "panic-hook")]
+    crate::install_panic_hook();
+}
+
+/// Records where the parent directory of the global testdir was found in its run store.
+pub fn record_target_dir_source(numdir: &crate::NumberedDir) -> Result<(), crate::Error> {
+    crate::target_dir::record_source(numdir)
+}
+
+/// Creates the global testdir using the builder from `override_builder_for_tests`, if set.
+pub fn create_overridden() -> Option<Result<crate::NumberedDir, crate::Error>> {
+    let builder = crate::BUILDER_OVERRIDE.get()?;
+    let testdir = builder.create();
+    if testdir.is_ok() {
+        init_from_env();
+    }
+    Some(testdir)
+}
+
+/// Whether a [`scoped_override`](crate::scoped_override) is active on this thread.
+pub fn is_overridden() -> bool {
+    crate::scoped::current().is_some()
+}
+
+/// Calls `func` with the scoped override, or with the global testdir returned by `init`.
+pub fn with_override_or<I, F, R>(init: I, func: F) -> Result<R, crate::Error>
+where
+    I: FnOnce() -> Result<&'static crate::NumberedDir, crate::Error>,
+    F: FnOnce(&crate::NumberedDir) -> Result<R, crate::Error>,
+{
+    match crate::scoped::current() {
+        Some(numdir) => func(&numdir),
+        None => func(init()?),
+    }
+}
+
+/// Returns the named global testdir, or an error if it was not initialised.
+pub fn named_testdir(name: &str) -> Result<&'static crate::NumberedDir, crate::Error> {
+    crate::named_testdir(name).ok_or_else(|| crate::Error::NotInitialized {
+        name: Some(name.to_string()),
+    })
+}
+
+/// Options for the directory created by the `testdir!` macro.
+#[derive(Clone, Copy, Debug)]
+pub struct SubdirOptions {
+    clean: bool,
+    create: bool,
+    strict: bool,
+    canonicalize: bool,
+    strip_unc_prefix: bool,
+    normalize_private: bool,
+    attempt: Option<u32>,
+}
+
+impl SubdirOptions {
+    /// The default options, used when none are given to the macro.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            clean: false,
+            create: true,
+            strict: true,
+            canonicalize: false,
+            strip_unc_prefix: false,
+            normalize_private: false,
+            attempt: None,
+        }
+    }
+
+    /// Whether the contents of an existing directory are removed.
+    pub fn clean(mut self, enable: bool) -> Self {
+        self.clean = enable;
+        self
+    }
+
+    /// Whether the directory is created.
+    pub fn create(mut self, enable: bool) -> Self {
+        self.create = enable;
+        self
+    }
+
+    /// Whether the path is verified to stay inside the numbered directory.
+    pub fn strict(mut self, enable: bool) -> Self {
+        self.strict = enable;
+        self
+    }
+
+    /// Whether the returned path is canonicalized.
+    pub fn canonicalize(mut self, enable: bool) -> Self {
+        self.canonicalize = enable;
+        self
+    }
+
+    /// Whether the Windows `\\?\` prefix is stripped from the returned path.
+    pub fn strip_unc_prefix(mut self, enable: bool) -> Self {
+        self.strip_unc_prefix = enable;
+        self
+    }
+
+    /// Whether a macOS `/private/var` prefix of the returned path is replaced by `/var`.
+    pub fn normalize_private(mut self, enable: bool) -> Self {
+        self.normalize_private = enable;
+        self
+    }
+
+    /// The retry attempt of the test, overriding the detected attempt.
+    pub fn attempt(mut self, attempt: u32) -> Self {
+        self.attempt = Some(attempt);
+        self
+    }
+
+    /// Appends the `attempt-N` directory to the path of a test-scoped directory.
+    ///
+    /// This is only done if an attempt was given or per-attempt directories are enabled.
+    pub fn attempt_dir(&self, path: PathBuf) -> PathBuf {
+        let attempt = self.attempt.or_else(|| per_attempt().then(current_attempt));
+        match attempt {
+            Some(attempt) => path.join(format!("{ATTEMPT_DIR_PREFIX}{attempt}")),
+            None => path,
+        }
+    }
+
+    /// Creates the sub-directory `rel_path` in `numdir` according to these options.
+    pub fn subdir(
+        self,
+        numdir: &crate::NumberedDir,
+        rel_path: impl AsRef<Path>,
+    ) -> Result<PathBuf, crate::Error> {
+        let rel_path = rel_path.as_ref();
+        let path = match (self.create, self.strict) {
+            (true, true) => numdir.create_subdir_strict(rel_path)?,
+            (true, false) => numdir.create_subdir(rel_path)?,
+            (false, strict) => {
+                if strict {
+                    numdir.check_contained(rel_path)?;
+                }
+                numdir.subdir_path(rel_path)?
+            }
+        };
+        if self.clean && path.is_dir() {
+            clean_dir(&path)?;
         }
+        let path = if self.canonicalize {
+            use anyhow::Context;
+
+            crate::simplify::canonicalize_lenient(&path)
+                .with_context(|| format!("Failed to canonicalize {}", path.display()))?
+        } else {
+            path
+        };
+        let path =
+            crate::simplify::simplify_path(&path, self.strip_unc_prefix, self.normalize_private);
+        crate::created::record_owner(&path, numdir);
+        Ok(path)
+    }
+}
+
+/// Removes all contents of the directory at `path`.
+fn clean_dir(path: &Path) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    for dirent in fs::read_dir(path)? {
+        let entry = dirent?.path();
+        crate::cleanup::remove_entry(&entry)
+            .with_context(|| format!("Failed to remove {}", entry.display()))?;
+    }
+    Ok(())
+}
+
+/// Records a directory returned by the macros, returning it again.
+pub fn record_created(path: PathBuf, scope: crate::Scope) -> PathBuf {
+    crate::created::record(&path, scope);
+    path
+}
+
+/// Maps the module path to a relative directory using the global [`PathMapper`].
+///
+/// When the global testdir is shared by the workspace this is inside the directory of the
+/// crate.
+///
+/// [`PathMapper`]: crate::PathMapper
+pub fn module_dir(module_path: &str) -> PathBuf {
+    let mapper = crate::PATH_MAPPER
+        .get()
+        .copied()
+        .unwrap_or(crate::map_module_path);
+    match workspace_crate() {
+        Some(name) => Path::new(name).join(mapper(module_path)),
+        None => mapper(module_path),
     }
 }
 
@@ -113,7 +654,11 @@ pub fn extract_test_name(module_path: &str) -> String {
         .expect("Test thread has no name, can not find test name")
         .to_string();
     if name == "main" {
-        name = extract_test_name_from_backtrace(module_path);
+        name = if is_restricted() {
+            String::from("unknown_test")
+        } else {
+            extract_test_name_from_backtrace(module_path)
+        };
     }
     if let Some(tail) = name.rsplit("::").next() {
         name = tail.to_string();
@@ -149,6 +694,62 @@ pub fn extract_test_name_from_backtrace(module_path: &str) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_global_testdir_reentrant() {
+        let parent = tempfile::tempdir().unwrap();
+        let global = GlobalTestdir::new();
+        let numdir = global.get_or_init(|| {
+            assert!(global.get().is_none());
+            let reentrant = std::panic::catch_unwind(|| {
+                global.get_or_init(|| unreachable!());
+            });
+            assert!(reentrant.is_err());
+            let mut builder = crate::NumberedDirBuilder::new(String::from("global"));
+            builder.set_parent(parent.path().to_path_buf());
+            builder.create().unwrap()
+        });
+        assert_eq!(global.get(), Some(numdir));
+        assert_eq!(global.get_or_init(|| unreachable!()), numdir);
+    }
+
+    #[test]
+    fn test_is_enabled() {
+        assert!(!is_enabled(None));
+        assert!(!is_enabled(Some("")));
+        assert!(!is_enabled(Some("0")));
+        assert!(is_enabled(Some("1")));
+        assert!(is_enabled(Some("yes")));
+    }
+
+    #[test]
+    fn test_attempt_from_env() {
+        let attempt = |val: Option<&'static str>| attempt_from_env(|_| val.map(String::from));
+        assert_eq!(attempt(None), 1);
+        assert_eq!(attempt(Some("3")), 3);
+        assert_eq!(attempt(Some("0")), 1);
+        assert_eq!(attempt(Some("nope")), 1);
+    }
+
+    #[test]
+    fn test_attempt_dir() {
+        let path = PathBuf::from("mod/test");
+        assert_eq!(SubdirOptions::new().attempt_dir(path.clone()), path);
+        assert_eq!(
+            SubdirOptions::new().attempt(2).attempt_dir(path.clone()),
+            path.join("attempt-2")
+        );
+    }
+
+    #[test]
+    fn test_parse_cached_cargo_pid() {
+        let cargo_pid = "1234".parse::<Pid>().unwrap();
+        assert_eq!(parse_cached_cargo_pid("1234:42", 42), Some(Some(cargo_pid)));
+        assert_eq!(parse_cached_cargo_pid(":42", 42), Some(None));
+        assert_eq!(parse_cached_cargo_pid("1234:42", 43), None);
+        assert_eq!(parse_cached_cargo_pid("1234", 1234), None);
+        assert_eq!(parse_cached_cargo_pid("nope:42", 42), None);
+    }
+
     #[test]
     fn test_cargo_pid() {
         let val = cargo_pid();
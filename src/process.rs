@@ -0,0 +1,210 @@
+//! Lightweight lookup of other processes, used to find the parent Cargo process.
+//!
+//! Building a [`sysinfo::System`] reads information about many processes, which is slow on
+//! machines with many processes.  Only the parent PID and executable of a single process are
+//! needed, which the OS can provide directly.  Other platforms fall back to `sysinfo`.
+
+use std::path::PathBuf;
+
+/// Returns the process ID of the parent of process `pid`.
+pub(crate) fn parent_pid(pid: u32) -> Option<u32> {
+    imp::parent_pid(pid)
+}
+
+/// Returns the path of the executable of process `pid`.
+pub(crate) fn exe_path(pid: u32) -> Option<PathBuf> {
+    imp::exe_path(pid)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod imp {
+    use std::fs;
+    use std::path::PathBuf;
+
+    pub(super) fn parent_pid(pid: u32) -> Option<u32> {
+        let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+        parse_stat_ppid(&stat)
+    }
+
+    pub(super) fn exe_path(pid: u32) -> Option<PathBuf> {
+        let path = fs::read_link(format!("/proc/{pid}/exe")).ok()?;
+        // The kernel appends this when the executable was replaced, e.g. by `rustup update`.
+        match path.to_str().and_then(|s| s.strip_suffix(" (deleted)")) {
+            Some(stripped) => Some(PathBuf::from(stripped)),
+            None => Some(path),
+        }
+    }
+
+    /// Parses the parent PID from the contents of `/proc/<pid>/stat`.
+    ///
+    /// The second field is the executable name in parentheses, which can itself contain
+    /// spaces and parentheses, so the fields are counted from the last closing parenthesis.
+    pub(super) fn parse_stat_ppid(stat: &str) -> Option<u32> {
+        let (_, rest) = stat.rsplit_once(')')?;
+        rest.split_whitespace().nth(1)?.parse().ok()
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::ffi::OsString;
+    use std::mem;
+    use std::os::unix::ffi::OsStringExt;
+    use std::path::PathBuf;
+
+    pub(super) fn parent_pid(pid: u32) -> Option<u32> {
+        let pid = libc::c_int::try_from(pid).ok()?;
+        // SAFETY: proc_bsdinfo is a plain C struct for which all zeroes are valid.
+        let mut info: libc::proc_bsdinfo = unsafe { mem::zeroed() };
+        let size = mem::size_of::<libc::proc_bsdinfo>() as libc::c_int;
+        // SAFETY: the buffer is a valid proc_bsdinfo of the given size.
+        let ret = unsafe {
+            libc::proc_pidinfo(
+                pid,
+                libc::PROC_PIDTBSDINFO,
+                0,
+                (&mut info as *mut libc::proc_bsdinfo).cast(),
+                size,
+            )
+        };
+        (ret == size).then_some(info.pbi_ppid)
+    }
+
+    pub(super) fn exe_path(pid: u32) -> Option<PathBuf> {
+        let pid = libc::c_int::try_from(pid).ok()?;
+        let mut buf = vec![0u8; libc::PROC_PIDPATHINFO_MAXSIZE as usize];
+        // SAFETY: the buffer is valid for writes of its length.
+        let len = unsafe { libc::proc_pidpath(pid, buf.as_mut_ptr().cast(), buf.len() as u32) };
+        if len <= 0 {
+            return None;
+        }
+        buf.truncate(len as usize);
+        Some(PathBuf::from(OsString::from_vec(buf)))
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::ffi::OsString;
+    use std::mem;
+    use std::os::windows::ffi::OsStringExt;
+    use std::path::PathBuf;
+
+    use winapi::shared::minwindef::{DWORD, FALSE, MAX_PATH};
+    use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+    use winapi::um::processthreadsapi::OpenProcess;
+    use winapi::um::tlhelp32::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+        TH32CS_SNAPPROCESS,
+    };
+    use winapi::um::winbase::QueryFullProcessImageNameW;
+    use winapi::um::winnt::{HANDLE, PROCESS_QUERY_LIMITED_INFORMATION};
+
+    /// Closes the handle when dropped.
+    struct Handle(HANDLE);
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            // SAFETY: the handle is valid and owned by us.
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    pub(super) fn parent_pid(pid: u32) -> Option<u32> {
+        // SAFETY: no pointers are passed.
+        let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+        if snapshot == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        let snapshot = Handle(snapshot);
+        // SAFETY: PROCESSENTRY32W is a plain C struct for which all zeroes are valid.
+        let mut entry: PROCESSENTRY32W = unsafe { mem::zeroed() };
+        entry.dwSize = mem::size_of::<PROCESSENTRY32W>() as DWORD;
+        // SAFETY: the entry is valid and has its size set.
+        let mut ok = unsafe { Process32FirstW(snapshot.0, &mut entry) };
+        while ok != FALSE {
+            if entry.th32ProcessID == pid {
+                return Some(entry.th32ParentProcessID);
+            }
+            // SAFETY: as above.
+            ok = unsafe { Process32NextW(snapshot.0, &mut entry) };
+        }
+        None
+    }
+
+    pub(super) fn exe_path(pid: u32) -> Option<PathBuf> {
+        // SAFETY: no pointers are passed.
+        let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid) };
+        if process.is_null() || process == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        let process = Handle(process);
+        let mut buf = vec![0u16; 4 * MAX_PATH];
+        let mut len = buf.len() as DWORD;
+        // SAFETY: the buffer is valid for writes of `len` characters.
+        let ok = unsafe { QueryFullProcessImageNameW(process.0, 0, buf.as_mut_ptr(), &mut len) };
+        if ok == FALSE {
+            return None;
+        }
+        buf.truncate(len as usize);
+        Some(PathBuf::from(OsString::from_wide(&buf)))
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    windows
+)))]
+mod imp {
+    use std::path::PathBuf;
+
+    use sysinfo::{Pid, PidExt, ProcessExt, ProcessRefreshKind, System, SystemExt};
+
+    pub(super) fn parent_pid(pid: u32) -> Option<u32> {
+        let pid = Pid::from_u32(pid);
+        let mut sys = System::new();
+        sys.refresh_process_specifics(pid, ProcessRefreshKind::new());
+        sys.process(pid)?.parent().map(|ppid| ppid.as_u32())
+    }
+
+    pub(super) fn exe_path(pid: u32) -> Option<PathBuf> {
+        let pid = Pid::from_u32(pid);
+        let mut sys = System::new();
+        sys.refresh_process_specifics(pid, ProcessRefreshKind::new());
+        Some(sys.process(pid)?.exe().to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_process() {
+        let exe = exe_path(std::process::id()).unwrap();
+        assert_eq!(
+            exe.canonicalize().unwrap(),
+            std::env::current_exe().unwrap().canonicalize().unwrap()
+        );
+        let ppid = parent_pid(std::process::id()).unwrap();
+        assert!(exe_path(ppid).is_some());
+        #[cfg(unix)]
+        assert_eq!(ppid, std::os::unix::process::parent_id());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_parse_stat_ppid() {
+        assert_eq!(
+            imp::parse_stat_ppid("123 (cargo) S 45 123 45 0 -1"),
+            Some(45)
+        );
+        assert_eq!(
+            imp::parse_stat_ppid("123 (a) b (c)) R 7 123 45 0 -1"),
+            Some(7)
+        );
+        assert_eq!(imp::parse_stat_ppid("garbage"), None);
+    }
+}
@@ -0,0 +1,104 @@
+//! Read-only protection of fixtures, see [`ProtectGuard`].
+
+use std::fs::{self, Permissions};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::Error;
+
+/// Restores the permissions of a subtree protected by [`TestDir::protect`].
+///
+/// While the guard is alive all files and directories of the subtree are read-only, so
+/// that any attempt of the code under test to modify them fails.  When dropped the
+/// original permissions are restored, so that the directory can be removed again.
+///
+/// [`TestDir::protect`]: crate::TestDir::protect
+#[derive(Debug)]
+#[must_use = "permissions are restored when the guard is dropped"]
+pub struct ProtectGuard {
+    entries: Vec<(PathBuf, Permissions)>,
+}
+
+impl ProtectGuard {
+    /// Makes the file or directory tree at `path` read-only.
+    pub(crate) fn new(path: &Path) -> Result<Self> {
+        let mut entries = Vec::new();
+        collect(path, &mut entries)?;
+        let mut guard = Self {
+            entries: Vec::with_capacity(entries.len()),
+        };
+        for (path, permissions) in entries {
+            let mut readonly = permissions.clone();
+            readonly.set_readonly(true);
+            fs::set_permissions(&path, readonly)
+                .with_context(|| format!("Failed to protect {}", path.display()))?;
+            guard.entries.push((path, permissions));
+        }
+        Ok(guard)
+    }
+
+    /// Restores the original permissions now, rather than when dropped.
+    pub fn restore(&mut self) -> Result<(), Error> {
+        while let Some((path, permissions)) = self.entries.pop() {
+            fs::set_permissions(&path, permissions)
+                .with_context(|| format!("Failed to restore permissions of {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ProtectGuard {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore() {
+            eprintln!("testdir: {err:#}");
+        }
+    }
+}
+
+/// Collects the permissions of `path` and everything inside it, parents first.
+///
+/// Symlinks are not followed, their own permissions are meaningless.
+fn collect(path: &Path, entries: &mut Vec<(PathBuf, Permissions)>) -> Result<()> {
+    let metadata = path
+        .symlink_metadata()
+        .with_context(|| format!("Failed to read metadata of {}", path.display()))?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    entries.push((path.to_path_buf(), metadata.permissions()));
+    if metadata.is_dir() {
+        let readdir = path
+            .read_dir()
+            .with_context(|| format!("Failed read_dir() on {}", path.display()))?;
+        for dirent in readdir {
+            let dirent =
+                dirent.with_context(|| format!("Failed read_dir() on {}", path.display()))?;
+            collect(&dirent.path(), entries)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protect() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().join("fixture");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/input.txt"), "data").unwrap();
+
+        let guard = ProtectGuard::new(&root).unwrap();
+        let readonly = |p: &Path| fs::metadata(p).unwrap().permissions().readonly();
+        assert!(readonly(&root.join("sub/input.txt")));
+        assert!(readonly(&root));
+
+        drop(guard);
+        assert!(!readonly(&root.join("sub/input.txt")));
+        fs::write(root.join("sub/input.txt"), "changed").unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
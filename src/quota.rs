@@ -0,0 +1,246 @@
+//! The [`SizeQuota`] and supporting code.
+
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+
+use crate::Error;
+
+/// An upper bound on the size of a test directory.
+///
+/// Tests can accidentally write huge files into their directory, which are then retained
+/// for several runs.  A [`SizeQuota`] can check a directory against a maximum number of
+/// bytes and take a [`QuotaAction`] when it is exceeded.
+///
+/// Checking can be done explicitly using [`SizeQuota::check`], or at the end of a scope
+/// using the [`QuotaGuard`] returned by [`SizeQuota::guard`].
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{testdir, QuotaAction, SizeQuota};
+///
+/// let dir = testdir!();
+/// let _guard = SizeQuota::new(1024 * 1024)
+///     .action(QuotaAction::Panic)
+///     .guard(&dir);
+/// std::fs::write(dir.join("small.txt"), "hello").unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct SizeQuota {
+    max_bytes: u64,
+    action: QuotaAction,
+}
+
+/// The action taken when a [`SizeQuota`] is exceeded.
+#[derive(Clone)]
+pub enum QuotaAction {
+    /// Prints a warning to stderr, this is the default.
+    Warn,
+    /// Panics, which fails the test.
+    Panic,
+    /// Removes all the contents of the directory.
+    Truncate,
+    /// Calls the function with the directory and its size in bytes.
+    #[allow(clippy::type_complexity)]
+    Callback(Arc<dyn Fn(&Path, u64) + Send + Sync>),
+}
+
+impl fmt::Debug for QuotaAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warn => write!(f, "Warn"),
+            Self::Panic => write!(f, "Panic"),
+            Self::Truncate => write!(f, "Truncate"),
+            Self::Callback(_) => write!(f, "Callback(<Fn(&Path, u64)>)"),
+        }
+    }
+}
+
+impl SizeQuota {
+    /// Creates a new quota of `max_bytes`, warning when exceeded.
+    pub fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            action: QuotaAction::Warn,
+        }
+    }
+
+    /// Sets the [`QuotaAction`] to take when the quota is exceeded.
+    pub fn action(mut self, action: QuotaAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Returns the maximum number of bytes allowed by this quota.
+    pub fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+
+    /// Checks the directory against the quota.
+    ///
+    /// If the total size of all files in `dir` exceeds the quota the configured
+    /// [`QuotaAction`] is taken and `true` is returned.
+    ///
+    /// # Panics
+    ///
+    /// If the quota is exceeded and the action is [`QuotaAction::Panic`].
+    pub fn check(&self, dir: impl AsRef<Path>) -> Result<bool, Error> {
+        let dir = dir.as_ref();
+        let size = dir_size(dir)?;
+        if size <= self.max_bytes {
+            return Ok(false);
+        }
+        match self.action {
+            QuotaAction::Warn => eprintln!(
+                "testdir: {} exceeds quota: {} > {} bytes",
+                dir.display(),
+                size,
+                self.max_bytes
+            ),
+            QuotaAction::Panic => panic!(
+                "testdir: {} exceeds quota: {} > {} bytes",
+                dir.display(),
+                size,
+                self.max_bytes
+            ),
+            QuotaAction::Truncate => {
+                for entry in fs::read_dir(dir)? {
+                    let path = entry?.path();
+                    let res = if path.is_dir() && !path.is_symlink() {
+                        crate::cleanup::remove_dir_all(&path)
+                    } else {
+                        fs::remove_file(&path)
+                    };
+                    res.with_context(|| format!("Failed to remove {}", path.display()))?;
+                }
+            }
+            QuotaAction::Callback(ref func) => func(dir, size),
+        }
+        Ok(true)
+    }
+
+    /// Returns a guard which checks the quota of `dir` when dropped.
+    pub fn guard(&self, dir: impl Into<PathBuf>) -> QuotaGuard {
+        QuotaGuard {
+            quota: self.clone(),
+            dir: dir.into(),
+        }
+    }
+}
+
+/// Checks a [`SizeQuota`] when dropped.
+///
+/// Created by [`SizeQuota::guard`].  Errors while checking the quota are printed to stderr.
+/// If the thread is already panicking the [`QuotaAction::Panic`] action is downgraded to a
+/// warning to avoid aborting the process.
+#[derive(Debug)]
+pub struct QuotaGuard {
+    quota: SizeQuota,
+    dir: PathBuf,
+}
+
+impl Drop for QuotaGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() && matches!(self.quota.action, QuotaAction::Panic) {
+            self.quota.action = QuotaAction::Warn;
+        }
+        if let Err(err) = self.quota.check(&self.dir) {
+            eprintln!("testdir: failed to check quota: {err:#}");
+        }
+    }
+}
+
+/// Returns the total size in bytes of all files in a directory.
+///
+/// Symbolic links are not followed.  Files which disappear while computing the size are
+/// ignored.
+pub fn dir_size(dir: impl AsRef<Path>) -> Result<u64, Error> {
+    let dir = dir.as_ref();
+    let mut total = 0;
+    let readdir = match fs::read_dir(dir) {
+        Ok(readdir) => readdir,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("Failed read_dir() on {}", dir.display()))
+                .map_err(Error::from)
+        }
+    };
+    for entry in readdir {
+        let entry = entry?;
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        if metadata.is_dir() {
+            total += dir_size(entry.path())?;
+        } else {
+            total += metadata.len();
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_dir_size() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("one"), [0u8; 10]).unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/two"), [0u8; 5]).unwrap();
+        assert_eq!(dir_size(dir.path()).unwrap(), 15);
+    }
+
+    #[test]
+    fn test_quota_callback() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("one"), [0u8; 10]).unwrap();
+
+        let seen = Arc::new(AtomicU64::new(0));
+        let seen_cb = seen.clone();
+        let quota = SizeQuota::new(5).action(QuotaAction::Callback(Arc::new(move |_, size| {
+            seen_cb.store(size, Ordering::SeqCst)
+        })));
+        assert!(quota.check(dir.path()).unwrap());
+        assert_eq!(seen.load(Ordering::SeqCst), 10);
+
+        let quota = SizeQuota::new(10);
+        assert!(!quota.check(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn test_quota_truncate() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("one"), [0u8; 10]).unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        {
+            let _guard = SizeQuota::new(5)
+                .action(QuotaAction::Truncate)
+                .guard(dir.path());
+        }
+        assert!(dir.path().is_dir());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_quota_panic() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("one"), [0u8; 10]).unwrap();
+        SizeQuota::new(5)
+            .action(QuotaAction::Panic)
+            .check(dir.path())
+            .ok();
+    }
+}
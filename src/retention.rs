@@ -0,0 +1,282 @@
+//! The [`Retention`] policies for numbered directories.
+
+use std::collections::BTreeMap;
+use std::num::NonZeroU8;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+
+use crate::NumberedDir;
+
+/// The number of seconds in a day.
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Policy deciding which older numbered directories are kept.
+///
+/// When a new [`NumberedDir`] is created older directories are removed according to the
+/// retention policy.  Directories with numbers higher than the most recent one are always
+/// kept since they are assumed to be created concurrently by other processes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Retention {
+    /// Keeps this total number of most recent directories, including the newly created one.
+    ///
+    /// This is the default, using [`KEEP_DEFAULT`](crate::KEEP_DEFAULT).
+    Count(NonZeroU8),
+    /// Keeps up to `per_day` directories for each calendar day of the last `days` days.
+    ///
+    /// Within a day the most recent directories are kept, including the newly created
+    /// directory for today.  Days are UTC calendar days and determined from the
+    /// modification time of the directory.  E.g. `PerDay { per_day: 3, days: 7 }` keeps
+    /// fine-grained history for today and coarse-grained history for a week.
+    PerDay {
+        /// The number of directories to keep for each day.
+        per_day: NonZeroU8,
+        /// The number of days, including today, to keep directories for.
+        days: NonZeroU8,
+    },
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self::Count(crate::KEEP_DEFAULT.unwrap())
+    }
+}
+
+impl From<NonZeroU8> for Retention {
+    fn from(count: NonZeroU8) -> Self {
+        Self::Count(count)
+    }
+}
+
+impl Retention {
+    /// Returns the directories which are obsolete under this policy.
+    ///
+    /// The [`NumberedDir`] is identified by the parent directory `dir` and its base name
+    /// `base`, `current` is the number of the most recent existing directory.  If
+    /// `reserve_new` is set room is reserved for one new directory to be created.
+    pub(crate) fn obsolete_dirs(
+        &self,
+        dir: impl AsRef<Path>,
+        base: &str,
+        current: u16,
+        reserve_new: bool,
+        now: SystemTime,
+    ) -> Result<Vec<NumberedDir>> {
+        let numdirs = NumberedDir::iterate(&dir, base)?;
+        Ok(self.obsolete_numdirs(numdirs, current, reserve_new, now))
+    }
+
+    /// Returns the obsolete directories among `numdirs`, like [`Retention::obsolete_dirs`].
+    pub(crate) fn obsolete_numdirs(
+        &self,
+        numdirs: impl Iterator<Item = NumberedDir>,
+        current: u16,
+        reserve_new: bool,
+        now: SystemTime,
+    ) -> Vec<NumberedDir> {
+        match *self {
+            Self::Count(count) => {
+                let keep = u8::from(count) - u8::from(reserve_new);
+                let oldest_to_keep = current.wrapping_sub(keep as u16).wrapping_add(1);
+                let oldest_to_delete = current.wrapping_add(u16::MAX / 2);
+                assert!(oldest_to_keep != oldest_to_delete);
+                numdirs
+                    .filter(|numdir| {
+                        let number = numdir.number();
+                        (oldest_to_keep > oldest_to_delete
+                            && (number < oldest_to_keep && number >= oldest_to_delete))
+                            || (oldest_to_keep < oldest_to_delete
+                                && (number < oldest_to_keep || number >= oldest_to_delete))
+                    })
+                    .collect()
+            }
+            Self::PerDay { per_day, days } => {
+                // Only consider directories not newer than current, sorted by most recent.
+                let mut candidates: Vec<(u16, NumberedDir)> = numdirs
+                    .map(|numdir| (current.wrapping_sub(numdir.number()), numdir))
+                    .filter(|(age, _)| *age < u16::MAX / 2)
+                    .collect();
+                candidates.sort_by_key(|(age, _)| *age);
+                let candidates = candidates.into_iter().map(|(_, numdir)| numdir).collect();
+                per_day_obsolete(
+                    candidates,
+                    |numdir| numdir.path(),
+                    per_day,
+                    days,
+                    reserve_new,
+                    now,
+                )
+            }
+        }
+    }
+
+    /// Returns the paths which are obsolete under this policy, based on their age.
+    ///
+    /// This is used for directories which are not numbered, the `dirs` must be sorted by
+    /// most recent first.  If `reserve_new` is set room is reserved for one new directory
+    /// to be created.
+    pub(crate) fn obsolete_by_age(
+        &self,
+        dirs: Vec<PathBuf>,
+        reserve_new: bool,
+        now: SystemTime,
+    ) -> Vec<PathBuf> {
+        match *self {
+            Self::Count(count) => {
+                let keep = usize::from(u8::from(count) - u8::from(reserve_new));
+                dirs.into_iter().skip(keep).collect()
+            }
+            Self::PerDay { per_day, days } => {
+                per_day_obsolete(dirs, |path| path, per_day, days, reserve_new, now)
+            }
+        }
+    }
+}
+
+/// A [`Retention`] policy for each base sharing a parent directory.
+///
+/// When several bases, e.g. `testdir`, `benchdir` and `fuzzdir`, share a parent directory
+/// they often need different policies.  Bases without an explicit policy use the default
+/// policy.  This can be used with [`gc_root`](crate::gc_root) and
+/// [`NumberedDirBuilder::base_retention`](crate::NumberedDirBuilder::base_retention).
+///
+/// # Examples
+///
+/// ```
+/// use std::num::NonZeroU8;
+/// use testdir::{Retention, RetentionMap};
+///
+/// let map = RetentionMap::new(Retention::default())
+///     .base("benchdir", Retention::Count(NonZeroU8::new(2).unwrap()));
+/// assert_eq!(map.get("benchdir"), Retention::Count(NonZeroU8::new(2).unwrap()));
+/// assert_eq!(map.get("testdir"), Retention::default());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RetentionMap {
+    pub(crate) default: Retention,
+    bases: BTreeMap<String, Retention>,
+}
+
+impl RetentionMap {
+    /// Creates a map using `default` for all bases.
+    pub fn new(default: Retention) -> Self {
+        Self {
+            default,
+            bases: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the policy for `base`.
+    pub fn base(mut self, base: impl Into<String>, retention: Retention) -> Self {
+        self.insert(base.into(), retention);
+        self
+    }
+
+    /// Returns the policy for `base`.
+    pub fn get(&self, base: &str) -> Retention {
+        self.bases.get(base).copied().unwrap_or(self.default)
+    }
+
+    pub(crate) fn insert(&mut self, base: String, retention: Retention) {
+        self.bases.insert(base, retention);
+    }
+}
+
+impl From<Retention> for RetentionMap {
+    fn from(retention: Retention) -> Self {
+        Self::new(retention)
+    }
+}
+
+/// Applies [`Retention::PerDay`] to `candidates`, sorted by most recent first.
+fn per_day_obsolete<T>(
+    candidates: Vec<T>,
+    path: impl Fn(&T) -> &Path,
+    per_day: NonZeroU8,
+    days: NonZeroU8,
+    reserve_new: bool,
+    now: SystemTime,
+) -> Vec<T> {
+    let today = day_number(now);
+    let mut obsolete = Vec::new();
+    let mut kept: Vec<(u64, u8)> = Vec::new();
+    for candidate in candidates {
+        let day = path(&candidate)
+            .metadata()
+            .and_then(|m| m.modified())
+            .map(day_number)
+            .unwrap_or(today);
+        if today.saturating_sub(day) >= u64::from(u8::from(days)) {
+            obsolete.push(candidate);
+            continue;
+        }
+        // Today may already have a slot reserved for the new directory.
+        let allowed = u8::from(per_day) - u8::from(reserve_new && day == today);
+        match kept.iter_mut().find(|(kept_day, _)| *kept_day == day) {
+            Some((_, n)) if *n >= allowed => obsolete.push(candidate),
+            Some((_, n)) => *n += 1,
+            None if allowed == 0 => obsolete.push(candidate),
+            None => kept.push((day, 1)),
+        }
+    }
+    obsolete
+}
+
+/// Returns the number of UTC calendar days since the UNIX epoch.
+fn day_number(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / SECS_PER_DAY)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_per_day_today() {
+        let parent = tempfile::tempdir().unwrap();
+        let count = NonZeroU8::new(10).unwrap();
+        let dirs: Vec<_> = (0..4)
+            .map(|_| NumberedDir::create(parent.path(), "base", count).unwrap())
+            .collect();
+
+        let retention = Retention::PerDay {
+            per_day: NonZeroU8::new(3).unwrap(),
+            days: NonZeroU8::new(2).unwrap(),
+        };
+        let mut obsolete = retention
+            .obsolete_dirs(parent.path(), "base", 3, true, SystemTime::now())
+            .unwrap();
+        obsolete.sort_by_key(|numdir| numdir.number());
+        assert_eq!(obsolete, dirs[..2]);
+    }
+
+    #[test]
+    fn test_per_day_expired() {
+        let parent = tempfile::tempdir().unwrap();
+        let count = NonZeroU8::new(10).unwrap();
+        for _ in 0..2 {
+            NumberedDir::create(parent.path(), "base", count).unwrap();
+        }
+
+        let retention = Retention::PerDay {
+            per_day: NonZeroU8::new(3).unwrap(),
+            days: NonZeroU8::new(2).unwrap(),
+        };
+        let tomorrow = SystemTime::now() + Duration::from_secs(SECS_PER_DAY);
+        let obsolete = retention
+            .obsolete_dirs(parent.path(), "base", 1, true, tomorrow)
+            .unwrap();
+        assert!(obsolete.is_empty());
+
+        let later = SystemTime::now() + Duration::from_secs(2 * SECS_PER_DAY);
+        let obsolete = retention
+            .obsolete_dirs(parent.path(), "base", 1, true, later)
+            .unwrap();
+        assert_eq!(obsolete.len(), 2);
+    }
+}
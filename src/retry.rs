@@ -0,0 +1,103 @@
+//! The [`RetryStrategy`] and supporting code.
+
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::fixtures::SplitMix64;
+
+/// How to retry creating a numbered directory when the number is already taken.
+///
+/// When several processes create numbered directories concurrently they race for the same
+/// next number.  The losers retry with the following number, up to a maximum number of
+/// attempts.  In environments with very high contention it can help to allow more attempts
+/// and to sleep for a random duration between them, so the processes spread out.
+///
+/// Only errors caused by the directory already existing are retried, other errors such as
+/// missing permissions fail immediately.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use testdir::{NumberedDirBuilder, RetryStrategy};
+///
+/// let mut builder = NumberedDirBuilder::new(String::from("test"));
+/// builder.retry_strategy(RetryStrategy::new(64).jitter(Duration::from_millis(5)));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryStrategy {
+    max_attempts: u32,
+    max_jitter: Option<Duration>,
+}
+
+impl Default for RetryStrategy {
+    /// Makes 16 attempts without sleeping in between.
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+impl RetryStrategy {
+    /// Creates a strategy making up to `max_attempts` attempts, at least one.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            max_jitter: None,
+        }
+    }
+
+    /// Sleeps for a random duration up to `max` between attempts.
+    pub fn jitter(mut self, max: Duration) -> Self {
+        self.max_jitter = Some(max);
+        self
+    }
+
+    /// Returns the maximum number of attempts.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns the maximum duration to sleep between attempts, if any.
+    pub fn max_jitter(&self) -> Option<Duration> {
+        self.max_jitter
+    }
+
+    /// Whether the error of an attempt should be retried.
+    pub(crate) fn is_retryable(&self, err: &io::Error) -> bool {
+        err.kind() == io::ErrorKind::AlreadyExists
+    }
+
+    /// Sleeps between attempts if jitter is configured.
+    pub(crate) fn pause(&self) {
+        let Some(max) = self.max_jitter else {
+            return;
+        };
+        let max_nanos = max.as_nanos() as u64;
+        if max_nanos == 0 {
+            return;
+        }
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+            ^ u64::from(std::process::id());
+        let nanos = SplitMix64::new(seed).next_u64() % max_nanos;
+        std::thread::sleep(Duration::from_nanos(nanos));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_strategy() {
+        assert_eq!(RetryStrategy::default().max_attempts(), 16);
+        assert_eq!(RetryStrategy::new(0).max_attempts(), 1);
+        let strategy = RetryStrategy::new(3).jitter(Duration::from_millis(1));
+        assert_eq!(strategy.max_jitter(), Some(Duration::from_millis(1)));
+        assert!(strategy.is_retryable(&io::Error::from(io::ErrorKind::AlreadyExists)));
+        assert!(!strategy.is_retryable(&io::Error::from(io::ErrorKind::PermissionDenied)));
+        strategy.pause();
+    }
+}
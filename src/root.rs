@@ -0,0 +1,274 @@
+//! The [`RootStrategy`] trait and its implementations.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Strategy selecting the directory in which the *root* is placed.
+///
+/// By default the [`NumberedDirBuilder`] places the *root* in the system's temporary
+/// directory as returned by [`std::env::temp_dir`].  A [`RootStrategy`] can select a
+/// different location, and can be used with [`NumberedDirBuilder::root_strategy`].
+///
+/// [`NumberedDirBuilder`]: crate::NumberedDirBuilder
+/// [`NumberedDirBuilder::root_strategy`]: crate::NumberedDirBuilder::root_strategy
+pub trait RootStrategy: fmt::Debug {
+    /// Returns the directory in which to place the *root*.
+    ///
+    /// If `None` is returned the current location is kept.
+    fn tmpdir(&self) -> Option<PathBuf>;
+}
+
+/// Uses the system's temporary directory, as returned by [`std::env::temp_dir`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemTemp;
+
+impl RootStrategy for SystemTemp {
+    fn tmpdir(&self) -> Option<PathBuf> {
+        Some(temp_dir())
+    }
+}
+
+/// Avoids ephemeral temporary directories inside containers.
+///
+/// Inside containers the system's temporary directory is often a small tmpfs which
+/// evaporates together with the container.  When running inside a container and the
+/// temporary directory is a tmpfs this strategy instead uses, in order of preference:
+///
+/// - The mount configured using [`ContainerAware::mount`].
+/// - The cargo target directory from the `CARGO_TARGET_DIR` environment variable.
+/// - The `target` directory in `CARGO_MANIFEST_DIR`, as set by cargo when running tests.
+///
+/// Otherwise the system's temporary directory is used.
+#[derive(Clone, Debug, Default)]
+pub struct ContainerAware {
+    mount: Option<PathBuf>,
+}
+
+impl ContainerAware {
+    /// Creates a new [`ContainerAware`] strategy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a preferred directory to use inside containers, e.g. a persistent volume.
+    pub fn mount(mut self, path: impl Into<PathBuf>) -> Self {
+        self.mount = Some(path.into());
+        self
+    }
+}
+
+impl RootStrategy for ContainerAware {
+    fn tmpdir(&self) -> Option<PathBuf> {
+        let temp = temp_dir();
+        if !in_container() || !is_tmpfs(&temp) {
+            return Some(temp);
+        }
+        self.mount
+            .clone()
+            .or_else(|| env::var_os("CARGO_TARGET_DIR").map(PathBuf::from))
+            .or_else(|| env::var_os("CARGO_MANIFEST_DIR").map(|p| PathBuf::from(p).join("target")))
+            .or(Some(temp))
+    }
+}
+
+/// Uses the user's cache directory.
+///
+/// On Linux and other UNIX platforms this is `$XDG_CACHE_HOME`, falling back to
+/// `$HOME/.cache`.  On macOS this is `$HOME/Library/Caches` and on Windows `%LOCALAPPDATA%`.
+/// If none of these are available the system's temporary directory is used.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct XdgCache;
+
+impl RootStrategy for XdgCache {
+    fn tmpdir(&self) -> Option<PathBuf> {
+        let home = || env::var_os("HOME").map(PathBuf::from);
+        let dir = if cfg!(target_os = "macos") {
+            home().map(|home| home.join("Library").join("Caches"))
+        } else if cfg!(windows) {
+            env::var_os("LOCALAPPDATA").map(PathBuf::from)
+        } else {
+            env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .or_else(|| home().map(|home| home.join(".cache")))
+        };
+        dir.or_else(|| Some(temp_dir()))
+    }
+}
+
+/// Uses the user's runtime directory.
+///
+/// This is `$XDG_RUNTIME_DIR`, usually a per-user tmpfs on Linux which is removed when the
+/// user logs out.  If this is not available the system's temporary directory is used.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct XdgRuntime;
+
+impl RootStrategy for XdgRuntime {
+    fn tmpdir(&self) -> Option<PathBuf> {
+        env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute() && path.is_dir())
+            .or_else(|| Some(temp_dir()))
+    }
+}
+
+/// Prefers a RAM-backed filesystem, speeding up I/O heavy test suites.
+///
+/// This uses, in order of preference:
+///
+/// - The directory configured using [`Ramdisk::path`], if it exists.
+/// - The directory found by [`ramdisk_dir`], e.g. `/dev/shm` on Linux.
+///
+/// Otherwise the system's temporary directory is used.  Keep in mind that RAM-backed
+/// filesystems are usually small, and their contents are lost on reboot.
+#[derive(Clone, Debug, Default)]
+pub struct Ramdisk {
+    path: Option<PathBuf>,
+}
+
+impl Ramdisk {
+    /// Creates a new [`Ramdisk`] strategy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a ramdisk to use, e.g. one mounted specifically for the test suite.
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+}
+
+impl RootStrategy for Ramdisk {
+    fn tmpdir(&self) -> Option<PathBuf> {
+        self.path
+            .clone()
+            .filter(|path| path.is_dir())
+            .or_else(ramdisk_dir)
+            .or_else(|| Some(temp_dir()))
+    }
+}
+
+/// Returns a writable directory on a RAM-backed filesystem, if one is available.
+///
+/// This is the system's temporary directory if it is a tmpfs, otherwise `/dev/shm` if it
+/// is a writable tmpfs.  This is only detected on Linux, on other platforms this always
+/// returns `None`.
+pub fn ramdisk_dir() -> Option<PathBuf> {
+    let temp = temp_dir();
+    if is_tmpfs(&temp) {
+        return Some(temp);
+    }
+    let shm = PathBuf::from("/dev/shm");
+    let writable =
+        fs::metadata(&shm).is_ok_and(|meta| meta.is_dir() && !meta.permissions().readonly());
+    (writable && is_tmpfs(&shm)).then_some(shm)
+}
+
+/// Returns the system's temporary directory, see [`std::env::temp_dir`].
+///
+/// Wasm targets have no temporary directory, there `tmp` relative to the current directory
+/// is used, which with WASI is usually inside the preopened directory.
+pub(crate) fn temp_dir() -> PathBuf {
+    if cfg!(target_family = "wasm") {
+        PathBuf::from("tmp")
+    } else {
+        env::temp_dir()
+    }
+}
+
+/// Returns whether this process appears to run inside a container.
+///
+/// This detects Docker, Podman and Kubernetes environments.
+pub fn in_container() -> bool {
+    if Path::new("/.dockerenv").exists() || Path::new("/run/.containerenv").exists() {
+        return true;
+    }
+    if env::var_os("KUBERNETES_SERVICE_HOST").is_some() || env::var_os("container").is_some() {
+        return true;
+    }
+    fs::read_to_string("/proc/1/cgroup")
+        .map(|cgroup| {
+            ["docker", "kubepods", "containerd", "libpod"]
+                .iter()
+                .any(|name| cgroup.contains(name))
+        })
+        .unwrap_or(false)
+}
+
+/// Returns whether the path is located on a tmpfs filesystem.
+///
+/// This is only detected on Linux, on other platforms this always returns `false`.
+pub fn is_tmpfs(path: impl AsRef<Path>) -> bool {
+    mount_fstype(path.as_ref()).is_some_and(|fstype| fstype == "tmpfs")
+}
+
+/// Returns the filesystem type of the mount containing `path`.
+///
+/// This uses `/proc/self/mounts` and thus only works on Linux.
+pub(crate) fn mount_fstype(path: &Path) -> Option<String> {
+    let path = path.canonicalize().ok()?;
+    let mounts = fs::read_to_string("/proc/self/mounts").ok()?;
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            let mount_point = fields.next()?.replace("\\040", " ");
+            let fstype = fields.next()?;
+            Some((PathBuf::from(mount_point), fstype))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+        .map(|(_, fstype)| fstype.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_temp() {
+        assert_eq!(SystemTemp.tmpdir(), Some(env::temp_dir()));
+    }
+
+    #[test]
+    fn test_container_aware() {
+        let mount = tempfile::tempdir().unwrap();
+        let strategy = ContainerAware::new().mount(mount.path());
+        let tmpdir = strategy.tmpdir().unwrap();
+        if in_container() && is_tmpfs(env::temp_dir()) {
+            assert_eq!(tmpdir, mount.path());
+        } else {
+            assert_eq!(tmpdir, env::temp_dir());
+        }
+    }
+
+    #[test]
+    fn test_xdg() {
+        assert!(XdgCache.tmpdir().is_some());
+        assert!(XdgRuntime.tmpdir().is_some());
+    }
+
+    #[test]
+    fn test_ramdisk() {
+        let path = tempfile::tempdir().unwrap();
+        let strategy = Ramdisk::new().path(path.path());
+        assert_eq!(strategy.tmpdir().unwrap(), path.path());
+
+        let expected = ramdisk_dir().unwrap_or_else(env::temp_dir);
+        let strategy = Ramdisk::new().path(path.path().join("missing"));
+        assert_eq!(strategy.tmpdir().unwrap(), expected);
+        if let Some(dir) = ramdisk_dir() {
+            assert!(is_tmpfs(dir));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_mount_fstype() {
+        assert!(mount_fstype(Path::new("/")).is_some());
+    }
+}
@@ -0,0 +1,222 @@
+//! Read-only inspection of run directories, see [`Run`].
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{Map, Value};
+
+use crate::{dir_size, Error, Outcome, VcsInfo};
+
+/// A read-only view of a previous run directory.
+///
+/// This parses the files testdir stores in a numbered directory and lists the test
+/// directories it contains, which is useful for tooling analysing the artifacts of
+/// previous test runs.  Nothing is ever written to the directory.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{testdir, Run};
+///
+/// let dir = testdir!();
+/// std::fs::write(dir.join("output.txt"), "hello").unwrap();
+/// let run_dir = testdir::with_testdir(|numdir| numdir.path().to_path_buf());
+/// let run = Run::load(&run_dir).unwrap();
+/// assert!(run.subdirs().iter().any(|sub| run_dir.join(&sub.path) == dir));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Run {
+    path: PathBuf,
+    number: Option<u16>,
+    format_version: Option<String>,
+    cargo_pid: Option<u32>,
+    metadata: Map<String, Value>,
+    vcs: Option<VcsInfo>,
+    subdirs: Vec<RunSubdir>,
+}
+
+/// A test directory inside a [`Run`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunSubdir {
+    /// The path relative to the run directory.
+    pub path: PathBuf,
+    /// The total size of the files in the directory, in bytes.
+    pub bytes: u64,
+    /// The outcome of the test, if recorded using [`record_outcome`].
+    ///
+    /// [`record_outcome`]: crate::record_outcome
+    pub outcome: Option<Outcome>,
+}
+
+impl Run {
+    /// Loads the run directory at `path`.
+    ///
+    /// Test directories are the directories inside the run directory which contain files,
+    /// or which are empty.  Directories only containing other directories are the
+    /// directories of modules and are not listed themselves.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Err(anyhow!("Not a run directory: {}", path.display()).into());
+        }
+        let number = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.rsplit_once('-'))
+            .and_then(|(_, number)| number.parse().ok());
+        let format_version =
+            read_optional(&path.join(crate::numbered_dir::FORMAT_VERSION_FILE_NAME))?
+                .map(|s| s.trim().to_string());
+        let cargo_pid = read_optional(&path.join(crate::private::CARGO_PID_FILE_NAME))?
+            .and_then(|s| s.trim().parse().ok());
+        let metadata = match read_optional(&path.join(crate::kv::RUN_KV_FILE_NAME))? {
+            Some(data) => serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse metadata in {}", path.display()))?,
+            None => Map::new(),
+        };
+        let vcs = match read_optional(&path.join(crate::vcs::VCS_FILE_NAME))? {
+            Some(data) => serde_json::from_str(&data)
+                .ok()
+                .and_then(|value| VcsInfo::from_json(&value)),
+            None => None,
+        };
+        let mut subdirs = Vec::new();
+        find_subdirs(path, Path::new(""), &mut subdirs)?;
+        subdirs.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Self {
+            path: path.to_path_buf(),
+            number,
+            format_version,
+            cargo_pid,
+            metadata,
+            vcs,
+            subdirs,
+        })
+    }
+
+    /// Returns the path of the run directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the number of the run directory, if its name has one.
+    pub fn number(&self) -> Option<u16> {
+        self.number
+    }
+
+    /// Returns the contents of the `format-version` file, if present.
+    pub fn format_version(&self) -> Option<&str> {
+        self.format_version.as_deref()
+    }
+
+    /// Returns the PID of the cargo process which created the run, if recorded.
+    pub fn cargo_pid(&self) -> Option<u32> {
+        self.cargo_pid
+    }
+
+    /// Returns the metadata stored using [`RunKv`](crate::RunKv).
+    pub fn metadata(&self) -> &Map<String, Value> {
+        &self.metadata
+    }
+
+    /// Returns the version control revision recorded for the run, if any.
+    ///
+    /// See [`NumberedDirBuilder::record_vcs`](crate::NumberedDirBuilder::record_vcs).
+    pub fn vcs(&self) -> Option<&VcsInfo> {
+        self.vcs.as_ref()
+    }
+
+    /// Returns the test directories of the run, sorted by path.
+    pub fn subdirs(&self) -> &[RunSubdir] {
+        &self.subdirs
+    }
+
+    /// Returns the total size of all test directories, in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.subdirs.iter().map(|sub| sub.bytes).sum()
+    }
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn find_subdirs(root: &Path, rel: &Path, subdirs: &mut Vec<RunSubdir>) -> Result<()> {
+    let dir = root.join(rel);
+    let mut children = Vec::new();
+    let mut has_files = false;
+    let readdir = dir
+        .read_dir()
+        .with_context(|| format!("Failed read_dir() on {}", dir.display()))?;
+    for dirent in readdir {
+        let dirent = dirent.with_context(|| format!("Failed read_dir() on {}", dir.display()))?;
+        if dirent.file_type().is_ok_and(|t| t.is_dir()) {
+            children.push(rel.join(dirent.file_name()));
+        } else {
+            has_files = true;
+        }
+    }
+    if rel.as_os_str().is_empty() {
+        // The files in the run directory itself are testdir's own.
+    } else if has_files || children.is_empty() {
+        subdirs.push(RunSubdir {
+            path: rel.to_path_buf(),
+            bytes: dir_size(&dir)?,
+            outcome: crate::outcome::read_outcome(&dir),
+        });
+        return Ok(());
+    }
+    for child in children {
+        find_subdirs(root, &child, subdirs)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let parent = tempfile::tempdir().unwrap();
+        let run_dir = parent.path().join("testdir-3");
+        fs::create_dir_all(run_dir.join("mod/test_a/nested")).unwrap();
+        fs::create_dir_all(run_dir.join("mod/test_b")).unwrap();
+        fs::write(run_dir.join("mod/test_a/out.txt"), "12345").unwrap();
+        fs::write(run_dir.join("mod/test_a/.testdir-outcome"), "failed").unwrap();
+        fs::write(run_dir.join("format-version"), "1 0.9\n").unwrap();
+        fs::write(run_dir.join("cargo-pid"), "42").unwrap();
+        fs::write(run_dir.join("run-kv.json"), r#"{"port": 8080}"#).unwrap();
+        fs::write(
+            run_dir.join("vcs.json"),
+            r#"{"commit": "abc", "branch": null}"#,
+        )
+        .unwrap();
+
+        let run = Run::load(&run_dir).unwrap();
+        assert_eq!(run.number(), Some(3));
+        assert_eq!(run.format_version(), Some("1 0.9"));
+        assert_eq!(run.cargo_pid(), Some(42));
+        assert_eq!(run.metadata()["port"], 8080);
+        assert_eq!(run.vcs().unwrap().commit, "abc");
+        let paths: Vec<_> = run.subdirs().iter().map(|sub| sub.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![PathBuf::from("mod/test_a"), PathBuf::from("mod/test_b")]
+        );
+        assert_eq!(run.bytes(), 11);
+        assert_eq!(run.subdirs()[0].outcome, Some(Outcome::Failed));
+        assert_eq!(run.subdirs()[1].outcome, None);
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let parent = tempfile::tempdir().unwrap();
+        assert!(Run::load(parent.path().join("nope")).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! Thread-local overrides of the global [`NumberedDir`], see [`scoped_override`].
+
+use std::cell::RefCell;
+
+use crate::NumberedDir;
+
+thread_local! {
+    /// The overrides active on this thread, the innermost last.
+    static OVERRIDES: RefCell<Vec<NumberedDir>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `func` with [`testdir!`] and [`with_testdir`] using `dir` on the current thread.
+///
+/// Within `func` the directories created by [`testdir!`], [`try_testdir!`] and
+/// [`with_testdir`] are created in `dir` instead of the global [`NumberedDir`], which is
+/// not initialised by them.  This only affects the current thread, other threads, including
+/// those spawned by `func`, keep using the global [`NumberedDir`].  Overrides can be
+/// nested, the innermost one is used.
+///
+/// This lets frameworks sandbox the code under test, which may use [`testdir!`] itself,
+/// without affecting the rest of the process.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{testdir, NumberedDir};
+///
+/// let parent = tempfile::tempdir().unwrap();
+/// let sandbox = NumberedDir::create(parent.path(), "sandbox", 3.try_into().unwrap()).unwrap();
+/// let dir = testdir::scoped_override(&sandbox, || testdir!("some/dir"));
+/// assert!(dir.starts_with(sandbox.path()));
+/// ```
+///
+/// [`testdir!`]: crate::testdir
+/// [`try_testdir!`]: crate::try_testdir
+/// [`with_testdir`]: crate::with_testdir
+pub fn scoped_override<F, R>(dir: &NumberedDir, func: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    /// Removes the override again, also when `func` panics.
+    struct Guard;
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            OVERRIDES.with(|overrides| overrides.borrow_mut().pop());
+        }
+    }
+
+    OVERRIDES.with(|overrides| overrides.borrow_mut().push(dir.clone()));
+    let _guard = Guard;
+    func()
+}
+
+/// Returns the innermost override active on the current thread.
+pub(crate) fn current() -> Option<NumberedDir> {
+    OVERRIDES.with(|overrides| overrides.borrow().last().cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU8;
+
+    use super::*;
+
+    #[test]
+    fn test_scoped_override() {
+        let parent = tempfile::tempdir().unwrap();
+        let count = NonZeroU8::new(3).unwrap();
+        let outer = NumberedDir::create(parent.path(), "outer", count).unwrap();
+        let inner = NumberedDir::create(parent.path(), "inner", count).unwrap();
+
+        scoped_override(&outer, || {
+            let path = crate::with_testdir(|dir| dir.create_subdir("a").unwrap());
+            assert_eq!(path, outer.path().join("a"));
+            scoped_override(&inner, || {
+                assert_eq!(crate::testdir!("b"), inner.path().join("b"));
+            });
+            assert_eq!(current().as_ref(), Some(&outer));
+            let other = std::thread::spawn(current).join().unwrap();
+            assert!(other.is_none());
+        });
+        assert!(current().is_none());
+    }
+}
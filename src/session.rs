@@ -0,0 +1,148 @@
+//! The [`TestSession`] and supporting code.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Map, Value};
+
+use crate::{gc_root, CleanupReport, Error, NumberedDir, NumberedDirBuilder};
+
+/// The name of the manifest file written by [`TestSession::write_manifest`].
+pub const SESSION_MANIFEST_FILE_NAME: &str = "session.json";
+
+/// A session owning several [`NumberedDir`]s with different bases.
+///
+/// Workspaces often produce several categories of artifacts per run, e.g. test
+/// directories, benchmark output and coverage data.  A session creates one
+/// [`NumberedDir`] per base on first use, all configured by the same
+/// [`NumberedDirBuilder`] in the same parent directory, so the [`Retention`] policies set
+/// using [`NumberedDirBuilder::base_retention`] are applied consistently.
+///
+/// [`Retention`]: crate::Retention
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{NumberedDirBuilder, TestSession};
+///
+/// let parent = std::env::temp_dir().join("testdir-session-example");
+/// let mut builder = NumberedDirBuilder::new(String::from("testdir"));
+/// builder.set_parent(parent.clone());
+/// let session = TestSession::new(builder);
+/// let tests = session.dir("testdir").unwrap();
+/// let benches = session.dir("benchdir").unwrap();
+/// assert_eq!(session.dir("testdir").unwrap(), tests);
+/// let manifest = session.write_manifest().unwrap();
+/// assert!(manifest.is_file());
+/// # std::fs::remove_dir_all(&parent).ok();
+/// ```
+#[derive(Debug)]
+pub struct TestSession {
+    builder: NumberedDirBuilder,
+    dirs: Mutex<BTreeMap<String, NumberedDir>>,
+}
+
+impl TestSession {
+    /// Creates a session creating its directories using `builder`.
+    ///
+    /// The **base** configured on the builder is ignored, each directory uses the base
+    /// passed to [`TestSession::dir`].
+    pub fn new(builder: NumberedDirBuilder) -> Self {
+        Self {
+            builder,
+            dirs: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the [`NumberedDir`] for `base`, creating it on first use.
+    pub fn dir(&self, base: &str) -> Result<NumberedDir, Error> {
+        let mut dirs = self
+            .dirs
+            .lock()
+            .map_err(|_| anyhow!("TestSession lock poisoned"))?;
+        if let Some(numdir) = dirs.get(base) {
+            return Ok(numdir.clone());
+        }
+        let numdir = self.builder.create_base(base)?;
+        dirs.insert(base.to_string(), numdir.clone());
+        Ok(numdir)
+    }
+
+    /// Returns all directories created by this session, ordered by base.
+    pub fn dirs(&self) -> Vec<NumberedDir> {
+        self.dirs
+            .lock()
+            .map(|dirs| dirs.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Applies the retention policies to all bases in the parent directory.
+    ///
+    /// This also cleans up bases which were not used by this session, see [`gc_root`].
+    pub fn gc(&self) -> Result<CleanupReport, Error> {
+        gc_root(self.builder.parent(), self.builder.retention_map().clone())
+    }
+
+    /// Writes a manifest of all directories of the session, returning its path.
+    ///
+    /// The manifest is a JSON file named [`SESSION_MANIFEST_FILE_NAME`] in the parent
+    /// directory, mapping each base to the path of its directory.
+    pub fn write_manifest(&self) -> Result<PathBuf, Error> {
+        let dirs: Map<String, Value> = self
+            .dirs()
+            .into_iter()
+            .map(|numdir| {
+                let entry = json!({
+                    "path": numdir.path(),
+                    "number": numdir.number(),
+                });
+                (numdir.base().to_string(), entry)
+            })
+            .collect();
+        let manifest = json!({ "dirs": dirs });
+        let path = self.builder.parent().join(SESSION_MANIFEST_FILE_NAME);
+        let data = serde_json::to_vec_pretty(&manifest).context("Failed to serialize manifest")?;
+        fs::write(&path, data).with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU8;
+
+    use super::*;
+    use crate::Retention;
+
+    #[test]
+    fn test_session() {
+        let parent = tempfile::tempdir().unwrap();
+        let mut builder = NumberedDirBuilder::new(String::from("ignored"));
+        builder
+            .set_parent(parent.path().to_path_buf())
+            .retention(Retention::Count(NonZeroU8::new(1).unwrap()));
+        let session = TestSession::new(builder.clone());
+        let tests = session.dir("testdir").unwrap();
+        let cov = session.dir("covdir").unwrap();
+        assert_eq!(session.dir("testdir").unwrap(), tests);
+        assert_eq!(session.dirs(), vec![cov.clone(), tests.clone()]);
+
+        let manifest = session.write_manifest().unwrap();
+        let manifest: Value = serde_json::from_slice(&fs::read(manifest).unwrap()).unwrap();
+        assert_eq!(manifest["dirs"]["covdir"]["number"], 0);
+        assert_eq!(
+            manifest["dirs"]["testdir"]["path"],
+            tests.path().to_str().unwrap()
+        );
+
+        let session = TestSession::new(builder);
+        session.dir("testdir").unwrap();
+        assert!(!tests.path().exists());
+        let report = session.gc().unwrap();
+        assert!(report.is_empty());
+        assert!(cov.path().exists());
+    }
+}
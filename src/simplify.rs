@@ -0,0 +1,146 @@
+//! Simplifying canonical paths for display.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The maximum length of a Windows path without the `\\?\` prefix.
+const MAX_PATH: usize = 260;
+
+/// The macOS directories which are symlinks into `/private`.
+const PRIVATE_DIRS: &[&str] = &["/var", "/tmp", "/etc"];
+
+/// Applies the enabled simplifications to `path`.
+pub(crate) fn simplify_path(path: &Path, strip_unc: bool, normalize_private: bool) -> PathBuf {
+    let mut path = path.to_path_buf();
+    if strip_unc {
+        path = strip_unc_prefix(&path);
+    }
+    if normalize_private {
+        path = strip_private(&path);
+    }
+    path
+}
+
+/// Removes the Windows verbatim `\\?\` prefix, if the path means the same without it.
+///
+/// This is what [`std::fs::canonicalize`] returns on Windows but many tools do not
+/// understand.  `\\?\C:\dir` becomes `C:\dir` and `\\?\UNC\server\share` becomes
+/// `\\server\share`.  Paths which are only valid in the verbatim form, e.g. because they are
+/// too long or contain reserved names, are returned unchanged.
+pub(crate) fn strip_unc_prefix(path: &Path) -> PathBuf {
+    let Some(s) = path.to_str() else {
+        return path.to_path_buf();
+    };
+    let simplified = if let Some(rest) = s.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{rest}")
+    } else if let Some(rest) = s.strip_prefix(r"\\?\") {
+        let bytes = rest.as_bytes();
+        if bytes.len() < 3 || !bytes[0].is_ascii_alphabetic() || &bytes[1..3] != br":\" {
+            return path.to_path_buf();
+        }
+        rest.to_string()
+    } else {
+        return path.to_path_buf();
+    };
+    let valid = simplified.len() < MAX_PATH
+        && !simplified.contains('/')
+        && simplified
+            .trim_start_matches('\\')
+            .split('\\')
+            .skip(1)
+            .all(|name| {
+                name != "."
+                    && name != ".."
+                    && crate::portable::encode_name(OsStr::new(name)).is_none()
+            });
+    if valid {
+        PathBuf::from(simplified)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Replaces a `/private/var`, `/private/tmp` or `/private/etc` prefix with `/var`, `/tmp` or
+/// `/etc`.
+///
+/// On macOS these are symlinks into `/private`, so canonicalizing e.g. the temporary
+/// directory gives a different path than [`std::env::temp_dir`].  The prefix is only replaced
+/// if the short form is such a symlink, so other platforms are not affected.
+pub(crate) fn strip_private(path: &Path) -> PathBuf {
+    for dir in PRIVATE_DIRS {
+        let private = Path::new("/private").join(dir.trim_start_matches('/'));
+        let Ok(rest) = path.strip_prefix(&private) else {
+            continue;
+        };
+        if fs::canonicalize(dir).is_ok_and(|target| target == private) {
+            return Path::new(dir).join(rest);
+        }
+    }
+    path.to_path_buf()
+}
+
+/// Canonicalizes `path`, which does not need to exist.
+///
+/// The deepest existing ancestor is canonicalized and the remaining components are appended.
+pub(crate) fn canonicalize_lenient(path: &Path) -> std::io::Result<PathBuf> {
+    let mut ancestor = path;
+    loop {
+        match fs::canonicalize(ancestor) {
+            Ok(canonical) => {
+                let rest = path.strip_prefix(ancestor).unwrap_or(Path::new(""));
+                return Ok(if rest.as_os_str().is_empty() {
+                    canonical
+                } else {
+                    canonical.join(rest)
+                });
+            }
+            Err(err) => match ancestor.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => ancestor = parent,
+                _ => return Err(err),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_unc_prefix() {
+        let strip = |path: &str| strip_unc_prefix(Path::new(path)).into_os_string();
+        assert_eq!(strip(r"\\?\C:\dir\file"), r"C:\dir\file");
+        assert_eq!(strip(r"\\?\UNC\server\share\dir"), r"\\server\share\dir");
+        assert_eq!(strip(r"C:\dir"), r"C:\dir");
+        assert_eq!(strip("/tmp/dir"), "/tmp/dir");
+        assert_eq!(strip(r"\\?\C:\dir\nul"), r"\\?\C:\dir\nul");
+        assert_eq!(strip(r"\\?\C:\dir."), r"\\?\C:\dir.");
+        assert_eq!(strip(r"\\?\C:\a/b"), r"\\?\C:\a/b");
+        assert_eq!(strip(r"\\?\Volume{1234}\dir"), r"\\?\Volume{1234}\dir");
+        let long = format!(r"\\?\C:\{}", "a".repeat(MAX_PATH));
+        assert_eq!(strip(&long), long.as_str());
+    }
+
+    #[test]
+    fn test_strip_private() {
+        let path = Path::new("/private/var/folders/dir");
+        if cfg!(target_os = "macos") {
+            assert_eq!(strip_private(path), Path::new("/var/folders/dir"));
+        } else {
+            assert_eq!(strip_private(path), path);
+        }
+        assert_eq!(strip_private(Path::new("/var/dir")), Path::new("/var/dir"));
+    }
+
+    #[test]
+    fn test_canonicalize_lenient() {
+        let temp = tempfile::tempdir().unwrap();
+        let canonical = temp.path().canonicalize().unwrap();
+        assert_eq!(
+            canonicalize_lenient(&temp.path().join("new/dir")).unwrap(),
+            canonical.join("new/dir")
+        );
+        assert_eq!(canonicalize_lenient(temp.path()).unwrap(), canonical);
+    }
+}
@@ -0,0 +1,76 @@
+//! Allocation of paths for Unix domain sockets.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::builder::restrict_permissions;
+
+/// The longest socket path which fits in `sockaddr_un` on all unix platforms.
+///
+/// Linux allows 107 bytes while macOS and the BSDs only allow 103, both excluding the
+/// terminating null byte.
+const MAX_SOCKET_PATH: usize = 103;
+
+/// Returns a path for a socket named `name` in `dir` which is short enough to bind to.
+///
+/// If `dir/name` is too long the socket is placed in a short directory in `/tmp` instead,
+/// derived from `dir` so it is unique to the test.  In that case `dir/name` is made a
+/// symlink to the returned path.  The directory in `/tmp` is not removed.
+pub(crate) fn socket_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    let path = dir.join(name);
+    if path.as_os_str().as_bytes().len() <= MAX_SOCKET_PATH {
+        return Ok(path);
+    }
+
+    let mut hasher = DefaultHasher::new();
+    dir.hash(&mut hasher);
+    let short_dir = Path::new("/tmp").join(format!("testdir-sock-{:016x}", hasher.finish()));
+    let short_path = short_dir.join(name);
+    if short_path.as_os_str().as_bytes().len() > MAX_SOCKET_PATH {
+        bail!("Socket name too long: {name}");
+    }
+    fs::create_dir_all(&short_dir)
+        .with_context(|| format!("Failed to create {}", short_dir.display()))?;
+    restrict_permissions(&short_dir)?;
+
+    if path.symlink_metadata().is_ok() {
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    }
+    symlink(&short_path, &path)
+        .with_context(|| format!("Failed to create symlink {}", path.display()))?;
+    Ok(short_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    use super::*;
+
+    #[test]
+    fn test_short_dir() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = socket_path(temp.path(), "a.sock").unwrap();
+        assert_eq!(path, temp.path().join("a.sock"));
+    }
+
+    #[test]
+    fn test_long_dir() {
+        let temp = tempfile::tempdir().unwrap();
+        let long = temp.path().join("x".repeat(60)).join("y".repeat(60));
+        fs::create_dir_all(&long).unwrap();
+        let path = socket_path(&long, "a.sock").unwrap();
+        assert!(path.starts_with("/tmp"));
+
+        let _listener = UnixListener::bind(&path).unwrap();
+        UnixStream::connect(&path).unwrap();
+        assert_eq!(fs::read_link(long.join("a.sock")).unwrap(), path);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+//! The end-of-run [`RunSummary`].
+
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+
+use crate::{dir_size, NumberedDir, TESTDIR};
+
+/// The environment variable enabling the summary at exit: `TESTDIR_SUMMARY`.
+pub const SUMMARY_ENV: &str = "TESTDIR_SUMMARY";
+
+/// Summary of what was done with the global [`NumberedDir`] by this process.
+///
+/// Use [`run_summary`] to get the summary, or [`print_summary_at_exit`] to print it to
+/// stderr when the process exits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunSummary {
+    /// The path of the numbered run directory.
+    pub run_dir: PathBuf,
+    /// The subdirectories created by this process.
+    pub subdirs: Vec<PathBuf>,
+    /// The total size in bytes of the files in the created subdirectories.
+    pub bytes: u64,
+    /// The obsolete run directories removed when the run directory was created.
+    pub removed: Vec<PathBuf>,
+}
+
+impl RunSummary {
+    /// Creates the summary for a [`NumberedDir`].
+    pub fn new(numdir: &NumberedDir) -> Self {
+        let subdirs = numdir.created_subdirs();
+        // Nested subdirectories are already counted as part of their parents.
+        let bytes = subdirs
+            .iter()
+            .filter(|dir| !subdirs.iter().any(|other| is_nested(dir, other)))
+            .filter_map(|dir| dir_size(dir).ok())
+            .sum();
+        Self {
+            run_dir: numdir.path().to_path_buf(),
+            subdirs,
+            bytes,
+            removed: numdir.removed_dirs().to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "testdir run directory: {}", self.run_dir.display())?;
+        writeln!(
+            f,
+            "  {} test directories created, {} bytes",
+            self.subdirs.len(),
+            self.bytes
+        )?;
+        if self.removed.is_empty() {
+            write!(f, "  no old runs removed")
+        } else {
+            write!(f, "  removed old runs:")?;
+            for path in &self.removed {
+                write!(f, "\n    {}", path.display())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Returns the [`RunSummary`] of the global [`NumberedDir`].
+///
+/// Returns `None` if the global [`NumberedDir`] was not yet initialised.
+pub fn run_summary() -> Option<RunSummary> {
+    TESTDIR.get().map(RunSummary::new)
+}
+
+/// Prints the [`RunSummary`] to stderr when the process exits.
+///
+/// This registers an exit handler, calling this multiple times only registers it once.
+/// Nothing is printed if the global [`NumberedDir`] was never initialised.  This is also
+/// enabled by [`init_testdir!`](crate::init_testdir) if the `TESTDIR_SUMMARY`
+/// environment variable is set to a non-empty value other than `0`.
+///
+/// On `wasm32-unknown-unknown` there are no exit handlers and this does nothing.
+pub fn print_summary_at_exit() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| register_exit_handler(print_summary));
+}
+
+/// Registers `handler` to be called when the process exits.
+///
+/// On `wasm32-unknown-unknown` this does nothing.
+pub(crate) fn register_exit_handler(handler: extern "C" fn()) {
+    #[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+    // SAFETY: The handler is a plain function with the required signature.
+    unsafe {
+        atexit(handler);
+    }
+    #[cfg(all(target_family = "wasm", target_os = "unknown"))]
+    let _ = handler;
+}
+
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+extern "C" {
+    fn atexit(cb: extern "C" fn()) -> std::os::raw::c_int;
+}
+
+extern "C" fn print_summary() {
+    if let Some(summary) = run_summary() {
+        eprintln!("{summary}");
+    }
+}
+
+/// Registers the exit handler if requested using the environment.
+pub(crate) fn register_from_env() {
+    if env::var(SUMMARY_ENV).is_ok_and(|val| !val.is_empty() && val != "0") {
+        print_summary_at_exit();
+    }
+}
+
+fn is_nested(dir: &Path, other: &Path) -> bool {
+    dir != other && dir.starts_with(other)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::num::NonZeroU8;
+
+    use super::*;
+
+    #[test]
+    fn test_run_summary() {
+        let parent = tempfile::tempdir().unwrap();
+        let count = NonZeroU8::new(1).unwrap();
+        let dir_0 = NumberedDir::create(parent.path(), "base", count).unwrap();
+        let dir_1 = NumberedDir::create(parent.path(), "base", count).unwrap();
+        let one = dir_1.create_subdir("one").unwrap();
+        let two = dir_1.create_subdir("one/two").unwrap();
+        fs::write(one.join("file"), [0u8; 10]).unwrap();
+        fs::write(two.join("file"), [0u8; 5]).unwrap();
+
+        let summary = RunSummary::new(&dir_1);
+        assert_eq!(summary.run_dir, dir_1.path());
+        assert_eq!(summary.subdirs.len(), 2);
+        assert_eq!(summary.bytes, 15);
+        assert_eq!(summary.removed, vec![dir_0.path().to_path_buf()]);
+        assert!(summary.to_string().contains("2 test directories created"));
+    }
+}
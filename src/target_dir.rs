@@ -0,0 +1,517 @@
+//! Locating the cargo target directory for the global testdir, see [`TargetDirSource`].
+
+use std::env;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cargo_metadata::MetadataCommand;
+use once_cell::sync::OnceCell;
+
+/// Files cargo creates in the root of a target directory.
+const TARGET_DIR_MARKERS: [&str; 2] = ["CACHEDIR.TAG", ".rustc_info.json"];
+
+/// How many levels above the test executable the target directory is searched for.
+///
+/// Test executables are in `target/[triple/]profile/deps/`.
+const MAX_EXE_LEVELS: usize = 4;
+
+/// The directory in the system temporary directory used when `cargo metadata` timed out.
+const TIMEOUT_DIR_NAME: &str = "testdir-fallback";
+
+/// The [`RunKv`](crate::RunKv) key recording the [`TargetDirSource`] of the global testdir.
+pub(crate) const SOURCE_KEY: &str = "target-dir-source";
+
+/// How often a running `cargo metadata` is checked for completion.
+const METADATA_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The default time `cargo metadata` may take before it is abandoned: 60 seconds.
+///
+/// See [`METADATA_TIMEOUT_ENV`].
+pub const METADATA_TIMEOUT_DEFAULT: Duration = Duration::from_secs(60);
+
+/// The environment variable setting the `cargo metadata` timeout in seconds:
+/// `TESTDIR_METADATA_TIMEOUT`.
+///
+/// When `cargo metadata` does not finish within this time, e.g. because it waits for the
+/// package cache lock or the network, it is killed and the `CARGO_TARGET_DIR` environment
+/// variable or the system temporary directory is used instead, see
+/// [`TargetDirSource::TimeoutEnvVar`] and [`TargetDirSource::TimeoutTempDir`].
+/// Defaults to [`METADATA_TIMEOUT_DEFAULT`].  This takes precedence over
+/// `init_testdir!(metadata_timeout = ...)`.
+pub const METADATA_TIMEOUT_ENV: &str = "TESTDIR_METADATA_TIMEOUT";
+
+/// A function customising the `cargo metadata` invocation of [`init_testdir!`].
+///
+/// This is set using `init_testdir!(metadata_command = my_fn)` and can e.g. add `--offline`
+/// or `--frozen` using [`MetadataCommand::other_options`], enable
+/// [`MetadataCommand::no_deps`] or set a [`MetadataCommand::manifest_path`].
+///
+/// [`init_testdir!`]: crate::init_testdir
+/// [`MetadataCommand::other_options`]: cargo_metadata::MetadataCommand::other_options
+/// [`MetadataCommand::no_deps`]: cargo_metadata::MetadataCommand::no_deps
+/// [`MetadataCommand::manifest_path`]: cargo_metadata::MetadataCommand::manifest_path
+pub type MetadataHook = fn(&mut MetadataCommand);
+
+/// The timeout set by `init_testdir!(metadata_timeout = ...)` in milliseconds.
+///
+/// `u64::MAX` means no timeout was set.
+static METADATA_TIMEOUT_MS: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Records the `metadata_timeout` argument of `init_testdir!`.
+pub(crate) fn set_metadata_timeout(timeout: Duration) {
+    let millis = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX - 1);
+    METADATA_TIMEOUT_MS.store(millis, Ordering::Relaxed);
+}
+
+/// Returns the timeout for `cargo metadata`.
+fn metadata_timeout() -> Duration {
+    let from_env = env::var(METADATA_TIMEOUT_ENV)
+        .ok()
+        .and_then(|secs| secs.trim().parse::<f64>().ok())
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok());
+    match (from_env, METADATA_TIMEOUT_MS.load(Ordering::Relaxed)) {
+        (Some(timeout), _) => timeout,
+        (None, u64::MAX) => METADATA_TIMEOUT_DEFAULT,
+        (None, millis) => Duration::from_millis(millis),
+    }
+}
+
+/// The source from which the parent directory of the global testdir was found.
+///
+/// [`init_testdir!`] tries the following sources in order, the first one found is used:
+///
+/// 1. [`TargetDirSource::BuildSystem`]
+/// 2. [`TargetDirSource::BuildScript`]
+/// 3. [`TargetDirSource::Miri`]
+/// 4. [`TargetDirSource::Wasm`]
+/// 5. [`TargetDirSource::Executable`]
+/// 6. [`TargetDirSource::EnvVar`]
+/// 7. [`TargetDirSource::Metadata`]
+/// 8. [`TargetDirSource::ExecutableDir`]
+///
+/// If `cargo metadata` times out, see [`METADATA_TIMEOUT_ENV`], the last step is replaced
+/// by [`TargetDirSource::TimeoutEnvVar`] or else [`TargetDirSource::TimeoutTempDir`].  The
+/// source used is recorded in the [`RunKv`](crate::RunKv) of the global testdir, under the
+/// `target-dir-source` key.
+///
+/// Locating the target directory from the test executable means `--target-dir`,
+/// `CARGO_TARGET_DIR` and `build.target-dir` from `.cargo/config.toml` are all respected
+/// exactly as used by cargo for this build.  Only doctests, which are compiled to a
+/// temporary location, need to rely on the environment or `cargo metadata`.
+///
+/// The source used is returned by [`target_dir_source`].
+///
+/// [`init_testdir!`]: crate::init_testdir
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TargetDirSource {
+    /// The temporary directory of a build system like Bazel, see
+    /// [`BuildSystemEnv`](crate::BuildSystemEnv).
+    BuildSystem,
+    /// The target directory captured at build time using
+    /// [`build_support`](crate::build_support).
+    BuildScript,
+    /// The fixed directory used in Miri mode, see [`MIRI_ENV`](crate::MIRI_ENV).
+    Miri,
+    /// The directory relative to the current directory used on wasm targets.
+    Wasm,
+    /// The target directory containing the test executable.
+    Executable,
+    /// The absolute path in the `CARGO_TARGET_DIR` environment variable.
+    EnvVar,
+    /// The target directory reported by `cargo metadata`.
+    Metadata,
+    /// The directory of the test executable, if nothing else worked.
+    ExecutableDir,
+    /// The `CARGO_TARGET_DIR` environment variable, after `cargo metadata` timed out.
+    ///
+    /// Unlike [`TargetDirSource::EnvVar`] this is also used for relative paths, which are
+    /// resolved against the current directory.
+    TimeoutEnvVar,
+    /// A `testdir-fallback` directory in the system temporary directory, after
+    /// `cargo metadata` timed out and `CARGO_TARGET_DIR` was not set.
+    TimeoutTempDir,
+}
+
+impl fmt::Display for TargetDirSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::BuildSystem => "build system",
+            Self::BuildScript => "build script",
+            Self::Miri => "miri",
+            Self::Wasm => "wasm",
+            Self::Executable => "test executable",
+            Self::EnvVar => "CARGO_TARGET_DIR",
+            Self::Metadata => "cargo metadata",
+            Self::ExecutableDir => "test executable directory",
+            Self::TimeoutEnvVar => "CARGO_TARGET_DIR after cargo metadata timed out",
+            Self::TimeoutTempDir => "temporary directory after cargo metadata timed out",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The source of the parent directory of the global testdir.
+static SOURCE: OnceCell<TargetDirSource> = OnceCell::new();
+
+/// Returns where the parent directory of the global testdir was found.
+///
+/// Returns `None` if the global testdir was not created by [`init_testdir!`], e.g. because
+/// it was not yet initialised or [`override_builder_for_tests`] was used.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::testdir;
+///
+/// let _dir = testdir!();
+/// assert!(testdir::target_dir_source().is_some());
+/// ```
+///
+/// [`init_testdir!`]: crate::init_testdir
+/// [`override_builder_for_tests`]: crate::override_builder_for_tests
+pub fn target_dir_source() -> Option<TargetDirSource> {
+    SOURCE.get().copied()
+}
+
+/// Resolves the parent directory of the global testdir.
+///
+/// The `build_script_dir` is the value of `TESTDIR_TARGET_DIR` at compile time of the
+/// crate using the macro.
+pub(crate) fn resolve(build_script_dir: Option<&str>) -> PathBuf {
+    let (dir, source) = find(build_script_dir);
+    SOURCE.set(source).ok();
+    dir
+}
+
+fn find(build_script_dir: Option<&str>) -> (PathBuf, TargetDirSource) {
+    if let Some(dir) = crate::private::build_system_root() {
+        return (dir, TargetDirSource::BuildSystem);
+    }
+    if let Some(dir) = build_script_dir {
+        return (PathBuf::from(dir), TargetDirSource::BuildScript);
+    }
+    if crate::private::miri_mode() {
+        return (crate::private::miri_root(), TargetDirSource::Miri);
+    }
+    if cfg!(target_family = "wasm") {
+        return (crate::private::wasm_root(), TargetDirSource::Wasm);
+    }
+    let current_exe = env::current_exe().ok();
+    if let Some(dir) = current_exe.as_deref().and_then(target_dir_of_exe) {
+        return (dir, TargetDirSource::Executable);
+    }
+    if let Some(dir) = env::var_os("CARGO_TARGET_DIR").map(PathBuf::from) {
+        if dir.is_absolute() {
+            return (dir, TargetDirSource::EnvVar);
+        }
+    }
+    let mut cmd = MetadataCommand::new();
+    if let Some(hook) = crate::METADATA_HOOK.get() {
+        hook(&mut cmd);
+    }
+    match metadata_target_dir(&cmd, metadata_timeout()) {
+        Ok(dir) => (dir, TargetDirSource::Metadata),
+        Err(MetadataError::TimedOut) => {
+            timeout_fallback(env::var_os("CARGO_TARGET_DIR").map(PathBuf::from))
+        }
+        Err(MetadataError::Failed) => {
+            // In some environments cargo-metadata is not available, e.g. cargo-dinghy.
+            let dir = current_exe
+                .as_deref()
+                .and_then(Path::parent)
+                .map(Path::to_path_buf)
+                .unwrap_or_else(crate::root::temp_dir);
+            (dir, TargetDirSource::ExecutableDir)
+        }
+    }
+}
+
+/// Returns the directory to use after `cargo metadata` timed out.
+fn timeout_fallback(cargo_target_dir: Option<PathBuf>) -> (PathBuf, TargetDirSource) {
+    let env_dir = cargo_target_dir
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .and_then(|dir| {
+            if dir.is_absolute() {
+                Some(dir)
+            } else {
+                env::current_dir().ok().map(|cwd| cwd.join(dir))
+            }
+        });
+    match env_dir {
+        Some(dir) => (dir, TargetDirSource::TimeoutEnvVar),
+        None => (
+            crate::root::temp_dir().join(TIMEOUT_DIR_NAME),
+            TargetDirSource::TimeoutTempDir,
+        ),
+    }
+}
+
+/// Records the source of the parent directory in the run key-value store of `numdir`.
+pub(crate) fn record_source(numdir: &crate::NumberedDir) -> Result<(), crate::Error> {
+    if let Some(source) = target_dir_source() {
+        crate::RunKv::open(numdir.path().join(crate::kv::RUN_KV_FILE_NAME))
+            .get_or_set_with(SOURCE_KEY, || source.to_string())?;
+    }
+    Ok(())
+}
+
+/// Why `cargo metadata` did not provide the target directory.
+#[derive(Debug, PartialEq, Eq)]
+enum MetadataError {
+    /// It did not finish within the timeout and was killed.
+    TimedOut,
+    /// It could not be run, failed or its output could not be parsed.
+    Failed,
+}
+
+/// Runs `cmd` and returns the target directory, unless it fails or exceeds `timeout`.
+fn metadata_target_dir(cmd: &MetadataCommand, timeout: Duration) -> Result<PathBuf, MetadataError> {
+    let mut child = cmd
+        .cargo_command()
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|_| MetadataError::Failed)?;
+    let mut stdout = child.stdout.take().ok_or(MetadataError::Failed)?;
+    // Read concurrently so a full pipe can not block cargo.
+    let reader = thread::spawn(move || {
+        let mut data = String::new();
+        stdout.read_to_string(&mut data).map(|_| data)
+    });
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => break,
+            Ok(None) if Instant::now() < deadline => thread::sleep(METADATA_POLL_INTERVAL),
+            Ok(None) => {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(MetadataError::TimedOut);
+            }
+            _ => return Err(MetadataError::Failed),
+        }
+    }
+    let data = reader
+        .join()
+        .ok()
+        .and_then(Result::ok)
+        .ok_or(MetadataError::Failed)?;
+    let json = data
+        .lines()
+        .find(|line| line.starts_with('{'))
+        .ok_or(MetadataError::Failed)?;
+    let metadata = MetadataCommand::parse(json).map_err(|_| MetadataError::Failed)?;
+    Ok(metadata.target_directory.into())
+}
+
+/// Returns the cargo build profile of the running executable, e.g. `debug` or `release`.
+///
+/// This is the profile directory in the target directory containing the executable, so
+/// custom profiles are detected as well.  If the executable is not in a target directory,
+/// e.g. for doctests, this is `debug` or `release` depending on whether testdir was built
+/// with debug assertions.
+pub(crate) fn build_profile() -> String {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| layout_of_exe(&exe))
+        .map(|layout| layout.profile)
+        .unwrap_or_else(|| {
+            let profile = if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            };
+            profile.to_string()
+        })
+}
+
+/// Returns the target triple the running executable was cross-compiled for.
+///
+/// This is only known when cargo was invoked with `--target` or `build.target`, in which
+/// case the executable is in a directory named after the target triple.  Returns `None` for
+/// builds for the host, and if the executable is not in a target directory.
+pub(crate) fn target_triple() -> Option<String> {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| layout_of_exe(&exe))
+        .and_then(|layout| layout.triple)
+}
+
+/// The location of an executable in the target directory.
+#[derive(Debug, PartialEq, Eq)]
+struct ExeLayout {
+    /// The target triple directory, only present for builds with an explicit target.
+    triple: Option<String>,
+    /// The profile directory.
+    profile: String,
+}
+
+/// Returns the layout of the executable `exe` in its target directory.
+///
+/// Test executables are in `target/[triple/]profile/deps/`.
+fn layout_of_exe(exe: &Path) -> Option<ExeLayout> {
+    let target_dir = target_dir_of_exe(exe)?;
+    let rel = exe.strip_prefix(target_dir).ok()?;
+    let components: Vec<_> = rel
+        .iter()
+        .map(|name| name.to_str())
+        .collect::<Option<_>>()?;
+    match components[..] {
+        [profile, _, _] => Some(ExeLayout {
+            triple: None,
+            profile: profile.to_string(),
+        }),
+        [triple, profile, _, _] => Some(ExeLayout {
+            triple: Some(triple.to_string()),
+            profile: profile.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Returns the cargo target directory containing the executable `exe`.
+fn target_dir_of_exe(exe: &Path) -> Option<PathBuf> {
+    exe.ancestors()
+        .skip(1)
+        .take(MAX_EXE_LEVELS)
+        .find(|dir| {
+            TARGET_DIR_MARKERS
+                .iter()
+                .any(|name| dir.join(name).is_file())
+        })
+        .map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_target_dir_of_exe() {
+        let temp = tempfile::tempdir().unwrap();
+        let target = temp.path().join("custom-target");
+        let deps = target.join("x86_64-unknown-linux-gnu/debug/deps");
+        fs::create_dir_all(&deps).unwrap();
+        let exe = deps.join("mytest-1234");
+        assert_eq!(target_dir_of_exe(&exe), None);
+
+        fs::write(target.join("CACHEDIR.TAG"), "").unwrap();
+        assert_eq!(target_dir_of_exe(&exe), Some(target.clone()));
+
+        let exe = target.join("a/b/c/d/e/exe");
+        assert_eq!(target_dir_of_exe(&exe), None);
+    }
+
+    #[test]
+    fn test_layout_of_exe() {
+        let temp = tempfile::tempdir().unwrap();
+        let target = temp.path().join("target");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join(".rustc_info.json"), "{}").unwrap();
+        let layout = |exe: &str| layout_of_exe(&target.join(exe));
+        let expected = |triple: Option<&str>, profile: &str| {
+            Some(ExeLayout {
+                triple: triple.map(String::from),
+                profile: profile.to_string(),
+            })
+        };
+        assert_eq!(layout("debug/deps/test-1234"), expected(None, "debug"));
+        assert_eq!(
+            layout("aarch64-unknown-linux-gnu/release/deps/test-1234"),
+            expected(Some("aarch64-unknown-linux-gnu"), "release")
+        );
+        assert_eq!(layout("bench/examples/ex"), expected(None, "bench"));
+        assert_eq!(layout("exe"), None);
+
+        let exe = env::current_exe().unwrap();
+        assert!(exe.iter().any(|name| name == build_profile().as_str()));
+        if let Some(triple) = target_triple() {
+            assert!(exe.iter().any(|name| name == triple.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_metadata_target_dir() {
+        let mut cmd = MetadataCommand::new();
+        cmd.no_deps();
+        let dir = metadata_target_dir(&cmd, METADATA_TIMEOUT_DEFAULT).unwrap();
+        assert!(dir.is_absolute());
+
+        cmd.other_options(vec![String::from("--no-such-option")]);
+        assert_eq!(
+            metadata_target_dir(&cmd, METADATA_TIMEOUT_DEFAULT),
+            Err(MetadataError::Failed)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_metadata_target_dir_timeout() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let cargo = temp.path().join("cargo");
+        fs::write(&cargo, "#!/bin/sh\nsleep 10\n").unwrap();
+        fs::set_permissions(&cargo, fs::Permissions::from_mode(0o755)).unwrap();
+        let mut cmd = MetadataCommand::new();
+        cmd.cargo_path(&cargo);
+        let start = Instant::now();
+        assert_eq!(
+            metadata_target_dir(&cmd, Duration::from_millis(100)),
+            Err(MetadataError::TimedOut)
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_timeout_fallback() {
+        let abs = env::temp_dir().join("custom-target");
+        assert_eq!(
+            timeout_fallback(Some(abs.clone())),
+            (abs, TargetDirSource::TimeoutEnvVar)
+        );
+        assert_eq!(
+            timeout_fallback(Some(PathBuf::from("rel-target"))),
+            (
+                env::current_dir().unwrap().join("rel-target"),
+                TargetDirSource::TimeoutEnvVar
+            )
+        );
+        let temp = (
+            crate::root::temp_dir().join(TIMEOUT_DIR_NAME),
+            TargetDirSource::TimeoutTempDir,
+        );
+        assert_eq!(timeout_fallback(None), temp);
+        assert_eq!(timeout_fallback(Some(PathBuf::new())), temp);
+    }
+
+    #[test]
+    fn test_find_build_script() {
+        if crate::BuildSystemEnv::detect().is_some() {
+            return;
+        }
+        let (dir, source) = find(Some("/baked/target"));
+        assert_eq!(dir, Path::new("/baked/target"));
+        assert_eq!(source, TargetDirSource::BuildScript);
+        assert_eq!(source.to_string(), "build script");
+    }
+
+    #[test]
+    fn test_find_executable() {
+        if crate::BuildSystemEnv::detect().is_some() || crate::private::miri_mode() {
+            return;
+        }
+        let (dir, source) = find(None);
+        assert_eq!(source, TargetDirSource::Executable);
+        assert!(env::current_exe().unwrap().starts_with(dir));
+    }
+}
@@ -0,0 +1,875 @@
+//! The [`TestDir`] handle and supporting code.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::thread;
+
+use anyhow::{Context, Result};
+
+use crate::fixtures;
+use crate::Error;
+
+/// A handle to a test directory.
+///
+/// This wraps the path of a directory, usually one returned by [`testdir!`], and provides
+/// helpers for common tasks in tests.  It dereferences to a [`Path`] so can be used
+/// wherever a path is expected.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{testdir, TestDir};
+///
+/// let dir = TestDir::new(testdir!());
+/// assert!(dir.is_dir());
+/// ```
+///
+/// [`testdir!`]: crate::testdir
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TestDir {
+    path: PathBuf,
+    auto_recreate: bool,
+}
+
+/// The result of [`TestDir::capture_output`].
+#[derive(Clone, Debug)]
+pub struct CapturedRun {
+    /// The exit status of the command.
+    pub status: ExitStatus,
+    /// The path of the file containing the stdout of the command.
+    pub stdout_path: PathBuf,
+    /// The path of the file containing the stderr of the command.
+    pub stderr_path: PathBuf,
+    /// The stdout of the command.
+    pub stdout: Vec<u8>,
+    /// The stderr of the command.
+    pub stderr: Vec<u8>,
+}
+
+impl TestDir {
+    /// Creates a handle for the directory at `path`.
+    ///
+    /// The directory is not created, it is expected to already exist.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            auto_recreate: false,
+        }
+    }
+
+    /// Recreates the directory automatically if it went missing.
+    ///
+    /// When enabled, the helpers on [`TestDir`] which write into the directory call
+    /// [`TestDir::ensure`] first.  This protects against the directory being removed
+    /// externally, e.g. by a tmp reaper, while a long test is running.
+    pub fn auto_recreate(mut self, enable: bool) -> Self {
+        self.auto_recreate = enable;
+        self
+    }
+
+    /// Ensures the directory exists, recreating it if it was removed.
+    ///
+    /// Recreating the directory is reported on stderr, as it usually means something else
+    /// is cleaning up directories which are still in use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// let dir = TestDir::new(testdir!());
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// dir.ensure().unwrap();
+    /// assert!(dir.is_dir());
+    /// ```
+    pub fn ensure(&self) -> Result<&Path, Error> {
+        if !self.path.is_dir() {
+            eprintln!(
+                "testdir: directory {} went missing, recreating it",
+                self.path.display()
+            );
+            fs::create_dir_all(&self.path)
+                .with_context(|| format!("Failed to recreate {}", self.path.display()))?;
+        }
+        Ok(&self.path)
+    }
+
+    /// Calls [`TestDir::ensure`] if [`TestDir::auto_recreate`] is enabled.
+    fn prepare(&self) -> Result<()> {
+        if self.auto_recreate {
+            self.ensure()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the test directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Consumes the handle, returning the path of the test directory.
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    /// Returns a [`ChildPath`] for a path relative to the test directory.
+    ///
+    /// Nothing is created until the child path is written to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// let dir = TestDir::new(testdir!());
+    /// let file = dir.child("a/b/c.txt");
+    /// file.write("hello").unwrap();
+    /// assert_eq!(file.read_to_string().unwrap(), "hello");
+    /// ```
+    pub fn child(&self, path: impl AsRef<Path>) -> ChildPath {
+        ChildPath::new(self.path.join(path))
+    }
+
+    /// Serialises `value` as JSON into the file at `rel`, creating parent directories.
+    ///
+    /// The JSON is pretty-printed to make it easy to inspect.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::BTreeMap;
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// let dir = TestDir::new(testdir!());
+    /// let value = BTreeMap::from([("answer".to_string(), 42)]);
+    /// dir.write_json("data/value.json", &value).unwrap();
+    /// let read: BTreeMap<String, i32> = dir.read_json("data/value.json").unwrap();
+    /// assert_eq!(read, value);
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn write_json<T>(&self, rel: impl AsRef<Path>, value: &T) -> Result<(), Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        let child = self.child(rel);
+        let data = serde_json::to_vec_pretty(value)
+            .with_context(|| format!("Failed to serialise JSON for {}", child.display()))?;
+        child.write(data)
+    }
+
+    /// Deserialises the JSON file at `rel`.
+    #[cfg(feature = "serde")]
+    pub fn read_json<T>(&self, rel: impl AsRef<Path>) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let child = self.child(rel);
+        let data = child.read()?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("Failed to parse JSON from {}", child.display()))
+            .map_err(Error::from)
+    }
+
+    /// Serialises `value` as TOML into the file at `rel`, creating parent directories.
+    #[cfg(feature = "toml")]
+    pub fn write_toml<T>(&self, rel: impl AsRef<Path>, value: &T) -> Result<(), Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        let child = self.child(rel);
+        let data = toml::to_string_pretty(value)
+            .with_context(|| format!("Failed to serialise TOML for {}", child.display()))?;
+        child.write(data)
+    }
+
+    /// Deserialises the TOML file at `rel`.
+    #[cfg(feature = "toml")]
+    pub fn read_toml<T>(&self, rel: impl AsRef<Path>) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let child = self.child(rel);
+        let data = child.read_to_string()?;
+        toml::from_str(&data)
+            .with_context(|| format!("Failed to parse TOML from {}", child.display()))
+            .map_err(Error::from)
+    }
+
+    /// Serialises `value` as YAML into the file at `rel`, creating parent directories.
+    #[cfg(feature = "yaml")]
+    pub fn write_yaml<T>(&self, rel: impl AsRef<Path>, value: &T) -> Result<(), Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        let child = self.child(rel);
+        let data = serde_yaml::to_string(value)
+            .with_context(|| format!("Failed to serialise YAML for {}", child.display()))?;
+        child.write(data)
+    }
+
+    /// Deserialises the YAML file at `rel`.
+    #[cfg(feature = "yaml")]
+    pub fn read_yaml<T>(&self, rel: impl AsRef<Path>) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let child = self.child(rel);
+        let data = child.read()?;
+        serde_yaml::from_slice(&data)
+            .with_context(|| format!("Failed to parse YAML from {}", child.display()))
+            .map_err(Error::from)
+    }
+
+    /// Creates a file of `size` bytes with pseudo-random content generated from `seed`.
+    ///
+    /// The same seed always produces the same content, on all platforms and versions.
+    /// Parent directories are created as needed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// let dir = TestDir::new(testdir!());
+    /// let file = dir.create_random_file("data.bin", 4096, 42).unwrap();
+    /// assert_eq!(file.metadata().unwrap().len(), 4096);
+    /// ```
+    pub fn create_random_file(
+        &self,
+        rel: impl AsRef<Path>,
+        size: u64,
+        seed: u64,
+    ) -> Result<ChildPath, Error> {
+        let child = self.child(rel);
+        fixtures::write_random_file(&child, size, seed)?;
+        Ok(child)
+    }
+
+    /// Creates a tree of `count` pseudo-random files of `size` bytes each below `rel`.
+    ///
+    /// The files are spread over subdirectories, each file gets its own content derived
+    /// from `seed`.  Returns the created files.
+    pub fn create_random_tree(
+        &self,
+        rel: impl AsRef<Path>,
+        count: usize,
+        size: u64,
+        seed: u64,
+    ) -> Result<Vec<ChildPath>, Error> {
+        fixtures::write_random_tree(&self.child(rel), count, size, seed).map_err(Error::from)
+    }
+
+    /// Creates a sparse file with a logical size of `logical_size` bytes.
+    ///
+    /// The file reads as all zeroes but uses almost no disk space on filesystems which
+    /// support sparse files, allowing to test with huge files.  On Windows the file is
+    /// marked sparse using `FSCTL_SET_SPARSE`.  Parent directories are created as needed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// let dir = TestDir::new(testdir!());
+    /// let file = dir.create_sparse_file("disk.img", 1 << 30).unwrap();
+    /// assert_eq!(file.metadata().unwrap().len(), 1 << 30);
+    /// ```
+    pub fn create_sparse_file(
+        &self,
+        rel: impl AsRef<Path>,
+        logical_size: u64,
+    ) -> Result<ChildPath, Error> {
+        let child = self.child(rel);
+        fixtures::create_sparse_file(&child, logical_size)?;
+        Ok(child)
+    }
+
+    /// Creates a named pipe, also known as a FIFO.
+    ///
+    /// The FIFO is only accessible by the owner.  Parent directories are created as needed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// let dir = TestDir::new(testdir!());
+    /// let fifo = dir.create_fifo("pipe").unwrap();
+    /// ```
+    #[cfg(unix)]
+    pub fn create_fifo(&self, rel: impl AsRef<Path>) -> Result<ChildPath, Error> {
+        let child = self.child(rel);
+        fixtures::create_fifo(&child)?;
+        Ok(child)
+    }
+
+    /// Creates a character device node with the given device numbers.
+    ///
+    /// Creating device nodes generally requires elevated privileges, so expect this to fail
+    /// when running as a normal user.  Parent directories are created as needed.
+    #[cfg(unix)]
+    pub fn create_char_device(
+        &self,
+        rel: impl AsRef<Path>,
+        major: u32,
+        minor: u32,
+    ) -> Result<ChildPath, Error> {
+        let child = self.child(rel);
+        fixtures::create_char_device(&child, major, minor)?;
+        Ok(child)
+    }
+
+    /// Returns a path for a Unix domain socket named `name` in the test directory.
+    ///
+    /// Socket paths are limited to around 100 bytes, which deeply nested test directories
+    /// easily exceed.  When the path in the test directory would be too long a short
+    /// directory in `/tmp` unique to this test directory is used instead, and a symlink
+    /// with the socket name in the test directory points to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::os::unix::net::UnixListener;
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// let dir = TestDir::new(testdir!());
+    /// let path = dir.socket_path("server.sock").unwrap();
+    /// let listener = UnixListener::bind(path).unwrap();
+    /// ```
+    #[cfg(unix)]
+    pub fn socket_path(&self, name: &str) -> Result<PathBuf, Error> {
+        self.prepare()?;
+        crate::socket::socket_path(&self.path, name).map_err(Error::from)
+    }
+
+    /// Sets the extended attribute `name` of the file at `rel`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// let dir = TestDir::new(testdir!());
+    /// dir.child("file").touch().unwrap();
+    /// dir.set_xattr("file", "user.origin", b"fixture").unwrap();
+    /// let value = dir.get_xattr("file", "user.origin").unwrap();
+    /// assert_eq!(value.as_deref(), Some(&b"fixture"[..]));
+    /// ```
+    #[cfg(all(unix, feature = "xattr"))]
+    pub fn set_xattr(&self, rel: impl AsRef<Path>, name: &str, value: &[u8]) -> Result<(), Error> {
+        self.child(rel).set_xattr(name, value)
+    }
+
+    /// Returns the extended attribute `name` of the file at `rel`, if it is set.
+    #[cfg(all(unix, feature = "xattr"))]
+    pub fn get_xattr(&self, rel: impl AsRef<Path>, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.child(rel).get_xattr(name)
+    }
+
+    /// Makes the fixture at `rel` read-only until the returned guard is dropped.
+    ///
+    /// All files and directories of the subtree are made read-only, so tests can prove
+    /// that the code under test does not modify its inputs.  The original permissions are
+    /// restored when the [`ProtectGuard`] is dropped.  Note that processes running with
+    /// elevated privileges, e.g. as root, can still modify read-only files.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// let dir = TestDir::new(testdir!());
+    /// dir.child("input/data.txt").write("data").unwrap();
+    /// let guard = dir.protect("input").unwrap();
+    /// assert!(dir.join("input/data.txt").metadata().unwrap().permissions().readonly());
+    /// drop(guard);
+    /// ```
+    ///
+    /// [`ProtectGuard`]: crate::ProtectGuard
+    pub fn protect(&self, rel: impl AsRef<Path>) -> Result<crate::ProtectGuard, Error> {
+        crate::ProtectGuard::new(&self.path.join(rel)).map_err(Error::from)
+    }
+
+    /// Redirects the build artifacts of compile tests into the test directory.
+    ///
+    /// This points `CARGO_TARGET_DIR` to the `target` directory inside the test directory
+    /// until the returned [`CompileTestGuard`] is dropped, so that UI tests using e.g.
+    /// trybuild build inside the numbered directory rather than the real target
+    /// directory.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// #[test]
+    /// fn ui() {
+    ///     let dir = TestDir::new(testdir!());
+    ///     let _guard = dir.compile_test().unwrap();
+    ///     let t = trybuild::TestCases::new();
+    ///     t.compile_fail("tests/ui/*.rs");
+    /// }
+    /// ```
+    ///
+    /// [`CompileTestGuard`]: crate::CompileTestGuard
+    pub fn compile_test(&self) -> Result<crate::CompileTestGuard, Error> {
+        crate::CompileTestGuard::new(&self.path).map_err(Error::from)
+    }
+
+    /// Snapshots the contents of the test directory as the checkpoint `name`.
+    ///
+    /// Multi-step tests can use this to save the state after a setup phase and return to
+    /// it using [`TestDir::restore`], e.g. to retry a phase against pristine state.  The
+    /// contents are copied into a `.testdir-checkpoints` directory inside the test
+    /// directory, an existing checkpoint with the same name is replaced.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// let dir = TestDir::new(testdir!());
+    /// dir.child("state.txt").write("pristine").unwrap();
+    /// dir.checkpoint("setup").unwrap();
+    /// dir.child("state.txt").write("modified").unwrap();
+    /// dir.restore("setup").unwrap();
+    /// assert_eq!(dir.child("state.txt").read_to_string().unwrap(), "pristine");
+    /// ```
+    pub fn checkpoint(&self, name: &str) -> Result<(), Error> {
+        crate::checkpoint::checkpoint(&self.path, name).map_err(Error::from)
+    }
+
+    /// Rolls the contents of the test directory back to the checkpoint `name`.
+    ///
+    /// Everything in the test directory is removed and replaced with the contents saved by
+    /// [`TestDir::checkpoint`].  The checkpoint itself is kept, so it can be restored
+    /// several times.
+    pub fn restore(&self, name: &str) -> Result<(), Error> {
+        crate::checkpoint::restore(&self.path, name).map_err(Error::from)
+    }
+
+    /// Writes a deterministic tar archive of the test directory to `dest`.
+    ///
+    /// The entries are sorted and their timestamps and ownership normalised, so the same
+    /// contents always produce the same archive.  This is useful to attach the artifacts
+    /// of a failing test to a bug report.  The archive extracts into a single directory
+    /// named like the test directory.  `dest` must not be inside the test directory.
+    ///
+    /// This requires the `tar` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// let dir = TestDir::new(testdir!());
+    /// dir.child("output.log").write("oops").unwrap();
+    /// let dest = dir.parent().unwrap().join("archive_to.tar");
+    /// dir.archive_to(&dest).unwrap();
+    /// assert!(dest.is_file());
+    /// ```
+    #[cfg(feature = "tar")]
+    pub fn archive_to(&self, dest: impl AsRef<Path>) -> Result<(), Error> {
+        crate::archive::archive_to(&self.path, dest.as_ref()).map_err(Error::from)
+    }
+
+    /// Writes a compressed deterministic tar archive of the test directory to `dest`.
+    ///
+    /// Like [`TestDir::archive_to`] but the archive is compressed using the
+    /// [`Compressor`], e.g. `Gzip` with the `gzip` feature or `Zstd` with the `zstd`
+    /// feature.
+    ///
+    /// This requires the `tar` feature.
+    ///
+    /// [`Compressor`]: crate::Compressor
+    #[cfg(feature = "tar")]
+    pub fn archive_to_with(
+        &self,
+        dest: impl AsRef<Path>,
+        compressor: &dyn crate::Compressor,
+    ) -> Result<(), Error> {
+        crate::archive::archive_to_with(&self.path, dest.as_ref(), compressor).map_err(Error::from)
+    }
+
+    /// Exports the test directory to the command in [`TEST_DIR_ENV`].
+    ///
+    /// The global [`NumberedDir`] is exported in [`RUN_DIR_ENV`], the command can find both
+    /// using [`from_env`](crate::from_env).
+    ///
+    /// [`TEST_DIR_ENV`]: crate::TEST_DIR_ENV
+    /// [`RUN_DIR_ENV`]: crate::RUN_DIR_ENV
+    /// [`NumberedDir`]: crate::NumberedDir
+    pub fn export_env<'a>(&self, cmd: &'a mut Command) -> &'a mut Command {
+        cmd.envs(self.env_vars())
+    }
+
+    /// Returns the environment variables set by [`TestDir::export_env`].
+    pub(crate) fn env_vars(&self) -> Vec<(&'static str, OsString)> {
+        crate::child_env::child_vars(&self.path)
+    }
+
+    /// Runs a command, capturing its output into the test directory.
+    ///
+    /// The stdout and stderr of the command are written to `stdout.log` and `stderr.log`
+    /// in the test directory while the command runs, overwriting any previous files.  Once
+    /// the command finished its output is also printed, so it shows up in the output of
+    /// failing tests as usual.  The test directory is exported to the command using
+    /// [`TestDir::export_env`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::process::Command;
+    /// use testdir::{testdir, TestDir};
+    ///
+    /// let dir = TestDir::new(testdir!());
+    /// let mut cmd = Command::new("cargo");
+    /// cmd.arg("--version");
+    /// let run = dir.capture_output(cmd).unwrap();
+    /// assert!(run.status.success());
+    /// assert!(run.stdout_path.is_file());
+    /// ```
+    pub fn capture_output(&self, mut cmd: Command) -> Result<CapturedRun, Error> {
+        self.prepare()?;
+        let stdout_path = self.path.join("stdout.log");
+        let stderr_path = self.path.join("stderr.log");
+        let stdout_file = File::create(&stdout_path)
+            .with_context(|| format!("Failed to create {}", stdout_path.display()))?;
+        let stderr_file = File::create(&stderr_path)
+            .with_context(|| format!("Failed to create {}", stderr_path.display()))?;
+
+        let mut child = self
+            .export_env(&mut cmd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to spawn {:?}", cmd))?;
+        let stdout = child.stdout.take().map(|pipe| tee(pipe, stdout_file));
+        let stderr = child.stderr.take().map(|pipe| tee(pipe, stderr_file));
+        let status = child.wait().context("Failed to wait for command")?;
+        let stdout = join_tee(stdout)?;
+        let stderr = join_tee(stderr)?;
+
+        print!("{}", String::from_utf8_lossy(&stdout));
+        eprint!("{}", String::from_utf8_lossy(&stderr));
+        Ok(CapturedRun {
+            status,
+            stdout_path,
+            stderr_path,
+            stdout,
+            stderr,
+        })
+    }
+
+    /// Writes already captured output to `stdout.log` and `stderr.log`.
+    #[cfg_attr(not(any(feature = "assert_cmd", feature = "duct")), allow(dead_code))]
+    pub(crate) fn write_output_logs(&self, output: &Output) -> Result<CapturedRun> {
+        self.prepare()?;
+        let stdout_path = self.path.join("stdout.log");
+        let stderr_path = self.path.join("stderr.log");
+        fs::write(&stdout_path, &output.stdout)
+            .with_context(|| format!("Failed to write {}", stdout_path.display()))?;
+        fs::write(&stderr_path, &output.stderr)
+            .with_context(|| format!("Failed to write {}", stderr_path.display()))?;
+        Ok(CapturedRun {
+            status: output.status,
+            stdout_path,
+            stderr_path,
+            stdout: output.stdout.clone(),
+            stderr: output.stderr.clone(),
+        })
+    }
+}
+
+/// A path inside a [`TestDir`], created from [`TestDir::child`].
+///
+/// Writing to a child path creates any missing parent directories first.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChildPath {
+    path: PathBuf,
+}
+
+impl ChildPath {
+    fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Returns the full path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns a [`ChildPath`] for a path relative to this one.
+    pub fn child(&self, path: impl AsRef<Path>) -> ChildPath {
+        ChildPath::new(self.path.join(path))
+    }
+
+    /// Writes `contents` to the file, creating parent directories as needed.
+    pub fn write(&self, contents: impl AsRef<[u8]>) -> Result<(), Error> {
+        self.create_parent()?;
+        fs::write(&self.path, contents)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+            .map_err(Error::from)
+    }
+
+    /// Creates the file if it does not exist, creating parent directories as needed.
+    ///
+    /// An existing file is left untouched.
+    pub fn touch(&self) -> Result<(), Error> {
+        self.create_parent()?;
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to touch {}", self.path.display()))?;
+        Ok(())
+    }
+
+    /// Creates the path as a directory, including all parents.
+    pub fn create_dir_all(&self) -> Result<(), Error> {
+        fs::create_dir_all(&self.path)
+            .with_context(|| format!("Failed to create {}", self.path.display()))
+            .map_err(Error::from)
+    }
+
+    /// Reads the contents of the file.
+    pub fn read(&self) -> Result<Vec<u8>, Error> {
+        fs::read(&self.path)
+            .with_context(|| format!("Failed to read {}", self.path.display()))
+            .map_err(Error::from)
+    }
+
+    /// Reads the contents of the file as a string.
+    pub fn read_to_string(&self) -> Result<String, Error> {
+        fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read {}", self.path.display()))
+            .map_err(Error::from)
+    }
+
+    /// Sets the extended attribute `name` of this path.
+    #[cfg(all(unix, feature = "xattr"))]
+    pub fn set_xattr(&self, name: &str, value: &[u8]) -> Result<(), Error> {
+        xattr::set(&self.path, name, value)
+            .with_context(|| format!("Failed to set xattr {name} on {}", self.path.display()))
+            .map_err(Error::from)
+    }
+
+    /// Returns the extended attribute `name` of this path, if it is set.
+    #[cfg(all(unix, feature = "xattr"))]
+    pub fn get_xattr(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        xattr::get(&self.path, name)
+            .with_context(|| format!("Failed to get xattr {name} from {}", self.path.display()))
+            .map_err(Error::from)
+    }
+
+    pub(crate) fn create_parent(&self) -> Result<()> {
+        match self.path.parent() {
+            Some(parent) => fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Deref for ChildPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl From<PathBuf> for ChildPath {
+    fn from(path: PathBuf) -> Self {
+        Self::new(path)
+    }
+}
+
+impl AsRef<Path> for ChildPath {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Copies everything from `reader` into `file`, collecting it as well.
+fn tee(
+    mut reader: impl Read + Send + 'static,
+    mut file: File,
+) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut collected = Vec::new();
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            file.write_all(&buf[..n])?;
+            collected.extend_from_slice(&buf[..n]);
+        }
+        file.flush()?;
+        Ok(collected)
+    })
+}
+
+fn join_tee(handle: Option<thread::JoinHandle<io::Result<Vec<u8>>>>) -> Result<Vec<u8>> {
+    match handle {
+        Some(handle) => handle
+            .join()
+            .map_err(|_| anyhow::Error::msg("Output capture thread panicked"))?
+            .context("Failed to capture output"),
+        None => Ok(Vec::new()),
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl From<PathBuf> for TestDir {
+    fn from(path: PathBuf) -> Self {
+        Self::new(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_capture_output() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "echo out; echo err >&2; exit 3"]);
+        let run = dir.capture_output(cmd).unwrap();
+        assert_eq!(run.status.code(), Some(3));
+        assert_eq!(run.stdout, b"out\n");
+        assert_eq!(run.stderr, b"err\n");
+        assert_eq!(fs::read_to_string(&run.stdout_path).unwrap(), "out\n");
+        assert_eq!(fs::read_to_string(&run.stderr_path).unwrap(), "err\n");
+        assert_eq!(run.stdout_path, temp.path().join("stdout.log"));
+    }
+
+    #[test]
+    fn test_child_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        let file = dir.child("a/b/c.txt");
+        assert!(!temp.path().join("a").exists());
+        file.write("hello").unwrap();
+        assert_eq!(file.read_to_string().unwrap(), "hello");
+
+        let other = dir.child("x").child("y/empty");
+        other.touch().unwrap();
+        assert!(other.is_file());
+        dir.child("a/b/c.txt").touch().unwrap();
+        assert_eq!(file.read().unwrap(), b"hello");
+        assert!(dir.child("nope").read().is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        dir.write_json("a/b.json", &vec![1, 2, 3]).unwrap();
+        let value: Vec<u32> = dir.read_json("a/b.json").unwrap();
+        assert_eq!(value, vec![1, 2, 3]);
+        assert!(dir.read_json::<String>("a/b.json").is_err());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        let value = std::collections::BTreeMap::from([("key".to_string(), "value".to_string())]);
+        dir.write_toml("conf/a.toml", &value).unwrap();
+        assert_eq!(
+            dir.child("conf/a.toml").read_to_string().unwrap().trim(),
+            r#"key = "value""#
+        );
+        let read: std::collections::BTreeMap<String, String> =
+            dir.read_toml("conf/a.toml").unwrap();
+        assert_eq!(read, value);
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        dir.write_yaml("conf/a.yaml", &vec!["one", "two"]).unwrap();
+        let read: Vec<String> = dir.read_yaml("conf/a.yaml").unwrap();
+        assert_eq!(read, vec!["one", "two"]);
+    }
+
+    #[cfg(all(unix, feature = "xattr"))]
+    #[test]
+    fn test_xattr() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = TestDir::new(temp.path());
+        let file = dir.child("file");
+        file.touch().unwrap();
+        if let Err(err) = file.set_xattr("user.testdir", b"value") {
+            // Not all filesystems support user extended attributes.
+            eprintln!("skipping: {err:#}");
+            return;
+        }
+        assert_eq!(
+            dir.get_xattr("file", "user.testdir").unwrap(),
+            Some(b"value".to_vec())
+        );
+        assert_eq!(file.get_xattr("user.missing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_auto_recreate() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("dir");
+        let plain = TestDir::new(&path);
+        let healing = TestDir::new(&path).auto_recreate(true);
+        assert!(plain.write_output_logs(&output()).is_err());
+        healing.write_output_logs(&output()).unwrap();
+        assert!(path.join("stdout.log").is_file());
+
+        fs::remove_dir_all(&path).unwrap();
+        assert_eq!(plain.ensure().unwrap(), path);
+        assert!(path.is_dir());
+    }
+
+    fn output() -> Output {
+        Output {
+            status: Command::new("cargo").arg("--version").status().unwrap(),
+            stdout: b"out".to_vec(),
+            stderr: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_deref() {
+        let dir = TestDir::from(PathBuf::from("/some/dir"));
+        assert!(dir.ends_with("dir"));
+        assert_eq!(dir.path(), Path::new("/some/dir"));
+        assert_eq!(dir.into_path(), PathBuf::from("/some/dir"));
+    }
+}
@@ -0,0 +1,121 @@
+//! Detection of the version control revision, see [`VcsInfo`].
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+/// The filename in which the [`VcsInfo`] of a numbered dir is stored: `vcs.json`.
+pub(crate) const VCS_FILE_NAME: &str = "vcs.json";
+
+/// The environment variables of CI systems holding the commit and branch.
+const VCS_VARS: [(&str, &str); 3] = [
+    ("GITHUB_SHA", "GITHUB_REF_NAME"),
+    ("CI_COMMIT_SHA", "CI_COMMIT_REF_NAME"),
+    ("GIT_COMMIT", "GIT_BRANCH"),
+];
+
+/// The version control revision the tests are run from.
+///
+/// This can be recorded in the numbered directory, see
+/// [`NumberedDirBuilder::record_vcs`], so artifacts can be traced back to the source
+/// revision which produced them.
+///
+/// [`NumberedDirBuilder::record_vcs`]: crate::NumberedDirBuilder::record_vcs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VcsInfo {
+    /// The full commit hash.
+    pub commit: String,
+    /// The branch name, if known.
+    pub branch: Option<String>,
+}
+
+impl VcsInfo {
+    /// Detects the current revision.
+    ///
+    /// The environment variables of common CI systems are used first: `GITHUB_SHA`,
+    /// `CI_COMMIT_SHA` and `GIT_COMMIT`.  Otherwise `git` is run in the current
+    /// directory.  Returns `None` if the revision could not be determined.
+    pub fn detect() -> Option<Self> {
+        Self::from_env(|name| std::env::var(name).ok()).or_else(Self::from_git)
+    }
+
+    /// Returns the abbreviated commit hash, its first 8 characters.
+    pub fn short_commit(&self) -> &str {
+        self.commit.get(..8).unwrap_or(&self.commit)
+    }
+
+    /// Detects the revision from CI environment variables, looked up using `var`.
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        VCS_VARS.iter().find_map(|(commit, branch)| {
+            let commit = var(commit).filter(|commit| !commit.is_empty())?;
+            Some(Self {
+                commit,
+                branch: var(branch).filter(|branch| !branch.is_empty()),
+            })
+        })
+    }
+
+    /// Detects the revision using the `git` command line tool.
+    fn from_git() -> Option<Self> {
+        let git = |args: &[&str]| {
+            let output = Command::new("git").args(args).output().ok()?;
+            let stdout = String::from_utf8(output.stdout).ok()?;
+            output.status.success().then(|| stdout.trim().to_string())
+        };
+        let commit = git(&["rev-parse", "HEAD"])?;
+        let branch = git(&["rev-parse", "--abbrev-ref", "HEAD"]).filter(|b| b != "HEAD");
+        Some(Self { commit, branch })
+    }
+
+    /// Returns the JSON representation used in the `vcs.json` file and manifests.
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "commit": self.commit,
+            "branch": self.branch,
+        })
+    }
+
+    /// Parses the JSON representation created by [`VcsInfo::to_json`].
+    pub(crate) fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            commit: value.get("commit")?.as_str()?.to_string(),
+            branch: value
+                .get("branch")
+                .and_then(Value::as_str)
+                .map(String::from),
+        })
+    }
+
+    /// Writes the `vcs.json` file into the numbered directory `dir`.
+    pub(crate) fn write(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(VCS_FILE_NAME);
+        let data = serde_json::to_vec_pretty(&self.to_json())?;
+        std::fs::write(&path, data).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_env() {
+        let vars = [
+            ("CI_COMMIT_SHA", "0123456789abcdef"),
+            ("CI_COMMIT_REF_NAME", "main"),
+        ];
+        let var = |name: &str| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        };
+        let info = VcsInfo::from_env(var).unwrap();
+        assert_eq!(info.commit, "0123456789abcdef");
+        assert_eq!(info.branch.as_deref(), Some("main"));
+        assert_eq!(info.short_commit(), "01234567");
+        assert_eq!(VcsInfo::from_json(&info.to_json()), Some(info));
+        assert_eq!(VcsInfo::from_env(|_| None), None);
+    }
+}
@@ -0,0 +1,130 @@
+//! Helpers to wait for files to appear in test directories.
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+use crate::Error;
+
+/// The initial delay between polls.
+const INITIAL_DELAY: Duration = Duration::from_millis(1);
+
+/// The maximum delay between polls.
+const MAX_DELAY: Duration = Duration::from_millis(100);
+
+/// Waits until a path exists.
+///
+/// This is useful for tests which spawn subprocesses which are expected to create files
+/// asynchronously.  The path is polled with an exponential backoff until it exists or the
+/// `timeout` expires, in which case an error is returned.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use testdir::{testdir, wait_for_path};
+///
+/// let dir = testdir!();
+/// let path = dir.join("output.txt");
+/// std::thread::spawn({
+///     let path = path.clone();
+///     move || std::fs::write(path, "done")
+/// });
+/// wait_for_path(&path, Duration::from_secs(10)).unwrap();
+/// ```
+pub fn wait_for_path(path: impl AsRef<Path>, timeout: Duration) -> Result<(), Error> {
+    let path = path.as_ref();
+    poll(timeout, || path.exists().then_some(()))
+        .ok_or_else(|| anyhow!("Timeout waiting for path to exist: {}", path.display()))
+        .map_err(Error::from)
+}
+
+/// Waits until a file exists and contains `needle`.
+///
+/// Like [`wait_for_path`] this polls with an exponential backoff until the file contains
+/// the requested string or the `timeout` expires, in which case an error is returned.  On
+/// success the full contents of the file are returned.
+pub fn wait_for_file_containing(
+    path: impl AsRef<Path>,
+    needle: &str,
+    timeout: Duration,
+) -> Result<String, Error> {
+    let path = path.as_ref();
+    poll(timeout, || {
+        fs::read_to_string(path)
+            .ok()
+            .filter(|content| content.contains(needle))
+    })
+    .ok_or_else(|| {
+        anyhow!(
+            "Timeout waiting for {:?} in file: {}",
+            needle,
+            path.display()
+        )
+    })
+    .map_err(Error::from)
+}
+
+/// Calls `func` with an exponential backoff until it returns `Some` or `timeout` expires.
+fn poll<T>(timeout: Duration, mut func: impl FnMut() -> Option<T>) -> Option<T> {
+    let deadline = Instant::now() + timeout;
+    let mut delay = INITIAL_DELAY;
+    loop {
+        if let Some(val) = func() {
+            return Some(val);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        thread::sleep(delay.min(deadline - now));
+        delay = (delay * 2).min(MAX_DELAY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_for_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        let handle = thread::spawn({
+            let path = path.clone();
+            move || {
+                thread::sleep(Duration::from_millis(20));
+                fs::write(path, "hello").unwrap();
+            }
+        });
+        wait_for_path(&path, Duration::from_secs(10)).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_for_path_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let res = wait_for_path(dir.path().join("nope"), Duration::from_millis(10));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_wait_for_file_containing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, "starting").unwrap();
+        let handle = thread::spawn({
+            let path = path.clone();
+            move || {
+                thread::sleep(Duration::from_millis(20));
+                fs::write(path, "starting\nready").unwrap();
+            }
+        });
+        let content = wait_for_file_containing(&path, "ready", Duration::from_secs(10)).unwrap();
+        assert_eq!(content, "starting\nready");
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+//! Sharing the global testdir between the crates of a workspace, see [`workspace_crates`].
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::kv::RUN_KV_FILE_NAME;
+use crate::{Error, NumberedDir, RunKv};
+
+/// The prefix of the [`RunKv`] keys under which the crates register their subdirectory.
+const CRATE_KEY_PREFIX: &str = "crate:";
+
+/// Returns the subdirectories of the crates sharing the global [`NumberedDir`].
+///
+/// With `init_testdir!(workspace = true)` all test directories of a crate are created in a
+/// subdirectory named after the package, and all crates of a workspace run by the same
+/// `cargo test` invocation share the same numbered directory.  Each crate registers its
+/// subdirectory in the [`run_kv`](crate::run_kv) store when it joins the run, this returns
+/// the package names and absolute paths of all crates which have joined so far.
+///
+/// Crates which did not enable workspace mode are not listed.
+///
+/// # Examples
+///
+/// ```
+/// use testdir::{init_testdir, testdir, workspace_crates};
+///
+/// init_testdir!(workspace = true);
+/// let dir = testdir!();
+/// let crates = workspace_crates().unwrap();
+/// assert!(dir.starts_with(&crates["testdir"]));
+/// ```
+pub fn workspace_crates() -> Result<BTreeMap<String, PathBuf>, Error> {
+    crate::with_testdir(crates_in)
+}
+
+/// Registers `crate_name` as a member of the workspace run in `numdir`.
+pub(crate) fn register(numdir: &NumberedDir, crate_name: &str) -> Result<(), Error> {
+    let kv = RunKv::open(numdir.path().join(RUN_KV_FILE_NAME));
+    kv.get_or_set_with(&format!("{CRATE_KEY_PREFIX}{crate_name}"), || {
+        crate_name.to_string()
+    })?;
+    Ok(())
+}
+
+fn crates_in(numdir: &NumberedDir) -> Result<BTreeMap<String, PathBuf>, Error> {
+    let kv = RunKv::open(numdir.path().join(RUN_KV_FILE_NAME));
+    Ok(kv
+        .entries()?
+        .into_iter()
+        .filter_map(|(key, subdir)| {
+            let name = key.strip_prefix(CRATE_KEY_PREFIX)?;
+            Some((name.to_string(), numdir.path().join(subdir)))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU8;
+
+    use super::*;
+
+    #[test]
+    fn test_register_crates() {
+        let parent = tempfile::tempdir().unwrap();
+        let numdir = NumberedDir::create(parent.path(), "ws", NonZeroU8::new(3).unwrap()).unwrap();
+        assert!(crates_in(&numdir).unwrap().is_empty());
+
+        register(&numdir, "alpha").unwrap();
+        register(&numdir, "beta").unwrap();
+        register(&numdir, "alpha").unwrap();
+        let crates = crates_in(&numdir).unwrap();
+        assert_eq!(crates.len(), 2);
+        assert_eq!(crates["alpha"], numdir.path().join("alpha"));
+        assert_eq!(crates["beta"], numdir.path().join("beta"));
+    }
+}
@@ -0,0 +1,15 @@
+//! Tests per-attempt directories using `init_testdir!(per_attempt = true)`.
+
+use testdir::{init_testdir, testdir};
+
+#[test]
+fn test_per_attempt() {
+    init_testdir!(per_attempt = true);
+    let attempt = std::env::var("NEXTEST_ATTEMPT").unwrap_or_else(|_| String::from("1"));
+    let dir = testdir!();
+    assert!(dir.is_dir());
+    assert!(dir.ends_with(format!("attempt/test_per_attempt/attempt-{attempt}")));
+
+    let dir = testdir!(attempt = 5);
+    assert!(dir.ends_with("attempt/test_per_attempt/attempt-5"));
+}
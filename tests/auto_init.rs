@@ -0,0 +1,21 @@
+//! Tests for [`testdir::auto_init_testdir`], requires the `ctor` feature.
+#![cfg(feature = "ctor")]
+
+use std::path::PathBuf;
+
+use once_cell::sync::Lazy;
+use testdir::with_testdir;
+
+testdir::auto_init_testdir!();
+
+static EARLY: Lazy<PathBuf> = Lazy::new(|| with_testdir(|dir| dir.path().to_path_buf()));
+
+#[test]
+fn test_auto_init() {
+    assert!(testdir::TESTDIR.get().is_some());
+    assert!(EARLY
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .starts_with("testdir-"));
+}
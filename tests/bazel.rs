@@ -0,0 +1,20 @@
+//! Running under a build system like Bazel, which provides its own temporary directory.
+//!
+//! This is a separate test binary since the environment must be set before the global
+//! testdir is created.
+
+use testdir::{testdir, BuildSystemEnv};
+
+#[test]
+fn test_bazel_tmpdir() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    std::env::set_var("TEST_TMPDIR", tmpdir.path());
+    assert_eq!(
+        BuildSystemEnv::detect().map(|env| env.tmpdir),
+        Some(tmpdir.path().to_path_buf())
+    );
+
+    let dir = testdir!();
+    assert!(dir.starts_with(tmpdir.path()));
+    assert!(dir.ends_with("test_bazel_tmpdir"));
+}
@@ -0,0 +1,36 @@
+//! Tests the capability-based handles of the `cap-std` feature.
+#![cfg(feature = "cap-std")]
+
+use std::fs;
+
+use testdir::testdir_cap;
+
+#[test]
+fn test_cap_dir() {
+    let dir = testdir_cap!();
+    dir.write("file", "content").unwrap();
+    let path = testdir::testdir!();
+    assert_eq!(fs::read_to_string(path.join("file")).unwrap(), "content");
+}
+
+#[test]
+fn test_cap_dir_escape() {
+    let path = testdir::testdir!();
+    let dir = testdir_cap!();
+    assert!(dir.write("../escape", "content").is_err());
+    assert!(!path.parent().unwrap().join("escape").exists());
+
+    let outside = path.parent().unwrap().join("absolute");
+    assert!(dir.write(&outside, "content").is_err());
+    assert!(!outside.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cap_dir_symlink_escape() {
+    let path = testdir::testdir!();
+    std::os::unix::fs::symlink(path.parent().unwrap(), path.join("link")).unwrap();
+    let dir = testdir_cap!();
+    assert!(dir.write("link/escape", "content").is_err());
+    assert!(!path.parent().unwrap().join("escape").exists());
+}
@@ -0,0 +1,54 @@
+//! Tests exporting the managed directories to child processes.
+
+use std::env;
+use std::ffi::OsStr;
+use std::process::Command;
+
+use testdir::{testdir, with_testdir, TestDir, RUN_DIR_ENV, TEST_DIR_ENV};
+
+#[test]
+fn test_run_dir_env() {
+    let dir = TestDir::new(testdir!());
+    let numdir = with_testdir(|numdir| numdir.path().to_path_buf());
+    assert_eq!(env::var_os(RUN_DIR_ENV), None);
+
+    let mut cmd = Command::new("true");
+    dir.export_env(&mut cmd);
+    let envs: Vec<_> = cmd.get_envs().collect();
+    assert!(envs.contains(&(OsStr::new(RUN_DIR_ENV), Some(numdir.as_os_str()))));
+    assert!(envs.contains(&(OsStr::new(TEST_DIR_ENV), Some(dir.as_os_str()))));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_child_process_env() {
+    let dir = TestDir::new(testdir!());
+    let mut cmd = Command::new("sh");
+    cmd.args([
+        "-c",
+        "echo \"$TESTDIR_RUN_DIR\"; echo \"$TESTDIR_TEST_DIR\"",
+    ]);
+    let run = dir.capture_output(cmd).unwrap();
+    assert!(run.status.success());
+
+    let stdout = String::from_utf8(run.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    let numdir = with_testdir(|numdir| numdir.path().to_path_buf());
+    assert_eq!(lines, [numdir.to_str().unwrap(), dir.to_str().unwrap()]);
+}
+
+#[test]
+fn test_cargo_pid_cached() {
+    let dir = TestDir::new(testdir!());
+    assert_eq!(env::var_os("TESTDIR_CARGO_PID"), None);
+
+    let mut cmd = Command::new("true");
+    dir.export_env(&mut cmd);
+    let (_, cached) = cmd
+        .get_envs()
+        .find(|(key, _)| *key == "TESTDIR_CARGO_PID")
+        .unwrap();
+    let cached = cached.unwrap().to_str().unwrap();
+    let (_, setter) = cached.rsplit_once(':').unwrap();
+    assert_eq!(setter, std::process::id().to_string());
+}
@@ -0,0 +1,17 @@
+use testdir::{testdir, TestDir};
+
+// The only test in this binary, as it modifies the environment of the process.
+#[test]
+fn test_compile_test() {
+    let dir = TestDir::new(testdir!());
+    let previous = std::env::var_os("CARGO_TARGET_DIR");
+
+    let guard = dir.compile_test().unwrap();
+    assert!(guard.target_dir().is_dir());
+    assert_eq!(
+        std::env::var_os("CARGO_TARGET_DIR"),
+        Some(dir.join("target").into_os_string())
+    );
+    drop(guard);
+    assert_eq!(std::env::var_os("CARGO_TARGET_DIR"), previous);
+}
@@ -0,0 +1,26 @@
+//! Tests using the global testdir from the hooks of the builder initialising it.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use testdir::NumberedDirBuilder;
+
+#[test]
+fn test_hook_is_initialized() {
+    let parent = tempfile::tempdir().unwrap();
+    let called = Arc::new(AtomicBool::new(false));
+    let mut builder = NumberedDirBuilder::new(String::from("hook"));
+    builder.set_parent(parent.path().to_path_buf());
+    builder.on_create({
+        let called = called.clone();
+        move |_path| {
+            assert!(!testdir::is_initialized());
+            assert_eq!(testdir::current_path(), None);
+            called.store(true, Ordering::SeqCst);
+        }
+    });
+    let numdir = testdir::init_with(builder).unwrap();
+    assert!(called.load(Ordering::SeqCst));
+    assert!(testdir::is_initialized());
+    assert_eq!(testdir::current_path().as_deref(), Some(numdir.path()));
+}
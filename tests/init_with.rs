@@ -0,0 +1,20 @@
+//! Tests initialising the global testdir using [`testdir::init_with`].
+
+use testdir::{testdir, with_testdir, NumberedDirBuilder};
+
+#[test]
+fn test_init_with() {
+    let parent = std::env::temp_dir().join("testdir-init-with");
+    let mut builder = NumberedDirBuilder::new(String::from("custom"));
+    builder.set_parent(parent.clone());
+    let numdir = testdir::init_with(builder).unwrap();
+    assert!(numdir.path().starts_with(&parent));
+    assert_eq!(numdir.base(), "custom");
+
+    let path = with_testdir(|dir| dir.path().to_path_buf());
+    assert_eq!(path, numdir.path());
+
+    let dir = testdir!();
+    assert!(dir.starts_with(numdir.path()));
+    assert!(dir.ends_with("init_with/test_init_with"));
+}
@@ -1,7 +1,7 @@
 use std::path::{Path, PathBuf};
 
 use once_cell::sync::Lazy;
-use testdir::testdir;
+use testdir::{testdir, testdir_run_root};
 
 static MOD_LEVEL: Lazy<PathBuf> = Lazy::new(|| testdir!(ModuleScope));
 
@@ -63,6 +63,27 @@ fn test_varname() {
     assert!(val.ends_with("sub/dir2"));
 }
 
+#[test]
+fn test_run_root() {
+    let root = testdir_run_root!();
+    let dir = testdir!();
+    assert!(root.is_dir());
+    assert!(dir.starts_with(&root));
+    assert!(root.join("cargo-pid").is_file());
+}
+
+#[test]
+fn test_created_dirs() {
+    let dir = testdir!();
+    let created = testdir::created_dirs();
+    assert!(created
+        .iter()
+        .any(|c| c.path == dir && c.scope == testdir::Scope::Test));
+    assert!(created
+        .iter()
+        .all(|c| c.path.starts_with(testdir_run_root!())));
+}
+
 #[test]
 fn test_cargo_pid_created() {
     let root = testdir!("spam");
@@ -89,3 +110,98 @@ mod submodule {
         assert!(SUB_MOD.ends_with("r#macro/submodule/mod"));
     }
 }
+
+#[test]
+fn test_clean() {
+    let dir = testdir!();
+    std::fs::create_dir(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("sub/file.txt"), "data").unwrap();
+    std::fs::write(dir.join("file.txt"), "data").unwrap();
+
+    let again = testdir!(clean = true);
+    assert_eq!(again, dir);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+    std::fs::write(dir.join("file.txt"), "data").unwrap();
+    let kept = testdir!(clean = false, TestScope);
+    assert!(kept.join("file.txt").is_file());
+    let sub = testdir!(clean = true, "sub/dir3");
+    assert!(sub.ends_with("sub/dir3"));
+}
+
+#[test]
+fn test_no_create() {
+    let dir = testdir!(create = false);
+    assert!(dir.ends_with("r#macro/test_no_create"));
+    assert!(!dir.exists());
+
+    let sub = testdir!(create = false, clean = true, "sub/dir4");
+    assert!(sub.ends_with("sub/dir4"));
+    assert!(!sub.exists());
+}
+
+#[test]
+fn test_try_testdir() {
+    let dir = testdir::try_testdir!().unwrap();
+    assert!(dir.ends_with("r#macro/test_try_testdir"));
+    assert!(dir.is_dir());
+
+    let sub = testdir::try_testdir!(clean = true, "sub/dir5").unwrap();
+    assert!(sub.ends_with("sub/dir5"));
+
+    let err = testdir::try_testdir!(named = "not-initialised", ModuleScope).unwrap_err();
+    assert!(err.to_string().contains("not-initialised"));
+}
+
+#[test]
+fn test_strict() {
+    let err = testdir::try_testdir!("../escape").unwrap_err();
+    assert!(matches!(err, testdir::Error::PathTraversal { .. }));
+
+    let dir = testdir!(strict = false, create = false, "sub/../dir6");
+    assert!(dir.ends_with("sub/../dir6"));
+}
+
+#[test]
+fn test_target_dir_source_recorded() {
+    let _dir = testdir!();
+    let source = testdir::run_kv().get("target-dir-source").unwrap();
+    assert!(source.is_some());
+}
+
+#[test]
+fn test_current_path() {
+    assert_eq!(testdir::current_test_path(), None);
+    let dir = testdir!();
+    assert_eq!(testdir::current_test_path(), Some(dir.clone()));
+    let run_dir = testdir::current_path().unwrap();
+    assert!(dir.starts_with(&run_dir));
+    assert_eq!(
+        run_dir,
+        testdir::with_testdir(|numdir| numdir.path().to_path_buf())
+    );
+}
+
+#[test]
+fn test_attempt() {
+    let dir = testdir!(attempt = 2);
+    assert!(dir.is_dir());
+    assert!(dir.ends_with("r#macro/test_attempt/attempt-2"));
+
+    let dir = testdir::try_testdir!(clean = true, attempt = 3).unwrap();
+    assert!(dir.ends_with("r#macro/test_attempt/attempt-3"));
+
+    let dir = testdir!(attempt = 4, ModuleScope);
+    assert!(dir.ends_with("r#macro/mod"));
+}
+
+#[test]
+fn test_canonicalize() {
+    let dir = testdir!(canonicalize = true, "canon/dir");
+    assert!(dir.is_absolute());
+    assert_eq!(dir, testdir!("canon/dir").canonicalize().unwrap());
+
+    let dir = testdir!(canonicalize = true, create = false, "canon/new");
+    assert!(!dir.exists());
+    assert!(dir.ends_with("canon/new"));
+}
@@ -0,0 +1,25 @@
+//! Tests for named global testdirs using [`testdir::init_named`].
+
+use testdir::{testdir, NumberedDirBuilder};
+
+#[test]
+fn test_named_testdir() {
+    let parent = std::env::temp_dir().join("testdir-named");
+    let mut builder = NumberedDirBuilder::new(String::from("scratch"));
+    builder.set_parent(parent.clone());
+    let numdir = testdir::init_named("scratch", builder).unwrap();
+    assert!(numdir.path().starts_with(&parent));
+
+    let dir = testdir!(named = "scratch");
+    assert!(dir.starts_with(numdir.path()));
+    assert!(dir.ends_with("named/test_named_testdir"));
+
+    let module = testdir!(named = "scratch", ModuleScope);
+    assert!(module.ends_with("named/mod"));
+
+    let sub = testdir!(named = "scratch", "some/sub");
+    assert_eq!(sub, numdir.path().join("some/sub"));
+
+    let default = testdir!();
+    assert!(!default.starts_with(numdir.path()));
+}
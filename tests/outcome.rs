@@ -0,0 +1,53 @@
+use testdir::{testdir, NumberedDirBuilder, Outcome, OutcomeGuard, Run};
+
+#[test]
+fn test_outcome_guard() {
+    let dir = testdir!();
+    {
+        let _guard = OutcomeGuard::new();
+    }
+    let run_dir = dir.parent().unwrap().parent().unwrap();
+    let run = Run::load(run_dir).unwrap();
+    let subdir = run
+        .subdirs()
+        .iter()
+        .find(|sub| run_dir.join(&sub.path) == dir)
+        .unwrap();
+    assert_eq!(subdir.outcome, Some(Outcome::Passed));
+}
+
+#[test]
+fn test_record_outcome() {
+    let dir = testdir!();
+    testdir::record_outcome(Outcome::Skipped).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join(".testdir-outcome")).unwrap(),
+        "skipped"
+    );
+}
+
+#[test]
+fn test_record_outcome_no_dir() {
+    std::thread::spawn(|| {
+        assert!(testdir::record_outcome(Outcome::Passed).is_err());
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn test_record_outcome_named() {
+    let parent = tempfile::tempdir().unwrap();
+    let mut builder = NumberedDirBuilder::new(String::from("outcome"));
+    builder.set_parent(parent.path().to_path_buf());
+    let numdir = testdir::init_named("outcome", builder).unwrap();
+
+    let dir = testdir!(named = "outcome");
+    testdir::record_outcome(Outcome::Failed).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join(".testdir-outcome")).unwrap(),
+        "failed"
+    );
+    let link = parent.path().join("outcome-last-failed");
+    assert_eq!(std::fs::read_link(link).unwrap(), numdir.path());
+}
@@ -0,0 +1,19 @@
+//! Tests redirecting the global testdir using [`testdir::override_builder_for_tests`].
+
+use testdir::{testdir, with_testdir, NumberedDirBuilder};
+
+#[test]
+fn test_override_builder() {
+    let parent = std::env::temp_dir().join("testdir-override");
+    let mut builder = NumberedDirBuilder::new(String::from("support"));
+    builder.set_parent(parent.clone());
+    assert!(testdir::override_builder_for_tests(builder.clone()));
+
+    let dir = testdir!();
+    assert!(dir.starts_with(&parent));
+    let base = with_testdir(|numdir| numdir.base().to_string());
+    assert_eq!(base, "support");
+
+    // Too late to override again.
+    assert!(!testdir::override_builder_for_tests(builder));
+}
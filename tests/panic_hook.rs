@@ -0,0 +1,28 @@
+//! Tests the panic report written with the `panic-hook` feature.
+#![cfg(feature = "panic-hook")]
+
+use std::fs;
+use std::panic;
+
+use testdir::{testdir, PANIC_FILE_NAME};
+
+#[test]
+fn test_panic_report() {
+    let dir = testdir!();
+    let result = panic::catch_unwind(|| panic!("expected failure {}", 42));
+    assert!(result.is_err());
+
+    let report = fs::read_to_string(dir.join(PANIC_FILE_NAME)).unwrap();
+    assert!(report.contains("thread: test_panic_report"), "{report}");
+    assert!(report.contains("message: expected failure 42"), "{report}");
+    assert!(report.contains("tests/panic_hook.rs"), "{report}");
+    assert!(report.contains("backtrace:"), "{report}");
+}
+
+#[test]
+fn test_no_report_without_testdir() {
+    let result = panic::catch_unwind(|| panic!("no directory"));
+    assert!(result.is_err());
+    let dir = testdir!();
+    assert!(!dir.join(PANIC_FILE_NAME).exists());
+}
@@ -0,0 +1,23 @@
+//! Tests for a custom [`testdir::PathMapper`].
+
+use std::path::PathBuf;
+
+use testdir::{init_testdir, testdir};
+
+fn mapper(module_path: &str) -> PathBuf {
+    PathBuf::from("mapped").join(testdir::map_module_path(module_path))
+}
+
+#[test]
+fn test_path_mapper() {
+    init_testdir!(path_mapper = mapper);
+    let path = testdir!();
+    assert!(path.ends_with("mapped/path_mapper/test_path_mapper"));
+}
+
+#[test]
+fn test_path_mapper_module_scope() {
+    init_testdir!(path_mapper = mapper);
+    let path = testdir!(ModuleScope);
+    assert!(path.ends_with("mapped/path_mapper/mod"));
+}
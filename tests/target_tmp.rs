@@ -0,0 +1,15 @@
+//! Tests placing the global testdir in `target/tmp` using `init_testdir!(target_tmp = true)`.
+
+use testdir::{init_testdir, testdir, with_testdir};
+
+#[test]
+fn test_target_tmp() {
+    init_testdir!(target_tmp = true);
+    let numdir = with_testdir(|numdir| numdir.path().to_path_buf());
+    let parent = numdir.parent().unwrap();
+    assert!(parent.ends_with("tmp"));
+    assert!(parent.join("testdir-current").exists());
+
+    let dir = testdir!();
+    assert!(dir.starts_with(parent));
+}
@@ -0,0 +1,21 @@
+//! Tests sharing the global testdir with a workspace using `init_testdir!(workspace = true)`.
+
+use testdir::{init_testdir, testdir, with_testdir, workspace_crates};
+
+#[test]
+fn test_workspace() {
+    init_testdir!(base = "testdir-ws", workspace = true);
+    let numdir = with_testdir(|numdir| numdir.path().to_path_buf());
+    assert!(numdir
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .starts_with("testdir-ws-"));
+
+    let dir = testdir!();
+    assert!(dir.starts_with(numdir.join("testdir").join("workspace")));
+
+    let crates = workspace_crates().unwrap();
+    assert_eq!(crates.len(), 1);
+    assert_eq!(crates["testdir"], numdir.join("testdir"));
+}